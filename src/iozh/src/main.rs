@@ -1,24 +1,252 @@
 use std::path::PathBuf;
 use iozh_parse::ast;
-use iozh_gen_scala2::gen::generate;
+use iozh_parse::error::IozhError;
+use iozh_output::{FsTree, MemTree, Output};
 
-fn read_file_and_parse(path: &str) -> ast::Project {
-    let source = std::fs::read_to_string(path).unwrap();
-    let project = ast::Project::parse(&source).unwrap();
-    // println!("{:#?}", project);
-    project
+fn read_file_and_parse(path: &PathBuf) -> Result<ast::Project, IozhError> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| IozhError::from(format!("Failed to read {}: {}", path.display(), e)))?;
+    ast::Project::parse(&source)
 }
 
-use clap::Parser as CliParser;
+use clap::{Parser as CliParser, Subcommand, ValueEnum};
 
 #[derive(CliParser)]
+#[command(name = "iozh")]
 struct IozhCli {
-    #[arg(num_args = 2..)]
-    folders: Vec<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate code for a target from an .iozh file
+    Generate {
+        #[arg(long, value_enum, default_value_t = Target::Scala2)]
+        target: Target,
+        input: PathBuf,
+        output: PathBuf,
+        /// Rust: service methods return futures
+        #[arg(long)]
+        async_services: bool,
+        /// TypeScript: emit zod validators
+        #[arg(long)]
+        validators: bool,
+        /// Go: package clause of the generated files
+        #[arg(long, default_value = "iozh")]
+        go_package: String,
+        /// Protobuf: lock file of the field numbers, kept in the output folder by default
+        #[arg(long)]
+        proto_lock: Option<PathBuf>,
+        /// Scala 2: effect the methods of service traits return in, over the scala2.effect option
+        #[arg(long, value_enum)]
+        scala_effect: Option<ScalaEffect>,
+        /// Scala 2: how service methods surface the error they declare, over the scala2.errors option
+        #[arg(long, value_enum)]
+        scala_errors: Option<ScalaErrors>,
+        /// Scala 2: a JSON-RPC 2.0 server and client per service
+        #[arg(long)]
+        json_rpc: bool,
+        /// List the files that would be created or changed, writing nothing
+        #[arg(long)]
+        dry_run: bool,
+        /// Print a unified diff of the changes against the files on disk, writing nothing
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Print an .iozh file in canonical layout
+    Fmt {
+        input: PathBuf,
+    },
+    /// Write the SQL migrating the pgsql tables of one version of an .iozh file to another
+    Migrate {
+        from: PathBuf,
+        to: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Bootstrap .iozh source from an external schema
+    Import {
+        #[arg(value_enum)]
+        format: ImportFormat,
+        input: PathBuf,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    Openapi,
+    Jsonschema,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ScalaEffect {
+    /// Plain values, `F[_]` for traits with methods declaring an error
+    Plain,
+    /// `trait UserService[F[_]]` with `mapK`
+    Tagless,
+    /// `ZIO[Any, E, A]`
+    Zio,
+    /// `Future[A]`
+    Future,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ScalaErrors {
+    /// `F[Either[E, A]]`
+    Either,
+    /// `F[A]` with an implicit `MonadError[F, E]`
+    MonadError,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Target {
+    Scala2,
+    Scala3,
+    JsonSchema,
+    Rust,
+    Typescript,
+    Kotlin,
+    Python,
+    Go,
+    Protobuf,
+    Avro,
+    AvroScala,
+    Graphql,
+    Pgsql,
+    PgsqlDoobie,
+    PgsqlSkunk,
+}
+
+fn run(cli: IozhCli) -> Result<(), IozhError> {
+    match cli.command {
+        Command::Generate { target, input, output, async_services, validators, go_package, proto_lock, scala_effect, scala_errors, json_rpc, dry_run, diff } => {
+            let mut p = read_file_and_parse(&input)?;
+            p.expand_rest()?;
+            let mut tree = MemTree::default();
+            let out: &mut dyn Output = if dry_run || diff { &mut tree } else { &mut FsTree };
+            match target {
+                Target::Scala2 => {
+                    let errors = scala_errors.map(|e| match e {
+                        ScalaErrors::Either => iozh_gen_scala2::gen::ErrorStyle::Either,
+                        ScalaErrors::MonadError => iozh_gen_scala2::gen::ErrorStyle::MonadError,
+                    });
+                    let effect = scala_effect.map(|e| match e {
+                        ScalaEffect::Plain => iozh_gen_scala2::gen::Effect::Plain,
+                        ScalaEffect::Tagless => iozh_gen_scala2::gen::Effect::Tagless,
+                        ScalaEffect::Zio => iozh_gen_scala2::gen::Effect::Zio,
+                        ScalaEffect::Future => iozh_gen_scala2::gen::Effect::Future,
+                    });
+                    let overrides = iozh_gen_scala2::gen::Scala2Overrides { effect, errors, json_rpc };
+                    iozh_gen_scala2::gen::generate_with(p, &output, out, overrides)
+                }
+                Target::Scala3 => iozh_gen_scala3::gen::generate(p, &output, out),
+                Target::JsonSchema => iozh_gen_jsonschema::gen::generate(p, &output, out),
+                Target::Rust => {
+                    let options = iozh_gen_rust::gen::RustOptions { async_services };
+                    iozh_gen_rust::gen::generate_with(p, &output, out, options)
+                }
+                Target::Typescript => {
+                    let options = iozh_gen_typescript::gen::TsOptions { validators };
+                    iozh_gen_typescript::gen::generate_with(p, &output, out, options)
+                }
+                Target::Kotlin => iozh_gen_kotlin::gen::generate(p, &output, out),
+                Target::Python => iozh_gen_python::gen::generate(p, &output, out),
+                Target::Go => {
+                    let options = iozh_gen_go::gen::GoOptions { package: go_package };
+                    iozh_gen_go::gen::generate_with(p, &output, out, options)
+                }
+                Target::Protobuf => {
+                    let options = iozh_gen_protobuf::gen::ProtoOptions { lock_file: proto_lock };
+                    iozh_gen_protobuf::gen::generate_with(p, &output, out, options)
+                }
+                Target::Avro => iozh_gen_avro::gen::generate(p, &output, out),
+                Target::AvroScala => iozh_gen_avro::scala::generate(p, &output, out),
+                Target::Graphql => iozh_gen_graphql::gen::generate(p, &output, out),
+                Target::Pgsql => iozh_gen_sql::gen::generate(p, &output, out),
+                Target::PgsqlDoobie => iozh_gen_sql::doobie::generate(p, &output, out),
+                Target::PgsqlSkunk => iozh_gen_sql::skunk::generate(p, &output, out),
+            }?;
+            if dry_run || diff {
+                report_changes(&tree, dry_run, diff)?;
+            }
+            Ok(())
+        }
+        Command::Fmt { input } => {
+            let p = read_file_and_parse(&input)?;
+            print!("{}", p.format());
+            Ok(())
+        }
+        Command::Migrate { from, to, output } => {
+            let old = read_file_and_parse(&from)?;
+            let new = read_file_and_parse(&to)?;
+            let migration = iozh_gen_sql::migrate::Migration::build(&old, &new)?;
+            for step in &migration.steps {
+                if let Some(why) = &step.destructive {
+                    eprintln!("destructive: {why}");
+                }
+            }
+            match output {
+                Some(path) => std::fs::write(&path, migration.sql())
+                    .map_err(|e| IozhError::from(format!("Failed to write {}: {}", path.display(), e))),
+                None => {
+                    print!("{}", migration.sql());
+                    Ok(())
+                }
+            }
+        }
+        Command::Import { format, input, output } => {
+            let source = std::fs::read_to_string(&input)
+                .map_err(|e| IozhError::from(format!("Failed to read {}: {}", input.display(), e)))?;
+            let p = match format {
+                ImportFormat::Openapi => iozh_import::import_openapi(&source)?,
+                ImportFormat::Jsonschema => {
+                    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("schema");
+                    let stem = stem.trim_end_matches(".schema");
+                    iozh_import::import_jsonschema(&source, stem)?
+                }
+            };
+            match output {
+                Some(path) => std::fs::write(&path, p.format())
+                    .map_err(|e| IozhError::from(format!("Failed to write {}: {}", path.display(), e))),
+                None => {
+                    print!("{}", p.format());
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+/// Lists the files of `tree` that differ from the disk, a unified diff of
+/// each after the list.
+fn report_changes(tree: &MemTree, list: bool, diff: bool) -> Result<(), IozhError> {
+    let changes = tree.changes()?;
+    if list {
+        for c in &changes {
+            let status = if c.old.is_some() { "changed" } else { "new" };
+            println!("{status:<8}{}", c.path.display());
+        }
+    }
+    if diff {
+        for c in &changes {
+            let path = c.path.display().to_string();
+            let old_header = if c.old.is_some() { path.clone() } else { "/dev/null".to_string() };
+            let old = c.old.as_deref().unwrap_or("");
+            let text = similar::TextDiff::from_lines(old, c.new);
+            print!("{}", text.unified_diff().header(&old_header, &path));
+        }
+    }
+    Ok(())
 }
 
 fn main() {
-    let p = read_file_and_parse("src/iozh_test/tgbot.iozh");
-    let s = generate(p, std::path::Path::new("/home/sherzod/work/iozh-proj/scala2-test/src/main/scala"));
-    println!("{:#?}", s);
-}
\ No newline at end of file
+    if let Err(e) = run(IozhCli::parse()) {
+        eprintln!("error at {:?}: {}", e.pos, e.msg);
+        std::process::exit(1);
+    }
+}
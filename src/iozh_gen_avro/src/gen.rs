@@ -0,0 +1,374 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde_json::{json, Map, Value};
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+
+const PRIMITIVES: [&str; 8] = ["null", "boolean", "int", "long", "float", "double", "bytes", "string"];
+
+pub struct AvroContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    /// Full names of the named types already written in the schema being
+    /// generated: Avro wants every later use to refer to them by name.
+    defined: HashSet<String>,
+}
+
+/// Type parameters of a generic declaration bound to the monomorphized
+/// names and schemas of the type arguments at a use site.
+type Subst = HashMap<String, (String, Value)>;
+
+/// Avro namespace of the types declared in a namespace.
+pub fn avro_namespace(path: &[String]) -> String {
+    path.join(".")
+}
+
+/// Name a schema contributes to the names of generic records: a use of
+/// `Result[User, I64]` is the record `Result_User_Long`. Derived from the
+/// schema rather than from the iozh type, so that the Scala codecs can
+/// compute the same name at runtime, see `AvroSupport.nameOf`.
+pub fn name_of(schema: &Value) -> String {
+    let capitalize = |s: &str| {
+        let mut chars = s.chars();
+        chars.next().map(|c| c.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+    };
+    match schema {
+        Value::String(s) if PRIMITIVES.contains(&s.as_str()) => capitalize(s),
+        Value::String(s) => s.rsplit('.').next().unwrap_or(s).to_string(),
+        Value::Object(m) => match m.get("type").and_then(Value::as_str) {
+            Some("record" | "enum" | "fixed") => m.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+            Some("array") => format!("List_{}", m.get("items").map(name_of).unwrap_or_default()),
+            Some(t) => capitalize(t),
+            None => "Unknown".to_string(),
+        },
+        Value::Array(members) => match members.as_slice() {
+            [Value::String(n), other] if n == "null" => format!("Option_{}", name_of(other)),
+            _ => "Union".to_string(),
+        },
+        _ => "Unknown".to_string(),
+    }
+}
+
+fn full_name(schema: &Map<String, Value>) -> String {
+    let name = schema.get("name").and_then(Value::as_str).unwrap_or_default();
+    match schema.get("namespace").and_then(Value::as_str) {
+        Some(ns) if !ns.is_empty() => format!("{ns}.{name}"),
+        _ => name.to_string(),
+    }
+}
+
+fn with_doc(schema: &mut Map<String, Value>, doc: &str) {
+    let text = doc_text(doc);
+    if !text.is_empty() {
+        schema.insert("doc".to_string(), Value::String(text));
+    }
+}
+
+/// Whether a choice becomes an enum: no parameters, only bare and literal
+/// items. Literals are not kept, the symbols are the item names.
+pub fn is_enum(c: &ast::Choice, variants: &[ChoiceVariant]) -> bool {
+    c.name.args.is_empty()
+        && !variants.is_empty()
+        && variants.iter().all(|v| matches!(v, ChoiceVariant::Unit { .. } | ChoiceVariant::Value { .. }))
+}
+
+impl <'a> AvroContext<'a> {
+    pub fn new(p: &'a ast::Project) -> AvroContext<'a> {
+        AvroContext {
+            p,
+            index: TypeIndex::build(p),
+            defined: HashSet::new(),
+        }
+    }
+
+    /// Self-contained schema of a declaration without type parameters.
+    pub fn gen_schema(&mut self, entry: &TypeEntry) -> Result<Value, IozhError> {
+        self.defined.clear();
+        let schema = self.gen_entry(entry, &entry.name, &Subst::new())?;
+        let mut definitions = HashMap::new();
+        collect_definitions(&schema, &mut definitions);
+        Ok(normalize(&schema, &definitions, &mut HashSet::new()))
+    }
+
+    /// A named type, defined on first use and referred to by name after.
+    fn named(&mut self, namespace: &str, name: &str, build: impl FnOnce(&mut Self) -> Result<Value, IozhError>) -> Result<Value, IozhError> {
+        let full = format!("{namespace}.{name}");
+        if !self.defined.insert(full.clone()) {
+            return Ok(Value::String(full));
+        }
+        build(self)
+    }
+
+    fn gen_record(&mut self, namespace: &str, name: &str, doc: &str, fields: &[&ast::Field], path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        self.named(namespace, name, |ctx| {
+            let mut avro_fields = vec![];
+            for f in fields {
+                let mut field = Map::new();
+                field.insert("name".to_string(), json!(f.name));
+                with_doc(&mut field, &f.doc);
+                field.insert("type".to_string(), ctx.gen_type(&f.type_path, path, subst)?);
+                match Builtin::from_path(&f.type_path) {
+                    Some(Builtin::Option) => {
+                        field.insert("default".to_string(), Value::Null);
+                    }
+                    Some(Builtin::List) => {
+                        field.insert("default".to_string(), json!([]));
+                    }
+                    _ => {}
+                }
+                avro_fields.push(Value::Object(field));
+            }
+            let mut schema = Map::new();
+            schema.insert("type".to_string(), json!("record"));
+            schema.insert("name".to_string(), json!(name));
+            schema.insert("namespace".to_string(), json!(namespace));
+            with_doc(&mut schema, doc);
+            schema.insert("fields".to_string(), Value::Array(avro_fields));
+            Ok(Value::Object(schema))
+        })
+    }
+
+    /// Choices are unions of records, one per item, named after the item
+    /// in the namespace of the choice: newtype items become records with
+    /// their single field, since a union cannot hold the same type twice.
+    fn gen_choice(&mut self, c: &ast::Choice, name: &str, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let namespace = avro_namespace(path);
+        let variants = c.variants(self.p);
+        if is_enum(c, &variants) {
+            return self.named(&namespace, name, |_| {
+                let mut schema = Map::new();
+                schema.insert("type".to_string(), json!("enum"));
+                schema.insert("name".to_string(), json!(name));
+                schema.insert("namespace".to_string(), json!(namespace));
+                with_doc(&mut schema, &c.doc);
+                let symbols = variants.iter().map(|v| json!(v.name().name)).collect();
+                schema.insert("symbols".to_string(), Value::Array(symbols));
+                Ok(Value::Object(schema))
+            });
+        }
+        let namespace = format!("{namespace}.{name}");
+        let mut members = vec![];
+        for v in &variants {
+            members.push(self.gen_item(v, &namespace, path, subst)?);
+        }
+        Ok(Value::Array(members))
+    }
+
+    fn gen_item(&mut self, v: &ChoiceVariant, namespace: &str, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let name = &v.name().name;
+        match v {
+            ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } => self.gen_record(namespace, name, doc, &[], path, subst),
+            ChoiceVariant::Wrap { doc, field, target, .. } => {
+                let field = ast::Field {
+                    pos: target.pos.clone(),
+                    doc: String::new(),
+                    name: field.to_string(),
+                    type_path: (*target).clone(),
+                };
+                self.gen_record(namespace, name, doc, &[&field], path, subst)
+            }
+            ChoiceVariant::Record { structure, fields, .. } => self.gen_record(namespace, name, &structure.doc, fields, path, subst),
+        }
+    }
+
+    fn gen_entry(&mut self, entry: &TypeEntry, name: &str, subst: &Subst) -> Result<Value, IozhError> {
+        match entry.def {
+            TypeDef::Structure(idx) => {
+                let s = self.p.get_structure(idx)?;
+                self.gen_record(&avro_namespace(&entry.path), name, &s.doc, &s.get_fields(), &entry.path, subst)
+            }
+            TypeDef::Choice(idx) => {
+                let c = self.p.get_choice(idx)?;
+                self.gen_choice(c, name, &entry.path, subst)
+            }
+            TypeDef::Variant { choice, structure } => {
+                let c = self.p.get_choice(choice)?;
+                let s = self.p.get_structure(structure)?;
+                let namespace = format!("{}.{}", avro_namespace(&entry.path), c.name.name);
+                let variant = c.variants(self.p).into_iter().find(|v| v.name().name == s.name.name);
+                match variant {
+                    Some(v) => self.gen_item(&v, &namespace, &entry.path, subst),
+                    None => self.gen_record(&namespace, &s.name.name, &s.doc, &[], &entry.path, subst),
+                }
+            }
+        }
+    }
+
+    fn gen_type_arg(&mut self, tp: &ast::TypePath, n: usize, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        match tp.last().and_then(|t| t.args.get(n)) {
+            Some(arg) => self.gen_type(arg, path, subst),
+            None => Err(IozhError {
+                pos: tp.pos.clone(),
+                msg: format!("Missing type argument {} of {:?}", n + 1, tp),
+            }),
+        }
+    }
+
+    pub fn gen_type(&mut self, tp: &ast::TypePath, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return Ok(json!("null")),
+        };
+        if tp.path.len() == 1 {
+            if let Some((_, bound)) = subst.get(&tag.name) {
+                return Ok(bound.clone());
+            }
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return Ok(match builtin {
+                Builtin::I32 => json!("int"),
+                Builtin::I64 => json!("long"),
+                Builtin::F32 => json!("float"),
+                Builtin::F64 => json!("double"),
+                Builtin::Bool => json!("boolean"),
+                Builtin::String | Builtin::File => json!("string"),
+                Builtin::DateTime => json!({ "type": "long", "logicalType": "timestamp-millis" }),
+                Builtin::Duration => self.named("iozh", "Duration", |_| {
+                    Ok(json!({ "type": "fixed", "name": "Duration", "namespace": "iozh", "size": 12, "logicalType": "duration" }))
+                })?,
+                Builtin::Option => match self.gen_type_arg(tp, 0, path, subst)? {
+                    // Unions cannot nest: an optional choice is its union with null.
+                    Value::Array(mut members) => {
+                        if !members.contains(&json!("null")) {
+                            members.insert(0, json!("null"));
+                        }
+                        Value::Array(members)
+                    }
+                    inner => json!(["null", inner]),
+                },
+                Builtin::List => json!({ "type": "array", "items": self.gen_type_arg(tp, 0, path, subst)? }),
+            });
+        }
+        let entry = match self.index.resolve_path(tp, path) {
+            Some(entry) => entry.clone(),
+            None => return Err(IozhError { pos: tp.pos.clone(), msg: format!("Unknown type {:?}", tp) }),
+        };
+        if tag.args.is_empty() || matches!(entry.def, TypeDef::Variant { .. }) {
+            let name = entry.name.rsplit('.').next().unwrap_or(&entry.name).to_string();
+            return self.gen_entry(&entry, &name, &Subst::new());
+        }
+        self.instantiate(&entry, tag, path, subst)
+    }
+
+    /// Generic declarations have no schema of their own: every distinct use
+    /// gets a monomorphized record named after the type arguments.
+    fn instantiate(&mut self, entry: &TypeEntry, tag: &ast::TypeTag, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let params = match entry.def {
+            TypeDef::Structure(idx) => self.p.get_structure(idx)?.name.param_names(),
+            TypeDef::Choice(idx) => self.p.get_choice(idx)?.name.param_names(),
+            TypeDef::Variant { .. } => unreachable!("variants are never instantiated"),
+        };
+        if params.len() != tag.args.len() {
+            return Err(IozhError {
+                pos: tag.pos.clone(),
+                msg: format!("{} expects {} type arguments, got {}", entry.name, params.len(), tag.args.len()),
+            });
+        }
+        let mut name = entry.name.clone();
+        let mut inner = Subst::new();
+        for (param, arg) in params.into_iter().zip(tag.args.iter()) {
+            let bound = self.gen_type(arg, path, subst)?;
+            let arg_name = name_of(&bound);
+            name.push('_');
+            name.push_str(&arg_name);
+            inner.insert(param, (arg_name, bound));
+        }
+        self.gen_entry(entry, &name, &inner)
+    }
+}
+
+fn collect_definitions(schema: &Value, acc: &mut HashMap<String, Value>) {
+    match schema {
+        Value::Array(members) => members.iter().for_each(|m| collect_definitions(m, acc)),
+        Value::Object(m) => match m.get("type").and_then(Value::as_str) {
+            Some("record") => {
+                acc.entry(full_name(m)).or_insert_with(|| schema.clone());
+                for f in m.get("fields").and_then(Value::as_array).into_iter().flatten() {
+                    if let Some(t) = f.get("type") {
+                        collect_definitions(t, acc);
+                    }
+                }
+            }
+            Some("enum" | "fixed") => {
+                acc.entry(full_name(m)).or_insert_with(|| schema.clone());
+            }
+            Some("array") => {
+                if let Some(items) = m.get("items") {
+                    collect_definitions(items, acc);
+                }
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// Rewrites a schema so that every named type is defined exactly once, at
+/// its first use in document order, as Avro requires: type arguments are
+/// built ahead of the records using them, so a use by name can come
+/// before the definition.
+fn normalize(schema: &Value, definitions: &HashMap<String, Value>, seen: &mut HashSet<String>) -> Value {
+    match schema {
+        Value::String(name) if !PRIMITIVES.contains(&name.as_str()) => match definitions.get(name) {
+            Some(definition) if !seen.contains(name) => normalize(definition, definitions, seen),
+            _ => schema.clone(),
+        },
+        Value::Array(members) => Value::Array(members.iter().map(|m| normalize(m, definitions, seen)).collect()),
+        Value::Object(m) => match m.get("type").and_then(Value::as_str) {
+            Some("record" | "enum" | "fixed") if !seen.insert(full_name(m)) => Value::String(full_name(m)),
+            Some("record") => {
+                let mut m = m.clone();
+                if let Some(Value::Array(fields)) = m.get_mut("fields") {
+                    for f in fields.iter_mut() {
+                        if let Some(t) = f.get("type").cloned() {
+                            f["type"] = normalize(&t, definitions, seen);
+                        }
+                    }
+                }
+                Value::Object(m)
+            }
+            Some("array") => {
+                let mut m = m.clone();
+                if let Some(items) = m.get("items").cloned() {
+                    m.insert("items".to_string(), normalize(&items, definitions, seen));
+                }
+                Value::Object(m)
+            }
+            _ => schema.clone(),
+        },
+        _ => schema.clone(),
+    }
+}
+
+fn io_error(e: impl std::fmt::Display) -> IozhError {
+    IozhError {
+        pos: ast::Pos { line: 0, col: 0 },
+        msg: format!("Failed to write file or dir: {}", e),
+    }
+}
+
+/// Writes a self-contained schema per declaration without type
+/// parameters, `a.b.User` to `a/b/User.avsc`, ready for a schema registry.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let mut ctx = AvroContext::new(&project);
+    for (path, nspace) in project.all_nspaces() {
+        let folder = target_folder.join(path.join("/"));
+        for item in &nspace.items {
+            let (name, def) = match item {
+                ast::NspaceItem::Structure(idx) => (&project.get_structure(*idx)?.name, TypeDef::Structure(*idx)),
+                ast::NspaceItem::Choice(idx) => (&project.get_choice(*idx)?.name, TypeDef::Choice(*idx)),
+                _ => continue,
+            };
+            if !name.args.is_empty() {
+                continue;
+            }
+            let entry = TypeEntry { path: path.clone(), name: name.name.clone(), def };
+            let schema = ctx.gen_schema(&entry)?;
+            let content = serde_json::to_string_pretty(&schema).map_err(io_error)?;
+            out.write(&folder.join(format!("{}.avsc", name.name)), &(content + "\n"))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,3 @@
+pub mod gen;
+pub mod scala;
+mod support;
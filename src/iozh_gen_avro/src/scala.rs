@@ -0,0 +1,281 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use iozh_gen_scala2::gen::sanitize;
+use crate::gen::{avro_namespace, is_enum};
+use crate::support::SUPPORT;
+
+const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+const INDENT: &str = "  ";
+
+/// The most fields `mapN` takes at once; larger records are read in
+/// chunks of tuples.
+const MAX_ARITY: usize = 22;
+
+pub fn scala_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn lower_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn doc_arg(doc: &str) -> Option<String> {
+    let text = doc_text(doc);
+    (!text.is_empty()).then(|| format!("doc = Some({})", scala_string(&text)))
+}
+
+/// Vulcan codecs for the classes of the Scala 2 backend, with the schemas
+/// `gen` writes as `.avsc` files.
+pub struct CodecContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    /// Namespaces whose codecs the file being generated uses.
+    imports: RefCell<BTreeSet<Vec<String>>>,
+}
+
+impl <'a> CodecContext<'a> {
+    pub fn new(p: &'a ast::Project) -> CodecContext<'a> {
+        CodecContext {
+            p,
+            index: TypeIndex::build(p),
+            imports: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    fn use_types(&self, tp: &ast::TypePath, nspace: &[String]) {
+        if Builtin::from_path(tp).is_none() {
+            if let Some(entry) = self.index.resolve_path(tp, nspace) {
+                if entry.path != nspace {
+                    self.imports.borrow_mut().insert(entry.path.clone());
+                }
+            }
+        }
+        for arg in tp.path.iter().flat_map(|t| t.args.iter()) {
+            self.use_types(arg, nspace);
+        }
+    }
+
+    fn gen_field(&self, f: &ast::Field, nspace: &[String]) -> String {
+        self.use_types(&f.type_path, nspace);
+        let mut args = vec![scala_string(&f.name), format!("_.{}", sanitize(&f.name))];
+        args.extend(doc_arg(&f.doc));
+        match Builtin::from_path(&f.type_path) {
+            Some(Builtin::Option) => args.push("default = Some(None)".to_string()),
+            Some(Builtin::List) => args.push("default = Some(List.empty)".to_string()),
+            _ => {}
+        }
+        format!("field({})", args.join(", "))
+    }
+
+    /// `Codec.record` for `class` with the given arguments, built with
+    /// `construct` from its fields.
+    fn gen_record(&self, class: &str, args: &[String], fields: &[&ast::Field], construct: &str, nspace: &[String]) -> String {
+        let inner = INDENT.repeat(2);
+        let mut out = format!("Codec.record[{class}](\n");
+        out.push_str(&args.iter().map(|a| format!("{inner}{a}")).collect::<Vec<_>>().join(",\n"));
+        out.push_str(&format!("\n{INDENT}) {{ field =>\n"));
+        let fields = fields.iter().map(|f| self.gen_field(f, nspace)).collect::<Vec<_>>();
+        let body = match fields.len() {
+            0 => format!("field.pure({construct})"),
+            1 => format!("{}.map({construct}(_))", fields[0]),
+            n if n <= MAX_ARITY => {
+                let placeholders = vec!["_"; n].join(", ");
+                format!("(\n{}\n{inner}).mapN({construct}({placeholders}))", Self::lines(&fields, &INDENT.repeat(3)))
+            }
+            n => {
+                let chunks = n.div_ceil(MAX_ARITY);
+                let size = n.div_ceil(chunks);
+                let mut groups = vec![];
+                let mut patterns = vec![];
+                for (i, chunk) in fields.chunks(size).enumerate() {
+                    let group = Self::lines(chunk, &INDENT.repeat(4));
+                    groups.push(format!("{}(\n{group}\n{}).tupled", INDENT.repeat(3), INDENT.repeat(3)));
+                    let binders = (0..chunk.len()).map(|j| format!("_{}", i * size + j)).collect::<Vec<_>>();
+                    patterns.push(format!("({})", binders.join(", ")));
+                }
+                let binders = (0..n).map(|j| format!("_{j}")).collect::<Vec<_>>();
+                format!(
+                    "(\n{}\n{inner}).mapN {{ case ({}) => {construct}({}) }}",
+                    groups.join(",\n"),
+                    patterns.join(", "),
+                    binders.join(", "),
+                )
+            }
+        };
+        out.push_str(&format!("{inner}{body}\n{INDENT}}}"));
+        out
+    }
+
+    fn record_args(name: String, namespace: &str, doc: &str) -> Vec<String> {
+        let mut args = vec![format!("name = {name}"), format!("namespace = {}", scala_string(namespace))];
+        args.extend(doc_arg(doc));
+        args
+    }
+
+    fn lines(items: &[String], indent: &str) -> String {
+        items.iter().map(|i| format!("{indent}{i}")).collect::<Vec<_>>().join(",\n")
+    }
+
+    pub fn gen_structure(&self, s: &ast::Structure, nspace: &[String]) -> String {
+        let name = &s.name.name;
+        let params = s.name.param_names();
+        let fields = s.get_fields();
+        if params.is_empty() {
+            let args = Self::record_args(scala_string(name), &avro_namespace(nspace), &s.doc);
+            let construct = if fields.is_empty() { format!("{name}()") } else { name.clone() };
+            let record = self.gen_record(name, &args, &fields, &construct, nspace);
+            return format!("{INDENT}implicit lazy val {}Codec: Codec[{name}] = {record}\n", lower_first(name));
+        }
+        // Records of generic classes are named after the type arguments,
+        // the way `gen` monomorphizes them.
+        let class = format!("{name}[{}]", params.join(", "));
+        let implicits = params.iter().map(|p| format!("codec{p}: Codec[{p}]")).collect::<Vec<_>>();
+        let names = params.iter().map(|p| format!("nameOf(codec{p})")).collect::<Vec<_>>();
+        let record_name = format!("List({}, {}).mkString(\"_\")", scala_string(name), names.join(", "));
+        let args = Self::record_args(record_name, &avro_namespace(nspace), &s.doc);
+        let record = self.gen_record(&class, &args, &fields, name, nspace);
+        format!(
+            "{INDENT}implicit def {}Codec[{}](implicit {}): Codec[{class}] = {record}\n",
+            lower_first(name),
+            params.join(", "),
+            implicits.join(", "),
+        )
+    }
+
+    /// Enums for choices of bare and literal items, unions of a record per
+    /// item otherwise. Generic choices are left out: the Scala 2 backend
+    /// does not give their items type parameters.
+    pub fn gen_choice(&self, c: &ast::Choice, nspace: &[String]) -> Option<String> {
+        if !c.name.args.is_empty() {
+            return None;
+        }
+        let name = &c.name.name;
+        let variants = c.variants(self.p);
+        let inner = INDENT.repeat(2);
+        if is_enum(c, &variants) {
+            let symbols = variants.iter().map(|v| scala_string(&v.name().name)).collect::<Vec<_>>();
+            let mut out = format!("{INDENT}implicit lazy val {}Codec: Codec[{name}] = Codec.enumeration[{name}](\n", lower_first(name));
+            out.push_str(&format!("{inner}name = {},\n", scala_string(name)));
+            out.push_str(&format!("{inner}namespace = {},\n", scala_string(&avro_namespace(nspace))));
+            if let Some(doc) = doc_arg(&c.doc) {
+                out.push_str(&format!("{inner}{doc},\n"));
+            }
+            out.push_str(&format!("{inner}symbols = List({}),\n", symbols.join(", ")));
+            out.push_str(&format!("{inner}encode = {{\n"));
+            for (v, symbol) in variants.iter().zip(&symbols) {
+                out.push_str(&format!("{inner}{INDENT}case {name}.{} => {symbol}\n", v.name().name));
+            }
+            out.push_str(&format!("{inner}}},\n{inner}decode = {{\n"));
+            for (v, symbol) in variants.iter().zip(&symbols) {
+                out.push_str(&format!("{inner}{INDENT}case {symbol} => Right({name}.{})\n", v.name().name));
+            }
+            out.push_str(&format!(
+                "{inner}{INDENT}case other => Left(AvroError({} + other))\n{inner}}}\n{INDENT})\n",
+                scala_string(&format!("Unexpected {name} symbol: ")),
+            ));
+            return Some(out);
+        }
+        let namespace = format!("{}.{name}", avro_namespace(nspace));
+        let mut out = String::new();
+        let mut alternatives = vec![];
+        for v in &variants {
+            let item = &v.name().name;
+            let codec = format!("{}{item}Codec", lower_first(name));
+            let (class, record) = match v {
+                ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } => {
+                    let class = format!("{name}.{item}.type");
+                    let args = Self::record_args(scala_string(item), &namespace, doc);
+                    let record = self.gen_record(&class, &args, &[], &format!("{name}.{item}"), nspace);
+                    (class, record)
+                }
+                ChoiceVariant::Wrap { doc, field, target, .. } => {
+                    let class = format!("{name}.{item}");
+                    let field = ast::Field {
+                        pos: target.pos.clone(),
+                        doc: String::new(),
+                        name: field.to_string(),
+                        type_path: (*target).clone(),
+                    };
+                    let args = Self::record_args(scala_string(item), &namespace, doc);
+                    let record = self.gen_record(&class, &args, &[&field], &class, nspace);
+                    (class, record)
+                }
+                ChoiceVariant::Record { structure, fields, .. } => {
+                    let class = format!("{name}.{item}");
+                    let args = Self::record_args(scala_string(item), &namespace, &structure.doc);
+                    let record = self.gen_record(&class, &args, fields, &class, nspace);
+                    (class, record)
+                }
+            };
+            out.push_str(&format!("{INDENT}implicit lazy val {codec}: Codec[{class}] = {record}\n\n"));
+            alternatives.push(format!("alt[{class}]"));
+        }
+        out.push_str(&format!("{INDENT}implicit lazy val {}Codec: Codec[{name}] = Codec.union[{name}] {{ alt =>\n", lower_first(name)));
+        out.push_str(&format!("{inner}{}\n{INDENT}}}\n", alternatives.join(&format!(" |+|\n{inner}{INDENT}"))));
+        Some(out)
+    }
+
+    pub fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<Option<String>, IozhError> {
+        self.imports.borrow_mut().clear();
+        let mut decls = vec![];
+        for item in &nspace.items {
+            match item {
+                ast::NspaceItem::Structure(idx) => decls.push(self.gen_structure(self.p.get_structure(*idx)?, path)),
+                ast::NspaceItem::Choice(idx) => decls.extend(self.gen_choice(self.p.get_choice(*idx)?, path)),
+                _ => {}
+            }
+        }
+        if decls.is_empty() {
+            return Ok(None);
+        }
+        let mut out = String::from(HEADER);
+        out.push_str(&format!("package {}\n\n", path.join(".")));
+        out.push_str("import cats.syntax.all._\n");
+        out.push_str("import iozh.avro.AvroSupport._\n");
+        for other in self.imports.borrow().iter() {
+            out.push_str(&format!("import {}.AvroCodecs._\n", other.join(".")));
+        }
+        out.push_str("import vulcan.{AvroError, Codec}\n\n");
+        out.push_str("object AvroCodecs {\n");
+        for decl in decls {
+            out.push('\n');
+            out.push_str(&decl);
+        }
+        out.push_str("}\n");
+        Ok(Some(out))
+    }
+}
+
+/// Writes `AvroCodecs.scala` next to where the Scala 2 backend puts the
+/// classes of each namespace, plus `iozh/avro/AvroSupport.scala`.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let ctx = CodecContext::new(&project);
+    let support = target_folder.join("iozh").join("avro");
+    out.write(&support.join("AvroSupport.scala"), SUPPORT)?;
+    for (path, nspace) in project.all_nspaces() {
+        if let Some(content) = ctx.gen_nspace(&path, nspace)? {
+            let folder = target_folder.join(path.join("/"));
+            out.write(&folder.join("AvroCodecs.scala"), &content)?;
+        }
+    }
+    Ok(())
+}
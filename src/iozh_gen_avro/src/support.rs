@@ -0,0 +1,63 @@
+/// Written as `iozh/avro/AvroSupport.scala`: vulcan codecs for the builtins
+/// vulcan has none for, with the schemas `gen` writes for them, and the
+/// naming of generic records.
+pub const SUPPORT: &str = r#"// Generated by iozh. Do not edit.
+package iozh.avro
+
+import java.io.File
+import java.nio.{ByteBuffer, ByteOrder}
+import org.apache.avro.Schema
+import scala.concurrent.duration.{Duration, FiniteDuration, MILLISECONDS}
+import scala.jdk.CollectionConverters._
+import vulcan.{AvroError, Codec, Props}
+
+object AvroSupport {
+
+  /**
+   * Avro `duration`: months, days and milliseconds as little-endian
+   * unsigned ints. Months are taken as 30 days when decoding.
+   */
+  implicit lazy val durationCodec: Codec[Duration] = Codec.fixed[Duration](
+    name = "Duration",
+    namespace = "iozh",
+    size = 12,
+    encode = { d =>
+      val millis = d.toMillis
+      ByteBuffer.allocate(12).order(ByteOrder.LITTLE_ENDIAN)
+        .putInt(0)
+        .putInt((millis / 86400000L).toInt)
+        .putInt((millis % 86400000L).toInt)
+        .array()
+    },
+    decode = { bytes =>
+      val b = ByteBuffer.wrap(bytes).order(ByteOrder.LITTLE_ENDIAN)
+      val months = Integer.toUnsignedLong(b.getInt)
+      val days = Integer.toUnsignedLong(b.getInt)
+      val millis = Integer.toUnsignedLong(b.getInt)
+      Right(Duration((months * 30 + days) * 86400000L + millis, MILLISECONDS))
+    },
+    props = Props.one("logicalType", "duration"),
+  )
+
+  implicit lazy val finiteDurationCodec: Codec[FiniteDuration] = durationCodec.imapError {
+    case d: FiniteDuration => Right(d)
+    case d => Left(AvroError("Expected a finite duration but got " + d))
+  }(identity)
+
+  implicit lazy val fileCodec: Codec[File] = Codec.string.imap(new File(_))(_.getName)
+
+  /** Name a type argument contributes to a generic record name: `Result[User, Long]` is `Result_User_Long`. */
+  def nameOf[A](codec: Codec[A]): String = codec.schema.fold(_ => "Unknown", nameOf(_))
+
+  def nameOf(schema: Schema): String = schema.getType match {
+    case Schema.Type.RECORD | Schema.Type.ENUM | Schema.Type.FIXED => schema.getName
+    case Schema.Type.ARRAY => "List_" + nameOf(schema.getElementType)
+    case Schema.Type.UNION =>
+      schema.getTypes.asScala.toList match {
+        case List(n, other) if n.getType == Schema.Type.NULL => "Option_" + nameOf(other)
+        case _ => "Union"
+      }
+    case other => other.getName.capitalize
+  }
+}
+"#;
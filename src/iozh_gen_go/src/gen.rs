@@ -0,0 +1,652 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use crate::support::SUPPORT;
+
+/// The marker `go generate` and linters recognize generated files by.
+const HEADER: &str = "// Code generated by iozh. DO NOT EDIT.\n";
+
+const KEYWORDS: [&str; 25] = [
+    "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for",
+    "func", "go", "goto", "if", "import", "interface", "map", "package", "range", "return",
+    "select", "struct", "switch", "type", "var",
+];
+
+pub struct GoOptions {
+    /// Package clause of the generated files.
+    pub package: String,
+}
+
+impl Default for GoOptions {
+    fn default() -> Self {
+        GoOptions { package: "iozh".to_string() }
+    }
+}
+
+/// Exported Go name: `telegramId` to `TelegramId`.
+pub fn exported(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().chain(chars).collect(),
+        _ => format!("X{name}"),
+    }
+}
+
+/// Local Go name: `UserId` to `userId`.
+pub fn local(name: &str) -> String {
+    let mut chars = name.chars();
+    let name: String = match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    };
+    if KEYWORDS.contains(&name.as_str()) || name == "ctx" {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+pub fn go_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn comment(doc: &str, indent: &str) -> String {
+    let text = doc_text(doc);
+    text.lines()
+        .map(|l| format!("{indent}// {l}").trim_end().to_string() + "\n")
+        .collect()
+}
+
+fn generics(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", params.join(", "))
+    }
+}
+
+fn type_params(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("[{} any]", params.join(", "))
+    }
+}
+
+/// Lines of `cells` with every column but the last padded to a common
+/// width, the way gofmt aligns struct fields and constants.
+fn aligned(rows: &[Vec<String>], indent: &str) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let widths = (0..columns)
+        .map(|i| rows.iter().filter(|r| r.len() > i + 1).map(|r| r[i].chars().count()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+    for row in rows {
+        let mut line = String::from(indent);
+        for (i, cell) in row.iter().enumerate() {
+            line.push_str(cell);
+            if i + 1 < row.len() {
+                line.push_str(&" ".repeat(widths[i] - cell.chars().count() + 1));
+            }
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Literal type of a choice that becomes a type with constants: one
+/// without parameters made only of bare and literal items, all strings or
+/// all integers.
+fn const_type(c: &ast::Choice, variants: &[ChoiceVariant]) -> Option<&'static str> {
+    if !c.name.args.is_empty() || variants.is_empty() {
+        return None;
+    }
+    let is_int = |v: &ChoiceVariant| matches!(v, ChoiceVariant::Value { value: ast::Literal::Int { .. }, .. });
+    let is_str = |v: &ChoiceVariant| matches!(v, ChoiceVariant::Unit { .. } | ChoiceVariant::Value { value: ast::Literal::String { .. }, .. });
+    if variants.iter().all(is_str) {
+        Some("string")
+    } else if variants.iter().all(is_int) {
+        Some("int64")
+    } else {
+        None
+    }
+}
+
+fn wire_literal(v: &ChoiceVariant) -> String {
+    match v {
+        ChoiceVariant::Unit { wire, .. } => go_string(wire),
+        ChoiceVariant::Value { value: ast::Literal::Int { value, .. }, .. } => value.to_string(),
+        ChoiceVariant::Value { value, .. } => go_string(&value.unquoted()),
+        _ => String::new(),
+    }
+}
+
+pub struct GoContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    pub options: GoOptions,
+    /// Go names of types and choice items by namespace and name, unique
+    /// across the package: items clashing with other names get the choice
+    /// name prepended, and names declared in several namespaces the
+    /// namespace path.
+    names: HashMap<(Vec<String>, String), String>,
+    /// Packages the file being generated imports.
+    imports: RefCell<BTreeSet<&'static str>>,
+}
+
+impl <'a> GoContext<'a> {
+    pub fn new(p: &'a ast::Project, options: GoOptions) -> GoContext<'a> {
+        let mut candidates = vec![];
+        for (path, nspace) in p.all_nspaces() {
+            for item in &nspace.items {
+                match item {
+                    ast::NspaceItem::Structure(idx) => {
+                        if let Ok(s) = p.get_structure(*idx) {
+                            let name = exported(&s.name.name);
+                            candidates.push(((path.clone(), s.name.name.clone()), name.clone(), name));
+                        }
+                    }
+                    ast::NspaceItem::Choice(idx) => {
+                        if let Ok(c) = p.get_choice(*idx) {
+                            let choice = exported(&c.name.name);
+                            candidates.push(((path.clone(), c.name.name.clone()), choice.clone(), choice.clone()));
+                            let variants = c.variants(p);
+                            if const_type(c, &variants).is_some() {
+                                continue;
+                            }
+                            for v in &variants {
+                                let item = &v.name().name;
+                                let key = (path.clone(), format!("{}.{item}", c.name.name));
+                                candidates.push((key, exported(item), format!("{choice}{}", exported(item))));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut counts = HashMap::<String, usize>::new();
+        for (_, short, _) in &candidates {
+            *counts.entry(short.clone()).or_default() += 1;
+        }
+        let named = candidates
+            .into_iter()
+            .map(|(key, short, prefixed)| {
+                let name = if counts[&short] > 1 { prefixed } else { short };
+                (key, name)
+            })
+            .collect::<Vec<_>>();
+        let mut counts = HashMap::<String, usize>::new();
+        for (_, name) in &named {
+            *counts.entry(name.clone()).or_default() += 1;
+        }
+        let names = named
+            .into_iter()
+            .map(|((path, local), name)| {
+                let name = if counts[&name] > 1 {
+                    path.iter().map(|p| exported(p)).collect::<String>() + &name
+                } else {
+                    name
+                };
+                ((path, local), name)
+            })
+            .collect();
+        GoContext {
+            p,
+            index: TypeIndex::build(p),
+            options,
+            names,
+            imports: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    fn import(&self, package: &'static str) {
+        self.imports.borrow_mut().insert(package);
+    }
+
+    fn name(&self, nspace: &[String], local: &str) -> String {
+        self.names.get(&(nspace.to_vec(), local.to_string())).cloned().unwrap_or_else(|| exported(local))
+    }
+
+    fn resolve(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> Option<&TypeEntry> {
+        match tp.last() {
+            Some(tag) if tp.path.len() == 1 && params.contains(&tag.name) => None,
+            Some(_) if Builtin::from_path(tp).is_some() => None,
+            _ => self.index.resolve_path(tp, nspace),
+        }
+    }
+
+    /// Whether `tp` is a choice, and so a Go interface that encoding/json
+    /// cannot decode on its own.
+    fn is_interface(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> bool {
+        match self.resolve(tp, nspace, params).map(|e| e.def) {
+            Some(TypeDef::Choice(idx)) => self.p.get_choice(idx).is_ok_and(|c| const_type(c, &c.variants(self.p)).is_none()),
+            _ => false,
+        }
+    }
+
+    fn has_interface(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> bool {
+        match Builtin::from_path(tp) {
+            Some(Builtin::Option | Builtin::List) => {
+                tp.last().and_then(|t| t.args.first()).is_some_and(|a| self.has_interface(a, nspace, params))
+            }
+            _ => self.is_interface(tp, nspace, params),
+        }
+    }
+
+    fn type_arg(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.first()) {
+            Some(arg) => self.go_type(arg, nspace, params),
+            None => "any".to_string(),
+        }
+    }
+
+    pub fn go_type(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "struct{}".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return tag.name.clone();
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 => "int32".to_string(),
+                Builtin::I64 => "int64".to_string(),
+                Builtin::F32 => "float32".to_string(),
+                Builtin::F64 => "float64".to_string(),
+                Builtin::Bool => "bool".to_string(),
+                Builtin::String | Builtin::File => "string".to_string(),
+                Builtin::DateTime => {
+                    self.import("time");
+                    "time.Time".to_string()
+                }
+                Builtin::Duration => "ScalaDuration".to_string(),
+                Builtin::Option => {
+                    let inner = self.type_arg(tp, nspace, params);
+                    let nilable = tp.last().and_then(|t| t.args.first()).is_some_and(|a| self.is_interface(a, nspace, params));
+                    if nilable { inner } else { format!("*{inner}") }
+                }
+                Builtin::List => format!("[]{}", self.type_arg(tp, nspace, params)),
+            };
+        }
+        let args = tag.args.iter().map(|a| self.go_type(a, nspace, params)).collect::<Vec<_>>();
+        match self.index.resolve_path(tp, nspace) {
+            Some(entry) => {
+                let name = match self.names.get(&(entry.path.clone(), entry.name.clone())) {
+                    Some(name) => name.clone(),
+                    // Items of choices that are constants have no type of their own.
+                    None => self.name(&entry.path, entry.name.split('.').next().unwrap_or(&entry.name)),
+                };
+                name + &generics(&args)
+            }
+            None => "any".to_string(),
+        }
+    }
+
+    /// Decoding function for a type that holds choices somewhere.
+    fn decoder(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let arg = tp.last().and_then(|t| t.args.first());
+        match (Builtin::from_path(tp), arg) {
+            (Some(Builtin::Option), Some(arg)) if self.is_interface(arg, nspace, params) => {
+                format!("nullable({})", self.decoder(arg, nspace, params))
+            }
+            (Some(Builtin::Option), Some(arg)) => format!("optionOf({})", self.decoder(arg, nspace, params)),
+            (Some(Builtin::List), Some(arg)) => format!("listOf({})", self.decoder(arg, nspace, params)),
+            _ => {
+                let ty = self.go_type(tp, nspace, params);
+                format!("Unmarshal{ty}")
+            }
+        }
+    }
+
+    fn field_name(f: &ast::Field) -> String {
+        exported(&f.name)
+    }
+
+    fn gen_struct_type(&self, name: &str, params: &[String], fields: &[&ast::Field], nspace: &[String]) -> String {
+        if fields.is_empty() {
+            return format!("type {name}{} struct{{}}\n", type_params(params));
+        }
+        let mut out = format!("type {name}{} struct {{\n", type_params(params));
+        let mut rows = vec![];
+        for f in fields {
+            let doc = comment(&f.doc, "\t");
+            if !doc.is_empty() {
+                out.push_str(&aligned(&rows, "\t"));
+                rows.clear();
+                out.push_str(&doc);
+            }
+            let omit = if matches!(Builtin::from_path(&f.type_path), Some(Builtin::Option)) { ",omitempty" } else { "" };
+            rows.push(vec![
+                Self::field_name(f),
+                self.go_type(&f.type_path, nspace, params),
+                format!("`json:{}`", go_string(&format!("{}{omit}", f.name))),
+            ]);
+            if !doc.is_empty() {
+                out.push_str(&aligned(&rows, "\t"));
+                rows.clear();
+            }
+        }
+        out.push_str(&aligned(&rows, "\t"));
+        out.push_str("}\n");
+        out
+    }
+
+    /// Marshals like circe: fields in declaration order, then the tag.
+    fn gen_marshal(&self, receiver: &str, fields: &[&ast::Field], tag: Option<&(String, String)>) -> String {
+        let mut rows = fields
+            .iter()
+            .map(|f| {
+                let optional = matches!(Builtin::from_path(&f.type_path), Some(Builtin::Option));
+                format!("objectField{{{}, v.{}, {optional}}},", go_string(&f.name), Self::field_name(f))
+            })
+            .collect::<Vec<_>>();
+        if let Some((key, value)) = tag {
+            rows.push(format!("objectField{{{}, {}, false}},", go_string(key), go_string(value)));
+        }
+        let mut out = format!("func (v {receiver}) MarshalJSON() ([]byte, error) {{\n");
+        if rows.is_empty() {
+            out.push_str("\treturn marshalObject()\n}\n");
+            return out;
+        }
+        out.push_str("\treturn marshalObject(\n");
+        for row in rows {
+            out.push_str(&format!("\t\t{row}\n"));
+        }
+        out.push_str("\t)\n}\n");
+        out
+    }
+
+    /// Structures holding choices decode field by field, since
+    /// encoding/json cannot decode into an interface.
+    fn gen_unmarshal(&self, receiver: &str, fields: &[&ast::Field], nspace: &[String], params: &[String]) -> Option<String> {
+        if !fields.iter().any(|f| self.has_interface(&f.type_path, nspace, params)) {
+            return None;
+        }
+        self.import("encoding/json");
+        let mut out = format!("func (v *{receiver}) UnmarshalJSON(data []byte) error {{\n");
+        out.push_str("\tvar raw map[string]json.RawMessage\n");
+        out.push_str("\tif err := json.Unmarshal(data, &raw); err != nil {\n\t\treturn err\n\t}\n");
+        for f in fields {
+            let field = Self::field_name(f);
+            out.push_str(&format!("\tif r, ok := raw[{}]; ok {{\n", go_string(&f.name)));
+            if self.has_interface(&f.type_path, nspace, params) {
+                out.push_str("\t\tvar err error\n");
+                out.push_str(&format!("\t\tif v.{field}, err = {}(r); err != nil {{\n", self.decoder(&f.type_path, nspace, params)));
+            } else {
+                out.push_str(&format!("\t\tif err := json.Unmarshal(r, &v.{field}); err != nil {{\n"));
+            }
+            out.push_str("\t\t\treturn err\n\t\t}\n\t}\n");
+        }
+        out.push_str("\treturn nil\n}\n");
+        Some(out)
+    }
+
+    pub fn gen_structure(&self, s: &ast::Structure, nspace: &[String]) -> Vec<String> {
+        let name = self.name(nspace, &s.name.name);
+        let params = s.name.param_names();
+        let fields = s.get_fields();
+        let receiver = format!("{name}{}", generics(&params));
+        let mut decls = vec![comment(&s.doc, "") + &self.gen_struct_type(&name, &params, &fields, nspace)];
+        decls.push(self.gen_marshal(&receiver, &fields, None));
+        decls.extend(self.gen_unmarshal(&receiver, &fields, nspace, &params));
+        decls
+    }
+
+    /// Choices of string or integer literals are a named type with one
+    /// constant per item; other choices are an interface implemented by a
+    /// type per item, with `Unmarshal<Choice>` to decode them.
+    pub fn gen_choice(&self, c: &ast::Choice, nspace: &[String]) -> Vec<String> {
+        let variants = c.variants(self.p);
+        match const_type(c, &variants) {
+            Some(ty) => self.gen_consts(c, &variants, ty, nspace),
+            None => self.gen_interface(c, &variants, nspace),
+        }
+    }
+
+    fn gen_consts(&self, c: &ast::Choice, variants: &[ChoiceVariant], ty: &str, nspace: &[String]) -> Vec<String> {
+        self.import("encoding/json");
+        self.import("fmt");
+        let name = self.name(nspace, &c.name.name);
+        let mut decls = vec![comment(&c.doc, "") + &format!("type {name} {ty}\n")];
+        let consts = variants
+            .iter()
+            .map(|v| {
+                let item = exported(&v.name().name);
+                let item = if item.starts_with(&name) { item } else { format!("{name}{item}") };
+                (item, v)
+            })
+            .collect::<Vec<_>>();
+        let mut out = String::from("const (\n");
+        let mut rows = vec![];
+        for (item, v) in &consts {
+            let doc = match v {
+                ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } => comment(doc, "\t"),
+                _ => String::new(),
+            };
+            if !doc.is_empty() {
+                out.push_str(&aligned(&rows, "\t"));
+                rows.clear();
+                out.push_str(&doc);
+            }
+            rows.push(vec![item.clone(), name.clone(), format!("= {}", wire_literal(v))]);
+        }
+        out.push_str(&aligned(&rows, "\t"));
+        out.push_str(")\n");
+        decls.push(out);
+
+        let mut out = format!("func (v *{name}) UnmarshalJSON(data []byte) error {{\n");
+        out.push_str(&format!("\tvar x {ty}\n"));
+        out.push_str("\tif err := json.Unmarshal(data, &x); err != nil {\n\t\treturn err\n\t}\n");
+        out.push_str(&format!("\tswitch {name}(x) {{\n"));
+        let items = consts.iter().map(|(item, _)| item.clone()).collect::<Vec<_>>();
+        out.push_str(&format!("\tcase {}:\n", items.join(", ")));
+        out.push_str(&format!("\t\t*v = {name}(x)\n\t\treturn nil\n\t}}\n"));
+        out.push_str(&format!("\treturn fmt.Errorf(\"iozh: unexpected {name}: %s\", data)\n}}\n"));
+        decls.push(out);
+        decls
+    }
+
+    fn gen_interface(&self, c: &ast::Choice, variants: &[ChoiceVariant], nspace: &[String]) -> Vec<String> {
+        let name = self.name(nspace, &c.name.name);
+        let params = c.name.param_names();
+        let marker = format!("is{name}");
+        let mut decls = vec![comment(&c.doc, "") + &format!("type {name}{} interface {{\n\t{marker}()\n}}\n", type_params(&params))];
+        for v in variants {
+            let item = self.name(nspace, &format!("{}.{}", c.name.name, v.name().name));
+            match v {
+                ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } => {
+                    self.import("encoding/json");
+                    decls.push(comment(doc, "") + &format!("type {item} struct{{}}\n"));
+                    decls.push(format!("func ({item}) {marker}() {{}}\n"));
+                    decls.push(format!("func ({item}) MarshalJSON() ([]byte, error) {{\n\treturn json.Marshal({})\n}}\n", wire_literal(v)));
+                }
+                ChoiceVariant::Wrap { doc, field, target, .. } => {
+                    let used = params.iter().filter(|p| target.mentions(p)).cloned().collect::<Vec<_>>();
+                    let receiver = format!("{item}{}", generics(&used));
+                    let field = exported(field);
+                    decls.push(comment(doc, "") + &format!(
+                        "type {item}{} struct {{\n\t{field} {}\n}}\n",
+                        type_params(&used),
+                        self.go_type(target, nspace, &params),
+                    ));
+                    decls.push(format!("func ({receiver}) {marker}() {{}}\n"));
+                    self.import("encoding/json");
+                    decls.push(format!("func (v {receiver}) MarshalJSON() ([]byte, error) {{\n\treturn json.Marshal(v.{field})\n}}\n"));
+                    let mut unmarshal = format!("func (v *{receiver}) UnmarshalJSON(data []byte) error {{\n");
+                    if self.has_interface(target, nspace, &params) {
+                        unmarshal.push_str(&format!("\tvar err error\n\tv.{field}, err = {}(data)\n\treturn err\n}}\n", self.decoder(target, nspace, &params)));
+                    } else {
+                        unmarshal.push_str(&format!("\treturn json.Unmarshal(data, &v.{field})\n}}\n"));
+                    }
+                    decls.push(unmarshal);
+                }
+                ChoiceVariant::Record { structure, fields, tag } => {
+                    let used = used_params(&params, fields);
+                    let receiver = format!("{item}{}", generics(&used));
+                    decls.push(comment(&structure.doc, "") + &self.gen_struct_type(&item, &used, fields, nspace));
+                    decls.push(format!("func ({receiver}) {marker}() {{}}\n"));
+                    decls.push(self.gen_marshal(&receiver, fields, tag.as_ref()));
+                    decls.extend(self.gen_unmarshal(&receiver, fields, nspace, &params));
+                }
+            }
+        }
+        decls.push(self.gen_dispatch(c, variants, nspace));
+        decls
+    }
+
+    /// `Unmarshal<Choice>`: items with the tag of `get_most_common_tag_key`
+    /// are picked by it, the others tried in order like the circe decoders.
+    fn gen_dispatch(&self, c: &ast::Choice, variants: &[ChoiceVariant], nspace: &[String]) -> String {
+        self.import("encoding/json");
+        self.import("fmt");
+        let name = self.name(nspace, &c.name.name);
+        let params = c.name.param_names();
+        let ty = format!("{name}{}", generics(&params));
+        let item_type = |v: &ChoiceVariant, used: &[String]| {
+            self.name(nspace, &format!("{}.{}", c.name.name, v.name().name)) + &generics(used)
+        };
+        let mut out = format!("// Unmarshal{name} decodes {name} values as written by circe.\n");
+        out.push_str(&format!("func Unmarshal{name}{}(data []byte) ({ty}, error) {{\n", type_params(&params)));
+        let tagged = variants.iter().filter_map(|v| match v {
+            ChoiceVariant::Record { fields, tag: Some((key, value)), .. } => Some((key, value, item_type(v, &used_params(&params, fields)))),
+            _ => None,
+        }).collect::<Vec<_>>();
+        if let Some((key, _, _)) = tagged.first() {
+            out.push_str(&format!("\tif tag, ok := tagOf(data, {}); ok {{\n\t\tswitch tag {{\n", go_string(key)));
+            for (_, value, item) in &tagged {
+                out.push_str(&format!("\t\tcase {}:\n", go_string(value)));
+                out.push_str(&format!("\t\t\tvar v {item}\n\t\t\terr := json.Unmarshal(data, &v)\n\t\t\treturn v, err\n"));
+            }
+            out.push_str("\t\t}\n\t}\n");
+        }
+        for v in variants {
+            match v {
+                ChoiceVariant::Unit { wire, .. } => {
+                    out.push_str(&format!("\tif isString(data, {}) {{\n\t\treturn {}{{}}, nil\n\t}}\n", go_string(wire), item_type(v, &[])));
+                }
+                ChoiceVariant::Value { value: ast::Literal::Int { value, .. }, .. } => {
+                    out.push_str(&format!("\tif isNumber(data, {value}) {{\n\t\treturn {}{{}}, nil\n\t}}\n", item_type(v, &[])));
+                }
+                ChoiceVariant::Value { value, .. } => {
+                    out.push_str(&format!("\tif isString(data, {}) {{\n\t\treturn {}{{}}, nil\n\t}}\n", go_string(&value.unquoted()), item_type(v, &[])));
+                }
+                ChoiceVariant::Wrap { target, .. } => {
+                    let used = params.iter().filter(|p| target.mentions(p)).cloned().collect::<Vec<_>>();
+                    out.push_str(&format!("\t{{\n\t\tvar v {}\n", item_type(v, &used)));
+                    out.push_str("\t\tif err := json.Unmarshal(data, &v); err == nil {\n\t\t\treturn v, nil\n\t\t}\n\t}\n");
+                }
+                ChoiceVariant::Record { fields, tag: None, .. } => {
+                    let required = fields
+                        .iter()
+                        .filter(|f| !matches!(Builtin::from_path(&f.type_path), Some(Builtin::Option | Builtin::List)))
+                        .map(|f| go_string(&f.name))
+                        .collect::<Vec<_>>();
+                    let keys = required.iter().map(|k| format!(", {k}")).collect::<String>();
+                    out.push_str(&format!("\tif hasKeys(data{keys}) {{\n\t\tvar v {}\n", item_type(v, &used_params(&params, fields))));
+                    out.push_str("\t\tif err := json.Unmarshal(data, &v); err == nil {\n\t\t\treturn v, nil\n\t\t}\n\t}\n");
+                }
+                ChoiceVariant::Record { .. } => {}
+            }
+        }
+        out.push_str(&format!("\treturn nil, fmt.Errorf(\"iozh: unexpected {name}: %s\", data)\n}}\n"));
+        out
+    }
+
+    /// Services are interfaces whose methods take a context and may fail.
+    /// Streams are receive-only channels, closed once drained.
+    pub fn gen_service(&self, s: &ast::Service, nspace: &[String]) -> String {
+        self.import("context");
+        let params = s.name.param_names();
+        let mut out = comment(&s.doc, "");
+        out.push_str(&format!("type {}{} interface {{\n", exported(&s.name.name), type_params(&params)));
+        for (i, m) in s.methods.iter().enumerate() {
+            if i > 0 && !doc_text(&m.doc).is_empty() {
+                out.push('\n');
+            }
+            let mut args = vec!["ctx context.Context".to_string()];
+            args.extend(m.args.iter().map(|a| {
+                let ty = self.go_type(&a.type_path, nspace, &params);
+                let ty = if m.is_stream_arg(&a.name) { format!("<-chan {ty}") } else { ty };
+                format!("{} {ty}", local(&a.name))
+            }));
+            let result = self.go_type(&m.result, nspace, &params);
+            let result = if m.stream_result { format!("<-chan {result}") } else { result };
+            out.push_str(&comment(&m.doc, "\t"));
+            out.push_str(&format!("\t{}({}) ({result}, error)\n", exported(&m.name.name), args.join(", ")));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<Option<String>, IozhError> {
+        self.imports.borrow_mut().clear();
+        let mut decls = vec![];
+        for item in &nspace.items {
+            match item {
+                ast::NspaceItem::Structure(idx) => decls.extend(self.gen_structure(self.p.get_structure(*idx)?, path)),
+                ast::NspaceItem::Choice(idx) => decls.extend(self.gen_choice(self.p.get_choice(*idx)?, path)),
+                ast::NspaceItem::Service(s) => decls.push(self.gen_service(s, path)),
+                ast::NspaceItem::HttpService(_) | ast::NspaceItem::Storage(_) | ast::NspaceItem::Events(_) | ast::NspaceItem::Nspace(_) => {}
+            }
+        }
+        if decls.is_empty() {
+            return Ok(None);
+        }
+        let mut out = String::from(HEADER);
+        out.push_str(&format!("\n// Declarations of namespace {}.\npackage {}\n", path.join("."), self.options.package));
+        let imports = self.imports.borrow();
+        match imports.len() {
+            0 => {}
+            1 => out.push_str(&format!("\nimport {}\n", go_string(imports.iter().next().unwrap_or(&"")))),
+            _ => {
+                out.push_str("\nimport (\n");
+                for i in imports.iter() {
+                    out.push_str(&format!("\t{}\n", go_string(i)));
+                }
+                out.push_str(")\n");
+            }
+        }
+        for decl in decls {
+            out.push('\n');
+            out.push_str(&decl);
+        }
+        Ok(Some(out))
+    }
+}
+
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    generate_with(project, target_folder, out, GoOptions::default())
+}
+
+/// Writes a single package, since Go does not allow the import cycles
+/// namespaces may have: one file per namespace, `a.b` to `a_b.go`, plus
+/// `iozh.go` with the support code.
+pub fn generate_with(project: ast::Project, target_folder: &Path, out: &mut dyn Output, options: GoOptions) -> Result<(), IozhError> {
+    let ctx = GoContext::new(&project, options);
+    let support = format!("{HEADER}\npackage {}\n{SUPPORT}", ctx.options.package);
+    out.write(&target_folder.join("iozh.go"), &support)?;
+    for (path, nspace) in project.all_nspaces() {
+        if let Some(content) = ctx.gen_nspace(&path, nspace)? {
+            out.write(&target_folder.join(format!("{}.go", path.join("_"))), &content)?;
+        }
+    }
+    Ok(())
+}
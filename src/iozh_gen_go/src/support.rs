@@ -0,0 +1,230 @@
+/// Written as `iozh.go`, after the package clause: the duration type and
+/// the helpers the generated (un)marshalers rely on to match the circe wire
+/// format.
+pub const SUPPORT: &str = r#"
+import (
+	"bytes"
+	"encoding/json"
+	"fmt"
+	"reflect"
+	"regexp"
+	"strconv"
+	"strings"
+	"time"
+)
+
+// ScalaDuration is scala.concurrent.duration.Duration as encoded by circe, e.g. "5 seconds".
+type ScalaDuration time.Duration
+
+var durationUnits = []struct {
+	name string
+	unit time.Duration
+}{
+	{"day", 24 * time.Hour},
+	{"hour", time.Hour},
+	{"minute", time.Minute},
+	{"second", time.Second},
+	{"millisecond", time.Millisecond},
+	{"microsecond", time.Microsecond},
+	{"nanosecond", time.Nanosecond},
+}
+
+var durationAliases = map[string]time.Duration{
+	"d": 24 * time.Hour, "h": time.Hour,
+	"m": time.Minute, "min": time.Minute, "mins": time.Minute,
+	"s": time.Second, "sec": time.Second, "secs": time.Second,
+	"ms": time.Millisecond, "milli": time.Millisecond, "millis": time.Millisecond,
+	"µs": time.Microsecond, "micro": time.Microsecond, "micros": time.Microsecond,
+	"ns": time.Nanosecond, "nano": time.Nanosecond, "nanos": time.Nanosecond,
+}
+
+var durationPattern = regexp.MustCompile(`^(-?\d+)\s*(\S*)$`)
+
+func init() {
+	for _, u := range durationUnits {
+		durationAliases[u.name] = u.unit
+		durationAliases[u.name+"s"] = u.unit
+	}
+}
+
+func (d ScalaDuration) MarshalJSON() ([]byte, error) {
+	for _, u := range durationUnits {
+		if time.Duration(d)%u.unit == 0 {
+			n := int64(time.Duration(d) / u.unit)
+			s := fmt.Sprintf("%d %s", n, u.name)
+			if n != 1 {
+				s += "s"
+			}
+			return json.Marshal(s)
+		}
+	}
+	return nil, fmt.Errorf("iozh: invalid duration %d", d)
+}
+
+func (d *ScalaDuration) UnmarshalJSON(data []byte) error {
+	var s string
+	if err := json.Unmarshal(data, &s); err != nil {
+		return err
+	}
+	m := durationPattern.FindStringSubmatch(strings.TrimSpace(s))
+	if m == nil {
+		return fmt.Errorf("iozh: invalid duration %q", s)
+	}
+	n, err := strconv.ParseInt(m[1], 10, 64)
+	if err != nil {
+		return err
+	}
+	unit := time.Second
+	if m[2] != "" {
+		u, ok := durationAliases[m[2]]
+		if !ok {
+			return fmt.Errorf("iozh: unknown duration unit %q", m[2])
+		}
+		unit = u
+	}
+	*d = ScalaDuration(time.Duration(n) * unit)
+	return nil
+}
+
+// objectField is a field of a JSON object written by marshalObject.
+type objectField struct {
+	key      string
+	value    any
+	optional bool
+}
+
+// marshalObject writes the fields in order like circe: optional fields that
+// are nil are left out, lists that are nil are written as empty.
+func marshalObject(fields ...objectField) ([]byte, error) {
+	var buf bytes.Buffer
+	buf.WriteByte('{')
+	first := true
+	for _, f := range fields {
+		null := isNil(f.value)
+		if f.optional && null {
+			continue
+		}
+		if !first {
+			buf.WriteByte(',')
+		}
+		first = false
+		key, err := json.Marshal(f.key)
+		if err != nil {
+			return nil, err
+		}
+		buf.Write(key)
+		buf.WriteByte(':')
+		if null && reflect.ValueOf(f.value).Kind() == reflect.Slice {
+			buf.WriteString("[]")
+			continue
+		}
+		value, err := json.Marshal(f.value)
+		if err != nil {
+			return nil, err
+		}
+		buf.Write(value)
+	}
+	buf.WriteByte('}')
+	return buf.Bytes(), nil
+}
+
+func isNil(v any) bool {
+	if v == nil {
+		return true
+	}
+	r := reflect.ValueOf(v)
+	switch r.Kind() {
+	case reflect.Pointer, reflect.Interface, reflect.Map, reflect.Slice:
+		return r.IsNil()
+	}
+	return false
+}
+
+func isNull(data []byte) bool {
+	return string(bytes.TrimSpace(data)) == "null"
+}
+
+// isString reports whether data is the JSON string s.
+func isString(data []byte, s string) bool {
+	var v string
+	return json.Unmarshal(data, &v) == nil && v == s
+}
+
+// isNumber reports whether data is the JSON number n.
+func isNumber(data []byte, n int64) bool {
+	var v int64
+	return json.Unmarshal(data, &v) == nil && v == n
+}
+
+// hasKeys reports whether data is a JSON object with all of the keys.
+func hasKeys(data []byte, keys ...string) bool {
+	var raw map[string]json.RawMessage
+	if json.Unmarshal(data, &raw) != nil || raw == nil {
+		return false
+	}
+	for _, k := range keys {
+		if _, ok := raw[k]; !ok {
+			return false
+		}
+	}
+	return true
+}
+
+// tagOf returns the string at key when data is a JSON object that has one.
+func tagOf(data []byte, key string) (string, bool) {
+	var raw map[string]json.RawMessage
+	if json.Unmarshal(data, &raw) != nil {
+		return "", false
+	}
+	var tag string
+	if r, ok := raw[key]; !ok || json.Unmarshal(r, &tag) != nil {
+		return "", false
+	}
+	return tag, true
+}
+
+func listOf[T any](dec func([]byte) (T, error)) func([]byte) ([]T, error) {
+	return func(data []byte) ([]T, error) {
+		var raw []json.RawMessage
+		if err := json.Unmarshal(data, &raw); err != nil {
+			return nil, err
+		}
+		if raw == nil {
+			return nil, nil
+		}
+		out := make([]T, len(raw))
+		for i, r := range raw {
+			v, err := dec(r)
+			if err != nil {
+				return nil, err
+			}
+			out[i] = v
+		}
+		return out, nil
+	}
+}
+
+func optionOf[T any](dec func([]byte) (T, error)) func([]byte) (*T, error) {
+	return func(data []byte) (*T, error) {
+		if isNull(data) {
+			return nil, nil
+		}
+		v, err := dec(data)
+		if err != nil {
+			return nil, err
+		}
+		return &v, nil
+	}
+}
+
+// nullable decodes null as the zero value, i.e. nil for choices.
+func nullable[T any](dec func([]byte) (T, error)) func([]byte) (T, error) {
+	return func(data []byte) (T, error) {
+		var zero T
+		if isNull(data) {
+			return zero, nil
+		}
+		return dec(data)
+	}
+}
+"#;
@@ -0,0 +1,482 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::format::format_type_path;
+use iozh_parse::types::*;
+use iozh_output::Output;
+
+const HEADER: &str = "# Generated by iozh. Do not edit.\n";
+const INDENT: &str = "  ";
+
+/// Method name prefixes that make a service method a `Query` field; any
+/// other method is a `Mutation`.
+const QUERY_PREFIXES: [&str; 11] = ["get", "find", "list", "search", "count", "fetch", "lookup", "query", "is", "has", "exists"];
+
+/// Whether a method reads rather than changes state, judged by its name:
+/// `findById` and `get` are queries, `register` is a mutation.
+pub fn is_query(method: &str) -> bool {
+    QUERY_PREFIXES.iter().any(|p| {
+        method.strip_prefix(p).is_some_and(|rest| rest.chars().next().is_none_or(|c| c.is_uppercase() || c.is_ascii_digit()))
+    })
+}
+
+fn pascal_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn lower_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn description(doc: &str, indent: &str) -> String {
+    let text = doc_text(doc).replace("\"\"\"", "\\\"\"\"");
+    match text.lines().count() {
+        0 => String::new(),
+        1 => format!("{indent}\"\"\"{text}\"\"\"\n"),
+        _ => {
+            let lines = text.lines().map(|l| format!("{indent}{l}").trim_end().to_string() + "\n").collect::<String>();
+            format!("{indent}\"\"\"\n{lines}{indent}\"\"\"\n")
+        }
+    }
+}
+
+/// Choices made only of bare and literal items, which become enums.
+fn is_enum(c: &ast::Choice, variants: &[ChoiceVariant]) -> bool {
+    c.name.args.is_empty()
+        && !variants.is_empty()
+        && variants.iter().all(|v| matches!(v, ChoiceVariant::Unit { .. } | ChoiceVariant::Value { .. }))
+}
+
+/// Choices whose common fields every item has, which become interfaces.
+fn is_interface(c: &ast::Choice, variants: &[ChoiceVariant]) -> bool {
+    !c.fields.is_empty() && variants.iter().all(|v| matches!(v, ChoiceVariant::Record { .. }))
+}
+
+/// A GraphQL type reference.
+#[derive(Clone)]
+struct Ty {
+    /// The type without the non-null marker.
+    base: String,
+    nullable: bool,
+    /// What the type contributes to the names of generic instances:
+    /// `Result[User, String]` is `ResultUserString`.
+    fragment: String,
+}
+
+impl Ty {
+    fn full(&self) -> String {
+        if self.nullable { self.base.clone() } else { format!("{}!", self.base) }
+    }
+}
+
+/// Type parameters of a generic declaration bound to the output and input
+/// types of the type arguments at a use site.
+type Subst = HashMap<String, (Ty, Ty)>;
+
+/// A use of a generic declaration, to be declared under `name`.
+struct Instance {
+    name: String,
+    entry: TypeEntry,
+    subst: Subst,
+}
+
+pub struct GraphqlContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    /// GraphQL names of types and choice items by namespace and name,
+    /// unique across the schema: items clashing with other names get the
+    /// choice name prepended, and names declared in several namespaces the
+    /// namespace path.
+    names: HashMap<(Vec<String>, String), String>,
+    /// Custom scalars the schema uses.
+    scalars: RefCell<BTreeSet<&'static str>>,
+    instances: RefCell<VecDeque<Instance>>,
+    instantiated: RefCell<HashSet<String>>,
+}
+
+impl <'a> GraphqlContext<'a> {
+    pub fn new(p: &'a ast::Project) -> GraphqlContext<'a> {
+        let mut candidates = vec![];
+        for (path, nspace) in p.all_nspaces() {
+            for item in &nspace.items {
+                match item {
+                    ast::NspaceItem::Structure(idx) => {
+                        if let Ok(s) = p.get_structure(*idx) {
+                            candidates.push(((path.clone(), s.name.name.clone()), s.name.name.clone(), s.name.name.clone()));
+                        }
+                    }
+                    ast::NspaceItem::Choice(idx) => {
+                        if let Ok(c) = p.get_choice(*idx) {
+                            let choice = &c.name.name;
+                            candidates.push(((path.clone(), choice.clone()), choice.clone(), choice.clone()));
+                            let variants = c.variants(p);
+                            if is_enum(c, &variants) {
+                                continue;
+                            }
+                            for v in &variants {
+                                let item = &v.name().name;
+                                candidates.push(((path.clone(), format!("{choice}.{item}")), item.clone(), format!("{choice}{item}")));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut counts = HashMap::<String, usize>::new();
+        for (_, short, _) in &candidates {
+            *counts.entry(short.clone()).or_default() += 1;
+        }
+        let named = candidates
+            .into_iter()
+            .map(|(key, short, prefixed)| {
+                let name = if counts[&short] > 1 { prefixed } else { short };
+                (key, name)
+            })
+            .collect::<Vec<_>>();
+        let mut counts = HashMap::<String, usize>::new();
+        for (_, name) in &named {
+            *counts.entry(name.clone()).or_default() += 1;
+        }
+        let names = named
+            .into_iter()
+            .map(|((path, local), name)| {
+                let name = if counts[&name] > 1 {
+                    path.iter().map(|p| pascal_case(p)).collect::<String>() + &name
+                } else {
+                    name
+                };
+                ((path, local), name)
+            })
+            .collect();
+        GraphqlContext {
+            p,
+            index: TypeIndex::build(p),
+            names,
+            scalars: RefCell::new(BTreeSet::new()),
+            instances: RefCell::new(VecDeque::new()),
+            instantiated: RefCell::new(HashSet::new()),
+        }
+    }
+
+    fn name(&self, nspace: &[String], local: &str) -> String {
+        self.names.get(&(nspace.to_vec(), local.to_string())).cloned().unwrap_or_else(|| local.to_string())
+    }
+
+    fn scalar(&self, name: &'static str) -> Ty {
+        self.scalars.borrow_mut().insert(name);
+        Ty { base: name.to_string(), nullable: false, fragment: name.to_string() }
+    }
+
+    fn builtin(&self, name: &str) -> Ty {
+        Ty { base: name.to_string(), nullable: false, fragment: name.to_string() }
+    }
+
+    /// Output type, or input type when `input`, of `tp`. `Unit`, which
+    /// GraphQL has no type for, is a `Boolean` left null.
+    fn gql_type(&self, tp: &ast::TypePath, path: &[String], subst: &Subst, input: bool) -> Result<Ty, IozhError> {
+        let unknown = || IozhError { pos: tp.pos.clone(), msg: format!("Unknown type {}", format_type_path(tp)) };
+        let tag = tp.last().ok_or_else(unknown)?;
+        if tp.path.len() == 1 {
+            if let Some((output, inp)) = subst.get(&tag.name) {
+                return Ok(if input { inp.clone() } else { output.clone() });
+            }
+        }
+        if is_unit(tp) {
+            return Ok(Ty { nullable: true, ..self.builtin("Boolean") });
+        }
+        let arg = || match tag.args.first() {
+            Some(arg) => self.gql_type(arg, path, subst, input),
+            None => Err(IozhError { pos: tp.pos.clone(), msg: format!("Missing type argument of {}", format_type_path(tp)) }),
+        };
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return Ok(match builtin {
+                Builtin::I32 => self.builtin("Int"),
+                Builtin::I64 => self.scalar("Long"),
+                Builtin::F32 | Builtin::F64 => self.builtin("Float"),
+                Builtin::Bool => self.builtin("Boolean"),
+                Builtin::String => self.builtin("String"),
+                Builtin::DateTime => self.scalar("DateTime"),
+                Builtin::Duration => self.scalar("Duration"),
+                Builtin::File => self.scalar("Upload"),
+                Builtin::Option => Ty { nullable: true, ..arg()? },
+                Builtin::List => {
+                    let inner = arg()?;
+                    Ty { base: format!("[{}]", inner.full()), nullable: false, fragment: format!("{}List", inner.fragment) }
+                }
+            });
+        }
+        let entry = self.index.resolve_path(tp, path).ok_or_else(unknown)?.clone();
+        let mut name = self.name(&entry.path, &entry.name);
+        if !tag.args.is_empty() && !matches!(entry.def, TypeDef::Variant { .. }) {
+            name = self.instantiate(&entry, name, tag, path, subst)?;
+        }
+        let fragment = name.clone();
+        let is_enum = match entry.def {
+            TypeDef::Choice(idx) => self.p.get_choice(idx).is_ok_and(|c| is_enum(c, &c.variants(self.p))),
+            _ => false,
+        };
+        let base = if input && !is_enum { format!("{name}Input") } else { name };
+        Ok(Ty { base, nullable: false, fragment })
+    }
+
+    /// Generic declarations have no GraphQL type of their own: every
+    /// distinct use gets one, named after the type arguments.
+    fn instantiate(&self, entry: &TypeEntry, base: String, tag: &ast::TypeTag, path: &[String], subst: &Subst) -> Result<String, IozhError> {
+        let params = match entry.def {
+            TypeDef::Structure(idx) => self.p.get_structure(idx).map(|s| s.name.param_names()).unwrap_or_default(),
+            TypeDef::Choice(idx) => self.p.get_choice(idx).map(|c| c.name.param_names()).unwrap_or_default(),
+            TypeDef::Variant { .. } => vec![],
+        };
+        let mut name = base;
+        let mut inner = Subst::new();
+        for (param, arg) in params.into_iter().zip(tag.args.iter()) {
+            let output = self.gql_type(arg, path, subst, false)?;
+            let input = self.gql_type(arg, path, subst, true)?;
+            name.push_str(&output.fragment);
+            inner.insert(param, (output, input));
+        }
+        if self.instantiated.borrow_mut().insert(name.clone()) {
+            self.instances.borrow_mut().push_back(Instance { name: name.clone(), entry: entry.clone(), subst: inner });
+        }
+        Ok(name)
+    }
+
+    fn gen_fields(&self, out: &mut String, fields: &[&ast::Field], path: &[String], subst: &Subst, input: bool) -> Result<(), IozhError> {
+        for f in fields {
+            out.push_str(&description(&f.doc, INDENT));
+            out.push_str(&format!("{INDENT}{}: {}\n", f.name, self.gql_type(&f.type_path, path, subst, input)?.full()));
+        }
+        Ok(())
+    }
+
+    /// An object type and the matching input type.
+    fn gen_object(&self, name: &str, doc: &str, implements: Option<&str>, fields: &[&ast::Field], path: &[String], subst: &Subst) -> Result<String, IozhError> {
+        let implements = implements.map(|i| format!(" implements {i}")).unwrap_or_default();
+        let mut out = description(doc, "");
+        out.push_str(&format!("type {name}{implements} {{\n"));
+        self.gen_fields(&mut out, fields, path, subst, false)?;
+        out.push_str("}\n\n");
+        out.push_str(&description(doc, ""));
+        out.push_str(&format!("input {name}Input {{\n"));
+        self.gen_fields(&mut out, fields, path, subst, true)?;
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    fn gen_structure(&self, out: &mut String, name: &str, s: &ast::Structure, path: &[String], subst: &Subst) -> Result<(), IozhError> {
+        if s.get_fields().is_empty() {
+            // Object types need a field.
+            out.push_str(&description(&s.doc, ""));
+            out.push_str(&format!("type {name} {{\n{INDENT}_empty: Boolean\n}}\n\n"));
+            out.push_str(&format!("input {name}Input {{\n{INDENT}_empty: Boolean\n}}\n"));
+            return Ok(());
+        }
+        out.push_str(&self.gen_object(name, &s.doc, None, &s.get_fields(), path, subst)?);
+        Ok(())
+    }
+
+    /// Enums for bare and literal items; otherwise an interface with the
+    /// common fields when every item is a structure, a union if not, with
+    /// a type per item. Input is a `@oneOf` input object with a field per
+    /// item.
+    fn gen_choice(&self, out: &mut String, suffix: &str, c: &ast::Choice, path: &[String], subst: &Subst) -> Result<(), IozhError> {
+        let name = self.name(path, &c.name.name) + suffix;
+        let variants = c.variants(self.p);
+        if is_enum(c, &variants) {
+            out.push_str(&description(&c.doc, ""));
+            out.push_str(&format!("enum {name} {{\n"));
+            for v in &variants {
+                if let ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } = v {
+                    out.push_str(&description(doc, INDENT));
+                }
+                out.push_str(&format!("{INDENT}{}\n", snake_case(&v.name().name).to_uppercase()));
+            }
+            out.push_str("}\n");
+            return Ok(());
+        }
+        let interface = is_interface(c, &variants);
+        let mut items = vec![];
+        let mut members = String::new();
+        for v in &variants {
+            let item = self.name(path, &format!("{}.{}", c.name.name, v.name().name)) + suffix;
+            let member = lower_first(&v.name().name);
+            out.push('\n');
+            match v {
+                ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } => {
+                    out.push_str(&description(doc, ""));
+                    out.push_str(&format!("type {item} {{\n{INDENT}_empty: Boolean\n}}\n"));
+                    members.push_str(&format!("{INDENT}{member}: Boolean\n"));
+                }
+                ChoiceVariant::Wrap { doc, field, target, .. } => {
+                    let ty = self.gql_type(target, path, subst, false)?;
+                    out.push_str(&description(doc, ""));
+                    out.push_str(&format!("type {item} {{\n{INDENT}{field}: {}\n}}\n", ty.full()));
+                    members.push_str(&format!("{INDENT}{member}: {}\n", self.gql_type(target, path, subst, true)?.base));
+                }
+                ChoiceVariant::Record { structure, fields, .. } => {
+                    let implements = interface.then_some(name.as_str());
+                    out.push_str(&self.gen_object(&item, &structure.doc, implements, fields, path, subst)?);
+                    members.push_str(&format!("{INDENT}{member}: {item}Input\n"));
+                }
+            }
+            items.push(item);
+        }
+        out.push('\n');
+        out.push_str(&description(&c.doc, ""));
+        if interface {
+            out.push_str(&format!("interface {name} {{\n"));
+            self.gen_fields(out, &c.fields.iter().collect::<Vec<_>>(), path, subst, false)?;
+            out.push_str("}\n");
+        } else {
+            out.push_str(&format!("union {name} = {}\n", items.join(" | ")));
+        }
+        out.push('\n');
+        out.push_str(&description(&c.doc, ""));
+        out.push_str(&format!("input {name}Input @oneOf {{\n{members}}}\n"));
+        Ok(())
+    }
+
+    /// Service methods as `Query` or `Mutation` fields, see `is_query`,
+    /// and those streaming their result as `Subscription` fields; those
+    /// taking streams have no GraphQL counterpart and are left out.
+    /// Methods of different services with the same name get the service
+    /// name prepended.
+    fn gen_operations(&self) -> Result<String, IozhError> {
+        let mut counts = HashMap::<String, usize>::new();
+        for (_, nspace) in self.p.all_nspaces() {
+            for item in &nspace.items {
+                if let ast::NspaceItem::Service(s) = item {
+                    for m in &s.methods {
+                        *counts.entry(m.name.name.clone()).or_default() += 1;
+                    }
+                }
+            }
+        }
+        let mut queries = String::new();
+        let mut mutations = String::new();
+        let mut subscriptions = String::new();
+        for (path, nspace) in self.p.all_nspaces() {
+            for item in &nspace.items {
+                let s = match item {
+                    ast::NspaceItem::Service(s) => s,
+                    _ => continue,
+                };
+                for m in s.methods.iter().filter(|m| m.stream_args.is_empty()) {
+                    let name = if counts[&m.name.name] > 1 {
+                        lower_first(&s.name.name) + &pascal_case(&m.name.name)
+                    } else {
+                        m.name.name.clone()
+                    };
+                    let args = m.args
+                        .iter()
+                        .map(|a| Ok(format!("{}: {}", a.name, self.gql_type(&a.type_path, &path, &Subst::new(), true)?.full())))
+                        .collect::<Result<Vec<_>, IozhError>>()?;
+                    let args = if args.is_empty() { String::new() } else { format!("({})", args.join(", ")) };
+                    let result = self.gql_type(&m.result, &path, &Subst::new(), false)?.full();
+                    let target = if m.stream_result {
+                        &mut subscriptions
+                    } else if is_query(&m.name.name) {
+                        &mut queries
+                    } else {
+                        &mut mutations
+                    };
+                    target.push_str(&description(&m.doc, INDENT));
+                    target.push_str(&format!("{INDENT}{name}{args}: {result}\n"));
+                }
+            }
+        }
+        // A schema must have a query root, with at least one field.
+        if queries.is_empty() {
+            queries.push_str(&description("/* No service method is a query. */", INDENT));
+            queries.push_str(&format!("{INDENT}_empty: Boolean\n"));
+        }
+        let mut out = format!("\ntype Query {{\n{queries}}}\n");
+        if !mutations.is_empty() {
+            out.push_str(&format!("\ntype Mutation {{\n{mutations}}}\n"));
+        }
+        if !subscriptions.is_empty() {
+            out.push_str(&format!("\ntype Subscription {{\n{subscriptions}}}\n"));
+        }
+        Ok(out)
+    }
+
+    pub fn gen_schema(&self) -> Result<String, IozhError> {
+        let mut decls = vec![];
+        for (path, nspace) in self.p.all_nspaces() {
+            for item in &nspace.items {
+                let mut out = String::new();
+                match item {
+                    ast::NspaceItem::Structure(idx) => {
+                        let s = self.p.get_structure(*idx)?;
+                        if !s.name.args.is_empty() {
+                            continue;
+                        }
+                        self.gen_structure(&mut out, &self.name(&path, &s.name.name), s, &path, &Subst::new())?;
+                    }
+                    ast::NspaceItem::Choice(idx) => {
+                        let c = self.p.get_choice(*idx)?;
+                        if !c.name.args.is_empty() {
+                            continue;
+                        }
+                        self.gen_choice(&mut out, "", c, &path, &Subst::new())?;
+                    }
+                    _ => continue,
+                }
+                decls.push(out);
+            }
+        }
+        let operations = self.gen_operations()?;
+        loop {
+            let instance = self.instances.borrow_mut().pop_front();
+            let Some(instance) = instance else { break };
+            let mut out = String::new();
+            let entry = &instance.entry;
+            match entry.def {
+                TypeDef::Structure(idx) => {
+                    let s = self.p.get_structure(idx)?;
+                    self.gen_structure(&mut out, &instance.name, s, &entry.path, &instance.subst)?;
+                }
+                TypeDef::Choice(idx) => {
+                    let c = self.p.get_choice(idx)?;
+                    let suffix = &instance.name[self.name(&entry.path, &entry.name).len()..];
+                    self.gen_choice(&mut out, suffix, c, &entry.path, &instance.subst)?;
+                }
+                TypeDef::Variant { .. } => continue,
+            }
+            decls.push(out);
+        }
+        let mut out = String::from(HEADER);
+        for scalar in self.scalars.borrow().iter() {
+            out.push_str(&format!("\nscalar {scalar}"));
+        }
+        if !self.scalars.borrow().is_empty() {
+            out.push('\n');
+        }
+        for decl in decls {
+            out.push('\n');
+            out.push_str(decl.trim_start_matches('\n'));
+        }
+        out.push_str(&operations);
+        Ok(out)
+    }
+}
+
+/// Writes the whole project as one `schema.graphql`, since GraphQL has no
+/// namespaces.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let ctx = GraphqlContext::new(&project);
+    let schema = ctx.gen_schema()?;
+    out.write(&target_folder.join("schema.graphql"), &schema)?;
+    Ok(())
+}
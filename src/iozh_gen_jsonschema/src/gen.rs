@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde_json::{json, Map, Value};
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::format::format_type_path;
+use iozh_parse::types::*;
+use iozh_output::Output;
+
+const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Matches `scala.concurrent.duration.Duration#toString`, which is what the
+/// circe codecs put on the wire, e.g. `"5 seconds"` or `"Duration.Inf"`.
+const DURATION_PATTERN: &str =
+    r"^(-?\d+ (day|hour|minute|second|millisecond|microsecond|nanosecond)s?|Duration\.(Inf|MinusInf|Undefined))$";
+
+pub struct SchemaContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    /// `$defs` of every namespace file, keyed by namespace path.
+    pub defs: Vec<(Vec<String>, Map<String, Value>)>,
+}
+
+/// Type parameters of a generic declaration bound to the monomorphized
+/// names and schemas of the type arguments at a use site.
+type Subst = HashMap<String, (String, Value)>;
+
+pub fn schema_file_name(path: &[String]) -> String {
+    format!("{}.schema.json", path.join("."))
+}
+
+impl <'a> SchemaContext<'a> {
+    pub fn new(p: &'a ast::Project) -> SchemaContext<'a> {
+        SchemaContext {
+            p,
+            index: TypeIndex::build(p),
+            defs: vec![],
+        }
+    }
+
+    fn defs_mut(&mut self, path: &[String]) -> &mut Map<String, Value> {
+        let pos = match self.defs.iter().position(|(p, _)| p == path) {
+            Some(pos) => pos,
+            None => {
+                self.defs.push((path.to_vec(), Map::new()));
+                self.defs.len() - 1
+            }
+        };
+        &mut self.defs[pos].1
+    }
+
+    fn has_def(&self, path: &[String], name: &str) -> bool {
+        self.defs.iter().any(|(p, defs)| p == path && defs.contains_key(name))
+    }
+
+    /// References are always qualified with the file name: schemas of type
+    /// arguments are built at the use site but may end up in another file.
+    fn def_ref(path: &[String], name: &str) -> Value {
+        json!({ "$ref": format!("{}#/$defs/{name}", schema_file_name(path)) })
+    }
+
+    pub fn gen_nspace(&mut self, path: &[String], nspace: &ast::Nspace) -> Result<(), IozhError> {
+        for item in &nspace.items {
+            match item {
+                ast::NspaceItem::Structure(idx) => {
+                    let s = self.p.get_structure(*idx)?;
+                    if s.name.args.is_empty() {
+                        let schema = self.gen_structure(s, path, &Subst::new())?;
+                        self.defs_mut(path).insert(s.name.name.clone(), schema);
+                    }
+                }
+                ast::NspaceItem::Choice(idx) => {
+                    let c = self.p.get_choice(*idx)?;
+                    if c.name.args.is_empty() {
+                        // Keep the choice ahead of the variant definitions it adds.
+                        self.defs_mut(path).insert(c.name.name.clone(), json!({}));
+                        let schema = self.gen_choice(c, path, &Subst::new())?;
+                        self.defs_mut(path).insert(c.name.name.clone(), schema);
+                    }
+                }
+                ast::NspaceItem::Events(e) => {
+                    let schema = self.gen_events(e, path)?;
+                    self.defs_mut(path).insert(e.name.clone(), schema);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Each event is an `{"event": name, "payload": ...}` envelope.
+    fn gen_events(&mut self, e: &ast::Events, path: &[String]) -> Result<Value, IozhError> {
+        let mut alternatives = vec![];
+        for ev in &e.events {
+            let mut event = json!({ "const": ev.name });
+            with_doc(&mut event, &ev.doc);
+            let payload = self.gen_type(&ev.type_path, path, &Subst::new())?;
+            alternatives.push(json!({
+                "title": ev.name,
+                "type": "object",
+                "properties": { "event": event, "payload": payload },
+                "required": ["event", "payload"],
+            }));
+        }
+        let mut schema = json!({ "oneOf": alternatives });
+        with_doc(&mut schema, &e.doc);
+        Ok(schema)
+    }
+
+    fn gen_structure(&mut self, s: &ast::Structure, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let mut schema = self.gen_object(&s.get_fields(), path, subst)?;
+        with_doc(&mut schema, &s.doc);
+        Ok(schema)
+    }
+
+    fn gen_object(&mut self, fields: &[&ast::Field], path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let mut properties = Map::new();
+        let mut required = vec![];
+        for f in fields {
+            // None is dropped by the encoder and missing lists decode as empty,
+            // so neither has to be present.
+            let optional = matches!(
+                Builtin::from_path(&f.type_path),
+                Some(Builtin::Option) | Some(Builtin::List)
+            );
+            let mut field_schema = match Builtin::from_path(&f.type_path) {
+                Some(Builtin::Option) => self.gen_type_arg(&f.type_path, 0, path, subst)?,
+                _ => self.gen_type(&f.type_path, path, subst)?,
+            };
+            with_doc(&mut field_schema, &f.doc);
+            properties.insert(f.name.clone(), field_schema);
+            if !optional {
+                required.push(Value::String(f.name.clone()));
+            }
+        }
+        let mut schema = Map::new();
+        schema.insert("type".to_string(), json!("object"));
+        schema.insert("properties".to_string(), Value::Object(properties));
+        if !required.is_empty() {
+            schema.insert("required".to_string(), Value::Array(required));
+        }
+        Ok(Value::Object(schema))
+    }
+
+    fn gen_choice(&mut self, c: &ast::Choice, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let mut alternatives = vec![];
+        let mut exclusive = true;
+        for variant in c.variants(self.p) {
+            let schema = match &variant {
+                ChoiceVariant::Unit { name, doc, wire } => {
+                    let mut v = json!({ "title": name.name, "const": wire });
+                    with_doc(&mut v, doc);
+                    v
+                }
+                ChoiceVariant::Value { name, doc, value } => {
+                    let literal = match value {
+                        ast::Literal::Int { value, .. } => json!(value),
+                        _ => json!(value.unquoted()),
+                    };
+                    let mut v = json!({ "title": name.name, "const": literal });
+                    with_doc(&mut v, doc);
+                    v
+                }
+                ChoiceVariant::Wrap { name, doc, target, .. } => {
+                    exclusive = false;
+                    let mut v = self.gen_type(target, path, subst)?;
+                    if let Value::Object(m) = &mut v {
+                        m.insert("title".to_string(), json!(name.name));
+                    }
+                    with_doc(&mut v, doc);
+                    v
+                }
+                ChoiceVariant::Record { structure, fields, tag } => {
+                    let mut v = self.gen_object(fields, path, subst)?;
+                    if let Value::Object(m) = &mut v {
+                        m.insert("title".to_string(), json!(structure.name.name));
+                        if let Some((key, value)) = tag {
+                            if let Some(Value::Object(props)) = m.get_mut("properties") {
+                                props.insert(key.clone(), json!({ "const": value }));
+                            }
+                            let required = m.entry("required").or_insert_with(|| json!([]));
+                            if let Value::Array(r) = required {
+                                r.insert(0, json!(key));
+                            }
+                        } else {
+                            exclusive = false;
+                        }
+                    }
+                    with_doc(&mut v, &structure.doc);
+                    if c.name.args.is_empty() {
+                        // Variants of plain choices can be referenced as
+                        // `Choice.Item`, so they get definitions of their own.
+                        let name = format!("{}.{}", c.name.name, structure.name.name);
+                        self.defs_mut(path).insert(name.clone(), v);
+                        Self::def_ref(path, &name)
+                    } else {
+                        v
+                    }
+                }
+            };
+            alternatives.push(schema);
+        }
+        // Untagged alternatives may overlap; circe picks the first decoder
+        // that succeeds, which is `anyOf` rather than `oneOf`.
+        let combinator = if exclusive { "oneOf" } else { "anyOf" };
+        let mut schema = json!({ combinator: alternatives });
+        with_doc(&mut schema, &c.doc);
+        Ok(schema)
+    }
+
+    fn gen_type_arg(&mut self, tp: &ast::TypePath, n: usize, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        match tp.last().and_then(|t| t.args.get(n)) {
+            Some(arg) => self.gen_type(arg, path, subst),
+            None => Err(IozhError {
+                pos: tp.pos.clone(),
+                msg: format!("Missing type argument {} of {}", n + 1, format_type_path(tp)),
+            }),
+        }
+    }
+
+    pub fn gen_type(&mut self, tp: &ast::TypePath, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return Ok(json!({})),
+        };
+        if tp.path.len() == 1 {
+            if let Some((_, bound)) = subst.get(&tag.name) {
+                return Ok(bound.clone());
+            }
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return Ok(match builtin {
+                Builtin::I32 => json!({ "type": "integer", "format": "int32" }),
+                Builtin::I64 => json!({ "type": "integer", "format": "int64" }),
+                Builtin::F32 => json!({ "type": "number", "format": "float" }),
+                Builtin::F64 => json!({ "type": "number", "format": "double" }),
+                Builtin::Bool => json!({ "type": "boolean" }),
+                Builtin::String => json!({ "type": "string" }),
+                Builtin::DateTime => json!({ "type": "string", "format": "date-time" }),
+                Builtin::Duration => json!({ "type": "string", "pattern": DURATION_PATTERN }),
+                Builtin::File => json!({ "type": "string" }),
+                Builtin::Option => {
+                    let inner = self.gen_type_arg(tp, 0, path, subst)?;
+                    json!({ "anyOf": [inner, { "type": "null" }] })
+                }
+                Builtin::List => {
+                    let items = self.gen_type_arg(tp, 0, path, subst)?;
+                    json!({ "type": "array", "items": items })
+                }
+            });
+        }
+        let entry = match self.index.resolve_path(tp, path) {
+            Some(entry) => entry.clone(),
+            None => return Err(IozhError { pos: tp.pos.clone(), msg: format!("Unknown type {}", format_type_path(tp)) }),
+        };
+        if tag.args.is_empty() || matches!(entry.def, TypeDef::Variant { .. }) {
+            return Ok(Self::def_ref(&entry.path, &entry.name));
+        }
+        self.instantiate(&entry, tag, path, subst)
+    }
+
+    /// Generic declarations have no schema of their own: every distinct use
+    /// site gets a monomorphized definition next to the declaration.
+    fn instantiate(&mut self, entry: &TypeEntry, tag: &ast::TypeTag, path: &[String], subst: &Subst) -> Result<Value, IozhError> {
+        let name = mono_name(tag, subst);
+        if !self.has_def(&entry.path, &name) {
+            let params = match entry.def {
+                TypeDef::Structure(idx) => self.p.get_structure(idx)?.name.param_names(),
+                TypeDef::Choice(idx) => self.p.get_choice(idx)?.name.param_names(),
+                TypeDef::Variant { .. } => unreachable!("variants are never instantiated"),
+            };
+            if params.len() != tag.args.len() {
+                return Err(IozhError {
+                    pos: tag.pos.clone(),
+                    msg: format!("{} expects {} type arguments, got {}", entry.name, params.len(), tag.args.len()),
+                });
+            }
+            let mut inner = Subst::new();
+            for (param, arg) in params.into_iter().zip(tag.args.iter()) {
+                let bound = self.gen_type(arg, path, subst)?;
+                inner.insert(param, (mono_path_name(arg, subst), bound));
+            }
+            // Reserve the name first so recursive types terminate.
+            self.defs_mut(&entry.path).insert(name.clone(), json!({}));
+            let schema = match entry.def {
+                TypeDef::Structure(idx) => {
+                    let s = self.p.get_structure(idx)?;
+                    self.gen_structure(s, &entry.path, &inner)?
+                }
+                TypeDef::Choice(idx) => {
+                    let c = self.p.get_choice(idx)?;
+                    self.gen_choice(c, &entry.path, &inner)?
+                }
+                TypeDef::Variant { .. } => unreachable!("variants are never instantiated"),
+            };
+            self.defs_mut(&entry.path).insert(name.clone(), schema);
+        }
+        Ok(Self::def_ref(&entry.path, &name))
+    }
+}
+
+fn mono_name(tag: &ast::TypeTag, subst: &Subst) -> String {
+    let mut name = tag.name.clone();
+    for arg in &tag.args {
+        name.push('_');
+        name.push_str(&mono_path_name(arg, subst));
+    }
+    name
+}
+
+fn mono_path_name(tp: &ast::TypePath, subst: &Subst) -> String {
+    match tp.path.as_slice() {
+        [t] if t.args.is_empty() && subst.contains_key(&t.name) => subst[&t.name].0.clone(),
+        path => path.iter().map(|t| mono_name(t, subst)).collect::<Vec<_>>().join("_"),
+    }
+}
+
+fn with_doc(schema: &mut Value, doc: &str) {
+    let text = doc_text(doc);
+    if let (Value::Object(m), false) = (schema, text.is_empty()) {
+        m.insert("description".to_string(), Value::String(text));
+    }
+}
+
+fn io_error(e: impl std::fmt::Display) -> IozhError {
+    IozhError {
+        pos: ast::Pos { line: 0, col: 0 },
+        msg: format!("Failed to write file or dir: {}", e),
+    }
+}
+
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let mut ctx = SchemaContext::new(&project);
+    for (path, nspace) in project.all_nspaces() {
+        ctx.gen_nspace(&path, nspace)?;
+    }
+    for (path, defs) in ctx.defs {
+        let file_name = schema_file_name(&path);
+        let schema = json!({
+            "$schema": SCHEMA_DIALECT,
+            "$id": file_name,
+            "title": path.join("."),
+            "$defs": defs,
+        });
+        let content = serde_json::to_string_pretty(&schema).map_err(io_error)?;
+        out.write(&target_folder.join(file_name), &(content + "\n"))?;
+    }
+    Ok(())
+}
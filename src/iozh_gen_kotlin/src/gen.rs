@@ -0,0 +1,484 @@
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use crate::support::SUPPORT;
+
+const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+const INDENT: &str = "    ";
+
+const KEYWORDS: [&str; 28] = [
+    "as", "break", "class", "continue", "do", "else", "false", "for", "fun", "if",
+    "in", "interface", "is", "null", "object", "package", "return", "super", "this", "throw",
+    "true", "try", "typealias", "typeof", "val", "var", "when", "while",
+];
+
+pub struct KotlinContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+}
+
+pub fn sanitize(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("`{name}`")
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn kt_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '$' => out.push_str("\\$"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn package(path: &[String]) -> String {
+    path.iter().map(|p| sanitize(p)).collect::<Vec<_>>().join(".")
+}
+
+fn doc_block(doc: &str, indent: &str) -> String {
+    let text = doc_text(doc);
+    if text.is_empty() {
+        return String::new();
+    }
+    let text = text.replace("*/", "* /");
+    if text.contains('\n') {
+        let lines = text.lines().map(|l| format!("{indent} * {l}").trim_end().to_string()).collect::<Vec<_>>();
+        format!("{indent}/**\n{}\n{indent} */\n", lines.join("\n"))
+    } else {
+        format!("{indent}/** {text} */\n")
+    }
+}
+
+fn generics(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", params.join(", "))
+    }
+}
+
+fn indented(block: &str) -> String {
+    block.lines().map(|l| if l.is_empty() { "\n".to_string() } else { format!("{INDENT}{l}\n") }).collect()
+}
+
+fn serializer_param(param: &str) -> String {
+    format!("serializer{param}")
+}
+
+/// Supertype of a choice item: the choice's parameters the item does not
+/// use are `Nothing`, which is why choice parameters are declared `out`.
+fn supertype(choice: &str, params: &[String], used: &[String]) -> String {
+    let args = params
+        .iter()
+        .map(|p| if used.contains(p) { p.clone() } else { "Nothing".to_string() })
+        .collect::<Vec<_>>();
+    format!("{choice}{}", generics(&args))
+}
+
+/// A choice maps onto kotlinx's own polymorphism only when every item is a
+/// tagged structure; anything else gets a serializer of its own.
+fn is_tagged(variants: &[ChoiceVariant]) -> bool {
+    !variants.is_empty() && variants.iter().all(|v| matches!(v, ChoiceVariant::Record { tag: Some(_), .. }))
+}
+
+impl <'a> KotlinContext<'a> {
+    pub fn new(p: &'a ast::Project) -> KotlinContext<'a> {
+        KotlinContext {
+            p,
+            index: TypeIndex::build(p),
+        }
+    }
+
+    fn qualified(entry: &TypeEntry, nspace: &[String]) -> String {
+        if entry.path == nspace {
+            entry.name.clone()
+        } else {
+            format!("{}.{}", package(&entry.path), entry.name)
+        }
+    }
+
+    fn type_arg(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.first()) {
+            Some(arg) => self.kt_type(arg, nspace, params),
+            None => "Any?".to_string(),
+        }
+    }
+
+    pub fn kt_type(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "Unit".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return tag.name.clone();
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 => "Int".to_string(),
+                Builtin::I64 => "Long".to_string(),
+                Builtin::F32 => "Float".to_string(),
+                Builtin::F64 => "Double".to_string(),
+                Builtin::Bool => "Boolean".to_string(),
+                Builtin::String | Builtin::File => "String".to_string(),
+                Builtin::DateTime => "kotlinx.datetime.Instant".to_string(),
+                Builtin::Duration => "iozh.ScalaDuration".to_string(),
+                Builtin::Option => {
+                    let inner = self.type_arg(tp, nspace, params);
+                    if inner.ends_with('?') { inner } else { inner + "?" }
+                }
+                Builtin::List => format!("List<{}>", self.type_arg(tp, nspace, params)),
+            };
+        }
+        let args = tag.args.iter().map(|a| self.kt_type(a, nspace, params)).collect::<Vec<_>>();
+        match self.index.resolve_path(tp, nspace) {
+            Some(entry) => Self::qualified(entry, nspace) + &generics(&args),
+            None => tp.path.iter().map(|t| t.name.clone()).collect::<Vec<_>>().join(".") + &generics(&args),
+        }
+    }
+
+    fn serializer_arg(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.first()) {
+            Some(arg) => self.kt_serializer(arg, nspace, params),
+            None => "JsonElement.serializer()".to_string(),
+        }
+    }
+
+    /// Serializer expression for `tp`, used by the hand-written choice
+    /// serializers where the type may mention the choice's parameters.
+    pub fn kt_serializer(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "Unit.serializer()".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return serializer_param(&tag.name);
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 => "Int.serializer()".to_string(),
+                Builtin::I64 => "Long.serializer()".to_string(),
+                Builtin::F32 => "Float.serializer()".to_string(),
+                Builtin::F64 => "Double.serializer()".to_string(),
+                Builtin::Bool => "Boolean.serializer()".to_string(),
+                Builtin::String | Builtin::File => "String.serializer()".to_string(),
+                Builtin::DateTime => "kotlinx.datetime.Instant.serializer()".to_string(),
+                Builtin::Duration => "iozh.ScalaDurationSerializer".to_string(),
+                Builtin::Option => format!("{}.nullable", self.serializer_arg(tp, nspace, params)),
+                Builtin::List => format!("ListSerializer({})", self.serializer_arg(tp, nspace, params)),
+            };
+        }
+        if !params.iter().any(|p| tp.mentions(p)) {
+            return format!("serializer<{}>()", self.kt_type(tp, nspace, params));
+        }
+        let args = tag.args.iter().map(|a| self.kt_serializer(a, nspace, params)).collect::<Vec<_>>();
+        match self.index.resolve_path(tp, nspace) {
+            Some(entry) => {
+                let name = Self::qualified(entry, nspace);
+                let custom = match entry.def {
+                    TypeDef::Choice(idx) => self.p.get_choice(idx).is_ok_and(|c| !is_tagged(&c.variants(self.p))),
+                    _ => false,
+                };
+                if custom {
+                    format!("{name}Serializer({})", args.join(", "))
+                } else {
+                    format!("{name}.serializer({})", args.join(", "))
+                }
+            }
+            None => "JsonElement.serializer()".to_string(),
+        }
+    }
+
+    /// Constructor properties of a data class; `overrides` are the common
+    /// fields a tagged choice declares on its interface.
+    fn gen_data_class(
+        &self,
+        header: &str,
+        fields: &[&ast::Field],
+        overrides: &[&ast::Field],
+        nspace: &[String],
+        params: &[String],
+    ) -> String {
+        if fields.is_empty() {
+            return format!("data object {header}\n");
+        }
+        let mut out = String::new();
+        let (name, supertypes) = header.split_once(" : ").map(|(n, s)| (n, format!(" : {s}"))).unwrap_or((header, String::new()));
+        out.push_str(&format!("data class {name}(\n"));
+        for f in fields {
+            out.push_str(&doc_block(&f.doc, INDENT));
+            let modifier = if overrides.iter().any(|o| o.name == f.name) { "override val" } else { "val" };
+            let ty = self.kt_type(&f.type_path, nspace, params);
+            let default = match Builtin::from_path(&f.type_path) {
+                Some(Builtin::Option) => " = null",
+                Some(Builtin::List) => " = emptyList()",
+                _ => "",
+            };
+            out.push_str(&format!("{INDENT}{modifier} {}: {ty}{default},\n", sanitize(&f.name)));
+        }
+        out.push_str(&format!("){supertypes}\n"));
+        out
+    }
+
+    pub fn gen_structure(&self, out: &mut String, s: &ast::Structure, nspace: &[String]) {
+        let params = s.name.param_names();
+        out.push_str(&doc_block(&s.doc, ""));
+        out.push_str("@Serializable\n");
+        let header = format!("{}{}", s.name.name, generics(&params));
+        out.push_str(&self.gen_data_class(&header, &s.get_fields(), &[], nspace, &params));
+    }
+
+    /// Tagged choices are sealed hierarchies with the tag key as class
+    /// discriminator and the tag values as `@SerialName`s; kotlinx writes
+    /// the discriminator first where circe writes it last, which decoders
+    /// on either side accept. Other choices get a `<Choice>Serializer` that
+    /// encodes every item the way circe does and decodes by trying the
+    /// items in order.
+    pub fn gen_choice(&self, out: &mut String, c: &ast::Choice, nspace: &[String]) {
+        let name = &c.name.name;
+        let params = c.name.param_names();
+        let variants = c.variants(self.p);
+        let tagged = is_tagged(&variants);
+        let out_params = params.iter().map(|p| format!("out {p}")).collect::<Vec<_>>();
+
+        out.push_str(&doc_block(&c.doc, ""));
+        if tagged {
+            out.push_str("@Serializable\n");
+            if let Some(ChoiceVariant::Record { tag: Some((key, _)), .. }) = variants.first() {
+                out.push_str(&format!("@JsonClassDiscriminator({})\n", kt_string(key)));
+            }
+        } else {
+            out.push_str(&format!("@Serializable(with = {name}Serializer::class)\n"));
+        }
+        out.push_str(&format!("sealed interface {name}{} {{\n", generics(&out_params)));
+        let mut first = true;
+        if tagged {
+            for f in &c.fields {
+                out.push_str(&doc_block(&f.doc, INDENT));
+                out.push_str(&format!("{INDENT}val {}: {}\n", sanitize(&f.name), self.kt_type(&f.type_path, nspace, &params)));
+                first = false;
+            }
+        }
+        let overrides = if tagged { c.fields.iter().collect::<Vec<_>>() } else { vec![] };
+        for v in &variants {
+            if !first {
+                out.push('\n');
+            }
+            first = false;
+            let item = &v.name().name;
+            match v {
+                ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } => {
+                    out.push_str(&doc_block(doc, INDENT));
+                    out.push_str(&format!("{INDENT}data object {item} : {}\n", supertype(name, &params, &[])));
+                }
+                ChoiceVariant::Wrap { doc, field, target, .. } => {
+                    let used = params.iter().filter(|p| target.mentions(p)).cloned().collect::<Vec<_>>();
+                    out.push_str(&doc_block(doc, INDENT));
+                    out.push_str(&format!("{INDENT}@Serializable\n{INDENT}@JvmInline\n"));
+                    out.push_str(&format!(
+                        "{INDENT}value class {item}{}(val {}: {}) : {}\n",
+                        generics(&used),
+                        sanitize(field),
+                        self.kt_type(target, nspace, &params),
+                        supertype(name, &params, &used),
+                    ));
+                }
+                ChoiceVariant::Record { structure, fields, tag } => {
+                    let used = used_params(&params, fields);
+                    out.push_str(&doc_block(&structure.doc, INDENT));
+                    out.push_str(&format!("{INDENT}@Serializable\n"));
+                    if let (true, Some((_, value))) = (tagged, tag) {
+                        out.push_str(&format!("{INDENT}@SerialName({})\n", kt_string(value)));
+                    }
+                    let header = format!("{item}{} : {}", generics(&used), supertype(name, &params, &used));
+                    out.push_str(&indented(&self.gen_data_class(&header, fields, &overrides, nspace, &params)));
+                }
+            }
+        }
+        out.push_str("}\n");
+        if !tagged {
+            out.push('\n');
+            self.gen_choice_serializer(out, c, &variants, nspace);
+        }
+    }
+
+    fn gen_choice_serializer(&self, out: &mut String, c: &ast::Choice, variants: &[ChoiceVariant], nspace: &[String]) {
+        let name = &c.name.name;
+        let params = c.name.param_names();
+        let ty = format!("{name}{}", generics(&params));
+        let body = format!("{INDENT}{INDENT}");
+        if params.is_empty() {
+            out.push_str(&format!("object {name}Serializer : KSerializer<{ty}> {{\n"));
+        } else {
+            let args = params
+                .iter()
+                .map(|p| format!("private val {}: KSerializer<{p}>", serializer_param(p)))
+                .collect::<Vec<_>>();
+            out.push_str(&format!("class {name}Serializer{}({}) : KSerializer<{ty}> {{\n", generics(&params), args.join(", ")));
+        }
+        let serial_name = kt_string(&format!("{}.{name}", nspace.join(".")));
+        out.push_str(&format!(
+            "{INDENT}override val descriptor: SerialDescriptor = SerialDescriptor({serial_name}, JsonElement.serializer().descriptor)\n\n",
+        ));
+
+        out.push_str(&format!("{INDENT}override fun serialize(encoder: Encoder, value: {ty}) {{\n"));
+        out.push_str(&format!("{body}val output = encoder as JsonEncoder\n"));
+        out.push_str(&format!("{body}val element = when (value) {{\n"));
+        for v in variants {
+            let item = format!("{name}.{}", v.name().name);
+            let arm = match v {
+                ChoiceVariant::Unit { wire, .. } => format!("{item} -> JsonPrimitive({})", kt_string(wire)),
+                ChoiceVariant::Value { value: ast::Literal::Int { value, .. }, .. } => format!("{item} -> JsonPrimitive({value})"),
+                ChoiceVariant::Value { value, .. } => format!("{item} -> JsonPrimitive({})", kt_string(&value.unquoted())),
+                ChoiceVariant::Wrap { field, target, .. } => format!(
+                    "is {item} -> output.json.encodeToJsonElement({}, value.{})",
+                    self.kt_serializer(target, nspace, &params),
+                    sanitize(field),
+                ),
+                ChoiceVariant::Record { fields, .. } => format!(
+                    "is {item} -> output.json.encodeToJsonElement({}, value)",
+                    self.record_serializer(&item, &used_params(&params, fields)),
+                ),
+            };
+            out.push_str(&format!("{body}{INDENT}{arm}\n"));
+        }
+        if variants.is_empty() {
+            out.push_str(&format!("{body}{INDENT}else -> JsonNull\n"));
+        }
+        out.push_str(&format!("{body}}}\n{body}output.encodeJsonElement(element)\n{INDENT}}}\n\n"));
+
+        out.push_str(&format!("{INDENT}override fun deserialize(decoder: Decoder): {ty} {{\n"));
+        out.push_str(&format!("{body}val input = decoder as JsonDecoder\n"));
+        out.push_str(&format!("{body}val element = input.decodeJsonElement()\n"));
+        for v in variants {
+            let item = format!("{name}.{}", v.name().name);
+            let line = match v {
+                ChoiceVariant::Unit { wire, .. } => format!(
+                    "if (element is JsonPrimitive && element.isString && element.content == {}) return {item}",
+                    kt_string(wire),
+                ),
+                ChoiceVariant::Value { value: ast::Literal::Int { value, .. }, .. } => format!(
+                    "if (element is JsonPrimitive && !element.isString && element.content == \"{value}\") return {item}",
+                ),
+                ChoiceVariant::Value { value, .. } => format!(
+                    "if (element is JsonPrimitive && element.isString && element.content == {}) return {item}",
+                    kt_string(&value.unquoted()),
+                ),
+                ChoiceVariant::Wrap { target, .. } => format!(
+                    "runCatching {{ input.json.decodeFromJsonElement({}, element) }}.onSuccess {{ return {item}(it) }}",
+                    self.kt_serializer(target, nspace, &params),
+                ),
+                ChoiceVariant::Record { fields, .. } => format!(
+                    "runCatching {{ input.json.decodeFromJsonElement({}, element) }}.onSuccess {{ return it }}",
+                    self.record_serializer(&item, &used_params(&params, fields)),
+                ),
+            };
+            out.push_str(&format!("{body}{line}\n"));
+        }
+        out.push_str(&format!("{body}throw SerializationException(\"Unexpected {name}: $element\")\n{INDENT}}}\n}}\n"));
+    }
+
+    fn record_serializer(&self, item: &str, used: &[String]) -> String {
+        let args = used.iter().map(|p| serializer_param(p)).collect::<Vec<_>>();
+        format!("{item}.serializer({})", args.join(", "))
+    }
+
+    /// Services are `suspend` interfaces, the natural shape for network calls on Android;
+    /// streams are cold `Flow`s.
+    pub fn gen_service(&self, out: &mut String, s: &ast::Service, nspace: &[String]) {
+        let params = s.name.param_names();
+        out.push_str(&doc_block(&s.doc, ""));
+        out.push_str(&format!("interface {}{} {{\n", s.name.name, generics(&params)));
+        for (i, m) in s.methods.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let mut mparams = params.clone();
+            let own = m.name.param_names();
+            mparams.extend(own.iter().cloned());
+            let args = m.args
+                .iter()
+                .map(|a| {
+                    let ty = self.kt_type(&a.type_path, nspace, &mparams);
+                    let ty = if m.is_stream_arg(&a.name) { format!("Flow<{ty}>") } else { ty };
+                    format!("{}: {ty}", sanitize(&a.name))
+                })
+                .collect::<Vec<_>>();
+            out.push_str(&doc_block(&m.doc, INDENT));
+            let type_params = if own.is_empty() { String::new() } else { format!("{} ", generics(&own)) };
+            let result = self.kt_type(&m.result, nspace, &mparams);
+            let (modifier, result) = if m.stream_result { ("", format!("Flow<{result}>")) } else { ("suspend ", result) };
+            out.push_str(&format!(
+                "{INDENT}{modifier}fun {type_params}{}({}): {result}\n",
+                sanitize(&m.name.name),
+                args.join(", "),
+            ));
+        }
+        out.push_str("}\n");
+    }
+
+    pub fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<Option<String>, IozhError> {
+        let mut body = String::new();
+        for item in &nspace.items {
+            let mut decl = String::new();
+            match item {
+                ast::NspaceItem::Structure(idx) => self.gen_structure(&mut decl, self.p.get_structure(*idx)?, path),
+                ast::NspaceItem::Choice(idx) => self.gen_choice(&mut decl, self.p.get_choice(*idx)?, path),
+                ast::NspaceItem::Service(s) => self.gen_service(&mut decl, s, path),
+                ast::NspaceItem::HttpService(_) | ast::NspaceItem::Storage(_) | ast::NspaceItem::Events(_) | ast::NspaceItem::Nspace(_) => {}
+            }
+            if !decl.is_empty() {
+                body.push('\n');
+                body.push_str(&decl);
+            }
+        }
+        if body.is_empty() {
+            return Ok(None);
+        }
+        let mut out = String::from(HEADER);
+        out.push_str("@file:OptIn(ExperimentalSerializationApi::class)\n\n");
+        out.push_str(&format!("package {}\n\n", package(path)));
+        let streams = nspace.items.iter().any(|i| matches!(i, ast::NspaceItem::Service(s) if s.methods.iter().any(|m| m.has_streams())));
+        if streams {
+            out.push_str("import kotlinx.coroutines.flow.Flow\n");
+        }
+        for import in ["kotlinx.serialization.*", "kotlinx.serialization.builtins.*", "kotlinx.serialization.descriptors.*", "kotlinx.serialization.encoding.*", "kotlinx.serialization.json.*"] {
+            out.push_str(&format!("import {import}\n"));
+        }
+        out.push_str(&body);
+        Ok(Some(out))
+    }
+}
+
+/// Writes one file per namespace, `a.b` to `a/b/B.kt`, plus `iozh/Iozh.kt`.
+/// The generated code needs kotlinx.serialization (json) and kotlinx-datetime,
+/// and must be (de)serialized with `iozh.IozhJson`.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let ctx = KotlinContext::new(&project);
+    let support = target_folder.join("iozh");
+    out.write(&support.join("Iozh.kt"), SUPPORT)?;
+    for (path, nspace) in project.all_nspaces() {
+        let content = match ctx.gen_nspace(&path, nspace)? {
+            Some(content) => content,
+            None => continue,
+        };
+        let dir = path.iter().fold(target_folder.to_path_buf(), |acc, p| acc.join(p));
+        let mut file_name = nspace.name.clone();
+        if let Some(first) = file_name.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        out.write(&dir.join(format!("{file_name}.kt")), &content)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+/// Written as `iozh/Iozh.kt`: the `Json` configuration and serializers the
+/// generated code relies on to match the circe wire format.
+pub const SUPPORT: &str = r#"// Generated by iozh. Do not edit.
+@file:OptIn(ExperimentalSerializationApi::class)
+
+package iozh
+
+import kotlin.time.Duration
+import kotlin.time.DurationUnit
+import kotlin.time.toDuration
+import kotlinx.serialization.*
+import kotlinx.serialization.descriptors.*
+import kotlinx.serialization.encoding.*
+import kotlinx.serialization.json.*
+
+/**
+ * `Json` configured like the circe codecs: absent optional fields are left
+ * out, empty lists are written, unknown keys are ignored.
+ */
+val IozhJson: Json = Json {
+    encodeDefaults = true
+    explicitNulls = false
+    ignoreUnknownKeys = true
+}
+
+/** `scala.concurrent.duration.Duration` as encoded by circe, e.g. `"5 seconds"`. */
+typealias ScalaDuration = @Serializable(with = ScalaDurationSerializer::class) Duration
+
+object ScalaDurationSerializer : KSerializer<Duration> {
+    private val units = listOf(
+        "day" to DurationUnit.DAYS,
+        "hour" to DurationUnit.HOURS,
+        "minute" to DurationUnit.MINUTES,
+        "second" to DurationUnit.SECONDS,
+        "millisecond" to DurationUnit.MILLISECONDS,
+        "microsecond" to DurationUnit.MICROSECONDS,
+        "nanosecond" to DurationUnit.NANOSECONDS,
+    )
+
+    private val aliases = mapOf(
+        "d" to DurationUnit.DAYS, "h" to DurationUnit.HOURS,
+        "m" to DurationUnit.MINUTES, "min" to DurationUnit.MINUTES, "mins" to DurationUnit.MINUTES,
+        "s" to DurationUnit.SECONDS, "sec" to DurationUnit.SECONDS, "secs" to DurationUnit.SECONDS,
+        "ms" to DurationUnit.MILLISECONDS, "milli" to DurationUnit.MILLISECONDS, "millis" to DurationUnit.MILLISECONDS,
+        "µs" to DurationUnit.MICROSECONDS, "micro" to DurationUnit.MICROSECONDS, "micros" to DurationUnit.MICROSECONDS,
+        "ns" to DurationUnit.NANOSECONDS, "nano" to DurationUnit.NANOSECONDS, "nanos" to DurationUnit.NANOSECONDS,
+    ) + units.flatMap { (name, unit) -> listOf(name to unit, name + "s" to unit) }
+
+    override val descriptor: SerialDescriptor = PrimitiveSerialDescriptor("iozh.ScalaDuration", PrimitiveKind.STRING)
+
+    override fun serialize(encoder: Encoder, value: Duration) {
+        if (value.isInfinite()) {
+            encoder.encodeString(if (value.isPositive()) "Duration.Inf" else "Duration.MinusInf")
+            return
+        }
+        val nanos = value.inWholeNanoseconds
+        val (name, unit) = units.first { (_, unit) -> nanos % 1.toDuration(unit).inWholeNanoseconds == 0L }
+        val length = nanos / 1.toDuration(unit).inWholeNanoseconds
+        encoder.encodeString("$length $name" + if (length == 1L) "" else "s")
+    }
+
+    override fun deserialize(decoder: Decoder): Duration {
+        val s = decoder.decodeString().trim()
+        when (s) {
+            "Duration.Inf" -> return Duration.INFINITE
+            "Duration.MinusInf" -> return -Duration.INFINITE
+        }
+        val match = Regex("""^(-?\d+)\s*(\S*)$""").matchEntire(s)
+            ?: throw SerializationException("Invalid duration: $s")
+        val unit = if (match.groupValues[2].isEmpty()) DurationUnit.SECONDS else aliases[match.groupValues[2]]
+            ?: throw SerializationException("Unknown duration unit: ${match.groupValues[2]}")
+        return match.groupValues[1].toLong().toDuration(unit)
+    }
+}
+"#;
@@ -0,0 +1,473 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::format::format_type_path;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use crate::lock::FieldNumbers;
+
+const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+const INDENT: &str = "  ";
+
+/// Name of the lock file written next to the generated files unless
+/// `ProtoOptions::lock_file` says otherwise.
+pub const LOCK_FILE: &str = "iozh.proto.lock";
+
+#[derive(Default)]
+pub struct ProtoOptions {
+    /// Lock file recording the field numbers, `LOCK_FILE` in the target
+    /// folder by default.
+    pub lock_file: Option<PathBuf>,
+}
+
+/// Protobuf package of a namespace: `Users` to `users`.
+pub fn package(path: &[String]) -> String {
+    path.iter().map(|p| snake_case(p)).collect::<Vec<_>>().join(".")
+}
+
+/// File a namespace is written to, relative to the target folder.
+pub fn file_name(path: &[String]) -> String {
+    path.iter().map(|p| snake_case(p)).collect::<Vec<_>>().join("/") + ".proto"
+}
+
+fn pascal_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn upper_snake(name: &str) -> String {
+    snake_case(name).to_uppercase()
+}
+
+fn comment(doc: &str, indent: &str) -> String {
+    doc_text(doc)
+        .lines()
+        .map(|l| format!("{indent}// {l}").trim_end().to_string() + "\n")
+        .collect()
+}
+
+fn reserved(numbers: &[u32], indent: &str) -> String {
+    if numbers.is_empty() {
+        return String::new();
+    }
+    let numbers = numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    format!("{indent}reserved {};\n", numbers.join(", "))
+}
+
+/// Choices made only of bare and literal items, which become enums.
+fn is_enum(c: &ast::Choice, variants: &[ChoiceVariant]) -> bool {
+    c.name.args.is_empty()
+        && !variants.is_empty()
+        && variants.iter().all(|v| matches!(v, ChoiceVariant::Unit { .. } | ChoiceVariant::Value { .. }))
+}
+
+/// A field type: the label (`optional`, `repeated` or none) and the type.
+struct FieldType {
+    label: &'static str,
+    ty: String,
+}
+
+impl FieldType {
+    fn declare(&self, name: &str, number: u32) -> String {
+        format!("{}{} {name} = {number};", self.label, self.ty)
+    }
+}
+
+pub struct ProtoContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    numbers: RefCell<FieldNumbers>,
+    /// Files the file being generated imports.
+    imports: RefCell<BTreeSet<String>>,
+    /// `repeated` wrappers for nested lists, declared at the end of the
+    /// file being generated: message name and element type.
+    lists: RefCell<BTreeMap<String, String>>,
+}
+
+impl <'a> ProtoContext<'a> {
+    pub fn new(p: &'a ast::Project, numbers: FieldNumbers) -> ProtoContext<'a> {
+        ProtoContext {
+            p,
+            index: TypeIndex::build(p),
+            numbers: RefCell::new(numbers),
+            imports: RefCell::new(BTreeSet::new()),
+            lists: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    fn import(&self, file: &str) {
+        self.imports.borrow_mut().insert(file.to_string());
+    }
+
+    fn number(&self, scope: &str, member: &str) -> u32 {
+        self.numbers.borrow_mut().number(scope, member)
+    }
+
+    fn any(&self) -> String {
+        self.import("google/protobuf/any.proto");
+        "google.protobuf.Any".to_string()
+    }
+
+    /// Whether the resolved type is a message, rather than an enum.
+    fn is_message(&self, entry: &TypeEntry) -> bool {
+        match entry.def {
+            TypeDef::Choice(idx) => self.p.get_choice(idx).is_ok_and(|c| !is_enum(c, &c.variants(self.p))),
+            _ => true,
+        }
+    }
+
+    fn reference(&self, entry: &TypeEntry, nspace: &[String]) -> String {
+        if entry.path == nspace {
+            entry.name.clone()
+        } else {
+            self.import(&file_name(&entry.path));
+            format!("{}.{}", package(&entry.path), entry.name)
+        }
+    }
+
+    /// Protobuf type of `tp`. Type parameters, which protobuf has no
+    /// notion of, become `google.protobuf.Any`, and `Unit`
+    /// `google.protobuf.Empty`.
+    fn field_type(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> Result<FieldType, IozhError> {
+        let plain = |ty: &str| FieldType { label: "", ty: ty.to_string() };
+        let unknown = || IozhError { pos: tp.pos.clone(), msg: format!("Unknown type {}", format_type_path(tp)) };
+        let tag = tp.last().ok_or_else(unknown)?;
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return Ok(plain(&self.any()));
+        }
+        if is_unit(tp) {
+            self.import("google/protobuf/empty.proto");
+            return Ok(plain("google.protobuf.Empty"));
+        }
+        let inner = || match tag.args.first() {
+            Some(arg) => self.field_type(arg, nspace, params),
+            None => Err(IozhError { pos: tp.pos.clone(), msg: format!("Missing type argument of {}", format_type_path(tp)) }),
+        };
+        Ok(match Builtin::from_path(tp) {
+            Some(Builtin::I32) => plain("int32"),
+            Some(Builtin::I64) => plain("int64"),
+            Some(Builtin::F32) => plain("float"),
+            Some(Builtin::F64) => plain("double"),
+            Some(Builtin::Bool) => plain("bool"),
+            Some(Builtin::String | Builtin::File) => plain("string"),
+            Some(Builtin::DateTime) => {
+                self.import("google/protobuf/timestamp.proto");
+                plain("google.protobuf.Timestamp")
+            }
+            Some(Builtin::Duration) => {
+                self.import("google/protobuf/duration.proto");
+                plain("google.protobuf.Duration")
+            }
+            Some(Builtin::Option) => {
+                let inner = inner()?;
+                match inner.label {
+                    "" => FieldType { label: "optional ", ty: inner.ty },
+                    _ => inner,
+                }
+            }
+            Some(Builtin::List) => {
+                let inner = inner()?;
+                match inner.label {
+                    "repeated " => FieldType { label: "repeated ", ty: self.list_wrapper(&inner.ty) },
+                    _ => FieldType { label: "repeated ", ty: inner.ty },
+                }
+            }
+            None => match self.index.resolve_path(tp, nspace) {
+                Some(entry) => plain(&self.reference(entry, nspace)),
+                None => return Err(unknown()),
+            },
+        })
+    }
+
+    /// Message holding a list, for lists of lists, which cannot be
+    /// `repeated` directly.
+    fn list_wrapper(&self, element: &str) -> String {
+        let base = element.rsplit('.').next().unwrap_or(element);
+        let name = pascal_case(base) + "List";
+        self.lists.borrow_mut().insert(name.clone(), element.to_string());
+        name
+    }
+
+    /// Fields of the message `scope`, numbered from the lock file.
+    fn gen_fields(&self, out: &mut String, indent: &str, scope: &str, fields: &[&ast::Field], nspace: &[String], params: &[String]) -> Result<(), IozhError> {
+        let mut used = BTreeSet::new();
+        for f in fields {
+            let number = self.number(scope, &f.name);
+            used.insert(number);
+            let ty = self.field_type(&f.type_path, nspace, params)?;
+            out.push_str(&comment(&f.doc, indent));
+            out.push_str(&format!("{indent}{}\n", ty.declare(&snake_case(&f.name), number)));
+        }
+        out.push_str(&reserved(&self.numbers.borrow().reserved(scope, &used), indent));
+        Ok(())
+    }
+
+    /// Message named after the last segment of its scope.
+    fn gen_message(&self, out: &mut String, indent: &str, scope: &str, fields: &[&ast::Field], nspace: &[String], params: &[String]) -> Result<(), IozhError> {
+        let name = scope.rsplit('.').next().unwrap_or(scope);
+        if fields.is_empty() && self.numbers.borrow().reserved(scope, &BTreeSet::new()).is_empty() {
+            out.push_str(&format!("{indent}message {name} {{}}\n"));
+            return Ok(());
+        }
+        out.push_str(&format!("{indent}message {name} {{\n"));
+        self.gen_fields(out, &format!("{indent}{INDENT}"), scope, fields, nspace, params)?;
+        out.push_str(&format!("{indent}}}\n"));
+        Ok(())
+    }
+
+    pub fn gen_structure(&self, out: &mut String, s: &ast::Structure, nspace: &[String]) -> Result<(), IozhError> {
+        let scope = format!("{}.{}", package(nspace), s.name.name);
+        let params = s.name.param_names();
+        out.push_str(&comment(&s.doc, ""));
+        self.gen_message(out, "", &scope, &s.get_fields(), nspace, &params)
+    }
+
+    /// Choices of bare and literal items are enums; others are a message
+    /// with a nested message per structure or bare item and a `oneof`
+    /// over the items.
+    pub fn gen_choice(&self, out: &mut String, c: &ast::Choice, nspace: &[String]) -> Result<(), IozhError> {
+        let variants = c.variants(self.p);
+        let scope = format!("{}.{}", package(nspace), c.name.name);
+        if is_enum(c, &variants) {
+            self.gen_enum(out, c, &variants, &scope);
+            return Ok(());
+        }
+        let params = c.name.param_names();
+        let inner = INDENT.repeat(2);
+        let mut nested = String::new();
+        let mut members = String::new();
+        let mut used = BTreeSet::new();
+        for v in &variants {
+            let item = &v.name().name;
+            let number = self.number(&scope, item);
+            used.insert(number);
+            let member = snake_case(item);
+            let item_scope = format!("{scope}.{item}");
+            let doc = match v {
+                ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } | ChoiceVariant::Wrap { doc, .. } => doc,
+                ChoiceVariant::Record { structure, .. } => structure.doc.as_str(),
+            };
+            members.push_str(&comment(doc, &inner));
+            match v {
+                ChoiceVariant::Wrap { field, target, .. } => {
+                    let ty = self.field_type(target, nspace, &params)?;
+                    if ty.label.is_empty() {
+                        members.push_str(&format!("{inner}{}\n", ty.declare(&member, number)));
+                        continue;
+                    }
+                    // Labeled fields are not allowed in a oneof.
+                    let number = self.number(&item_scope, field);
+                    nested.push_str(&format!("{INDENT}message {item} {{\n{inner}{}\n{INDENT}}}\n", ty.declare(&snake_case(field), number)));
+                }
+                ChoiceVariant::Record { fields, .. } => {
+                    if !nested.is_empty() {
+                        nested.push('\n');
+                    }
+                    self.gen_message(&mut nested, INDENT, &item_scope, fields, nspace, &params)?;
+                }
+                ChoiceVariant::Unit { .. } | ChoiceVariant::Value { .. } => {
+                    nested.push_str(&format!("{INDENT}message {item} {{}}\n"));
+                }
+            }
+            members.push_str(&format!("{inner}{item} {member} = {number};\n"));
+        }
+        out.push_str(&comment(&c.doc, ""));
+        out.push_str(&format!("message {} {{\n", c.name.name));
+        if !nested.is_empty() {
+            out.push_str(&nested);
+            out.push('\n');
+        }
+        out.push_str(&reserved(&self.numbers.borrow().reserved(&scope, &used), INDENT));
+        out.push_str(&format!("{INDENT}oneof value {{\n{members}{INDENT}}}\n}}\n"));
+        Ok(())
+    }
+
+    /// Enum values are prefixed with the choice name, since protobuf
+    /// scopes them to the package, and start at 1: 0 is the unset value.
+    fn gen_enum(&self, out: &mut String, c: &ast::Choice, variants: &[ChoiceVariant], scope: &str) {
+        let prefix = upper_snake(&c.name.name);
+        out.push_str(&comment(&c.doc, ""));
+        out.push_str(&format!("enum {} {{\n", c.name.name));
+        out.push_str(&format!("{INDENT}{prefix}_UNSPECIFIED = 0;\n"));
+        let mut used = BTreeSet::new();
+        for v in variants {
+            let item = &v.name().name;
+            let number = self.number(scope, item);
+            used.insert(number);
+            if let ChoiceVariant::Unit { doc, .. } | ChoiceVariant::Value { doc, .. } = v {
+                out.push_str(&comment(doc, INDENT));
+            }
+            let item = upper_snake(item);
+            let value = if item.starts_with(&format!("{prefix}_")) { item } else { format!("{prefix}_{item}") };
+            out.push_str(&format!("{INDENT}{value} = {number};\n"));
+        }
+        out.push_str(&reserved(&self.numbers.borrow().reserved(scope, &used), INDENT));
+        out.push_str("}\n");
+    }
+
+    /// A service with a `Request` message per method holding its
+    /// arguments. Results that are not messages of their own are wrapped
+    /// in a `Response` message. Streamed results make server streaming
+    /// rpcs; streamed arguments client streaming ones, each request
+    /// carrying the next values.
+    pub fn gen_service(&self, out: &mut String, s: &ast::Service, nspace: &[String], clashing: &BTreeSet<String>) -> Result<(), IozhError> {
+        let params = s.name.param_names();
+        let mut rpcs = String::new();
+        for m in &s.methods {
+            let base = if clashing.contains(&m.name.name) {
+                s.name.name.clone() + &pascal_case(&m.name.name)
+            } else {
+                pascal_case(&m.name.name)
+            };
+            let request = format!("{base}Request");
+            let scope = format!("{}.{request}", package(nspace));
+            let args = m.args.iter().collect::<Vec<_>>();
+            self.gen_message(out, "", &scope, &args, nspace, &params)?;
+            out.push('\n');
+            let result = self.field_type(&m.result, nspace, &params)?;
+            let own_message = is_unit(&m.result)
+                || result.label.is_empty()
+                    && !params.iter().any(|p| m.result.mentions(p))
+                    && self.index.resolve_path(&m.result, nspace).is_some_and(|e| self.is_message(e));
+            let response = if own_message {
+                result.ty
+            } else {
+                let response = format!("{base}Response");
+                let scope = format!("{}.{response}", package(nspace));
+                let number = self.number(&scope, "value");
+                out.push_str(&format!("message {response} {{\n{INDENT}{}\n}}\n\n", result.declare("value", number)));
+                response
+            };
+            rpcs.push_str(&comment(&m.doc, INDENT));
+            let streamed = |yes: bool| if yes { "stream " } else { "" };
+            rpcs.push_str(&format!(
+                "{INDENT}rpc {}({}{request}) returns ({}{response});\n",
+                pascal_case(&m.name.name),
+                streamed(!m.stream_args.is_empty()),
+                streamed(m.stream_result),
+            ));
+        }
+        out.push_str(&comment(&s.doc, ""));
+        out.push_str(&format!("service {} {{\n{rpcs}}}\n", s.name.name));
+        Ok(())
+    }
+
+    pub fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<Option<String>, IozhError> {
+        self.imports.borrow_mut().clear();
+        self.lists.borrow_mut().clear();
+        let mut methods = HashMap::<&str, usize>::new();
+        for item in &nspace.items {
+            if let ast::NspaceItem::Service(s) = item {
+                for m in &s.methods {
+                    *methods.entry(m.name.name.as_str()).or_default() += 1;
+                }
+            }
+        }
+        let clashing = methods.into_iter().filter(|(_, n)| *n > 1).map(|(m, _)| m.to_string()).collect();
+        let mut decls = vec![];
+        for item in &nspace.items {
+            let mut out = String::new();
+            match item {
+                ast::NspaceItem::Structure(idx) => self.gen_structure(&mut out, self.p.get_structure(*idx)?, path)?,
+                ast::NspaceItem::Choice(idx) => self.gen_choice(&mut out, self.p.get_choice(*idx)?, path)?,
+                ast::NspaceItem::Service(s) => self.gen_service(&mut out, s, path, &clashing)?,
+                ast::NspaceItem::HttpService(_) | ast::NspaceItem::Storage(_) | ast::NspaceItem::Events(_) | ast::NspaceItem::Nspace(_) => continue,
+            }
+            decls.push(out);
+        }
+        if decls.is_empty() {
+            return Ok(None);
+        }
+        for (name, element) in self.lists.borrow().iter() {
+            decls.push(format!("message {name} {{\n{INDENT}repeated {element} values = 1;\n}}\n"));
+        }
+        let mut out = String::from(HEADER);
+        out.push_str(&format!("syntax = \"proto3\";\n\npackage {};\n", package(path)));
+        let own = file_name(path);
+        let imports = self.imports.borrow();
+        let imports = imports.iter().filter(|i| **i != own).collect::<Vec<_>>();
+        if !imports.is_empty() {
+            out.push('\n');
+            for i in imports {
+                out.push_str(&format!("import \"{i}\";\n"));
+            }
+        }
+        for decl in decls {
+            out.push('\n');
+            out.push_str(&decl);
+        }
+        Ok(Some(out))
+    }
+}
+
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    generate_with(project, target_folder, out, ProtoOptions::default())
+}
+
+/// Writes one `.proto` file per namespace, `a.b` to `a/b.proto`, then the
+/// lock file with the field numbers assigned so far, so that they stay
+/// stable across regenerations.
+pub fn generate_with(project: ast::Project, target_folder: &Path, out: &mut dyn Output, options: ProtoOptions) -> Result<(), IozhError> {
+    let lock_file = options.lock_file.unwrap_or_else(|| target_folder.join(LOCK_FILE));
+    let numbers = match fs::read_to_string(&lock_file) {
+        Ok(source) => FieldNumbers::parse(&source)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => FieldNumbers::default(),
+        Err(e) => return Err(IozhError::from(format!("Failed to read {}: {}", lock_file.display(), e))),
+    };
+    let ctx = ProtoContext::new(&project, numbers);
+    for (path, nspace) in project.all_nspaces() {
+        if let Some(content) = ctx.gen_nspace(&path, nspace)? {
+            let file = target_folder.join(file_name(&path));
+            out.write(&file, &content)?;
+        }
+    }
+    let numbers = ctx.numbers.borrow();
+    if numbers.changed() || !lock_file.exists() {
+        out.write(&lock_file, &numbers.format())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `.proto` of the only namespace of `source` and the lock file
+    /// after generating it from `lock`.
+    fn proto(source: &str, lock: &str) -> (String, String) {
+        let p = ast::Project::parse(source).unwrap();
+        let ctx = ProtoContext::new(&p, FieldNumbers::parse(lock).unwrap());
+        let (path, nspace) = p.all_nspaces().into_iter().next().unwrap();
+        let content = ctx.gen_nspace(&path, nspace).unwrap().unwrap();
+        let lock = ctx.numbers.borrow().format();
+        (content, lock)
+    }
+
+    #[test]
+    fn keeps_field_numbers_across_schema_versions() {
+        let (_, lock) = proto("namespace shop\n\nUser\n  id: I64\n  name: String\n  email: String\n", "");
+        let (content, lock) = proto("namespace shop\n\nUser\n  id: I64\n  email: String\n  phone: String\n", &lock);
+        assert!(content.contains("  int64 id = 1;\n"), "{content}");
+        assert!(content.contains("  string email = 3;\n"), "{content}");
+        assert!(content.contains("  string phone = 4;\n"), "{content}");
+        assert!(content.contains("  reserved 2;\n"), "{content}");
+        let (content, _) = proto("namespace shop\n\nUser\n  id: I64\n  name: String\n  email: String\n  phone: String\n", &lock);
+        assert!(content.contains("  string name = 2;\n"), "{content}");
+        assert!(!content.contains("reserved"), "{content}");
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        let p = ast::Project::parse("namespace shop\n\nUser\n  id: Identifier\n").unwrap();
+        let ctx = ProtoContext::new(&p, FieldNumbers::default());
+        let (path, nspace) = p.all_nspaces().into_iter().next().unwrap();
+        let error = ctx.gen_nspace(&path, nspace).unwrap_err();
+        assert_eq!(error.msg, "Unknown type Identifier");
+    }
+}
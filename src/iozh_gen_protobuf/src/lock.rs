@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, BTreeSet};
+use iozh_parse::error::IozhError;
+
+const LOCK_HEADER: &str = "\
+# Field numbers assigned by iozh to the generated protobuf messages and enums.
+# Keep this file under version control: a number must never change or be
+# reused once messages using it have been exchanged.
+";
+
+/// Field numbers protobuf keeps for its own implementation.
+const IMPLEMENTATION_RESERVED: std::ops::RangeInclusive<u32> = 19000..=19999;
+
+/// Field numbers by scope (a message or enum, as `package.Message`) and
+/// member name. Numbers are assigned once and kept forever: members that
+/// disappear from the schema keep theirs, reported as `reserved`.
+#[derive(Default)]
+pub struct FieldNumbers {
+    scopes: BTreeMap<String, BTreeMap<String, u32>>,
+    changed: bool,
+}
+
+impl FieldNumbers {
+    /// Parses lines of `scope member = number`; blank lines and `#`
+    /// comments are skipped.
+    pub fn parse(source: &str) -> Result<FieldNumbers, IozhError> {
+        let mut numbers = FieldNumbers::default();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || IozhError::from(format!("Malformed protobuf lock entry at line {}: {}", i + 1, line));
+            let (key, number) = line.rsplit_once('=').ok_or_else(malformed)?;
+            let (scope, member) = key.trim().split_once(' ').ok_or_else(malformed)?;
+            let number = number.trim().parse::<u32>().map_err(|_| malformed())?;
+            numbers.scopes.entry(scope.to_string()).or_default().insert(member.trim().to_string(), number);
+        }
+        Ok(numbers)
+    }
+
+    /// Number of `member` in `scope`, the next free one if it has none yet,
+    /// skipping the range reserved by protobuf.
+    pub fn number(&mut self, scope: &str, member: &str) -> u32 {
+        let members = self.scopes.entry(scope.to_string()).or_default();
+        if let Some(n) = members.get(member) {
+            return *n;
+        }
+        let mut next = members.values().max().map_or(1, |n| n + 1);
+        if IMPLEMENTATION_RESERVED.contains(&next) {
+            next = IMPLEMENTATION_RESERVED.end() + 1;
+        }
+        members.insert(member.to_string(), next);
+        self.changed = true;
+        next
+    }
+
+    /// Numbers of `scope` not among `used`, left by removed members.
+    pub fn reserved(&self, scope: &str, used: &BTreeSet<u32>) -> Vec<u32> {
+        self.scopes
+            .get(scope)
+            .map(|m| m.values().filter(|n| !used.contains(n)).copied().collect::<BTreeSet<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn format(&self) -> String {
+        let mut out = String::from(LOCK_HEADER);
+        for (scope, members) in &self.scopes {
+            out.push('\n');
+            let mut members = members.iter().collect::<Vec<_>>();
+            members.sort_by_key(|(_, n)| **n);
+            for (member, n) in members {
+                out.push_str(&format!("{scope} {member} = {n}\n"));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn used(numbers: &[u32]) -> BTreeSet<u32> {
+        numbers.iter().copied().collect()
+    }
+
+    #[test]
+    fn numbers_members_in_order() {
+        let mut numbers = FieldNumbers::default();
+        assert_eq!(numbers.number("p.User", "id"), 1);
+        assert_eq!(numbers.number("p.User", "name"), 2);
+        assert_eq!(numbers.number("p.Game", "id"), 1);
+        assert_eq!(numbers.number("p.User", "id"), 1);
+        assert!(numbers.changed());
+    }
+
+    #[test]
+    fn keeps_numbers_across_runs() {
+        let mut first = FieldNumbers::default();
+        for member in ["id", "name", "email"] {
+            first.number("p.User", member);
+        }
+        let mut second = FieldNumbers::parse(&first.format()).unwrap();
+        assert_eq!(second.number("p.User", "email"), 3);
+        assert_eq!(second.number("p.User", "id"), 1);
+        assert!(!second.changed());
+    }
+
+    #[test]
+    fn reserves_removed_members_and_keeps_their_number() {
+        let mut numbers = FieldNumbers::default();
+        for member in ["id", "name", "email"] {
+            numbers.number("p.User", member);
+        }
+        // `name` is removed: its number is reserved, never reused.
+        let mut numbers = FieldNumbers::parse(&numbers.format()).unwrap();
+        assert_eq!(numbers.reserved("p.User", &used(&[1, 3])), vec![2]);
+        assert_eq!(numbers.number("p.User", "phone"), 4);
+        // Added again, it gets its old number back.
+        assert_eq!(numbers.number("p.User", "name"), 2);
+        assert!(numbers.reserved("p.User", &used(&[1, 2, 3, 4])).is_empty());
+    }
+
+    #[test]
+    fn skips_the_implementation_range() {
+        let mut numbers = FieldNumbers::parse("p.User last = 18999\n").unwrap();
+        assert_eq!(numbers.number("p.User", "next"), 20000);
+        assert_eq!(numbers.number("p.User", "after"), 20001);
+    }
+
+    #[test]
+    fn skips_comments_and_rejects_malformed_lines() {
+        let numbers = FieldNumbers::parse("# numbers\n\np.User id = 1\n").unwrap();
+        assert_eq!(numbers.reserved("p.User", &used(&[])), vec![1]);
+        assert!(FieldNumbers::parse("p.User id 1\n").is_err());
+        assert!(FieldNumbers::parse("p.User id = one\n").is_err());
+    }
+}
@@ -0,0 +1,523 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use crate::support::SUPPORT;
+
+const HEADER: &str = "# Generated by iozh. Do not edit.\n";
+const INDENT: &str = "    ";
+
+const KEYWORDS: [&str; 35] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+    "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+    "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+    "return", "try", "while", "with", "yield",
+];
+
+/// Modules imported from, grouped the way isort would.
+const STDLIB: [&str; 5] = ["collections.abc", "datetime", "enum", "typing", "typing_extensions"];
+
+pub fn sanitize(name: &str) -> String {
+    if KEYWORDS.contains(&name) || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn py_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn module(path: &[String]) -> String {
+    path.iter().map(|p| sanitize(p)).collect::<Vec<_>>().join(".")
+}
+
+/// Upper snake case name of an enum member.
+fn member_name(name: &str) -> String {
+    sanitize(&snake_case(name).to_ascii_uppercase())
+}
+
+fn docstring(doc: &str, indent: &str) -> String {
+    let text = doc_text(doc).replace('\\', "\\\\").replace("\"\"\"", "\\\"\\\"\\\"");
+    if text.is_empty() {
+        return String::new();
+    }
+    let lines = text.lines().collect::<Vec<_>>();
+    if lines.len() == 1 {
+        return format!("{indent}\"\"\"{text}\"\"\"\n");
+    }
+    let mut out = format!("{indent}\"\"\"{}\n", lines[0]);
+    for l in &lines[1..] {
+        if l.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{indent}{l}\n"));
+        }
+    }
+    out.push_str(&format!("{indent}\"\"\"\n"));
+    out
+}
+
+fn generics(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", params.join(", "))
+    }
+}
+
+/// Python spelling of a choice that is not an enum: tagged choices use the
+/// tag as pydantic discriminator, others are tried left to right like the
+/// circe decoders. Bare and literal items are their wire values.
+fn is_tagged(variants: &[ChoiceVariant]) -> bool {
+    !variants.is_empty() && variants.iter().all(|v| matches!(v, ChoiceVariant::Record { tag: Some(_), .. }))
+}
+
+/// Whether a choice is made only of bare and literal items.
+fn is_enum(variants: &[ChoiceVariant]) -> bool {
+    !variants.is_empty() && variants.iter().all(|v| matches!(v, ChoiceVariant::Unit { .. } | ChoiceVariant::Value { .. }))
+}
+
+fn literal(value: &ast::Literal) -> String {
+    match value {
+        ast::Literal::Int { value, .. } => value.to_string(),
+        value => py_string(&value.unquoted()),
+    }
+}
+
+#[derive(Default)]
+struct Imports {
+    from: BTreeMap<String, BTreeSet<String>>,
+    modules: BTreeSet<String>,
+    typevars: BTreeSet<String>,
+}
+
+pub struct PythonContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    /// Class names of choice items that become classes, by namespace and
+    /// `Choice.Item`. Items are named after themselves unless that clashes
+    /// with another name of the namespace, in which case the choice name
+    /// is prepended.
+    item_classes: HashMap<(Vec<String>, String), String>,
+    /// What the module being generated needs to import.
+    imports: RefCell<Imports>,
+}
+
+impl <'a> PythonContext<'a> {
+    pub fn new(p: &'a ast::Project) -> PythonContext<'a> {
+        let mut item_classes = HashMap::new();
+        for (path, nspace) in p.all_nspaces() {
+            let mut counts = HashMap::<String, usize>::new();
+            let mut items = vec![];
+            for item in &nspace.items {
+                match item {
+                    ast::NspaceItem::Structure(idx) => {
+                        if let Ok(s) = p.get_structure(*idx) {
+                            *counts.entry(s.name.name.clone()).or_default() += 1;
+                        }
+                    }
+                    ast::NspaceItem::Choice(idx) => {
+                        if let Ok(c) = p.get_choice(*idx) {
+                            *counts.entry(c.name.name.clone()).or_default() += 1;
+                            for v in c.variants(p) {
+                                if matches!(v, ChoiceVariant::Wrap { .. } | ChoiceVariant::Record { .. }) {
+                                    *counts.entry(v.name().name.clone()).or_default() += 1;
+                                    items.push((c.name.name.clone(), v.name().name.clone()));
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            for (choice, item) in items {
+                let class = if counts[&item] > 1 { format!("{choice}{item}") } else { item.clone() };
+                item_classes.insert((path.clone(), format!("{choice}.{item}")), sanitize(&class));
+            }
+        }
+        PythonContext {
+            p,
+            index: TypeIndex::build(p),
+            item_classes,
+            imports: RefCell::new(Imports::default()),
+        }
+    }
+
+    fn import(&self, module: &str, name: &str) -> String {
+        self.imports.borrow_mut().from.entry(module.to_string()).or_default().insert(name.to_string());
+        name.to_string()
+    }
+
+    fn typevars(&self, params: &[String]) {
+        self.imports.borrow_mut().typevars.extend(params.iter().cloned());
+        if !params.is_empty() {
+            self.import("typing", "TypeVar");
+        }
+    }
+
+    fn item_class(&self, nspace: &[String], choice: &str, item: &str) -> String {
+        self.item_classes
+            .get(&(nspace.to_vec(), format!("{choice}.{item}")))
+            .cloned()
+            .unwrap_or_else(|| sanitize(item))
+    }
+
+    fn type_arg(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.first()) {
+            Some(arg) => self.py_type(arg, nspace, params),
+            None => self.import("typing", "Any"),
+        }
+    }
+
+    pub fn py_type(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "None".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return tag.name.clone();
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 | Builtin::I64 => "int".to_string(),
+                Builtin::F32 | Builtin::F64 => "float".to_string(),
+                Builtin::Bool => "bool".to_string(),
+                Builtin::String | Builtin::File => "str".to_string(),
+                Builtin::DateTime => self.import("datetime", "datetime"),
+                Builtin::Duration => self.import("iozh_support", "ScalaDuration"),
+                Builtin::Option => format!("{}[{}]", self.import("typing", "Optional"), self.type_arg(tp, nspace, params)),
+                Builtin::List => format!("list[{}]", self.type_arg(tp, nspace, params)),
+            };
+        }
+        let args = tag.args.iter().map(|a| self.py_type(a, nspace, params)).collect::<Vec<_>>();
+        let entry = match self.index.resolve_path(tp, nspace) {
+            Some(entry) => entry,
+            None => return self.import("typing", "Any"),
+        };
+        let name = match entry.def {
+            // Bare items have no class of their own, the choice stands in.
+            TypeDef::Variant { .. } => match self.item_classes.get(&(entry.path.clone(), entry.name.clone())) {
+                Some(class) => class.clone(),
+                None => sanitize(entry.name.split('.').next().unwrap_or(&entry.name)),
+            },
+            _ => sanitize(&entry.name),
+        };
+        let name = if entry.path == nspace {
+            name
+        } else {
+            let m = module(&entry.path);
+            self.imports.borrow_mut().modules.insert(m.clone());
+            format!("{m}.{name}")
+        };
+        name + &generics(&args)
+    }
+
+    fn gen_field(&self, f: &ast::Field, nspace: &[String], params: &[String]) -> String {
+        let attr = sanitize(&snake_case(&f.name));
+        let ty = self.py_type(&f.type_path, nspace, params);
+        let mut args = vec![];
+        match Builtin::from_path(&f.type_path) {
+            Some(Builtin::Option) => args.push("default=None".to_string()),
+            Some(Builtin::List) => args.push("default_factory=list".to_string()),
+            _ => {}
+        }
+        if attr != f.name {
+            args.push(format!("alias={}", py_string(&f.name)));
+        }
+        let line = match args.as_slice() {
+            [] => format!("{INDENT}{attr}: {ty}\n"),
+            [d] if d == "default=None" => format!("{INDENT}{attr}: {ty} = None\n"),
+            _ => format!("{INDENT}{attr}: {ty} = {}({})\n", self.import("pydantic", "Field"), args.join(", ")),
+        };
+        line + &docstring(&f.doc, INDENT)
+    }
+
+    /// A pydantic model; `tag` adds the discriminator of a tagged choice,
+    /// declared last since circe writes it after the fields.
+    fn gen_model(&self, header: &str, doc: &str, fields: &[&ast::Field], tag: Option<&(String, String)>, nspace: &[String], params: &[String]) -> String {
+        let mut out = format!("class {header}:\n");
+        let doc = docstring(doc, INDENT);
+        let mut body = String::new();
+        for f in fields {
+            body.push_str(&self.gen_field(f, nspace, params));
+        }
+        if let Some((key, value)) = tag {
+            let attr = sanitize(&snake_case(key));
+            let ty = format!("{}[{}]", self.import("typing", "Literal"), py_string(value));
+            if attr == *key {
+                body.push_str(&format!("{INDENT}{attr}: {ty} = {}\n", py_string(value)));
+            } else {
+                let field = self.import("pydantic", "Field");
+                body.push_str(&format!("{INDENT}{attr}: {ty} = {field}(default={}, alias={})\n", py_string(value), py_string(key)));
+            }
+        }
+        out.push_str(&doc);
+        match (doc.is_empty(), body.is_empty()) {
+            (true, true) => out.push_str(&format!("{INDENT}pass\n")),
+            (false, false) => {
+                out.push('\n');
+                out.push_str(&body);
+            }
+            _ => out.push_str(&body),
+        }
+        out
+    }
+
+    fn model_header(&self, name: &str, params: &[String]) -> String {
+        let base = self.import("iozh_support", "IozhModel");
+        if params.is_empty() {
+            format!("{name}({base})")
+        } else {
+            self.typevars(params);
+            format!("{name}({base}, {}{})", self.import("typing", "Generic"), generics(params))
+        }
+    }
+
+    pub fn gen_structure(&self, s: &ast::Structure, nspace: &[String]) -> String {
+        let params = s.name.param_names();
+        let header = self.model_header(&sanitize(&s.name.name), &params);
+        self.gen_model(&header, &s.doc, &s.get_fields(), None, nspace, &params)
+    }
+
+    /// Newtype items are root models, so that they encode as the wrapped
+    /// value; the field is kept as a read-only property.
+    fn gen_wrap(&self, class: &str, doc: &str, field: &str, target: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let ty = self.py_type(target, nspace, params);
+        // Other types may be declared further down, or in a module that is
+        // still being imported, so the base is given as a forward reference.
+        let eager = params.contains(&ty)
+            || Builtin::from_path(target).is_some_and(|b| !matches!(b, Builtin::Option | Builtin::List));
+        let root = if eager { ty.clone() } else { py_string(&ty) };
+        let mut out = format!("class {class}({}[{root}]):\n", self.import("pydantic", "RootModel"));
+        let doc = docstring(doc, INDENT);
+        if !doc.is_empty() {
+            out.push_str(&doc);
+            out.push('\n');
+        }
+        out.push_str(&format!("{INDENT}@property\n"));
+        out.push_str(&format!("{INDENT}def {}(self) -> {ty}:\n", sanitize(&snake_case(field))));
+        out.push_str(&format!("{INDENT}{INDENT}return self.root\n"));
+        out
+    }
+
+    /// Choices of bare and literal items only are enums of their wire
+    /// values. Any other choice is a union of its items: structure items
+    /// are models, newtype items root models, and bare or literal items
+    /// the `Literal` they are encoded as.
+    pub fn gen_choice(&self, c: &ast::Choice, nspace: &[String]) -> Vec<String> {
+        let name = sanitize(&c.name.name);
+        let params = c.name.param_names();
+        let variants = c.variants(self.p);
+        if params.is_empty() && is_enum(&variants) {
+            return vec![self.gen_enum(c, &variants)];
+        }
+        if variants.is_empty() {
+            let header = self.model_header(&name, &params);
+            return vec![self.gen_model(&header, &c.doc, &[], None, nspace, &params)];
+        }
+        self.typevars(&params);
+        let mut decls = vec![];
+        let mut members = vec![];
+        for v in &variants {
+            match v {
+                ChoiceVariant::Unit { wire, .. } => {
+                    members.push(format!("{}[{}]", self.import("typing", "Literal"), py_string(wire)));
+                }
+                ChoiceVariant::Value { value, .. } => {
+                    members.push(format!("{}[{}]", self.import("typing", "Literal"), literal(value)));
+                }
+                ChoiceVariant::Wrap { name: item, doc, field, target } => {
+                    let used = params.iter().filter(|p| target.mentions(p)).cloned().collect::<Vec<_>>();
+                    let class = self.item_class(nspace, &c.name.name, &item.name);
+                    decls.push(self.gen_wrap(&class, doc, field, target, nspace, &params));
+                    members.push(class + &generics(&used));
+                }
+                ChoiceVariant::Record { structure, fields, tag } => {
+                    let used = used_params(&params, fields);
+                    let class = self.item_class(nspace, &c.name.name, &structure.name.name);
+                    let header = self.model_header(&class, &used);
+                    decls.push(self.gen_model(&header, &structure.doc, fields, tag.as_ref(), nspace, &params));
+                    members.push(class + &generics(&used));
+                }
+            }
+        }
+        let field = self.import("pydantic", "Field");
+        let annotation = match variants.first() {
+            Some(ChoiceVariant::Record { tag: Some((key, _)), .. }) if is_tagged(&variants) => {
+                format!("{field}(discriminator={})", py_string(&sanitize(&snake_case(key))))
+            }
+            _ => format!("{field}(union_mode=\"left_to_right\")"),
+        };
+        let value = format!(
+            "{}[{}[{}], {annotation}]",
+            self.import("typing", "Annotated"),
+            self.import("typing", "Union"),
+            members.join(", "),
+        );
+        let mut alias = if params.is_empty() {
+            format!("{name} = {value}\n")
+        } else {
+            let alias_type = self.import("typing_extensions", "TypeAliasType");
+            format!("{name} = {alias_type}(\n{INDENT}{},\n{INDENT}{value},\n{INDENT}type_params=({}{}),\n)\n", py_string(&name), params.join(", "), if params.len() == 1 { "," } else { "" })
+        };
+        alias.push_str(&docstring(&c.doc, ""));
+        decls.push(alias);
+        decls
+    }
+
+    fn gen_enum(&self, c: &ast::Choice, variants: &[ChoiceVariant]) -> String {
+        let enum_base = self.import("enum", "Enum");
+        let strings = variants.iter().all(|v| !matches!(v, ChoiceVariant::Value { value: ast::Literal::Int { .. }, .. }));
+        let ints = variants.iter().all(|v| matches!(v, ChoiceVariant::Value { value: ast::Literal::Int { .. }, .. }));
+        let base = match (strings, ints) {
+            (true, _) => format!("str, {enum_base}"),
+            (_, true) => format!("int, {enum_base}"),
+            _ => enum_base,
+        };
+        let mut out = format!("class {}({base}):\n", sanitize(&c.name.name));
+        let doc = docstring(&c.doc, INDENT);
+        if !doc.is_empty() {
+            out.push_str(&doc);
+            out.push('\n');
+        }
+        for v in variants {
+            let (doc, value) = match v {
+                ChoiceVariant::Unit { doc, wire, .. } => (doc, py_string(wire)),
+                ChoiceVariant::Value { doc, value, .. } => (doc, literal(value)),
+                _ => continue,
+            };
+            out.push_str(&format!("{INDENT}{} = {value}\n", member_name(&v.name().name)));
+            out.push_str(&docstring(doc, INDENT));
+        }
+        out
+    }
+
+    /// Services are protocols of coroutines, streams async iterators.
+    pub fn gen_service(&self, s: &ast::Service, nspace: &[String]) -> String {
+        let params = s.name.param_names();
+        let protocol = self.import("typing", "Protocol");
+        self.typevars(&params);
+        let mut out = format!("class {}({protocol}{}):\n", sanitize(&s.name.name), generics(&params));
+        let doc = docstring(&s.doc, INDENT);
+        if !doc.is_empty() {
+            out.push_str(&doc);
+            if !s.methods.is_empty() {
+                out.push('\n');
+            }
+        }
+        if doc.is_empty() && s.methods.is_empty() {
+            out.push_str(&format!("{INDENT}pass\n"));
+        }
+        for (i, m) in s.methods.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let mut mparams = params.clone();
+            let own = m.name.param_names();
+            self.typevars(&own);
+            mparams.extend(own);
+            let mut args = vec!["self".to_string()];
+            args.extend(m.args.iter().map(|a| {
+                let ty = self.py_type(&a.type_path, nspace, &mparams);
+                let ty = match m.is_stream_arg(&a.name) {
+                    true => format!("{}[{ty}]", self.import("collections.abc", "AsyncIterable")),
+                    false => ty,
+                };
+                format!("{}: {ty}", sanitize(&snake_case(&a.name)))
+            }));
+            // A streamed result is an async generator, which is called
+            // without awaiting.
+            let result = self.py_type(&m.result, nspace, &mparams);
+            let (def, result) = match m.stream_result {
+                true => ("def", format!("{}[{result}]", self.import("collections.abc", "AsyncIterator"))),
+                false => ("async def", result),
+            };
+            let signature = format!(
+                "{INDENT}{def} {}({}) -> {result}:",
+                sanitize(&snake_case(&m.name.name)),
+                args.join(", "),
+            );
+            let doc = docstring(&m.doc, &format!("{INDENT}{INDENT}"));
+            if doc.is_empty() {
+                out.push_str(&format!("{signature} ...\n"));
+            } else {
+                out.push_str(&format!("{signature}\n{doc}{INDENT}{INDENT}...\n"));
+            }
+        }
+        out
+    }
+
+    pub fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<String, IozhError> {
+        *self.imports.borrow_mut() = Imports::default();
+        let mut decls = vec![];
+        for item in &nspace.items {
+            match item {
+                ast::NspaceItem::Structure(idx) => decls.push(self.gen_structure(self.p.get_structure(*idx)?, path)),
+                ast::NspaceItem::Choice(idx) => decls.extend(self.gen_choice(self.p.get_choice(*idx)?, path)),
+                ast::NspaceItem::Service(s) => decls.push(self.gen_service(s, path)),
+                ast::NspaceItem::HttpService(_) | ast::NspaceItem::Storage(_) | ast::NspaceItem::Events(_) | ast::NspaceItem::Nspace(_) => {}
+            }
+        }
+        let mut out = String::from(HEADER);
+        if decls.is_empty() {
+            return Ok(out);
+        }
+        out.push_str("from __future__ import annotations\n");
+        let imports = self.imports.borrow();
+        let stdlib = imports.from.iter().filter(|(m, _)| STDLIB.contains(&m.as_str())).collect::<Vec<_>>();
+        let third_party = imports.from.iter().filter(|(m, _)| m.as_str() == "pydantic").collect::<Vec<_>>();
+        let local = imports.from.iter().filter(|(m, _)| m.as_str() == "iozh_support").collect::<Vec<_>>();
+        for group in [stdlib, third_party, local] {
+            if group.is_empty() {
+                continue;
+            }
+            out.push('\n');
+            for (m, names) in group {
+                out.push_str(&format!("from {m} import {}\n", names.iter().cloned().collect::<Vec<_>>().join(", ")));
+            }
+        }
+        for m in &imports.modules {
+            out.push_str(&format!("import {m}\n"));
+        }
+        if !imports.typevars.is_empty() {
+            out.push('\n');
+            for t in &imports.typevars {
+                out.push_str(&format!("{t} = TypeVar({})\n", py_string(t)));
+            }
+        }
+        for decl in decls {
+            out.push_str("\n\n");
+            out.push_str(&decl);
+        }
+        Ok(out)
+    }
+}
+
+/// Writes one package per namespace, `a.b` to `a/b/__init__.py`, plus
+/// `iozh_support.py`. The output folder must be on the import path.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let ctx = PythonContext::new(&project);
+    out.write(&target_folder.join("iozh_support.py"), SUPPORT)?;
+    for (path, nspace) in project.all_nspaces() {
+        let content = ctx.gen_nspace(&path, nspace)?;
+        let dir = path.iter().fold(target_folder.to_path_buf(), |acc, p| acc.join(sanitize(p)));
+        out.write(&dir.join("__init__.py"), &content)?;
+    }
+    Ok(())
+}
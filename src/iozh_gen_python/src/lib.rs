@@ -0,0 +1,2 @@
+pub mod gen;
+mod support;
@@ -0,0 +1,82 @@
+/// Written as `iozh_support.py`: the base model and types the generated
+/// modules rely on to match the circe wire format.
+pub const SUPPORT: &str = r#"# Generated by iozh. Do not edit.
+"""Base model and types shared by the generated modules. Requires pydantic >= 2.11."""
+import re
+from datetime import timedelta
+from typing import Annotated, Any
+
+from pydantic import BaseModel, BeforeValidator, ConfigDict, PlainSerializer, model_serializer
+
+
+class IozhModel(BaseModel):
+    """Model (de)serialized like the circe codecs: JSON names as declared,
+    absent optional fields left out, unknown keys ignored."""
+
+    model_config = ConfigDict(
+        populate_by_name=True,
+        serialize_by_alias=True,
+        use_attribute_docstrings=True,
+        extra="ignore",
+    )
+
+    @model_serializer(mode="wrap")
+    def _drop_nulls(self, handler: Any) -> Any:
+        data = handler(self)
+        if isinstance(data, dict):
+            return {k: v for k, v in data.items() if v is not None}
+        return data
+
+
+_UNITS = [
+    ("day", timedelta(days=1)),
+    ("hour", timedelta(hours=1)),
+    ("minute", timedelta(minutes=1)),
+    ("second", timedelta(seconds=1)),
+    ("millisecond", timedelta(milliseconds=1)),
+    ("microsecond", timedelta(microseconds=1)),
+]
+
+_ALIASES = {
+    "d": "day", "h": "hour",
+    "m": "minute", "min": "minute", "mins": "minute",
+    "s": "second", "sec": "second", "secs": "second",
+    "ms": "millisecond", "milli": "millisecond", "millis": "millisecond",
+    "µs": "microsecond", "micro": "microsecond", "micros": "microsecond",
+    "ns": "nanosecond", "nano": "nanosecond", "nanos": "nanosecond",
+}
+
+
+def format_duration(value: timedelta) -> str:
+    """`scala.concurrent.duration.Duration` as encoded by circe, e.g. `"5 seconds"`."""
+    micros = value // timedelta(microseconds=1)
+    for name, unit in _UNITS:
+        step = unit // timedelta(microseconds=1)
+        if micros % step == 0:
+            length = micros // step
+            return f"{length} {name}" + ("" if length == 1 else "s")
+    raise ValueError(f"Invalid duration: {value}")
+
+
+def parse_duration(value: Any) -> Any:
+    if not isinstance(value, str):
+        return value
+    match = re.fullmatch(r"\s*(-?\d+)\s*(\S*)\s*", value)
+    if match is None:
+        raise ValueError(f"Invalid duration: {value}")
+    length, unit = int(match.group(1)), match.group(2) or "second"
+    unit = _ALIASES.get(unit, unit[:-1] if unit.endswith("s") else unit)
+    units = dict(_UNITS)
+    if unit == "nanosecond":
+        return timedelta(microseconds=length / 1000)
+    if unit not in units:
+        raise ValueError(f"Unknown duration unit: {match.group(2)}")
+    return units[unit] * length
+
+
+ScalaDuration = Annotated[
+    timedelta,
+    BeforeValidator(parse_duration),
+    PlainSerializer(format_duration, return_type=str, when_used="json"),
+]
+"#;
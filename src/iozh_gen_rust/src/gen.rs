@@ -0,0 +1,514 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use crate::support::SUPPORT;
+
+const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+const DERIVES: &str = "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]";
+const SUPPORT_MODULE: &str = "iozh_support";
+
+#[derive(Debug, Clone, Default)]
+pub struct RustOptions {
+    /// Service methods return `impl Future` instead of plain values.
+    pub async_services: bool,
+}
+
+pub struct RustContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    pub options: RustOptions,
+    /// Declarations each declaration holds inline, see `is_recursive`.
+    edges: RefCell<HashMap<(ModPath, String), Vec<TypeEntry>>>,
+}
+
+/// Rust module a namespace or choice is generated into, as seen from the
+/// root of the generated tree.
+type ModPath = Vec<String>;
+
+const KEYWORDS: [&str; 50] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override",
+    "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+    "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while",
+];
+
+/// Escapes keywords; the few that cannot be raw identifiers get an underscore.
+pub fn sanitize(name: &str) -> String {
+    match name {
+        "crate" | "self" | "Self" | "super" => format!("{name}_"),
+        n if KEYWORDS.contains(&n) || n == "yield" => format!("r#{n}"),
+        n => n.to_string(),
+    }
+}
+
+pub fn pascal_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn mod_name(name: &str) -> String {
+    sanitize(&snake_case(name))
+}
+
+fn doc_lines(doc: &str, indent: &str) -> String {
+    doc_text(doc)
+        .lines()
+        .map(|l| if l.is_empty() { format!("{indent}///\n") } else { format!("{indent}/// {l}\n") })
+        .collect()
+}
+
+fn generics(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", params.join(", "))
+    }
+}
+
+fn bounded_generics(params: &[String], bound: &str) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        let params = params.iter().map(|p| format!("{p}: {bound}")).collect::<Vec<_>>();
+        format!("<{}>", params.join(", "))
+    }
+}
+
+/// Path of `to` relative to module `from`.
+fn relative(from: &[String], to: &[String], name: &str) -> String {
+    let common = from.iter().zip(to.iter()).take_while(|(a, b)| a == b).count();
+    let mut segments = vec!["super".to_string(); from.len() - common];
+    segments.extend(to[common..].iter().cloned());
+    segments.push(name.to_string());
+    segments.join("::")
+}
+
+struct Field {
+    ident: String,
+    wire: String,
+    doc: String,
+    ty: String,
+    optional: bool,
+    list: bool,
+}
+
+impl <'a> RustContext<'a> {
+    pub fn new(p: &'a ast::Project, options: RustOptions) -> RustContext<'a> {
+        RustContext {
+            p,
+            index: TypeIndex::build(p),
+            options,
+            edges: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Module and type name a declaration is generated as.
+    fn declared_at(entry: &TypeEntry) -> (ModPath, String) {
+        let mut module = entry.path.iter().map(|n| mod_name(n)).collect::<Vec<_>>();
+        match entry.name.split_once('.') {
+            Some((choice, item)) => {
+                module.push(mod_name(choice));
+                (module, item.to_string())
+            }
+            None => (module, entry.name.clone()),
+        }
+    }
+
+    fn type_arg(&self, tp: &ast::TypePath, n: usize, nspace: &[String], from: &[String], params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.get(n)) {
+            Some(arg) => self.rust_type(arg, nspace, from, params),
+            None => "()".to_string(),
+        }
+    }
+
+    /// Rust type of `tp` as resolved from namespace `nspace`, referenced
+    /// from module `from`.
+    pub fn rust_type(&self, tp: &ast::TypePath, nspace: &[String], from: &[String], params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "()".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return tag.name.clone();
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 => "i32".to_string(),
+                Builtin::I64 => "i64".to_string(),
+                Builtin::F32 => "f32".to_string(),
+                Builtin::F64 => "f64".to_string(),
+                Builtin::Bool => "bool".to_string(),
+                Builtin::String => "String".to_string(),
+                Builtin::DateTime => "chrono::DateTime<chrono::Utc>".to_string(),
+                Builtin::Duration => relative(from, &[], &format!("{SUPPORT_MODULE}::ScalaDuration")),
+                Builtin::File => "std::path::PathBuf".to_string(),
+                Builtin::Option => format!("Option<{}>", self.type_arg(tp, 0, nspace, from, params)),
+                Builtin::List => format!("Vec<{}>", self.type_arg(tp, 0, nspace, from, params)),
+            };
+        }
+        if tp.path.len() == 1 && tag.name == "Unit" {
+            return "()".to_string();
+        }
+        let args = tag.args.iter().map(|a| self.rust_type(a, nspace, from, params)).collect::<Vec<_>>();
+        match self.index.resolve_path(tp, nspace) {
+            Some(entry) => {
+                let (module, name) = Self::declared_at(entry);
+                relative(from, &module, &name) + &generics(&args)
+            }
+            None => tp.path.iter().map(|t| t.name.clone()).collect::<Vec<_>>().join("::") + &generics(&args),
+        }
+    }
+
+    /// Declarations a value of type `tp` holds inline, i.e. not behind a `Vec`.
+    fn value_refs(&self, tp: &ast::TypePath, nspace: &[String], acc: &mut Vec<TypeEntry>) {
+        let args = tp.last().map(|t| t.args.as_slice()).unwrap_or(&[]);
+        match Builtin::from_path(tp) {
+            Some(Builtin::Option) => args.iter().for_each(|a| self.value_refs(a, nspace, acc)),
+            Some(_) => {}
+            None => {
+                if let Some(entry) = self.index.resolve_path(tp, nspace) {
+                    acc.push(entry.clone());
+                }
+                args.iter().for_each(|a| self.value_refs(a, nspace, acc));
+            }
+        }
+    }
+
+    fn value_edges(&self, entry: &TypeEntry) -> Vec<TypeEntry> {
+        let mut acc = vec![];
+        match entry.def {
+            TypeDef::Structure(idx) => {
+                if let Ok(s) = self.p.get_structure(idx) {
+                    s.get_fields().iter().for_each(|f| self.value_refs(&f.type_path, &entry.path, &mut acc));
+                }
+            }
+            TypeDef::Variant { choice, structure } => {
+                if let (Ok(c), Ok(s)) = (self.p.get_choice(choice), self.p.get_structure(structure)) {
+                    s.get_fields().into_iter()
+                        .chain(c.fields.iter())
+                        .for_each(|f| self.value_refs(&f.type_path, &entry.path, &mut acc));
+                }
+            }
+            TypeDef::Choice(idx) => {
+                if let Ok(c) = self.p.get_choice(idx) {
+                    for v in c.variants(self.p) {
+                        match v {
+                            ChoiceVariant::Wrap { target, .. } => self.value_refs(target, &entry.path, &mut acc),
+                            ChoiceVariant::Record { structure, .. } => {
+                                let name = format!("{}.{}", c.name.name, structure.name.name);
+                                acc.extend(self.index.resolve(&name, &entry.path).cloned());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        acc
+    }
+
+    /// Whether a field holds one of the `enclosing` declarations inline,
+    /// directly or through other declarations, which needs a `Box` to give
+    /// the type a finite size.
+    fn is_recursive(&self, tp: &ast::TypePath, nspace: &[String], enclosing: &[(ModPath, String)]) -> bool {
+        let mut pending = vec![];
+        self.value_refs(tp, nspace, &mut pending);
+        let mut visited = HashSet::new();
+        while let Some(entry) = pending.pop() {
+            let key = Self::declared_at(&entry);
+            if enclosing.contains(&key) {
+                return true;
+            }
+            if visited.insert(key.clone()) {
+                let edges = self.edges.borrow().get(&key).cloned();
+                let edges = edges.unwrap_or_else(|| {
+                    let edges = self.value_edges(&entry);
+                    self.edges.borrow_mut().insert(key, edges.clone());
+                    edges
+                });
+                pending.extend(edges);
+            }
+        }
+        false
+    }
+
+    fn field(&self, f: &ast::Field, nspace: &[String], from: &[String], params: &[String], enclosing: &[(ModPath, String)]) -> Field {
+        let builtin = Builtin::from_path(&f.type_path);
+        let optional = builtin == Some(Builtin::Option);
+        let mut ty = self.rust_type(&f.type_path, nspace, from, params);
+        if self.is_recursive(&f.type_path, nspace, enclosing) {
+            ty = match optional {
+                true => format!("Option<Box<{}>>", self.type_arg(&f.type_path, 0, nspace, from, params)),
+                false => format!("Box<{ty}>"),
+            };
+        }
+        Field {
+            ident: sanitize(&snake_case(&f.name)),
+            wire: f.name.clone(),
+            doc: f.doc.clone(),
+            ty,
+            optional,
+            list: builtin == Some(Builtin::List),
+        }
+    }
+
+    fn gen_struct(&self, out: &mut String, indent: &str, doc: &str, name: &str, params: &[String], fields: &[Field]) {
+        out.push_str(&doc_lines(doc, indent));
+        out.push_str(&format!("{indent}{DERIVES}\n"));
+        if fields.is_empty() {
+            out.push_str(&format!("{indent}pub struct {name}{} {{}}\n", generics(params)));
+            return;
+        }
+        out.push_str(&format!("{indent}pub struct {name}{} {{\n", generics(params)));
+        for f in fields {
+            out.push_str(&doc_lines(&f.doc, &format!("{indent}    ")));
+            let mut attrs = vec![];
+            if f.ident.trim_start_matches("r#") != f.wire {
+                attrs.push(format!("rename = {:?}", f.wire));
+            }
+            if f.optional {
+                attrs.push("skip_serializing_if = \"Option::is_none\"".to_string());
+            } else if f.list {
+                attrs.push("default = \"Vec::new\"".to_string());
+            }
+            if !attrs.is_empty() {
+                out.push_str(&format!("{indent}    #[serde({})]\n", attrs.join(", ")));
+            }
+            out.push_str(&format!("{indent}    pub {}: {},\n", f.ident, f.ty));
+        }
+        out.push_str(&format!("{indent}}}\n"));
+    }
+
+    pub fn gen_structure(&self, out: &mut String, s: &ast::Structure, nspace: &[String]) {
+        let module = nspace.iter().map(|n| mod_name(n)).collect::<Vec<_>>();
+        let params = s.name.param_names();
+        let enclosing = [(module.clone(), s.name.name.clone())];
+        let fields = s.get_fields()
+            .into_iter()
+            .map(|f| self.field(f, nspace, &module, &params, &enclosing))
+            .collect::<Vec<_>>();
+        self.gen_struct(out, "", &s.doc, &s.name.name, &params, &fields);
+    }
+
+    /// Choices whose items are all tagged structures use serde's internal
+    /// tagging to decode, but are encoded by hand: circe appends the tag
+    /// after the fields, serde would put it first. Any other choice is an
+    /// enum whose bare and string items are plain unit variants and whose
+    /// remaining items are `untagged`, tried in order like circe does.
+    pub fn gen_choice(&self, out: &mut String, c: &ast::Choice, nspace: &[String]) {
+        let module = nspace.iter().map(|n| mod_name(n)).collect::<Vec<_>>();
+        let mut variant_module = module.clone();
+        variant_module.push(mod_name(&c.name.name));
+        let params = c.name.param_names();
+        let name = &c.name.name;
+        let variants = c.variants(self.p);
+        let tagged = !variants.is_empty() && variants.iter().all(|v| matches!(v, ChoiceVariant::Record { tag: Some(_), .. }));
+
+        // Structure items live in a module named after the choice, like the
+        // companion object on the Scala side.
+        let mut records = String::new();
+        for v in &variants {
+            if let ChoiceVariant::Record { structure, fields, .. } = v {
+                let vparams = used_params(&params, fields);
+                let enclosing = [(variant_module.clone(), structure.name.name.clone()), (module.clone(), name.clone())];
+                let fields = fields.iter()
+                    .map(|f| self.field(f, nspace, &variant_module, &vparams, &enclosing))
+                    .collect::<Vec<_>>();
+                records.push('\n');
+                self.gen_struct(&mut records, "    ", &structure.doc, &structure.name.name, &vparams, &fields);
+            }
+        }
+        let record_type = |s: &ast::Structure, fields: &[&ast::Field]| {
+            format!("{}::{}{}", mod_name(name), s.name.name, generics(&used_params(&params, fields)))
+        };
+
+        out.push_str(&doc_lines(&c.doc, ""));
+        if tagged {
+            out.push_str("#[derive(Debug, Clone, PartialEq, Deserialize)]\n");
+        } else {
+            out.push_str(&format!("{DERIVES}\n"));
+        }
+        if let (true, Some(ChoiceVariant::Record { tag: Some((key, _)), .. })) = (tagged, variants.first()) {
+            out.push_str(&format!("#[serde(tag = {key:?})]\n"));
+        }
+        out.push_str(&format!("pub enum {name}{} {{\n", generics(&params)));
+        let (plain, untagged): (Vec<_>, Vec<_>) = variants.iter().partition(|v| match v {
+            ChoiceVariant::Unit { .. } => true,
+            ChoiceVariant::Value { value, .. } => !matches!(value, ast::Literal::Int { .. }),
+            ChoiceVariant::Record { .. } => tagged,
+            ChoiceVariant::Wrap { .. } => false,
+        });
+        // serde wants untagged variants last.
+        for v in plain.iter().chain(untagged.iter()) {
+            out.push_str(&doc_lines(match v {
+                ChoiceVariant::Unit { doc, .. } => doc,
+                ChoiceVariant::Value { doc, .. } => doc,
+                ChoiceVariant::Wrap { doc, .. } => doc,
+                ChoiceVariant::Record { structure, .. } => &structure.doc,
+            }, "    "));
+            let variant = pascal_case(&v.name().name);
+            match v {
+                ChoiceVariant::Unit { wire, .. } => {
+                    out.push_str(&format!("    #[serde(rename = {wire:?})]\n    {variant},\n"));
+                }
+                ChoiceVariant::Value { value: ast::Literal::Int { value, .. }, .. } => {
+                    let support = relative(&module, &[], SUPPORT_MODULE);
+                    out.push_str(&format!("    #[serde(untagged)]\n    {variant}({support}::IntLiteral<{value}>),\n"));
+                }
+                ChoiceVariant::Value { value, .. } => {
+                    out.push_str(&format!("    #[serde(rename = {:?})]\n    {variant},\n", value.unquoted()));
+                }
+                ChoiceVariant::Wrap { target, .. } => {
+                    let enclosing = [(module.clone(), name.clone())];
+                    let target_field = ast::Field {
+                        pos: ast::Pos::default(),
+                        doc: String::new(),
+                        name: String::new(),
+                        type_path: (*target).clone(),
+                    };
+                    let ty = self.field(&target_field, nspace, &module, &params, &enclosing).ty;
+                    out.push_str(&format!("    #[serde(untagged)]\n    {variant}({ty}),\n"));
+                }
+                ChoiceVariant::Record { structure, fields, tag } => {
+                    match tag {
+                        Some((_, value)) if tagged => out.push_str(&format!("    #[serde(rename = {value:?})]\n")),
+                        _ => out.push_str("    #[serde(untagged)]\n"),
+                    }
+                    out.push_str(&format!("    {variant}({}),\n", record_type(structure, fields)));
+                }
+            }
+        }
+        out.push_str("}\n");
+
+        if tagged {
+            self.gen_tagged_serialize(out, c, &variants, &params);
+        }
+        if !records.is_empty() {
+            out.push_str(&format!("\npub mod {} {{\n    use serde::{{Deserialize, Serialize}};\n", mod_name(name)));
+            out.push_str(&records);
+            out.push_str("}\n");
+        }
+    }
+
+    fn gen_tagged_serialize(&self, out: &mut String, c: &ast::Choice, variants: &[ChoiceVariant], params: &[String]) {
+        let name = &c.name.name;
+        out.push_str(&format!(
+            "\nimpl{} Serialize for {name}{} {{\n",
+            bounded_generics(params, "Serialize"),
+            generics(params),
+        ));
+        out.push_str("    fn serialize<S: serde::Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {\n");
+        out.push_str("        use serde::ser::SerializeMap;\n");
+        out.push_str("        let mut map = serializer.serialize_map(None)?;\n");
+        out.push_str("        match self {\n");
+        for v in variants {
+            if let ChoiceVariant::Record { structure, fields, tag: Some((key, value)) } = v {
+                out.push_str(&format!("            {name}::{}(x) => {{\n", pascal_case(&structure.name.name)));
+                for f in fields {
+                    let ident = sanitize(&snake_case(&f.name));
+                    if Builtin::from_path(&f.type_path) == Some(Builtin::Option) {
+                        out.push_str(&format!(
+                            "                if let Some(v) = &x.{ident} {{\n                    map.serialize_entry({:?}, v)?;\n                }}\n",
+                            f.name,
+                        ));
+                    } else {
+                        out.push_str(&format!("                map.serialize_entry({:?}, &x.{ident})?;\n", f.name));
+                    }
+                }
+                out.push_str(&format!("                map.serialize_entry({key:?}, {value:?})?;\n"));
+                out.push_str("            }\n");
+            }
+        }
+        out.push_str("        }\n        map.end()\n    }\n}\n");
+    }
+
+    pub fn gen_service(&self, out: &mut String, s: &ast::Service, nspace: &[String]) {
+        let module = nspace.iter().map(|n| mod_name(n)).collect::<Vec<_>>();
+        let params = s.name.param_names();
+        out.push_str(&doc_lines(&s.doc, ""));
+        out.push_str(&format!("pub trait {}{} {{\n", s.name.name, generics(&params)));
+        for (i, m) in s.methods.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let mut mparams = params.clone();
+            let own = m.name.param_names();
+            mparams.extend(own.iter().cloned());
+            let args = m.args
+                .iter()
+                .map(|a| format!(", {}: {}", sanitize(&snake_case(&a.name)), self.rust_type(&a.type_path, nspace, &module, &mparams)))
+                .collect::<String>();
+            let result = self.rust_type(&m.result, nspace, &module, &mparams);
+            let result = match self.options.async_services {
+                true => format!("impl std::future::Future<Output = {result}> + Send"),
+                false => result,
+            };
+            out.push_str(&doc_lines(&m.doc, "    "));
+            out.push_str(&format!(
+                "    fn {}{}(&self{args}) -> {result};\n",
+                sanitize(&snake_case(&m.name.name)),
+                generics(&own),
+            ));
+        }
+        out.push_str("}\n");
+    }
+
+    /// Contents of the `mod.rs` of a namespace.
+    pub fn gen_nspace(&self, nspace: &ast::Nspace, path: &[String]) -> Result<String, IozhError> {
+        let mut out = String::from(HEADER);
+        out.push_str("#[allow(unused_imports)]\nuse serde::{Deserialize, Serialize};\n");
+        for item in &nspace.items {
+            if let ast::NspaceItem::Nspace(n) = item {
+                out.push_str(&format!("\npub mod {};", mod_name(&n.name)));
+            }
+        }
+        if nspace.items.iter().any(|i| matches!(i, ast::NspaceItem::Nspace(_))) {
+            out.push('\n');
+        }
+        for item in &nspace.items {
+            let mut decl = String::new();
+            match item {
+                ast::NspaceItem::Structure(idx) => self.gen_structure(&mut decl, self.p.get_structure(*idx)?, path),
+                ast::NspaceItem::Choice(idx) => self.gen_choice(&mut decl, self.p.get_choice(*idx)?, path),
+                ast::NspaceItem::Service(s) => self.gen_service(&mut decl, s, path),
+                ast::NspaceItem::HttpService(_) | ast::NspaceItem::Storage(_) | ast::NspaceItem::Events(_) | ast::NspaceItem::Nspace(_) => {}
+            }
+            if !decl.is_empty() {
+                out.push('\n');
+                out.push_str(&decl);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Writes one `mod.rs` per namespace under `target_folder`, plus a root
+/// `mod.rs` to mount with `mod <folder>;` and the `iozh_support` helpers.
+/// The generated code needs `serde` and, for `DateTime`, `chrono` with its
+/// `serde` feature.
+pub fn generate_with(project: ast::Project, target_folder: &Path, out: &mut dyn Output, options: RustOptions) -> Result<(), IozhError> {
+    let ctx = RustContext::new(&project, options);
+    let mut root = format!("{HEADER}pub mod {SUPPORT_MODULE};\n");
+    for nspace in &project.nspaces {
+        root.push_str(&format!("pub mod {};\n", mod_name(&nspace.name)));
+    }
+    out.write(&target_folder.join("mod.rs"), &root)?;
+    out.write(&target_folder.join(format!("{SUPPORT_MODULE}.rs")), SUPPORT)?;
+    for (path, nspace) in project.all_nspaces() {
+        let dir = path.iter().fold(target_folder.to_path_buf(), |acc, n| acc.join(mod_name(n).trim_start_matches("r#")));
+        out.write(&dir.join("mod.rs"), &ctx.gen_nspace(nspace, &path)?)?;
+    }
+    Ok(())
+}
+
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    generate_with(project, target_folder, out, RustOptions::default())
+}
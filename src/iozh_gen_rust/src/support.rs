@@ -0,0 +1,75 @@
+/// Written next to the generated modules as `iozh_support.rs`: serde
+/// helpers for the parts of the circe wire format serde has no notion of.
+pub const SUPPORT: &str = r#"//! Generated by iozh. Do not edit.
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+/// `scala.concurrent.duration.Duration` as encoded by circe: its
+/// `toString`, e.g. `"5 seconds"` or `"1 minute"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ScalaDuration(pub std::time::Duration);
+
+const UNITS: [(&str, u128); 7] = [
+    ("day", 86_400_000_000_000),
+    ("hour", 3_600_000_000_000),
+    ("minute", 60_000_000_000),
+    ("second", 1_000_000_000),
+    ("millisecond", 1_000_000),
+    ("microsecond", 1_000),
+    ("nanosecond", 1),
+];
+
+impl Serialize for ScalaDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+        let nanos = self.0.as_nanos();
+        let (unit, size) = UNITS.iter().copied().find(|(_, size)| nanos % size == 0).unwrap_or(UNITS[6]);
+        let length = nanos / size;
+        let plural = if length == 1 { "" } else { "s" };
+        serializer.serialize_str(&format!("{length} {unit}{plural}"))
+    }
+}
+
+impl<'de> Deserialize<'de> for ScalaDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::core::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let length: u128 = s[..split].parse().map_err(D::Error::custom)?;
+        let unit = s[split..].trim();
+        let size = match unit {
+            "d" | "day" | "days" => UNITS[0].1,
+            "h" | "hour" | "hours" => UNITS[1].1,
+            "m" | "min" | "mins" | "minute" | "minutes" => UNITS[2].1,
+            "" | "s" | "sec" | "secs" | "second" | "seconds" => UNITS[3].1,
+            "ms" | "milli" | "millis" | "millisecond" | "milliseconds" => UNITS[4].1,
+            "µs" | "micro" | "micros" | "microsecond" | "microseconds" => UNITS[5].1,
+            "ns" | "nano" | "nanos" | "nanosecond" | "nanoseconds" => UNITS[6].1,
+            other => return Err(D::Error::custom(format!("unknown duration unit: {other}"))),
+        };
+        let nanos = length * size;
+        Ok(ScalaDuration(std::time::Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)))
+    }
+}
+
+/// Choice item bound to an integer literal: encoded as the literal and
+/// accepted only when the literal matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct IntLiteral<const N: i64>;
+
+impl<const N: i64> Serialize for IntLiteral<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+        serializer.serialize_i64(N)
+    }
+}
+
+impl<'de, const N: i64> Deserialize<'de> for IntLiteral<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::core::result::Result<Self, D::Error> {
+        let v = i64::deserialize(deserializer)?;
+        if v == N {
+            Ok(IntLiteral)
+        } else {
+            Err(D::Error::custom(format!("expected {N} but got {v}")))
+        }
+    }
+}
+"#;
@@ -7,6 +7,7 @@ use crate::gen::*;
 #[derive(Debug)]
 pub struct ProjectContext<'a> {
     pub p: &'a ast::Project,
+    pub options: Scala2Options,
 }
 
 #[derive(Debug)]
@@ -36,15 +37,20 @@ pub struct ServiceContext<'a> {
     pub nspace: &'a NspaceContext<'a>,
     pub base_name: String,
     pub full_type_name: String,
+    /// Methods return `F[...]`, see `ProjectContext::is_effectful`.
+    pub effectful: bool,
 }
 
 pub struct HttpServiceContext<'a> {
     pub nspace: &'a NspaceContext<'a>,
     pub base_name: String,
     pub full_type_name: String,
+    /// Services the routes call, by namespace and name.
+    pub services: Vec<(Vec<String>, String)>,
 }
 
 pub struct MethodContext {
+    #[allow(dead_code)]
     pub name: String,
 }
 
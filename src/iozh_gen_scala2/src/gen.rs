@@ -1,14 +1,81 @@
-use std::fs;
-use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
-use std::collections::HashMap;
 use itertools::Itertools;
 use iozh_parse::ast;
 use iozh_parse::error::IozhError;
+use iozh_output::Output;
 use crate::utils::*;
 use crate::context::*;
 use crate::gen_circe::*;
+use crate::gen_http4s::*;
+use crate::gen_jsonrpc::*;
+
+/// How service methods declaring an error (`=> A ! E`) surface it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorStyle {
+    /// `F[Either[E, A]]`.
+    #[default]
+    Either,
+    /// `F[A]`, failing through an implicit `MonadError[F, E]`.
+    MonadError,
+}
+
+/// Effect the methods of service traits return in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Effect {
+    /// Plain values; traits with methods declaring an error take an `F[_]`.
+    #[default]
+    Plain,
+    /// `trait UserService[F[_]]`, with `mapK` in the companion.
+    Tagless,
+    /// `ZIO[Any, E, A]`, `E` being `Throwable` for methods without an error.
+    Zio,
+    /// `Future[A]`, or `Future[Either[E, A]]` for methods with an error.
+    Future,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scala2Options {
+    pub effect: Effect,
+    /// Applies to traits parameterized by `F[_]`.
+    pub errors: ErrorStyle,
+    /// A JSON-RPC 2.0 server and client next to each service.
+    pub json_rpc: bool,
+}
+
+/// Settings given on the command line, taking precedence over the
+/// `scala2.*` options of the project.
+#[derive(Debug, Clone, Default)]
+pub struct Scala2Overrides {
+    pub effect: Option<Effect>,
+    pub errors: Option<ErrorStyle>,
+    pub json_rpc: bool,
+}
+
+impl Scala2Options {
+    /// Options of the project, `scala2.effect = plain | tagless | zio | future`
+    /// and `scala2.errors = either | monad-error`, then the overrides.
+    pub fn read(project: &ast::Project, overrides: &Scala2Overrides) -> Result<Scala2Options, IozhError> {
+        let mut options = Scala2Options { json_rpc: overrides.json_rpc, ..Scala2Options::default() };
+        for o in project.options.iter().filter(|o| o.key.starts_with("scala2.")) {
+            let error = |msg: String| Err(IozhError { pos: o.pos.clone(), msg });
+            match (o.key.as_str(), o.value.as_str()) {
+                ("scala2.effect", "plain") => options.effect = Effect::Plain,
+                ("scala2.effect", "tagless") => options.effect = Effect::Tagless,
+                ("scala2.effect", "zio") => options.effect = Effect::Zio,
+                ("scala2.effect", "future") => options.effect = Effect::Future,
+                ("scala2.effect", v) => return error(format!("scala2.effect is plain, tagless, zio or future, not {v}")),
+                ("scala2.errors", "either") => options.errors = ErrorStyle::Either,
+                ("scala2.errors", "monad-error") => options.errors = ErrorStyle::MonadError,
+                ("scala2.errors", v) => return error(format!("scala2.errors is either or monad-error, not {v}")),
+                (k, _) => return error(format!("Unknown option {k}")),
+            }
+        }
+        options.effect = overrides.effect.unwrap_or(options.effect);
+        options.errors = overrides.errors.unwrap_or(options.errors);
+        Ok(options)
+    }
+}
 
 #[derive(Debug)]
 pub struct GenResult {
@@ -27,7 +94,7 @@ impl GenResult {
         Ok(vec![
             GenResult {
                 unit: None,
-                content: content,
+                content,
                 imports: vec![],
                 package: vec![],
                 block: None,
@@ -43,46 +110,46 @@ pub trait FileWriter {
     fn ln(& mut self) -> std::result::Result<(), IozhError>;
 }
 
-impl FileWriter for std::fs::File {
+impl FileWriter for String {
     fn put(& mut self, content: &str) -> std::result::Result<(), IozhError> {
-        self.write_all(content.as_bytes())
-            .map_err(|e| IozhError {
-                pos: ast::Pos { line: 0, col: 0},
-                msg: format!("Failed to write file: {}", e),
-            })
+        self.push_str(content);
+        Ok(())
     }
     fn ln(& mut self) -> std::result::Result<(), IozhError> {
-        self.write_all("\n".as_bytes()).map_err(|e| IozhError {
-            pos: ast::Pos { line: 0, col: 0},
-            msg: format!("Failed to write file: {}", e),
-        })
+        self.push('\n');
+        Ok(())
     }
     fn putln(& mut self, content: &str) -> std::result::Result<(), IozhError> {
-        self.put(&content)?;
+        self.put(content)?;
         self.ln()
     }
     fn putlnln(& mut self, content: &str) -> std::result::Result<(), IozhError> {
-        self.put(&content)?;
+        self.put(content)?;
         self.ln()?;
         self.ln()
     }
 }
 
+/// Merges the items of each file, keyed by package, unit and block, in
+/// the order they are generated: files come in source order and their
+/// declarations too, the imports sorted, so that regenerating gives the
+/// same output.
 pub fn group(items: Vec<GenResult>) -> Vec<GenResult> {
-    let mut m = HashMap::<String, GenResult>::new();
+    let mut groups = Vec::<GenResult>::new();
     for mut item in items {
-        let mut key: String = item.unit.clone().unwrap_or_else(|| "".to_string());
-        key.push_str(&item.block.iter().map(|x| x.to_string()).join(""));
-        if let Some(existing) = m.get_mut(&key) {
-            existing.content.push_str("\n");
+        let existing = groups
+            .iter_mut()
+            .find(|g| g.package == item.package && g.unit == item.unit && g.block == item.block);
+        if let Some(existing) = existing {
+            existing.content.push('\n');
             existing.content.push_str(&item.content);
             existing.imports.append(&mut item.imports);
         } else {
-            m.insert(key, item);
+            groups.push(item);
         }
     }
-    m.into_iter()
-        .map(|(_, mut v)| {
+    groups.into_iter()
+        .map(|mut v| {
             let imports = v.imports.clone().into_iter().sorted().unique();
             v.imports.clear();
             v.imports.extend(imports);
@@ -91,7 +158,7 @@ pub fn group(items: Vec<GenResult>) -> Vec<GenResult> {
         .collect::<Vec<_>>()
 }
 
-pub fn write_fs_tree(items: Vec<GenResult>, target_folder: &Path) -> std::result::Result<(), IozhError> {
+pub fn write_fs_tree(items: Vec<GenResult>, target_folder: &Path, out: &mut dyn Output) -> std::result::Result<(), IozhError> {
     let grouped_items = group(items);
     for item in grouped_items {
         let rel_package_path = item.package
@@ -101,11 +168,10 @@ pub fn write_fs_tree(items: Vec<GenResult>, target_folder: &Path) -> std::result
                 acc
             });
         let abs_package_path = target_folder.join(rel_package_path);
-        fs::create_dir_all(&abs_package_path).to_iozh()?;
         if let Some(unit) = &item.unit {
             let file_name = gen_filename(unit);
             let file_path = abs_package_path.as_path().join(file_name);
-            let mut file = fs::File::create(abs_package_path.join(file_path)).to_iozh()?;
+            let mut file = String::new();
             file.putlnln(&format!("package {}", item.package.join(".")))?;
             if !item.imports.is_empty() {
                 for import in &item.imports {
@@ -116,10 +182,11 @@ pub fn write_fs_tree(items: Vec<GenResult>, target_folder: &Path) -> std::result
             if let Some(block) = &item.block {
                 file.putln(&format!("{block} {{"))?;
                 file.putln(&item.content)?;
-                file.putln(&format!("}}"))?;
+                file.putln("}")?;
             } else {
                 file.put(&item.content)?;
             }
+            out.write(&file_path, &file)?;
         }
     }
     Ok(())
@@ -245,7 +312,7 @@ impl <A> GenItems<A> for Vec<A> {
     {
         Ok(self
             .iter()
-            .map(|x| g(x))
+            .map(g)
             .collect::<Result<Vec<Vec<GenResult>>, IozhError>>()
             .map(|vec| vec.into_iter().flatten())?
             .collect::<Vec<_>>())
@@ -259,7 +326,7 @@ impl <A> GenItems<A> for Vec<A> {
         Ok(self
             .iter()
             .filter(|x| f(x))
-            .map(|x| g(x))
+            .map(g)
             .collect::<Result<Vec<Vec<GenResult>>, IozhError>>()
             .map(|vec| vec.into_iter().flatten())?
             .collect::<Vec<_>>())
@@ -276,12 +343,25 @@ impl GenResults for Vec<GenResult> {
         self.iter().map(|x| x.content.clone()).collect::<Vec<_>>()
     }
     fn map_imports(&self) -> Vec<String> {
-        self.iter().map(|x| x.imports.clone()).flatten().collect::<Vec<_>>()
+        self.iter().flat_map(|x| x.imports.clone()).collect::<Vec<_>>()
     }
 }
 
 impl <'a> ProjectContext<'a> {
-    pub fn push_nspace(&self, nspace: &ast::Nspace) -> NspaceContext {
+    /// Whether the trait of a service is parameterized by an effect `F[_]`:
+    /// always for tagless-final, for plain traits as soon as a method
+    /// declares an error, and for all but ZIO ones as soon as a method
+    /// streams, `fs2.Stream` running in `F`.
+    pub fn is_effectful(&self, s: &ast::Service) -> bool {
+        match self.options.effect {
+            Effect::Tagless => true,
+            Effect::Plain => s.methods.iter().any(|m| m.error.is_some() || m.has_streams()),
+            Effect::Future => s.methods.iter().any(|m| m.has_streams()),
+            Effect::Zio => false,
+        }
+    }
+
+    pub fn push_nspace(&self, nspace: &ast::Nspace) -> NspaceContext<'_> {
         let nspace_name = &nspace.name;
         NspaceContext {
             project: self,
@@ -291,16 +371,16 @@ impl <'a> ProjectContext<'a> {
 }
 
 impl <'a> NspaceContext<'a> {
-    pub fn push_nspace(&self, nspace: &ast::Nspace) -> NspaceContext {
+    pub fn push_nspace(&self, nspace: &ast::Nspace) -> NspaceContext<'_> {
         let nspace_name = &nspace.name;
         let mut nspace = self.path.clone();
         nspace.push(nspace_name.to_string());
         NspaceContext {
-            project: self.project.clone(),
+            project: self.project,
             path: nspace,
         }
     }
-    pub fn push_struct(&self, s: &ast::Structure) -> Result<StructContext, IozhError> {
+    pub fn push_struct(&self, s: &ast::Structure) -> Result<StructContext<'_>, IozhError> {
         let base_name = sanitize(&s.name.name);
         let full_type_name = s.name.gen()?.to_string();
         let type_args = gen_type_args(&s.name.args)?;
@@ -311,10 +391,10 @@ impl <'a> NspaceContext<'a> {
             type_args,
         })
     }
-    pub fn push_choice(&'a self, c: &'a ast::Choice) -> Result<ChoiceContext, IozhError> {
+    pub fn push_choice(&'a self, c: &'a ast::Choice) -> Result<ChoiceContext<'a>, IozhError> {
         let base_name = sanitize(&c.name.name);
         let full_type_name = c.name.gen()?.to_string();
-        let tag_opt = c.get_most_common_tag_key(&self.project.p);
+        let tag_opt = c.get_most_common_tag_key(self.project.p);
         Ok(ChoiceContext {
             nspace: self,
             p: c,
@@ -323,33 +403,25 @@ impl <'a> NspaceContext<'a> {
             most_common_tag_key: tag_opt,
         })
     }
-    pub fn push_service(&self, s: &ast::Service) -> Result<ServiceContext, IozhError> {
+    pub fn push_service(&self, s: &ast::Service) -> Result<ServiceContext<'_>, IozhError> {
         let base_name = sanitize(&s.name.name);
         let full_type_name = s.name.gen()?.to_string();
         Ok(ServiceContext {
             nspace: self,
             base_name,
             full_type_name,
-        })
-    }
-    pub fn push_http_service(&self, s: &ast::HttpService) -> Result<HttpServiceContext, IozhError> {
-        let base_name = sanitize(&s.name.name);
-        let full_type_name = s.name.gen()?.to_string();
-        Ok(HttpServiceContext {
-            nspace: self,
-            base_name,
-            full_type_name,
+            effectful: self.project.is_effectful(s),
         })
     }
 }
 
 impl <'a> ChoiceContext<'a> {
-    pub fn push_struct(&self, s: &ast::Structure) -> Result<StructContext, IozhError> {
+    pub fn push_struct(&self, s: &ast::Structure) -> Result<StructContext<'_>, IozhError> {
         let base_name = sanitize(&s.name.name);
         let full_type_name = s.name.gen()?.to_string();
         let type_args = gen_type_args(&s.name.args)?;
         Ok(StructContext {
-            nspace: self.nspace.clone(),
+            nspace: self.nspace,
             base_name,
             full_type_name,
             type_args,
@@ -394,8 +466,8 @@ impl Gen for ast::TypeTag {
     fn gen(&self) -> Result<Vec<GenResult>, IozhError> {
         let args = gen_type_args(&self.args)?.join(",");
         let name = map_type(sanitize(&self.name).as_str()).to_string();
-        if args.len() == 0 {
-            GenResult::single(format!("{}", name))
+        if args.is_empty() {
+            GenResult::single(name.to_string())
         } else {
             GenResult::single(format!("{}[{}]", name, args))
         }
@@ -405,7 +477,7 @@ impl Gen for ast::TypeTag {
 impl Gen for ast::TypePath {
     fn gen(&self) -> Result<Vec<GenResult>, IozhError> {
         let path = self.path.mapg(|x| x.gen())?.map_content().join(".");
-        GenResult::single(format!("{}", path))
+        GenResult::single(path.to_string())
     }
 }
 
@@ -414,7 +486,7 @@ impl InChoice for ast::ChoiceItem {
         match self {
             ast::ChoiceItem::Structure(idx) => {
                 let s = parent.nspace.project.p.get_structure(*idx)?;
-                s.gen_in_choice(&parent)
+                s.gen_in_choice(parent)
             }
             ast::ChoiceItem::TypeTag { doc: _, choice } => {
                 let choice_content = choice.gen()?.map_content().join("\n");
@@ -422,7 +494,7 @@ impl InChoice for ast::ChoiceItem {
             }
             ast::ChoiceItem::Value { doc: _, name, value } => {
                 let name_content = name.gen()?.map_content().join("\n");
-                let value_content = value.gen_in_choice(&parent)?.map_content().join("\n");
+                let value_content = value.gen_in_choice(parent)?.map_content().join("\n");
                 GenResult::single(format!("case object {} extends {} {{\n{}\n}}", name_content, parent.base_name, value_content))
             }
             ast::ChoiceItem::Wrap { doc: _, name, field, target } => {
@@ -431,7 +503,7 @@ impl InChoice for ast::ChoiceItem {
                 let content = format!("case class {nn}({field}: {targetn}) extends {}", parent.base_name);
                 Ok(vec![GenResult {
                     unit: None,
-                    content: content,
+                    content,
                     imports: imports_for(&targetn),
                     package: vec![],
                     block: None,
@@ -462,7 +534,7 @@ impl InNspace for ast::Choice {
             .map(|f| format!("def {f}"))
             .collect::<Vec<_>>()
             .join("\n");
-        let header = if fields.len() > 0 {
+        let header = if !fields.is_empty() {
             format!("sealed trait {} {{ {fields} }}", scope.full_type_name)
         } else {
             format!("sealed trait {}", scope.full_type_name)
@@ -517,7 +589,7 @@ impl InStruct for ast::Field {
 impl InMethod for ast::Field {
     fn gen_in_method(&self, _parent: &MethodContext) -> Result<Vec<GenResult>, IozhError> {
         let tp = self.type_path.gen()?.to_string();
-        GenResult::single(format!("{}: {}", self.name, tp))
+        GenResult::single(format!("{}: {}", sanitize(&self.name), tp))
     }
 }
 
@@ -534,15 +606,12 @@ impl InChoice for ast::Structure {
     fn gen_in_choice(&self, parent: &ChoiceContext) -> Result<Vec<GenResult>, IozhError> {
         let scope = parent.push_struct(self)?;
         let mut result = self.fields
-            .filter_gen(|x| match x {
-                ast::StructItem::Field(_) => true,
-                _ => false,
-            }, |x| x.gen_in_struct(&scope))?;
+            .filter_gen(|x| matches!(x, ast::StructItem::Field(_)), |x| x.gen_in_struct(&scope))?;
         let imports = result.map_imports();
         let mut inherited_fields = parent.p.fields.mapg(|x| x.gen_in_struct(&scope))?;
         result.append(&mut inherited_fields);
         let fields = result.map_content().join(",");
-        let content = if fields.len() > 0 {
+        let content = if !fields.is_empty() {
             format!("case class {}({fields}) extends {}", scope.full_type_name, parent.full_type_name)
         } else {
             format!("case object {} extends {}", scope.full_type_name, parent.full_type_name)
@@ -563,11 +632,7 @@ impl InNspace for ast::Structure {
     fn gen_in_nspace(&self, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
         let scope = parent.push_struct(self)?;
         let result = self.fields
-            .filter_gen(|x|
-                match x {
-                    ast::StructItem::Field(_) => true,
-                    _ => false,
-                },
+            .filter_gen(|x| matches!(x, ast::StructItem::Field(_)),
                 |x| x.gen_in_struct(&scope)
             )?;
         let imports = result.map_imports();
@@ -588,13 +653,137 @@ impl InNspace for ast::Structure {
     }
 }
 
+impl <'a> ServiceContext<'a> {
+    /// Implicit parameters and result type of a method whose trait is
+    /// parameterized by `effect`, with the imports they need.
+    pub(crate) fn method_type(&self, m: &ast::Method, effect: &str) -> Result<(String, String, Vec<String>), IozhError> {
+        let ret = m.result.gen()?.to_string();
+        let mut imports = imports_for(&ret);
+        let error = m.error.as_ref().map(|e| e.gen()).transpose()?.map(|e| e.to_string());
+        if let Some(e) = &error {
+            imports.extend(imports_for(e));
+        }
+        let options = &self.nspace.project.options;
+        if m.stream_result {
+            return Ok(self.stream_type(&ret, error.as_deref(), effect, imports));
+        }
+        let typed = match (options.effect, &error) {
+            (Effect::Zio, e) => {
+                imports.push("zio.ZIO".to_string());
+                (String::new(), format!("ZIO[Any, {}, {ret}]", e.as_deref().unwrap_or("Throwable")))
+            }
+            (Effect::Future, e) => {
+                imports.push("scala.concurrent.Future".to_string());
+                let ret = e.as_ref().map(|e| format!("Either[{e}, {ret}]")).unwrap_or(ret);
+                (String::new(), format!("Future[{ret}]"))
+            }
+            _ if !self.effectful => (String::new(), ret),
+            (_, None) => (String::new(), format!("{effect}[{ret}]")),
+            (_, Some(e)) => match options.errors {
+                ErrorStyle::Either => (String::new(), format!("{effect}[Either[{e}, {ret}]]")),
+                ErrorStyle::MonadError => {
+                    imports.push("cats.MonadError".to_string());
+                    (format!("(implicit F: MonadError[{effect}, {e}])"), format!("{effect}[{ret}]"))
+                }
+            },
+        };
+        Ok((typed.0, typed.1, imports))
+    }
+
+    /// Same as `method_type` for a streamed result: a `ZStream` with ZIO, an
+    /// `fs2.Stream` in `effect` otherwise, its errors as for other methods
+    /// but `Either` with futures.
+    fn stream_type(&self, ret: &str, error: Option<&str>, effect: &str, mut imports: Vec<String>) -> (String, String, Vec<String>) {
+        let options = &self.nspace.project.options;
+        let (implicits, ty) = match (options.effect, error) {
+            (Effect::Zio, e) => (String::new(), format!("ZStream[Any, {}, {ret}]", e.unwrap_or("Throwable"))),
+            (_, None) => (String::new(), format!("Stream[{effect}, {ret}]")),
+            (Effect::Plain | Effect::Tagless, Some(e)) if options.errors == ErrorStyle::MonadError => {
+                imports.push("cats.MonadError".to_string());
+                (format!("(implicit F: MonadError[{effect}, {e}])"), format!("Stream[{effect}, {ret}]"))
+            }
+            (_, Some(e)) => (String::new(), format!("Stream[{effect}, Either[{e}, {ret}]]")),
+        };
+        imports.push(stream_import(options.effect));
+        (implicits, ty, imports)
+    }
+
+    /// Parameters of a method, streamed arguments as a `ZStream` with ZIO
+    /// and an `fs2.Stream` in `effect` otherwise.
+    pub(crate) fn method_args(&self, m: &ast::Method, effect: &str) -> Result<(String, Vec<String>), IozhError> {
+        let scope = self.push_method(m);
+        let mut args = vec![];
+        let mut imports = vec![];
+        for a in &m.args {
+            if m.is_stream_arg(&a.name) {
+                let ty = a.type_path.gen()?.to_string();
+                imports.extend(imports_for(&ty));
+                imports.push(stream_import(self.nspace.project.options.effect));
+                args.push(match self.nspace.project.options.effect {
+                    Effect::Zio => format!("{}: ZStream[Any, Throwable, {ty}]", sanitize(&a.name)),
+                    _ => format!("{}: Stream[{effect}, {ty}]", sanitize(&a.name)),
+                });
+            } else {
+                args.extend(a.gen_in_method(&scope)?.map_content());
+            }
+        }
+        Ok((args.join(", "), imports))
+    }
+
+    /// `mapK` in the companion of a tagless-final trait, turning a service
+    /// in `F` into one in `G`; none for traits with type parameters.
+    fn map_k(&self, s: &ast::Service) -> Result<Option<String>, IozhError> {
+        // Streamed arguments would have to go back from `G` to `F`.
+        if !s.name.args.is_empty() || s.methods.iter().any(|m| !m.stream_args.is_empty()) {
+            return Ok(None);
+        }
+        let mut defs = vec![];
+        let mut instances: Vec<String> = vec![];
+        for m in &s.methods {
+            let scope = self.push_method(m);
+            let args = m.args.mapg(|x| x.gen_in_method(&scope))?.map_content().join(", ");
+            let names = m.args.iter().map(|a| sanitize(&a.name)).collect::<Vec<_>>().join(", ");
+            let (implicits, ty, _) = self.method_type(m, "G")?;
+            let mut call = format!("service.{}({names})", sanitize(&m.name.name));
+            if !implicits.is_empty() {
+                let e = m.error.as_ref().map(|e| e.gen()).transpose()?.map(|e| e.to_string()).unwrap_or_default();
+                let instance = format!("{}F", lower_first(&e.chars().filter(|c| c.is_alphanumeric()).collect::<String>()));
+                call.push_str(&format!("({instance})"));
+                let param = format!("{instance}: MonadError[F, {e}]");
+                if !instances.contains(&param) {
+                    instances.push(param);
+                }
+            }
+            let body = if m.stream_result { format!("{call}.translate(f)") } else { format!("f({call})") };
+            defs.push(format!("    def {}({args}){implicits}: {ty} = {body}", m.name.gen()?.to_string()));
+        }
+        let instances = if instances.is_empty() { String::new() } else { format!("(implicit {})", instances.join(", ")) };
+        Ok(Some(format!(
+            "object {} {{\n  def mapK[F[_], G[_]](service: {}[F])(f: F ~> G){instances}: {}[G] =\n    new {}[G] {{\n{}\n    }}\n}}",
+            self.base_name, self.base_name, self.base_name, self.base_name,
+            defs.iter().map(|d| format!("  {d}")).collect::<Vec<_>>().join("\n"),
+        )))
+    }
+}
+
+fn lower_first(s: &str) -> String {
+    let mut chars = s.chars();
+    chars.next().map(|c| c.to_lowercase().collect::<String>() + chars.as_str()).unwrap_or_default()
+}
+
 impl InService for ast::Method {
     fn gen_in_service(&self, parent: &ServiceContext) -> Result<Vec<GenResult>, IozhError> {
         let name = self.name.gen()?.to_string();
-        let scope = parent.push_method(self);
-        let args = self.args.mapg(|x| x.gen_in_method(&scope))?.map_content().join("\n");
-        let ret = self.result.gen()?.to_string();
-        GenResult::single(format!("def {}({}): {}", name, args, ret))
+        let (args, mut imports) = parent.method_args(self, "F")?;
+        let (implicits, ty, mut typed) = parent.method_type(self, "F")?;
+        imports.append(&mut typed);
+        Ok(vec![GenResult {
+            unit: None,
+            content: format!("def {name}({args}){implicits}: {ty}"),
+            imports,
+            package: vec![],
+            block: None,
+        }])
     }
 }
 
@@ -602,14 +791,21 @@ impl InNspace for ast::Service {
     fn gen_in_nspace(&self, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
         let scope = parent.push_service(self)?;
         let methods_results = self.methods.mapg(|x| x.gen_in_service(&scope))?;
-        let imports = methods_results.map_imports();
+        let mut imports = methods_results.map_imports();
         let methods = methods_results.map_content().join("\n");
-        let content = format!("trait {} {{\n{}\n}}", scope.full_type_name, methods);
+        let name = if scope.effectful { effect_param(&scope.full_type_name) } else { scope.full_type_name.clone() };
+        let mut content = format!("trait {} {{\n{}\n}}", name, methods);
+        if parent.project.options.effect == Effect::Tagless {
+            if let Some(companion) = scope.map_k(self)? {
+                imports.push("cats.~>".to_string());
+                content.push_str(&format!("\n\n{companion}"));
+            }
+        }
         let unit = Some(scope.base_name.clone());
         Ok(vec![
             GenResult {
                 unit,
-                content: content,
+                content,
                 imports,
                 package: scope.nspace.path.clone(),
                 block: None,
@@ -618,26 +814,6 @@ impl InNspace for ast::Service {
     }
 }
 
-impl InHttpService for ast::HttpRoute {
-    fn gen_in_http_service(&self, _parent: &HttpServiceContext) -> Result<Vec<GenResult>, IozhError> {
-        Ok(vec![
-        ])
-    }
-}
-
-impl InNspace for ast::HttpService {
-    fn gen_in_nspace(&self, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
-        let scope = parent.push_http_service(self)?;
-        let methods = self.routes
-            .mapg(|x| x.gen_in_http_service(&scope))?
-            .map_content()
-            .join("\n");
-        let header = format!("trait {}", scope.full_type_name);
-        let body = format!("object {} {{ {} }}", scope.base_name, methods);
-        GenResult::single(format!("{}\n{}", header, body))
-    }
-}
-
 impl InNspace for ast::NspaceItem {
     fn gen_in_nspace(&self, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
         match self {
@@ -649,8 +825,16 @@ impl InNspace for ast::NspaceItem {
                 let c = parent.project.p.get_choice(*idx)?;
                 c.gen_in_nspace(parent)
             }
-            ast::NspaceItem::Service(v) => v.gen_in_nspace(parent),
+            ast::NspaceItem::Service(v) => {
+                let mut results = v.gen_in_nspace(parent)?;
+                if parent.project.options.json_rpc {
+                    results.extend(json_rpc_service(v, parent)?);
+                }
+                Ok(results)
+            }
             ast::NspaceItem::HttpService(v) => v.gen_in_nspace(parent),
+            ast::NspaceItem::Events(v) => v.gen_in_nspace(parent),
+            ast::NspaceItem::Storage(_) => Ok(vec![]),
             ast::NspaceItem::Nspace(v) => v.gen_in_nspace(parent),
         }
     }
@@ -665,15 +849,47 @@ impl InNspace for ast::Nspace {
 
 impl InProject for ast::Nspace {
     fn gen_in_project(&self, parent: &ProjectContext) -> Result<Vec<GenResult>, IozhError> {
-        let scope = parent.push_nspace(&self);
+        let scope = parent.push_nspace(self);
         self.items.mapg(|x| x.gen_in_nspace(&scope))
     }
 }
 
-pub fn generate(project: ast::Project, target_folder: &std::path::Path) -> Result<(), IozhError> {
-    let scope = ProjectContext { p: &project };
+fn stream_import(effect: Effect) -> String {
+    match effect {
+        Effect::Zio => "zio.stream.ZStream".to_string(),
+        _ => "fs2.Stream".to_string(),
+    }
+}
+
+/// `Name[F[_]]`, or `Name[F[_], A]` for a type with parameters.
+pub fn effect_param(type_name: &str) -> String {
+    match type_name.split_once('[') {
+        Some((name, args)) => format!("{name}[F[_], {args}"),
+        None => format!("{type_name}[F[_]]"),
+    }
+}
+
+pub fn generate(project: ast::Project, target_folder: &std::path::Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    generate_with(project, target_folder, out, Scala2Overrides::default())
+}
+
+pub fn generate_with(project: ast::Project, target_folder: &std::path::Path, out: &mut dyn Output, overrides: Scala2Overrides) -> Result<(), IozhError> {
+    let options = Scala2Options::read(&project, &overrides)?;
+    let scope = ProjectContext { p: &project, options };
     let mut items = project.nspaces.mapg(|x| x.gen_in_project(&scope))?;
     let mut circe_items = circe_pack(&scope)?;
     items.append(&mut circe_items);
-    write_fs_tree(items, target_folder)
+    let has_routes = project.all_nspaces()
+        .iter()
+        .any(|(_, n)| n.items.iter().any(|i| matches!(i, ast::NspaceItem::HttpService(_))));
+    if has_routes {
+        items.append(&mut http4s_pack(&scope)?);
+    }
+    if scope.options.json_rpc {
+        for (path, nspace) in project.all_nspaces() {
+            check_rpc_names(nspace, &path)?;
+        }
+        items.append(&mut json_rpc_pack(&scope)?);
+    }
+    write_fs_tree(items, target_folder, out)
 }
\ No newline at end of file
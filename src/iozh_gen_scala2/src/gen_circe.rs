@@ -42,10 +42,7 @@ fn literal_decoder(l: &ast::Literal) -> &str {
 fn decoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&ChoiceContext>) -> std::result::Result<Vec<GenResult>, IozhError> {
     let scope = ctx.push_struct(s)?;
     let mut fields = Vec::new();
-    fields.append(s.fields.iter().filter(|f| match f {
-        ast::StructItem::Field(_) => true,
-        _ => false,
-    }).map(|f| f.clone()).collect::<Vec<_>>().as_mut());
+    fields.append(s.fields.iter().filter(|f| matches!(f, ast::StructItem::Field(_))).cloned().collect::<Vec<_>>().as_mut());
     let name = if let Some(pp) = parent {
         fields.append(pp.p.fields.iter().map(|f| ast::StructItem::Field(f.clone())).collect::<Vec<_>>().as_mut());
         if fields.is_empty() {
@@ -54,7 +51,7 @@ fn decoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
                 choice: s.name.clone(),
             }.decoder_in_choice(pp);
         }
-        if pp.base_name.len() > 0 {
+        if !pp.base_name.is_empty() {
             pp.base_name.to_string() + "." + &scope.base_name
         } else {
             scope.base_name.to_string()
@@ -69,10 +66,7 @@ fn decoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
     let decoder_fields_parse = fields_decoders.map_content().join("\n");
     let decoder_fields_list = fields
         .iter()
-        .filter(|x| match x {
-            ast::StructItem::Field(_) => true,
-            _ => false,
-        })
+        .filter(|x| matches!(x, ast::StructItem::Field(_)))
         .map(|x| match x {
             ast::StructItem::Field(f) => {
                 let arg = sanitize(&f.name);
@@ -97,7 +91,7 @@ fn decoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
     };
     let type_bounds = scope.type_args.join(": Decoder, ") + ": Decoder";
     let type_args = scope.type_args.join(",");
-    let decoder = if type_args.len() > 0 {
+    let decoder = if !type_args.is_empty() {
         format!("implicit def {decoder_name}Decoder[{type_bounds}]: Decoder[{name}[{type_args}]{postfix}] = {decoder_body}")
     } else {
         format!("implicit lazy val {decoder_name}Decoder: Decoder[{name}{postfix}] = {decoder_body}")
@@ -117,10 +111,7 @@ fn decoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
 fn encoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&ChoiceContext>) -> std::result::Result<Vec<GenResult>, IozhError> {
     let scope = ctx.push_struct(s)?;
     let mut fields = Vec::new();
-    fields.append(s.fields.iter().filter(|f| match f {
-        ast::StructItem::Field(_) => true,
-        _ => false,
-    }).map(|f| f.clone()).collect::<Vec<_>>().as_mut());
+    fields.append(s.fields.iter().filter(|f| matches!(f, ast::StructItem::Field(_))).cloned().collect::<Vec<_>>().as_mut());
     let name = if let Some(pp) = parent {
         fields.append(pp.p.fields.iter().map(|f| ast::StructItem::Field(f.clone())).collect::<Vec<_>>().as_mut());
         if fields.is_empty() {
@@ -129,7 +120,7 @@ fn encoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
                 choice: s.name.clone(),
             }.encoder_in_choice(pp);
         }
-        if pp.base_name.len() > 0 {
+        if !pp.base_name.is_empty() {
             pp.base_name.to_string() + "." + &scope.base_name
         } else {
             scope.base_name.to_string()
@@ -144,7 +135,7 @@ fn encoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
     let encoder_fields_parse = fields_encoders.map_content().join(",\n");
     let type_bounds = scope.type_args.join(": Encoder, ") + ": Encoder";
     let type_args = scope.type_args.join(",");
-    let type_args_opt = if type_args.len() > 0 {
+    let type_args_opt = if !type_args.is_empty() {
         format!("[{type_args}]", type_args = type_args)
     } else {
         "".to_string()
@@ -161,7 +152,7 @@ fn encoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
     } else {
         format!("(_: HCursor) => Right({name})")
     };
-    let encoder = if type_args.len() > 0 {
+    let encoder = if !type_args.is_empty() {
         format!("implicit def {encoder_name}encoder[{type_bounds}]: Encoder[{name}[{type_args}]{postfix}] = {encoder_body}")
     } else {
         format!("implicit lazy val {encoder_name}encoder: Encoder[{name}{postfix}] = {encoder_body}")
@@ -181,7 +172,7 @@ fn encoder_for_struct(s: &ast::Structure, ctx: &NspaceContext, parent: Option<&C
 fn decoder_for_choice_in_nspace(c: &ast::Choice, path: &str, parent: &NspaceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
     let project = &parent.project.p;
     let scope = parent.push_choice(c)?;
-    let name = if path.len() > 0 {
+    let name = if !path.is_empty() {
         path.to_owned() + "." + &scope.base_name
     } else {
         scope.base_name.to_string()
@@ -190,13 +181,7 @@ fn decoder_for_choice_in_nspace(c: &ast::Choice, path: &str, parent: &NspaceCont
     let postfix = if c.choices.is_empty() { ".type" } else { "" };
     let items = c.choices
         .iter()
-        .filter(|x| match x {
-            ast::ChoiceItem::Structure(_) => true,
-            ast::ChoiceItem::TypeTag{ doc: _, choice: _} => true,
-            ast::ChoiceItem::Value{doc: _, name: _, value: _ } => true,
-            ast::ChoiceItem::Wrap{doc: _, name: _, field: _, target: _ } => true,
-            _ => false,
-        })
+        .filter(|x| !matches!(x, ast::ChoiceItem::Nil))
         .map(|x| {
             let type_name = match x {
                 ast::ChoiceItem::Structure(idx) => {
@@ -257,7 +242,7 @@ fn decoder_for_choice_in_nspace(c: &ast::Choice, path: &str, parent: &NspaceCont
 fn encoder_for_choice_in_nspace(c: &ast::Choice, path: &str, parent: &NspaceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
     let project = &parent.project.p;
     let scope = parent.push_choice(c)?;
-    let name = if path.len() > 0 {
+    let name = if !path.is_empty() {
         path.to_owned() + "." + &scope.base_name
     } else {
         scope.base_name.to_string()
@@ -266,13 +251,7 @@ fn encoder_for_choice_in_nspace(c: &ast::Choice, path: &str, parent: &NspaceCont
     let postfix = if c.choices.is_empty() { ".type" } else { "" };
     let encoder_items = c.choices
         .iter()
-        .filter(|x| match x {
-            ast::ChoiceItem::Structure(_) => true,
-            ast::ChoiceItem::TypeTag{ doc: _, choice: _} => true,
-            ast::ChoiceItem::Value{doc: _, name: _, value: _ } => true,
-            ast::ChoiceItem::Wrap{doc: _, name: _, field: _, target: _ } => true,
-            _ => false,
-        })
+        .filter(|x| !matches!(x, ast::ChoiceItem::Nil))
         .map(|x| {
             let nn = match x {
                 ast::ChoiceItem::Structure(idx) => {
@@ -295,7 +274,7 @@ fn encoder_for_choice_in_nspace(c: &ast::Choice, path: &str, parent: &NspaceCont
             } else {
                 "".to_string()
             };
-            if path.len() > 0 {
+            if !path.is_empty() {
                 format!("case x: {path}.{type_name} => x.asJson{postfix}")
             } else {
                 format!("case x: {type_name} => x.asJson{postfix}")
@@ -390,23 +369,23 @@ impl CirceInStruct for ast::StructItem {
 
 impl CirceInNspace for ast::Structure {
     fn decoder_in_nspace(&self, parent: &NspaceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
-        decoder_for_struct(&self, parent, None)
+        decoder_for_struct(self, parent, None)
     }
 
     fn encoder_in_nspace(&self, parent: &NspaceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
-        encoder_for_struct(&self, parent, None)
+        encoder_for_struct(self, parent, None)
     }
 
     fn codec_in_nspace(&self, parent: &NspaceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
-        let decoder_res = self.decoder_in_nspace(&parent)?;
-        let encoder_res = self.encoder_in_nspace(&parent)?;
+        let decoder_res = self.decoder_in_nspace(parent)?;
+        let encoder_res = self.encoder_in_nspace(parent)?;
         let decoder = decoder_res.into_iter().map(|x| x.content).collect::<Vec<_>>().join("\n");
         let encoder = encoder_res.into_iter().map(|x| x.content).collect::<Vec<_>>().join("\n");
         let content = format!("{decoder}\n{encoder}\n");
         Ok(vec![
             GenResult {
                 unit: Some("package".to_string()),
-                content: content,
+                content,
                 imports: vec![
                     "io.circe.Decoder".to_string(),
                     "io.circe.Encoder".to_string(),
@@ -424,11 +403,11 @@ impl CirceInNspace for ast::Structure {
 
 impl CirceInChoice for ast::Structure {
     fn decoder_in_choice(&self, parent: &ChoiceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
-        decoder_for_struct(self, &parent.nspace, Some(parent))
+        decoder_for_struct(self, parent.nspace, Some(parent))
     }
 
     fn encoder_in_choice(&self, parent: &ChoiceContext) -> std::result::Result<Vec<GenResult>, IozhError> {
-        encoder_for_struct(self, &parent.nspace, Some(&parent))
+        encoder_for_struct(self, parent.nspace, Some(parent))
     }
 }
 
@@ -437,12 +416,12 @@ impl CirceInChoice for ast::ChoiceItem {
         match self {
             ast::ChoiceItem::Structure(idx) => {
                 let s = parent.nspace.project.p.get_structure(*idx)?;
-                s.decoder_in_choice(&parent)
+                s.decoder_in_choice(parent)
             }
             ast::ChoiceItem::TypeTag{ doc: _, choice } => {
                 let type_name = &choice.name;
                 let name = parent.base_name.to_string() + "." + type_name;
-                let codec_name = (parent.base_name.to_string() + &type_name).to_ascii_lowercase();
+                let codec_name = (parent.base_name.to_string() + type_name).to_ascii_lowercase();
                 let decoder = format!(r#"
                     |implicit lazy val {codec_name}Decoder: Decoder[{name}.type] = Decoder.decodeString.emap {{ v =>
                     |  if (v == "{codec_name}") Right({name})
@@ -463,7 +442,7 @@ impl CirceInChoice for ast::ChoiceItem {
                 let type_name = &name.name;
                 let name = parent.base_name.to_string() + "." + type_name;
                 let ldecoder = literal_decoder(value);
-                let codec_name = (parent.base_name.to_string() + &type_name).to_ascii_lowercase();
+                let codec_name = (parent.base_name.to_string() + type_name).to_ascii_lowercase();
                 let decoder = format!(r#"
                     |implicit lazy val {codec_name}Decoder: Decoder[{name}.type] = Decoder.{ldecoder}.emap {{ v =>
                     |  if (v == {name}.getValue) Right({name})
@@ -483,7 +462,7 @@ impl CirceInChoice for ast::ChoiceItem {
             ast::ChoiceItem::Wrap { doc: _, name, field: _, target } => {
                 let type_name = &name.name;
                 let name = parent.base_name.to_string() + "." + type_name;
-                let codec_name = (parent.base_name.to_string() + &type_name).to_ascii_lowercase();
+                let codec_name = (parent.base_name.to_string() + type_name).to_ascii_lowercase();
                 let target_name = &target.gen()?.to_string();
                 let mut imports: Vec<String> = vec![];
                 let decoder_body = if target_name == "File" {
@@ -497,7 +476,7 @@ impl CirceInChoice for ast::ChoiceItem {
                     GenResult {
                         unit: None,
                         content: decoder,
-                        imports: imports,
+                        imports,
                         package: vec![],
                         block: None,
                     }
@@ -511,12 +490,12 @@ impl CirceInChoice for ast::ChoiceItem {
         match self {
             ast::ChoiceItem::Structure(idx) => {
                 let s = parent.nspace.project.p.get_structure(*idx)?;
-                s.encoder_in_choice(&parent)
+                s.encoder_in_choice(parent)
             }
             ast::ChoiceItem::TypeTag{ doc: _, choice } => {
                 let type_name = &choice.name;
                 let name = parent.base_name.to_string() + "." + type_name;
-                let codec_name = (parent.base_name.to_string() + &type_name).to_ascii_lowercase();
+                let codec_name = (parent.base_name.to_string() + type_name).to_ascii_lowercase();
                 let v = codec_name.to_string();
                 let encoder = format!(r#"
                     |implicit lazy val {codec_name}Encoder: Encoder[{name}.type] = (_: {name}.type) => "{v}".asJson
@@ -534,10 +513,10 @@ impl CirceInChoice for ast::ChoiceItem {
             ast::ChoiceItem::Value{doc: _, name, value } => {
                 let type_name = &name.name;
                 let name = parent.base_name.to_string() + "." + type_name;
-                let codec_name = (parent.base_name.to_string() + &type_name).to_ascii_lowercase();
+                let codec_name = (parent.base_name.to_string() + type_name).to_ascii_lowercase();
                 let v = match value {
                     ast::Literal::Int{ pos: _, value } => format!("{}", value),
-                    ast::Literal::String{ pos: _, value } => format!("{}", value),
+                    ast::Literal::String{ pos: _, value } => value.to_string(),
                     ast::Literal::Nil => todo!(),
                 };
                 let encoder = format!(r#"
@@ -556,7 +535,7 @@ impl CirceInChoice for ast::ChoiceItem {
             ast::ChoiceItem::Wrap{doc: _, name, field, target } => {
                 let type_name = &name.name;
                 let name = parent.base_name.to_string() + "." + type_name;
-                let codec_name = (parent.base_name.to_string() + &type_name).to_ascii_lowercase();
+                let codec_name = (parent.base_name.to_string() + type_name).to_ascii_lowercase();
                 let target_name = &target.gen()?.to_string();
                 let encoder_body = if target_name == "File" {
                     format!("(x: {name}) => x.{field}.getName.asJson")
@@ -589,13 +568,7 @@ impl CirceInNspace for ast::Choice {
         let content = format!("{decoder}\n{encoder}\n");
         let unit = Some("package".to_string());
         let mut items_decoders = self.choices
-            .filter_gen(|x| match x {
-                ast::ChoiceItem::Structure(_) => true,
-                ast::ChoiceItem::TypeTag{ doc: _, choice: _} => true,
-                ast::ChoiceItem::Value{doc: _, name: _, value: _ } => true,
-                ast::ChoiceItem::Wrap{doc: _, name: _, field: _, target: _ } => true,
-                _ => false,
-            }, |x| x.decoder_in_choice(&scope))?;
+            .filter_gen(|x| !matches!(x, ast::ChoiceItem::Nil), |x| x.decoder_in_choice(&scope))?;
         items_decoders
             .iter_mut()
             .for_each(|res| {
@@ -604,13 +577,7 @@ impl CirceInNspace for ast::Choice {
                 res.block = Some("object CirceImplicits".to_string());
             });
         let mut items_encoders = self.choices
-            .filter_gen(|x| match x {
-                ast::ChoiceItem::Structure(_) => true,
-                ast::ChoiceItem::TypeTag{ doc: _, choice: _} => true,
-                ast::ChoiceItem::Value{doc: _, name: _, value: _ } => true,
-                ast::ChoiceItem::Wrap{doc: _, name: _, field: _, target: _ } => true,
-                _ => false,
-            }, |x| x.encoder_in_choice(&scope))?;
+            .filter_gen(|x| !matches!(x, ast::ChoiceItem::Nil), |x| x.encoder_in_choice(&scope))?;
         items_encoders
             .iter_mut()
             .for_each(|res| {
@@ -620,7 +587,7 @@ impl CirceInNspace for ast::Choice {
             });
         let body = GenResult {
             unit,
-            content: content,
+            content,
             imports: vec![
                 "io.circe.Decoder".to_string(),
                 "io.circe.Encoder".to_string(),
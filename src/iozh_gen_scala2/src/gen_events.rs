@@ -0,0 +1,137 @@
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::{doc_text, TypeIndex};
+
+use crate::gen::*;
+use crate::context::*;
+use crate::gen_http4s::{scala_string, type_imports};
+use crate::utils::ResultVec;
+
+/// Case class of an event, `updated` becoming `Updated`.
+fn case_name(event: &str) -> String {
+    sanitize(&(event[..1].to_uppercase() + &event[1..]))
+}
+
+/// A sealed ADT of the events with a `Handler` per kind, routing them by
+/// the `event` discriminator of their JSON, and a `Publisher`. The JSON
+/// of an event is `{"event": "updated", "payload": ...}`.
+impl InNspace for ast::Events {
+    fn gen_in_nspace(&self, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
+        let index = TypeIndex::build(parent.project.p);
+        let name = sanitize(&self.name);
+        let cases = self.events.iter().map(|e| case_name(&e.name)).collect::<Vec<_>>();
+        let mut imports = vec![
+            "cats.MonadThrow".to_string(),
+            "cats.syntax.all._".to_string(),
+            "io.circe.Decoder".to_string(),
+            "io.circe.DecodingFailure".to_string(),
+            "io.circe.Encoder".to_string(),
+            "io.circe.Json".to_string(),
+            "io.circe.syntax._".to_string(),
+            "iozh.circe.Implicits._".to_string(),
+        ];
+        let mut members = vec![];
+        let mut handlers = vec![];
+        let mut publishers = vec![];
+        let mut routes = vec![];
+        let mut encoders = vec![];
+        let mut decoders = vec![];
+        for (e, case) in self.events.iter().zip(&cases) {
+            let mut ty = e.type_path.gen()?.to_string();
+            imports.extend(imports_for(&ty));
+            type_imports(&index, &e.type_path, &parent.path, &mut imports);
+            // A payload named like one of the cases would be shadowed by it.
+            if cases.iter().any(|c| ty.split('[').next() == Some(c.as_str())) {
+                if let Some(entry) = index.resolve_path(&e.type_path, &parent.path) {
+                    ty = format!("_root_.{}.{ty}", entry.path.join("."));
+                }
+            }
+            let method = sanitize(&e.name);
+            let wire = scala_string(&e.name);
+            members.push(format!("{}final case class {case}(payload: {ty}) extends {name}", doc_comment(&e.doc)));
+            handlers.push(format!("{}def {method}(payload: {ty}): F[Unit]", doc_comment(&e.doc)));
+            publishers.push(format!("def {method}(payload: {ty}): F[Unit] = publish({case}(payload))"));
+            routes.push(format!("case {case}(payload) => handler.{method}(payload)"));
+            encoders.push(format!("case {case}(payload) => envelope({wire}, payload.asJson)"));
+            decoders.push(format!("case {wire} => c.downField(\"payload\").as[{ty}].map({case}(_))"));
+        }
+        let indent = |lines: &[String], n: usize| {
+            lines
+                .iter()
+                .flat_map(|l| l.lines().map(|x| format!("{}{x}", " ".repeat(n)).trim_end().to_string()).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let content = format!(
+            r#"{doc}sealed trait {name}
+
+object {name} {{
+{members}
+
+  /** Handles each event of {name}. */
+  trait Handler[F[_]] {{
+{handlers}
+  }}
+
+  /** Emits {name}, with a method per event. */
+  trait Publisher[F[_]] {{
+    def publish(event: {name}): F[Unit]
+
+{publishers}
+  }}
+
+  /** Routes an event to the handler of its kind. */
+  def route[F[_]](handler: Handler[F])(event: {name}): F[Unit] =
+    event match {{
+{routes}
+    }}
+
+  /** Decodes an event by its `event` discriminator, then routes it. */
+  def routeJson[F[_]: MonadThrow](handler: Handler[F])(json: Json): F[Unit] =
+    json.as[{name}].liftTo[F].flatMap(route(handler))
+
+  implicit lazy val encoder: Encoder[{name}] = Encoder.instance {{
+{encoders}
+  }}
+
+  implicit lazy val decoder: Decoder[{name}] = Decoder.instance {{ c =>
+    c.downField("event").as[String].flatMap {{
+{decoders}
+      case other => Left(DecodingFailure(s"Unknown event $other", c.history))
+    }}
+  }}
+
+  private def envelope(event: String, payload: Json): Json =
+    Json.obj("event" -> Json.fromString(event), "payload" -> payload)
+}}
+"#,
+            doc = doc_comment(&self.doc),
+            members = indent(&members, 2),
+            handlers = indent(&handlers, 4),
+            publishers = indent(&publishers, 4),
+            routes = indent(&routes, 6),
+            encoders = indent(&encoders, 4),
+            decoders = indent(&decoders, 6),
+        );
+        Ok(vec![GenResult {
+            unit: Some(name),
+            content,
+            imports,
+            package: parent.path.clone(),
+            block: None,
+        }])
+    }
+}
+
+/// Scaladoc of a declaration, the `/* ... */` of the source.
+fn doc_comment(doc: &str) -> String {
+    let text = doc_text(doc).replace("*/", "* /");
+    if text.is_empty() {
+        String::new()
+    } else if text.contains('\n') {
+        let lines = text.lines().map(|l| format!(" * {l}").trim_end().to_string()).collect::<Vec<_>>();
+        format!("/**\n{}\n */\n", lines.join("\n"))
+    } else {
+        format!("/** {text} */\n")
+    }
+}
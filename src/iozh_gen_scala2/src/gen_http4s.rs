@@ -0,0 +1,591 @@
+use stripmargin::StripMargin;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::{is_unit, BodyKind, Builtin, ResponseCase, RouteParam, RouteResponse, TypeDef, TypeIndex};
+
+use crate::gen::*;
+use crate::context::*;
+use crate::utils::ResultVec;
+
+pub fn http4s_pack(_project: &ProjectContext) -> std::result::Result<Vec<GenResult>, IozhError> {
+    let content = r#"
+    |/** A parameter missing or failing to decode, answered with 400 Bad Request. */
+    |final case class ParamError(message: String) extends Exception(message)
+    |
+    |def handle[F[_]: Async](response: F[Response[F]]): F[Response[F]] =
+    |  response.recover { case ParamError(message) => Response[F](Status.BadRequest).withEntity(message) }
+    |
+    |/** Decodes a parameter sent as text: each value as JSON, so that numbers
+    |  * and booleans come through, or as a string; then all values as a list. */
+    |def param[F[_]: Async, A: Decoder](name: String, values: List[String]): F[A] = {
+    |  def parsed(s: String): Json = parse(s).getOrElse(Json.fromString(s))
+    |  val candidates = values match {
+    |    case Nil => List(Json.Null, Json.arr())
+    |    case first :: _ =>
+    |      List(parsed(first), Json.fromString(first), Json.arr(values.map(parsed): _*), Json.arr(values.map(Json.fromString): _*))
+    |  }
+    |  candidates.collectFirstSome(_.as[A].toOption).liftTo[F](ParamError(if (values.isEmpty) s"Missing $name" else s"Invalid $name"))
+    |}
+    |
+    |/** A parameter taken from the query, else from the JSON body. */
+    |def paramOrProperty[F[_]: Async, A: Decoder](name: String, values: List[String], body: Json): F[A] =
+    |  if (values.nonEmpty) param[F, A](name, values) else property[F, A](name, body)
+    |
+    |def query[F[_]](req: Request[F], name: String): List[String] =
+    |  req.multiParams.get(name).map(_.toList).getOrElse(Nil)
+    |
+    |def header[F[_]](req: Request[F], name: String): List[String] =
+    |  req.headers.get(CIString(name)).map(_.toList.map(_.value)).getOrElse(Nil)
+    |
+    |def jsonBody[F[_]: Async](req: Request[F]): F[Json] =
+    |  req.bodyText.compile.string.flatMap { s =>
+    |    if (s.trim.isEmpty) Json.obj().pure[F]
+    |    else parse(s).leftMap(e => ParamError(s"Invalid body: ${e.message}")).liftTo[F]
+    |  }
+    |
+    |def input[F[_]: Async, A: Decoder](body: Json): F[A] =
+    |  body.as[A].leftMap(e => ParamError(s"Invalid body: ${e.message}")).liftTo[F]
+    |
+    |def property[F[_]: Async, A: Decoder](name: String, body: Json): F[A] =
+    |  body.hcursor.downField(name).focus match {
+    |    case None => Json.Null.as[A].leftMap(_ => ParamError(s"Missing $name")).liftTo[F]
+    |    case Some(json) => json.as[A].leftMap(e => ParamError(s"Invalid $name: ${e.message}")).liftTo[F]
+    |  }
+    |
+    |def formBody[F[_]: Async](req: Request[F]): F[Map[String, List[String]]] =
+    |  req.as[UrlForm].map(_.values.view.mapValues(_.toList).toMap)
+    |
+    |def multipartBody[F[_]: Async](req: Request[F]): F[Multipart[F]] =
+    |  req.as[Multipart[F]]
+    |
+    |/** Parts other than files, as text. */
+    |def textParts[F[_]: Async](body: Multipart[F]): F[Map[String, List[String]]] =
+    |  body.parts.toList
+    |    .filter(_.filename.isEmpty)
+    |    .traverse(part => part.bodyText.compile.string.map(part.name.getOrElse("") -> _))
+    |    .map(_.groupMap(_._1)(_._2))
+    |
+    |/** A file part, saved to a temporary file. */
+    |def optionalFilePart[F[_]: Async](body: Multipart[F], name: String): F[Option[File]] =
+    |  body.parts.find(_.name.contains(name)).traverse { part =>
+    |    Files[F].createTempFile.flatMap { path =>
+    |      part.body.through(Files[F].writeAll(path)).compile.drain.as(path.toNioPath.toFile)
+    |    }
+    |  }
+    |
+    |def filePart[F[_]: Async](body: Multipart[F], name: String): F[File] =
+    |  optionalFilePart[F](body, name).flatMap(_.liftTo[F](ParamError(s"Missing $name")))
+    |
+    |/** A declared response of a route. */
+    |def respond[F[_]: Async](status: Int, body: Option[Json], headers: List[(String, String)]): F[Response[F]] = {
+    |  val response = Response[F](Status.fromInt(status).getOrElse(Status.InternalServerError))
+    |    .withHeaders(new Headers(headers.map { case (name, value) => Header.Raw(CIString(name), value) }))
+    |  body.fold(response)(response.withEntity(_)).pure[F]
+    |}
+    |
+    |/** A body of newline-delimited JSON values, decoded as they come. */
+    |def jsonLines[F[_]: Async, A: Decoder](req: Request[F]): Stream[F, A] =
+    |  req.body.through(text.utf8.decode).through(text.lines).filter(_.trim.nonEmpty).evalMap { line =>
+    |    parse(line).flatMap(_.as[A]).leftMap(e => ParamError(s"Invalid body: ${e.getMessage}")).liftTo[F]
+    |  }
+    |
+    |/** A streamed result, each value sent as it comes: server-sent events
+    |  * for clients asking for them, newline-delimited JSON otherwise. */
+    |def streamed[F[_]: Async](req: Request[F], values: Stream[F, Json]): F[Response[F]] = {
+    |  val lines = values.map(_.noSpaces)
+    |  val response =
+    |    if (header(req, "Accept").exists(_.contains("text/event-stream")))
+    |      Response[F](Status.Ok)
+    |        .withContentType(`Content-Type`(MediaType.`text/event-stream`))
+    |        .withBodyStream(lines.map(data => ServerSentEvent(data = Some(data))).through(ServerSentEvent.encoder))
+    |    else
+    |      Response[F](Status.Ok)
+    |        .withContentType(`Content-Type`(MediaType.unsafeParse("application/x-ndjson")))
+    |        .withBodyStream(lines.map(_ + "\n").through(text.utf8.encode))
+    |  response.pure[F]
+    |}
+    |
+    |/** A header value: strings as they are, other values as JSON. */
+    |def headerValue[A: Encoder](value: A): String = {
+    |  val json = value.asJson
+    |  json.asString.getOrElse(json.noSpaces)
+    |}
+    "#.strip_margin();
+    Ok(vec![GenResult {
+        unit: Some("Http4sSupport".to_string()),
+        content,
+        imports: vec![
+            "cats.effect.Async".to_string(),
+            "cats.syntax.all._".to_string(),
+            "fs2.Stream".to_string(),
+            "fs2.io.file.Files".to_string(),
+            "fs2.text".to_string(),
+            "io.circe.Decoder".to_string(),
+            "io.circe.Encoder".to_string(),
+            "io.circe.Json".to_string(),
+            "io.circe.parser.parse".to_string(),
+            "io.circe.syntax._".to_string(),
+            "java.io.File".to_string(),
+            "org.http4s._".to_string(),
+            "org.http4s.circe._".to_string(),
+            "org.http4s.headers.`Content-Type`".to_string(),
+            "org.http4s.multipart.Multipart".to_string(),
+            "org.typelevel.ci.CIString".to_string(),
+        ],
+        package: vec![
+            "iozh".to_string(),
+            "http".to_string(),
+        ],
+        block: Some("object Http4sSupport".to_string()),
+    }])
+}
+
+/// Name of the constructor parameter holding a service.
+pub(crate) fn service_param(name: &str) -> String {
+    sanitize(&(name[..1].to_lowercase() + &name[1..]))
+}
+
+pub(crate) fn scala_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Variable holding the decoded value of a route field.
+pub(crate) fn var(name: &str) -> String {
+    format!("_{name}")
+}
+
+/// Variable a path segment is matched into.
+fn segment_var(name: &str) -> String {
+    format!("seg{}{}", name[..1].to_uppercase(), &name[1..])
+}
+
+/// Scala type of a route field with `Option` stripped.
+fn value_type(tp: &ast::TypePath) -> &ast::TypePath {
+    match Builtin::from_path(tp) {
+        Some(Builtin::Option) => tp.path[0].args.first().unwrap_or(tp),
+        _ => tp,
+    }
+}
+
+/// Step of the `for` reading a route field.
+fn read_param(p: &RouteParam, pattern: &ast::HttpRoutePattern, body: BodyKind) -> Result<String, IozhError> {
+    let f = &p.field;
+    let ty = f.type_path.gen()?.to_string();
+    let name = scala_string(&f.name);
+    let read = match &p.source {
+        ast::ParamSource::Path if pattern.segments.iter().any(|s| matches!(s, ast::PathSegment::Wildcard(w) if *w == f.name)) => {
+            format!("param[F, {ty}]({name}, List({}.segments.map(_.decoded()).mkString(\"/\")))", segment_var(&f.name))
+        }
+        ast::ParamSource::Path => format!("param[F, {ty}]({name}, List({}))", segment_var(&f.name)),
+        ast::ParamSource::Query if p.fallback => match body {
+            BodyKind::Json | BodyKind::None => format!("paramOrProperty[F, {ty}]({name}, query(req, {name}), json)"),
+            BodyKind::Form => format!("param[F, {ty}]({name}, query(req, {name}) ++ form.getOrElse({name}, Nil))"),
+            BodyKind::Multipart => format!("param[F, {ty}]({name}, query(req, {name}) ++ parts.getOrElse({name}, Nil))"),
+        },
+        ast::ParamSource::Query => format!("param[F, {ty}]({name}, query(req, {name}))"),
+        ast::ParamSource::Header(header) => format!("param[F, {ty}]({}, header(req, {}))", scala_string(header), scala_string(header)),
+        ast::ParamSource::Body => format!("property[F, {ty}]({name}, json)"),
+        ast::ParamSource::Form => format!("param[F, {ty}]({name}, form.getOrElse({name}, Nil))"),
+        ast::ParamSource::Multipart => match (Builtin::from_path(&f.type_path), Builtin::from_path(value_type(&f.type_path))) {
+            (Some(Builtin::File), _) => format!("filePart[F](multipart, {name})"),
+            (Some(Builtin::Option), Some(Builtin::File)) => format!("optionalFilePart[F](multipart, {name})"),
+            _ => format!("param[F, {ty}]({name}, parts.getOrElse({name}, Nil))"),
+        },
+    };
+    Ok(format!("{} <- {read}", var(&f.name)))
+}
+
+/// `respond[F](...)` call answering with a declared response, its body
+/// encoded from `body` and its headers read from the fields of `value`.
+fn respond_with(r: &RouteResponse, result: &ast::TypePath, body: &str, value: &str) -> String {
+    let body = if r.case.has_body(result) { format!("Some({body}.asJson)") } else { "None".to_string() };
+    let headers = r.response.headers
+        .iter()
+        .map(|h| format!("{} -> headerValue({value}.{})", scala_string(&h.name), sanitize(&h.field)))
+        .collect::<Vec<_>>();
+    let headers = if headers.is_empty() { "Nil".to_string() } else { format!("List({})", headers.join(", ")) };
+    format!("respond[F]({}, {body}, {headers})", r.response.status)
+}
+
+/// Expression answering `value`, a result or error of type `tp`, with the
+/// declared responses: a match on `Some` and `None`, or on the items of a
+/// choice, when responses are declared for them. Values without a response
+/// of their own get the one typed as the whole `tp`, else `default`; `None`
+/// gets 404.
+fn respond(responses: &[&RouteResponse], tp: &ast::TypePath, items: usize, value: &str, default: u16) -> Result<String, IozhError> {
+    let find = |case: &ResponseCase| responses.iter().find(|r| r.case == *case);
+    let fallback = match find(&ResponseCase::Result) {
+        Some(r) => respond_with(r, tp, value, value),
+        None if is_unit(tp) => format!("respond[F]({default}, None, Nil)"),
+        None => format!("respond[F]({default}, Some({value}.asJson), Nil)"),
+    };
+    let some = find(&ResponseCase::Some);
+    let none = find(&ResponseCase::None);
+    let mut cases = vec![];
+    if some.is_some() || none.is_some() {
+        cases.push(format!("case Some(v) => {}", some.map(|r| respond_with(r, tp, "v", "v")).unwrap_or_else(|| fallback.clone())));
+        cases.push(format!("case None => {}", none.map(|r| respond_with(r, tp, value, value)).unwrap_or_else(|| "respond[F](404, None, Nil)".to_string())));
+    } else {
+        // Items are answered encoded as the whole choice, as clients
+        // decode every declared status as the method result or error.
+        let ty = tp.gen()?.to_string();
+        for r in responses {
+            match &r.case {
+                ResponseCase::Item { name, object: true } => cases.push(format!("case {ty}.{name} => {}", respond_with(r, tp, value, value))),
+                ResponseCase::Item { name, object: false } => {
+                    let binder = if r.response.headers.is_empty() { "_" } else { "v" };
+                    cases.push(format!("case {binder}: {ty}.{name} => {}", respond_with(r, tp, value, "v")));
+                }
+                _ => {}
+            }
+        }
+        if cases.is_empty() {
+            return Ok(fallback);
+        }
+        if cases.len() < items {
+            cases.push(format!("case _ => {fallback}"));
+        }
+    }
+    Ok(format!("{value} match {{\n{}\n}}", cases.iter().map(|c| format!("  {c}")).collect::<Vec<_>>().join("\n")))
+}
+
+/// Number of items of `tp` when it is a choice.
+fn choice_items(p: &ast::Project, index: &TypeIndex, tp: &ast::TypePath, from: &[String]) -> Result<usize, IozhError> {
+    Ok(match index.resolve_path(tp, from).map(|e| e.def) {
+        Some(TypeDef::Choice(idx)) => p.get_choice(idx)?.choices.iter().filter(|c| !matches!(c, ast::ChoiceItem::Nil)).count(),
+        _ => 0,
+    })
+}
+
+/// `case` of a match whose body may span several lines.
+fn match_case(pattern: &str, body: &str) -> String {
+    let body = body.lines().collect::<Vec<_>>().join("\n  ");
+    format!("  case {pattern} => {body}")
+}
+
+/// Parameter of the routes class holding the `MonadError` of an error type.
+pub(crate) fn errors_param(error: &str) -> String {
+    service_param(&format!("{}Errors", error.chars().filter(|c| c.is_alphanumeric()).collect::<String>()))
+}
+
+impl InHttpService for ast::HttpRoute {
+    fn gen_in_http_service(&self, parent: &HttpServiceContext) -> Result<Vec<GenResult>, IozhError> {
+        let p = parent.nspace.project.p;
+        let (path, service, method) = p.resolve_method(&self.method, &parent.nspace.path).ok_or_else(|| IozhError {
+            pos: self.method.pos.clone(),
+            msg: "Unknown service method".to_string(),
+        })?;
+        let params = self.params()?;
+        let body = self.body_kind()?;
+        let options = &parent.nspace.project.options;
+        if options.effect == Effect::Zio && method.has_streams() {
+            return Err(IozhError {
+                pos: self.pos.clone(),
+                msg: format!("{}.{} streams, which routes do not support for ZIO services", service.name.name, method.name.name),
+            });
+        }
+        // A streamed argument takes the body, read as newline-delimited JSON,
+        // so fields cannot fall back to it.
+        let streamed_input = self.streams_input(method, &params);
+        if streamed_input && params.iter().any(|p| p.source == ast::ParamSource::Body) {
+            return Err(IozhError {
+                pos: self.pos.clone(),
+                msg: format!("{} {} streams its body, so no field can be read from it", self.verb, self.pattern),
+            });
+        }
+
+        // A trailing wildcard needs the `"a" /: rest` form, matching from
+        // the first segment on.
+        let wildcard = self.pattern.segments.iter().any(|s| matches!(s, ast::PathSegment::Wildcard(_)));
+        let mut parts = vec![];
+        for s in &self.pattern.segments {
+            match s {
+                ast::PathSegment::Literal(l) => parts.push(scala_string(l)),
+                ast::PathSegment::Var { name, .. } | ast::PathSegment::Wildcard(name) => parts.push(segment_var(name)),
+            }
+        }
+        let pattern = if wildcard {
+            parts.join(" /: ")
+        } else {
+            std::iter::once("Root".to_string()).chain(parts).collect::<Vec<_>>().join(" / ")
+        };
+
+        let mut input_used = false;
+        let mut args = vec![];
+        for arg in &method.args {
+            let bound = params.iter().any(|p| p.field.name == arg.name);
+            if bound && method.is_stream_arg(&arg.name) {
+                return Err(IozhError {
+                    pos: self.pos.clone(),
+                    msg: format!("Argument {} of {}.{} is a stream, which only the route input can be", arg.name, service.name.name, method.name.name),
+                });
+            } else if bound {
+                args.push(var(&arg.name));
+            } else if self.has_input() && !input_used {
+                input_used = true;
+                args.push("body".to_string());
+            } else if Builtin::from_path(&arg.type_path) == Some(Builtin::Option) && !method.is_stream_arg(&arg.name) {
+                args.push("None".to_string());
+            } else {
+                return Err(IozhError {
+                    pos: self.pos.clone(),
+                    msg: format!("Argument {} of {}.{} is not bound by the route", arg.name, service.name.name, method.name.name),
+                });
+            }
+        }
+        // The input is only decoded for the argument taking it.
+        let body = if body == BodyKind::Json && !input_used && !params.iter().any(|p| p.source == ast::ParamSource::Body) {
+            BodyKind::None
+        } else {
+            body
+        };
+
+        let mut steps = vec![];
+        let needs_json = !streamed_input
+            && (body == BodyKind::Json || (body == BodyKind::None && params.iter().any(|p| p.fallback)));
+        if needs_json {
+            steps.push("json <- jsonBody[F](req)".to_string());
+        }
+        match body {
+            BodyKind::Form => steps.push("form <- formBody[F](req)".to_string()),
+            BodyKind::Multipart => {
+                steps.push("multipart <- multipartBody[F](req)".to_string());
+                steps.push("parts <- textParts[F](multipart)".to_string());
+            }
+            _ => {}
+        }
+        let mut imports = vec![];
+        if input_used {
+            let ty = self.input.gen()?.to_string();
+            imports.extend(imports_for(&ty));
+            if streamed_input {
+                steps.push(format!("body <- jsonLines[F, {ty}](req).pure[F]"));
+            } else {
+                steps.push(format!("body <- input[F, {ty}](json)"));
+            }
+        }
+        for param in &params {
+            imports.extend(imports_for(&param.field.type_path.gen()?.to_string()));
+            let param = RouteParam { fallback: param.fallback && !streamed_input, ..param.clone() };
+            steps.push(read_param(&param, &self.pattern, body)?);
+        }
+
+        let receiver = parent.service_param(&path, &service.name.name);
+        let call = format!("{receiver}.{}({})", sanitize(&method.name.name), args.join(", "));
+        let error = method.error.as_ref().map(|e| e.gen()).transpose()?.map(|e| e.to_string());
+        let index = TypeIndex::build(p);
+        let responses = self.responses(p, &index, &path, method)?;
+        if method.stream_result {
+            return Ok(stream_route(self, &pattern, steps, imports, &call, error.as_deref(), options));
+        }
+        steps.push(call_step(parent.nspace.project, service, &call, error.as_deref()));
+        if self.responses.is_empty() && error.is_none() {
+            steps.push(if is_unit(&method.result) { "response <- NoContent()".to_string() } else { "response <- Ok(result.asJson)".to_string() });
+        } else {
+            let (failures, results): (Vec<&RouteResponse>, Vec<&RouteResponse>) = responses.iter().partition(|r| r.error);
+            let items = choice_items(p, &index, &method.result, &path)?;
+            let default = if is_unit(&method.result) { 204 } else { 200 };
+            let answer = respond(&results, &method.result, items, "result", default)?;
+            match &method.error {
+                None => steps.push(format!("response <- {answer}")),
+                // Errors without a response of their own are a 500.
+                Some(e) => {
+                    let items = choice_items(p, &index, e, &path)?;
+                    let failure = respond(&failures, e, items, "error", 500)?;
+                    steps.push(format!(
+                        "response <- outcome match {{\n{}\n{}\n}}",
+                        match_case("Left(error)", &failure),
+                        match_case("Right(result)", &answer),
+                    ));
+                }
+            }
+        }
+
+        Ok(route_case(self, &pattern, &steps, imports))
+    }
+}
+
+/// Step of a `for` in `F` calling a service method: `result <- ...`, or
+/// `outcome <- ...` holding an `Either` for a method declaring an error.
+/// ZIO services run on a `runtime`; with `MonadError`, each error type
+/// comes with its instance, see `errors_param`.
+pub(crate) fn call_step(project: &ProjectContext, service: &ast::Service, call: &str, error: Option<&str>) -> String {
+    let options = &project.options;
+    let value = if error.is_some() { "outcome" } else { "result" };
+    match (options.effect, error) {
+        (Effect::Future, _) => format!("{value} <- Async[F].fromFuture(Async[F].delay({call}))"),
+        // Run on the given runtime, typed errors as a `Left`.
+        (Effect::Zio, _) => {
+            let call = if error.is_some() { format!("{call}.either") } else { call.to_string() };
+            format!("{value} <- Async[F].fromFuture(Async[F].delay(Unsafe.unsafe {{ implicit u => runtime.unsafe.runToFuture({call}) }}))")
+        }
+        (_, Some(e)) if options.errors == ErrorStyle::MonadError => {
+            let errors = errors_param(e);
+            format!("outcome <- {errors}.attempt({call}({errors}))")
+        }
+        _ if project.is_effectful(service) => format!("{value} <- {call}"),
+        _ => format!("result <- Async[F].delay({call})"),
+    }
+}
+
+/// Parameters holding the `MonadError` of each error type `methods`
+/// declare, passed explicitly: an implicit would be ambiguous with `Async`.
+pub(crate) fn error_instances<'m>(project: &ProjectContext, methods: impl IntoIterator<Item = &'m ast::Method>) -> Result<Vec<String>, IozhError> {
+    let mut errors = vec![];
+    let options = &project.options;
+    if options.errors == ErrorStyle::MonadError && matches!(options.effect, Effect::Plain | Effect::Tagless) {
+        for e in methods.into_iter().filter_map(|m| m.error.as_ref()) {
+            let e = e.gen()?.to_string();
+            let param = format!("{}: MonadError[F, {e}]", errors_param(&e));
+            if !errors.contains(&param) {
+                errors.push(param);
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// Route answering with the values of a stream as they come; the
+/// errors of a stream of `Either` are sent along with its results.
+fn stream_route(route: &ast::HttpRoute, pattern: &str, mut steps: Vec<String>, imports: Vec<String>, call: &str, error: Option<&str>, options: &Scala2Options) -> Vec<GenResult> {
+    let monad_error = options.errors == ErrorStyle::MonadError && matches!(options.effect, Effect::Plain | Effect::Tagless);
+    match error {
+        Some(e) if monad_error => {
+            steps.push(format!("result <- {call}({}).pure[F]", errors_param(e)));
+            steps.push("response <- streamed[F](req, result.map(_.asJson))".to_string());
+        }
+        Some(_) => {
+            steps.push(format!("result <- {call}.pure[F]"));
+            steps.push("response <- streamed[F](req, result.map(_.fold(_.asJson, _.asJson)))".to_string());
+        }
+        None => {
+            steps.push(format!("result <- {call}.pure[F]"));
+            steps.push("response <- streamed[F](req, result.map(_.asJson))".to_string());
+        }
+    }
+    route_case(route, pattern, &steps, imports)
+}
+
+/// `case` of the routes reading and answering a request with `steps`.
+fn route_case(route: &ast::HttpRoute, pattern: &str, steps: &[String], imports: Vec<String>) -> Vec<GenResult> {
+    let content = format!(
+        "case req @ {} -> {pattern} =>\n  handle {{\n    for {{\n{}\n    }} yield response\n  }}",
+        route.verb,
+        steps.iter().flat_map(|s| s.lines()).map(|s| format!("      {s}")).collect::<Vec<_>>().join("\n"),
+    );
+    vec![GenResult {
+        unit: None,
+        content,
+        imports,
+        package: vec![],
+        block: None,
+    }]
+}
+
+impl <'a> HttpServiceContext<'a> {
+    fn service_param(&self, path: &[String], name: &str) -> String {
+        self.services
+            .iter()
+            .find(|(p, n)| p == path && n == name)
+            .map(|(_, n)| service_param(n))
+            .unwrap_or_else(|| service_param(name))
+    }
+}
+
+/// Namespaces whose circe codecs the routes need, and the types declared
+/// outside the namespace of the routes.
+pub(crate) fn type_imports(index: &TypeIndex, tp: &ast::TypePath, from: &[String], acc: &mut Vec<String>) {
+    if let Some(entry) = index.resolve_path(tp, from) {
+        let package = entry.path.join(".");
+        acc.push(format!("{package}.CirceImplicits._"));
+        if entry.path != from {
+            let top = match entry.def {
+                TypeDef::Variant { .. } => entry.name.split('.').next().unwrap_or(&entry.name),
+                _ => &entry.name,
+            };
+            acc.push(format!("{package}.{top}"));
+        }
+    }
+    for t in &tp.path {
+        for arg in &t.args {
+            type_imports(index, arg, from, acc);
+        }
+    }
+}
+
+impl InNspace for ast::HttpService {
+    fn gen_in_nspace(&self, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
+        let p = parent.project.p;
+        let index = TypeIndex::build(p);
+        let mut services: Vec<(Vec<String>, String)> = vec![];
+        let mut dependencies = vec![];
+        let mut imports = vec![
+            "cats.effect.Async".to_string(),
+            "cats.syntax.all._".to_string(),
+            "io.circe.syntax._".to_string(),
+            "iozh.circe.Implicits._".to_string(),
+            "iozh.http.Http4sSupport._".to_string(),
+            "org.http4s._".to_string(),
+            "org.http4s.circe._".to_string(),
+            "org.http4s.dsl.Http4sDsl".to_string(),
+        ];
+        for r in &self.routes {
+            if let Some((path, s, m)) = p.resolve_method(&r.method, &parent.path) {
+                if !services.iter().any(|(sp, sn)| *sp == path && *sn == s.name.name) {
+                    services.push((path.clone(), s.name.name.clone()));
+                    let name = sanitize(&s.name.name);
+                    let ty = if path == parent.path { name } else { format!("{}.{name}", path.join(".")) };
+                    let ty = if parent.project.is_effectful(s) { format!("{ty}[F]") } else { ty };
+                    dependencies.push(format!("{}: {ty}", service_param(&s.name.name)));
+                }
+                type_imports(&index, &m.result, &path, &mut imports);
+                if let Some(e) = &m.error {
+                    type_imports(&index, e, &path, &mut imports);
+                }
+            }
+            type_imports(&index, &r.input, &parent.path, &mut imports);
+            for f in &r.fields {
+                type_imports(&index, &f.field.type_path, &parent.path, &mut imports);
+            }
+        }
+        let scope = HttpServiceContext {
+            nspace: parent,
+            base_name: sanitize(&self.name.name),
+            full_type_name: self.name.gen()?.to_string(),
+            services,
+        };
+        let routes = self.routes.mapg(|x| x.gen_in_http_service(&scope))?;
+        imports.extend(routes.map_imports());
+        if parent.project.options.effect == Effect::Zio && !dependencies.is_empty() {
+            dependencies.push("runtime: Runtime[Any]".to_string());
+            imports.push("zio.Runtime".to_string());
+            imports.push("zio.Unsafe".to_string());
+        }
+        let dependencies = dependencies.join(", ");
+        let errors = error_instances(
+            parent.project,
+            self.routes.iter().filter_map(|r| p.resolve_method(&r.method, &parent.path).map(|(_, _, m)| m)),
+        )?;
+        if !errors.is_empty() {
+            imports.push("cats.MonadError".to_string());
+        }
+        let errors = if errors.is_empty() { String::new() } else { format!("({})", errors.join(", ")) };
+        let cases = routes
+            .map_content()
+            .iter()
+            .map(|c| c.lines().map(|l| format!("    {l}")).collect::<Vec<_>>().join("\n"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let content = format!(
+            "class {}[F[_]: Async]({dependencies}){errors} extends Http4sDsl[F] {{\n  val routes: HttpRoutes[F] = HttpRoutes.of[F] {{\n{cases}\n  }}\n}}\n",
+            scope.full_type_name,
+        );
+        Ok(vec![GenResult {
+            unit: Some(scope.base_name.clone()),
+            content,
+            imports,
+            package: parent.path.clone(),
+            block: None,
+        }])
+    }
+}
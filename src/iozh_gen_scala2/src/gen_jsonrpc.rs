@@ -0,0 +1,284 @@
+use stripmargin::StripMargin;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::TypeIndex;
+
+use crate::gen::*;
+use crate::context::*;
+use crate::utils::ResultVec;
+use crate::gen_http4s::{call_step, error_instances, scala_string, service_param, type_imports, var};
+
+pub fn json_rpc_pack(_project: &ProjectContext) -> std::result::Result<Vec<GenResult>, IozhError> {
+    let content = r#"
+    |/** An error object of JSON-RPC 2.0; methods fail with it to answer with it. */
+    |final case class RpcError(code: Int, message: String, data: Option[Json] = None) extends Exception(message)
+    |
+    |object RpcError {
+    |  val ParseError = -32700
+    |  val InvalidRequest = -32600
+    |  val MethodNotFound = -32601
+    |  val InvalidParams = -32602
+    |  val InternalError = -32603
+    |  /** The error a service method declares, sent as `data`. */
+    |  val ServiceError = -32000
+    |
+    |  implicit val encoder: Encoder[RpcError] = Encoder.instance { e =>
+    |    Json.obj("code" -> e.code.asJson, "message" -> e.message.asJson).deepMerge(e.data.fold(Json.obj())(d => Json.obj("data" -> d)))
+    |  }
+    |
+    |  implicit val decoder: Decoder[RpcError] = Decoder.instance { c =>
+    |    for {
+    |      code <- c.downField("code").as[Int]
+    |      message <- c.downField("message").as[String]
+    |      data <- c.downField("data").as[Option[Json]]
+    |    } yield RpcError(code, message, data)
+    |  }
+    |}
+    |
+    |/** A named parameter of a call, missing ones read as `null`. */
+    |def param[F[_]: Async, A: Decoder](params: Json, name: String): F[A] =
+    |  params.hcursor.downField(name).focus.getOrElse(Json.Null).as[A]
+    |    .leftMap(e => RpcError(RpcError.InvalidParams, s"Invalid $name: ${e.message}"))
+    |    .liftTo[F]
+    |
+    |/** Fails a call with the error its method declares. */
+    |def failure[F[_]: Async, E: Encoder](error: E): F[Json] =
+    |  RpcError(RpcError.ServiceError, "Service error", Some(error.asJson)).raiseError[F, Json]
+    |
+    |/** Answers a request or a batch of them; notifications are not answered. */
+    |def dispatch[F[_]: Async](methods: Map[String, Json => F[Json]], request: Json): F[Option[Json]] =
+    |  request.asArray match {
+    |    case Some(batch) if batch.nonEmpty =>
+    |      batch.toList.traverse(dispatchOne[F](methods, _)).map(_.flatten).map {
+    |        case Nil => None
+    |        case answers => Some(Json.arr(answers: _*))
+    |      }
+    |    case Some(_) => Some(errorResponse(Json.Null, RpcError(RpcError.InvalidRequest, "Invalid Request"))).pure[F]
+    |    case None => dispatchOne[F](methods, request)
+    |  }
+    |
+    |/** Same as `dispatch` for the text of a request. */
+    |def dispatchText[F[_]: Async](methods: Map[String, Json => F[Json]], request: String): F[Option[String]] =
+    |  parse(request) match {
+    |    case Left(_) => Some(errorResponse(Json.Null, RpcError(RpcError.ParseError, "Parse error")).noSpaces).pure[F]
+    |    case Right(json) => dispatch[F](methods, json).map(_.map(_.noSpaces))
+    |  }
+    |
+    |private def dispatchOne[F[_]: Async](methods: Map[String, Json => F[Json]], request: Json): F[Option[Json]] = {
+    |  val c = request.hcursor
+    |  val id = c.downField("id").focus
+    |  val call = for {
+    |    _ <- c.downField("jsonrpc").as[String].toOption.filter(_ == "2.0")
+    |    method <- c.downField("method").as[String].toOption
+    |  } yield (method, c.downField("params").focus.getOrElse(Json.obj()))
+    |  call match {
+    |    case None => Some(errorResponse(id.getOrElse(Json.Null), RpcError(RpcError.InvalidRequest, "Invalid Request"))).pure[F]
+    |    case Some((method, params)) =>
+    |      val answer = methods.get(method) match {
+    |        case None => RpcError(RpcError.MethodNotFound, s"Method not found: $method").raiseError[F, Json]
+    |        case Some(_) if !params.isObject => RpcError(RpcError.InvalidParams, "Params must be an object").raiseError[F, Json]
+    |        case Some(run) => run(params)
+    |      }
+    |      answer.attempt.map { outcome =>
+    |        id.map { id =>
+    |          outcome match {
+    |            case Right(result) => Json.obj("jsonrpc" -> "2.0".asJson, "result" -> result, "id" -> id)
+    |            case Left(e: RpcError) => errorResponse(id, e)
+    |            case Left(_) => errorResponse(id, RpcError(RpcError.InternalError, "Internal error"))
+    |          }
+    |        }
+    |      }
+    |  }
+    |}
+    |
+    |private def errorResponse(id: Json, error: RpcError): Json =
+    |  Json.obj("jsonrpc" -> "2.0".asJson, "error" -> error.asJson, "id" -> id)
+    |
+    |/** Calls `method`, `transport` sending the request and returning the response. */
+    |def call[F[_]: Async](transport: Json => F[Json], method: String, params: Json): F[Json] =
+    |  Async[F].delay(UUID.randomUUID().toString).flatMap { id =>
+    |    transport(Json.obj("jsonrpc" -> "2.0".asJson, "method" -> method.asJson, "params" -> params, "id" -> id.asJson))
+    |  }
+    |
+    |/** The result of a response, failing with its error. */
+    |def result[F[_]: Async, A: Decoder](response: Json): F[A] =
+    |  response.hcursor.downField("error").focus match {
+    |    case Some(error) => error.as[RpcError].getOrElse(invalid(response)).raiseError[F, A]
+    |    case None => response.hcursor.downField("result").as[A].leftMap(_ => invalid(response)).liftTo[F]
+    |  }
+    |
+    |/** Same as `result` for a method declaring an error, read from the `data` of a service error. */
+    |def outcome[F[_]: Async, E: Decoder, A: Decoder](response: Json): F[Either[E, A]] =
+    |  result[F, A](response).map(_.asRight[E]).recoverWith {
+    |    case RpcError(RpcError.ServiceError, _, Some(data)) => data.as[E].map(_.asLeft[A]).leftMap(_ => invalid(response)).liftTo[F]
+    |  }
+    |
+    |private def invalid(response: Json): RpcError =
+    |  RpcError(RpcError.InternalError, s"Invalid response: ${response.noSpaces}")
+    "#.strip_margin();
+    Ok(vec![GenResult {
+        unit: Some("JsonRpc".to_string()),
+        content,
+        imports: vec![
+            "cats.effect.Async".to_string(),
+            "cats.syntax.all._".to_string(),
+            "io.circe.Decoder".to_string(),
+            "io.circe.Encoder".to_string(),
+            "io.circe.Json".to_string(),
+            "io.circe.parser.parse".to_string(),
+            "io.circe.syntax._".to_string(),
+            "java.util.UUID".to_string(),
+        ],
+        package: vec![
+            "iozh".to_string(),
+            "jsonrpc".to_string(),
+        ],
+        block: Some("object JsonRpc".to_string()),
+    }])
+}
+
+/// Name of a method over JSON-RPC: its namespace path, its service and
+/// its own name, `app.UserService.findAll`, so that services of a
+/// namespace may declare methods of the same name.
+fn rpc_name(path: &[String], s: &ast::Service, m: &ast::Method) -> String {
+    path.iter().chain([&s.name.name, &m.name.name]).cloned().collect::<Vec<_>>().join(".")
+}
+
+/// Methods served over JSON-RPC, which has no streams.
+fn rpc_methods(s: &ast::Service) -> impl Iterator<Item = &ast::Method> {
+    s.methods.iter().filter(|m| !m.has_streams())
+}
+
+/// A JSON-RPC 2.0 server dispatching to a service and a client calling
+/// it, params sent as an object of the named arguments. Services and
+/// methods with type parameters are left out, their codecs being unknown.
+pub fn json_rpc_service(s: &ast::Service, parent: &NspaceContext) -> Result<Vec<GenResult>, IozhError> {
+    let project = parent.project;
+    if !s.name.args.is_empty() || s.methods.iter().any(|m| !m.name.args.is_empty()) {
+        return Ok(vec![]);
+    }
+    let index = TypeIndex::build(project.p);
+    let mut imports = vec![
+        "cats.effect.Async".to_string(),
+        "cats.syntax.all._".to_string(),
+        "io.circe.Json".to_string(),
+        "io.circe.syntax._".to_string(),
+        "iozh.circe.Implicits._".to_string(),
+        "iozh.jsonrpc.JsonRpc._".to_string(),
+    ];
+    let mut handlers = vec![];
+    for m in rpc_methods(s) {
+        let mut steps = vec![];
+        for a in &m.args {
+            let ty = a.type_path.gen()?.to_string();
+            imports.extend(imports_for(&ty));
+            type_imports(&index, &a.type_path, &parent.path, &mut imports);
+            steps.push(format!("{} <- param[F, {ty}](params, {})", var(&a.name), scala_string(&a.name)));
+        }
+        type_imports(&index, &m.result, &parent.path, &mut imports);
+        let args = m.args.iter().map(|a| var(&a.name)).collect::<Vec<_>>().join(", ");
+        let call = format!("{}.{}({args})", service_param(&s.name.name), sanitize(&m.name.name));
+        let error = m.error.as_ref().map(|e| e.gen()).transpose()?.map(|e| e.to_string());
+        steps.push(call_step(project, s, &call, error.as_deref()));
+        let answer = match (&error, &m.error) {
+            (Some(e), Some(tp)) => {
+                type_imports(&index, tp, &parent.path, &mut imports);
+                steps.push(format!("answer <- outcome.fold(failure[F, {e}](_), _.asJson.pure[F])"));
+                "answer"
+            }
+            _ => "result.asJson",
+        };
+        handlers.push(format!(
+            "{} -> {{ params =>\n  for {{\n{}\n  }} yield {answer}\n}}",
+            scala_string(&rpc_name(&parent.path, s, m)),
+            steps.iter().map(|s| format!("    {s}")).collect::<Vec<_>>().join("\n"),
+        ));
+    }
+
+    let base_name = sanitize(&s.name.name);
+    let effectful = project.is_effectful(s);
+    let service_type = if effectful { format!("{base_name}[F]") } else { base_name.clone() };
+    let mut dependencies = vec![format!("{}: {service_type}", service_param(&s.name.name))];
+    if project.options.effect == Effect::Zio {
+        dependencies.push("runtime: Runtime[Any]".to_string());
+        imports.push("zio.Runtime".to_string());
+        imports.push("zio.Unsafe".to_string());
+    }
+    let errors = error_instances(project, rpc_methods(s))?;
+    if !errors.is_empty() {
+        imports.push("cats.MonadError".to_string());
+    }
+    let errors = if errors.is_empty() { String::new() } else { format!("({})", errors.join(", ")) };
+    let handlers = handlers
+        .iter()
+        .map(|h| h.lines().map(|l| format!("    {l}")).collect::<Vec<_>>().join("\n"))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    let server = format!(
+        "/** {base_name} over JSON-RPC 2.0. */\nclass {base_name}JsonRpcServer[F[_]: Async]({}){errors} {{\n  val methods: Map[String, Json => F[Json]] = Map(\n{handlers}\n  )\n\n  def handle(request: Json): F[Option[Json]] = dispatch[F](methods, request)\n}}",
+        dependencies.join(", "),
+    );
+
+    // The client implements the trait when its methods run in `F`.
+    let implements = effectful
+        && matches!(project.options.effect, Effect::Plain | Effect::Tagless)
+        && !s.methods.iter().any(|m| m.has_streams());
+    let scope = parent.push_service(s)?;
+    let mut defs = vec![];
+    for m in rpc_methods(s) {
+        let (args, _) = scope.method_args(m, "F")?;
+        let params = m.args
+            .iter()
+            .map(|a| format!("{} -> {}.asJson", scala_string(&a.name), sanitize(&a.name)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let request = format!("call[F](transport, {}, Json.obj({params}))", scala_string(&rpc_name(&parent.path, s, m)));
+        let ret = m.result.gen()?.to_string();
+        let error = m.error.as_ref().map(|e| e.gen()).transpose()?.map(|e| e.to_string());
+        let (implicits, ty, body) = match &error {
+            None => (String::new(), format!("F[{ret}]"), format!("{request}.flatMap(result[F, {ret}](_))")),
+            Some(e) if implements && project.options.errors == ErrorStyle::MonadError => {
+                let (implicits, ty, _) = scope.method_type(m, "F")?;
+                // Spelled out: `F` and `Async[F]` would make syntax ambiguous.
+                let body = format!("F.flatMap(Async[F].flatMap({request})(outcome[F, {e}, {ret}](_)))(F.fromEither(_))");
+                (implicits, ty, body)
+            }
+            Some(e) => (String::new(), format!("F[Either[{e}, {ret}]]"), format!("{request}.flatMap(outcome[F, {e}, {ret}](_))")),
+        };
+        defs.push(format!("  def {}({args}){implicits}: {ty} =\n    {body}", m.name.gen()?.to_string()));
+    }
+    let extends = if implements { format!(" extends {base_name}[F]") } else { String::new() };
+    let client = format!(
+        "/** Calls {base_name} over JSON-RPC 2.0, `transport` sending a request and returning the response. */\nclass {base_name}JsonRpcClient[F[_]: Async](transport: Json => F[Json]){extends} {{\n{}\n}}",
+        defs.join("\n\n"),
+    );
+
+    Ok(vec![GenResult {
+        unit: Some(format!("{base_name}JsonRpc")),
+        content: format!("{server}\n\n{client}\n"),
+        imports,
+        package: parent.path.clone(),
+        block: None,
+    }])
+}
+
+/// Methods of a service must have distinct names over JSON-RPC, which
+/// has no overloading.
+pub fn check_rpc_names(nspace: &ast::Nspace, path: &[String]) -> Result<(), IozhError> {
+    for item in &nspace.items {
+        if let ast::NspaceItem::Service(s) = item {
+            let mut seen: Vec<String> = vec![];
+            for m in rpc_methods(s) {
+                let name = rpc_name(path, s, m);
+                if seen.contains(&name) {
+                    return Err(IozhError {
+                        pos: m.pos.clone(),
+                        msg: format!("JSON-RPC method {name} is declared twice by {}", s.name.name),
+                    });
+                }
+                seen.push(name);
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,4 +2,7 @@ mod utils;
 mod loc;
 pub mod gen;
 mod gen_circe;
+mod gen_http4s;
+mod gen_jsonrpc;
+mod gen_events;
 mod context;
@@ -1,6 +1,7 @@
 use iozh_parse::ast;
 
 pub trait Loc {
+    #[allow(dead_code)]
     fn get_pos(&self) -> ast::Pos;
 }
 
@@ -93,4 +94,10 @@ impl Loc for ast::Nspace {
     fn get_pos(&self) -> ast::Pos {
         self.pos.clone()
     }
-}
\ No newline at end of file
+}
+
+impl Loc for ast::Events {
+    fn get_pos(&self) -> ast::Pos {
+        self.pos.clone()
+    }
+}
@@ -0,0 +1,485 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+use iozh_gen_scala2::gen::{fs_sanitize, imports_for, map_type, sanitize};
+use crate::support::SUPPORT;
+
+const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+const INDENT: &str = "  ";
+
+const IMPORTS: [&str; 3] = [
+    "io.circe.{Decoder, DecodingFailure, Encoder, Json}",
+    "io.circe.syntax.*",
+    "iozh.circe.given",
+];
+
+pub struct Scala3Context<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    /// Imports needed by the types mapped so far, collected per file.
+    imports: RefCell<BTreeSet<String>>,
+}
+
+pub fn sc_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn package(path: &[String]) -> String {
+    path.iter().map(|p| sanitize(p)).collect::<Vec<_>>().join(".")
+}
+
+fn doc_block(doc: &str, indent: &str) -> String {
+    let text = doc_text(doc);
+    if text.is_empty() {
+        return String::new();
+    }
+    let text = text.replace("*/", "* /");
+    if text.contains('\n') {
+        let lines = text.lines().map(|l| format!("{indent} * {l}").trim_end().to_string()).collect::<Vec<_>>();
+        format!("{indent}/**\n{}\n{indent} */\n", lines.join("\n"))
+    } else {
+        format!("{indent}/** {text} */\n")
+    }
+}
+
+fn generics(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", params.join(", "))
+    }
+}
+
+fn indented(block: &str) -> String {
+    block.lines().map(|l| if l.is_empty() { "\n".to_string() } else { format!("{INDENT}{l}\n") }).collect()
+}
+
+/// Name a field is bound to in decoders and encoder patterns.
+fn binder(field: &str) -> String {
+    sanitize(&format!("_{field}"))
+}
+
+/// `given` header, with a context bound per type parameter for generic types.
+fn given(typeclass: &str, ty: &str, params: &[String]) -> String {
+    if params.is_empty() {
+        format!("given {typeclass}[{ty}] =")
+    } else {
+        let bounds = params.iter().map(|p| format!("{p}: {typeclass}")).collect::<Vec<_>>();
+        format!("given [{}]: {typeclass}[{ty}] =", bounds.join(", "))
+    }
+}
+
+/// Extends clause of an enum case: the enum's parameters the case does not
+/// use are `Nothing`, which is why enum parameters are declared covariant.
+fn extends(choice: &str, params: &[String], used: &[String]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let args = params
+        .iter()
+        .map(|p| if used.contains(p) { p.clone() } else { "Nothing".to_string() })
+        .collect::<Vec<_>>();
+    format!(" extends {choice}{}", generics(&args))
+}
+
+/// Literal type of a choice made only of `Value` items of one kind, which
+/// becomes an enum parameterized by that value.
+fn value_type(variants: &[ChoiceVariant]) -> Option<&'static str> {
+    let mut kinds = variants.iter().map(|v| match v {
+        ChoiceVariant::Value { value: ast::Literal::Int { .. }, .. } => Some("Int"),
+        ChoiceVariant::Value { value: ast::Literal::String { .. }, .. } => Some("String"),
+        _ => None,
+    });
+    let first = kinds.next()??;
+    kinds.all(|k| k == Some(first)).then_some(first)
+}
+
+fn value_literal(value: &ast::Literal) -> String {
+    match value {
+        ast::Literal::Int { value, .. } => value.to_string(),
+        value => sc_string(&value.unquoted()),
+    }
+}
+
+/// Circe's encoding of a record: fields with `null` values left out, then
+/// the tag when the choice is tagged.
+fn json_object(fields: &[(String, String)], tag: Option<&(String, String)>) -> String {
+    let tag = tag.map(|(key, value)| format!("{} -> Json.fromString({})", sc_string(key), sc_string(value)));
+    if fields.is_empty() {
+        return format!("Json.obj({})", tag.unwrap_or_default());
+    }
+    let mut out = String::from("Json.fromFields(\n");
+    out.push_str(&format!("{INDENT}List(\n"));
+    for (key, expr) in fields {
+        out.push_str(&format!("{INDENT}{INDENT}{} -> {expr}.asJson,\n", sc_string(key)));
+    }
+    out.push_str(&format!("{INDENT}).filter(!_._2.isNull)"));
+    if let Some(tag) = tag {
+        out.push_str(&format!(" :+ ({tag})"));
+    }
+    out.push_str("\n)");
+    out
+}
+
+impl <'a> Scala3Context<'a> {
+    pub fn new(p: &'a ast::Project) -> Scala3Context<'a> {
+        Scala3Context {
+            p,
+            index: TypeIndex::build(p),
+            imports: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    fn qualified(entry: &TypeEntry, nspace: &[String]) -> String {
+        let name = entry.name.split('.').map(sanitize).collect::<Vec<_>>().join(".");
+        if entry.path == nspace {
+            name
+        } else {
+            format!("{}.{name}", package(&entry.path))
+        }
+    }
+
+    pub fn sc_type(&self, tp: &ast::TypePath, nspace: &[String], params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "Unit".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return tag.name.clone();
+        }
+        let args = tag.args.iter().map(|a| self.sc_type(a, nspace, params)).collect::<Vec<_>>();
+        if let Some(builtin) = Builtin::from_path(tp) {
+            let name = if builtin == Builtin::File { "File" } else { map_type(&tag.name) };
+            self.imports.borrow_mut().extend(imports_for(name));
+            return name.to_string() + &generics(&args);
+        }
+        match self.index.resolve_path(tp, nspace) {
+            Some(entry) => Self::qualified(entry, nspace) + &generics(&args),
+            None => tp.path.iter().map(|t| sanitize(&t.name)).collect::<Vec<_>>().join(".") + &generics(&args),
+        }
+    }
+
+    /// Constructor parameters; one per line when there are docs to attach
+    /// or more than a couple of them.
+    fn gen_params(&self, fields: &[&ast::Field], nspace: &[String], params: &[String]) -> String {
+        if fields.len() <= 2 && fields.iter().all(|f| doc_text(&f.doc).is_empty()) {
+            let fields = fields
+                .iter()
+                .map(|f| format!("{}: {}", sanitize(&f.name), self.sc_type(&f.type_path, nspace, params)))
+                .collect::<Vec<_>>();
+            return format!("({})", fields.join(", "));
+        }
+        let mut out = String::from("(\n");
+        for f in fields {
+            out.push_str(&doc_block(&f.doc, INDENT));
+            out.push_str(&format!("{INDENT}{}: {},\n", sanitize(&f.name), self.sc_type(&f.type_path, nspace, params)));
+        }
+        out.push(')');
+        out
+    }
+
+    /// Decodes the fields of a record and builds it with `ctor`. Missing
+    /// lists decode as empty, like the Scala 2 codecs.
+    fn record_decoder(&self, ctor: &str, fields: &[&ast::Field], nspace: &[String], params: &[String]) -> String {
+        let mut out = String::from("for {\n");
+        for f in fields {
+            let ty = self.sc_type(&f.type_path, nspace, params);
+            let key = sc_string(&f.name);
+            let get = match Builtin::from_path(&f.type_path) {
+                Some(Builtin::List) => format!("h.getOrElse[{ty}]({key})(List.empty)"),
+                _ => format!("h.get[{ty}]({key})"),
+            };
+            out.push_str(&format!("{INDENT}{} <- {get}\n", binder(&f.name)));
+        }
+        let args = fields
+            .iter()
+            .map(|f| format!("{} = {}", sanitize(&f.name), binder(&f.name)))
+            .collect::<Vec<_>>();
+        out.push_str(&format!("}} yield {ctor}({})", args.join(", ")));
+        out
+    }
+
+    pub fn gen_structure(&self, out: &mut String, s: &ast::Structure, nspace: &[String]) {
+        let name = sanitize(&s.name.name);
+        let params = s.name.param_names();
+        let ty = format!("{name}{}", generics(&params));
+        let fields = s.get_fields();
+        out.push_str(&doc_block(&s.doc, ""));
+        out.push_str(&format!("final case class {ty}{} derives CanEqual\n\n", self.gen_params(&fields, nspace, &params)));
+
+        let decoder = if fields.is_empty() {
+            format!("Decoder.instance(_ => Right({name}()))")
+        } else {
+            format!("Decoder.instance {{ h =>\n{}}}", indented(&self.record_decoder(&name, &fields, nspace, &params)))
+        };
+        let pairs = fields.iter().map(|f| (f.name.clone(), format!("x.{}", sanitize(&f.name)))).collect::<Vec<_>>();
+        let encoder = format!("(x: {ty}) => {}", json_object(&pairs, None));
+        out.push_str(&format!("object {name} {{\n"));
+        out.push_str(&indented(&format!("{} {decoder}\n\n{} {encoder}", given("Decoder", &ty, &params), given("Encoder", &ty, &params))));
+        out.push_str("}\n");
+    }
+
+    /// Choices are enums: bare and literal items are singleton cases, `Wrap`
+    /// items single-field cases and structures class cases. A choice of
+    /// literals of one type is parameterized by them. The codecs are written
+    /// out as `given`s rather than derived, since they must keep the wire
+    /// format of the Scala 2 backend.
+    pub fn gen_choice(&self, out: &mut String, c: &ast::Choice, nspace: &[String]) {
+        let name = sanitize(&c.name.name);
+        let params = c.name.param_names();
+        let ty = format!("{name}{}", generics(&params));
+        let variants = c.variants(self.p);
+        out.push_str(&doc_block(&c.doc, ""));
+        if variants.is_empty() {
+            out.push_str(&format!("sealed trait {ty}\n\nobject {name} {{\n"));
+            let decoder = format!("Decoder.failedWithMessage({})", sc_string(&format!("{name} has no items")));
+            let encoder = format!("(_: {ty}) => Json.Null");
+            out.push_str(&indented(&format!("{} {decoder}\n\n{} {encoder}", given("Decoder", &ty, &params), given("Encoder", &ty, &params))));
+            out.push_str("}\n");
+            return;
+        }
+
+        let values = if params.is_empty() { value_type(&variants) } else { None };
+        let covariant = params.iter().map(|p| format!("+{p}")).collect::<Vec<_>>();
+        match values {
+            Some(vt) => out.push_str(&format!("enum {name}(val value: {vt}) derives CanEqual {{\n")),
+            None => out.push_str(&format!("enum {name}{} derives CanEqual {{\n", generics(&covariant))),
+        }
+        let mut cases = vec![];
+        if variants.iter().all(|v| matches!(v, ChoiceVariant::Record { .. })) {
+            for f in &c.fields {
+                let mut def = doc_block(&f.doc, INDENT);
+                def.push_str(&format!("{INDENT}def {}: {}\n", sanitize(&f.name), self.sc_type(&f.type_path, nspace, &params)));
+                cases.push(def);
+            }
+        }
+        for v in &variants {
+            let mut out = String::new();
+            let item = sanitize(&v.name().name);
+            match v {
+                ChoiceVariant::Unit { doc, .. } => {
+                    out.push_str(&doc_block(doc, INDENT));
+                    out.push_str(&format!("{INDENT}case {item}{}\n", extends(&name, &params, &[])));
+                }
+                ChoiceVariant::Value { doc, value, .. } => {
+                    out.push_str(&doc_block(doc, INDENT));
+                    let parent = match values {
+                        Some(_) => format!(" extends {name}({})", value_literal(value)),
+                        None => extends(&name, &params, &[]),
+                    };
+                    out.push_str(&format!("{INDENT}case {item}{parent}\n"));
+                }
+                ChoiceVariant::Wrap { doc, field, target, .. } => {
+                    let used = params.iter().filter(|p| target.mentions(p)).cloned().collect::<Vec<_>>();
+                    out.push_str(&doc_block(doc, INDENT));
+                    out.push_str(&format!(
+                        "{INDENT}case {item}{}({}: {}){}\n",
+                        generics(&used),
+                        sanitize(field),
+                        self.sc_type(target, nspace, &params),
+                        extends(&name, &params, &used),
+                    ));
+                }
+                ChoiceVariant::Record { structure, fields, .. } => {
+                    let used = used_params(&params, fields);
+                    out.push_str(&doc_block(&structure.doc, INDENT));
+                    let case = format!(
+                        "case {item}{}{}{}",
+                        generics(&used),
+                        self.gen_params(fields, nspace, &params),
+                        extends(&name, &params, &used),
+                    );
+                    out.push_str(&indented(&case));
+                }
+            }
+            cases.push(out);
+        }
+        // Multi-line cases are set apart by blank lines, one-liners kept together.
+        for (i, case) in cases.iter().enumerate() {
+            let multiline = |c: &String| c.lines().count() > 1;
+            if i > 0 && (multiline(case) || multiline(&cases[i - 1])) {
+                out.push('\n');
+            }
+            out.push_str(case);
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!("object {name} {{\n"));
+        let decoder = self.choice_decoder(c, &variants, &ty, nspace);
+        let encoder = self.choice_encoder(c, &variants, &ty);
+        out.push_str(&indented(&format!("{} {decoder}\n\n{} {encoder}", given("Decoder", &ty, &params), given("Encoder", &ty, &params))));
+        out.push_str("}\n");
+    }
+
+    /// Tagged choices dispatch on the tag; any other choice tries its items
+    /// in order, as the Scala 2 codecs do.
+    fn choice_decoder(&self, c: &ast::Choice, variants: &[ChoiceVariant], ty: &str, nspace: &[String]) -> String {
+        let name = sanitize(&c.name.name);
+        let params = c.name.param_names();
+        let tagged = variants.iter().all(|v| matches!(v, ChoiceVariant::Record { tag: Some(_), .. }));
+        if tagged {
+            let key = match variants.first() {
+                Some(ChoiceVariant::Record { tag: Some((key, _)), .. }) => key.clone(),
+                _ => String::new(),
+            };
+            let mut cases = String::new();
+            for v in variants {
+                if let ChoiceVariant::Record { structure, fields, tag: Some((_, value)) } = v {
+                    let ctor = format!("{name}.{}", sanitize(&structure.name.name));
+                    cases.push_str(&format!("case {} =>\n", sc_string(value)));
+                    cases.push_str(&indented(&self.record_decoder(&ctor, fields, nspace, &params)));
+                }
+            }
+            let unexpected = sc_string(&format!("Unexpected {key}: "));
+            cases.push_str(&format!("case other => Left(DecodingFailure({unexpected} + other, h.history))\n"));
+            let body = format!("h.get[String]({}).flatMap {{\n{}}}", sc_string(&key), indented(&cases));
+            return format!("Decoder.instance {{ h =>\n{}}}", indented(&body));
+        }
+
+        let mut items = String::new();
+        for v in variants {
+            let item = format!("{name}.{}", sanitize(&v.name().name));
+            let decoder = match v {
+                ChoiceVariant::Unit { wire, .. } => {
+                    let expected = sc_string(&format!("Expected {wire} but got "));
+                    format!("Decoder.decodeString.emap(v => if v == {} then Right({item}) else Left({expected} + v))", sc_string(wire))
+                }
+                ChoiceVariant::Value { value, .. } => {
+                    let (decoder, literal) = match value {
+                        ast::Literal::Int { .. } => ("decodeLong", format!("{}L", value_literal(value))),
+                        _ => ("decodeString", value_literal(value)),
+                    };
+                    format!("Decoder.{decoder}.emap(v => if v == {literal} then Right({item}) else Left(\"Expected {item} but got \" + v))")
+                }
+                ChoiceVariant::Wrap { target, .. } => {
+                    format!("Decoder.instance(_.as[{}].map({item}(_)))", self.sc_type(target, nspace, &params))
+                }
+                ChoiceVariant::Record { fields, .. } => {
+                    format!("Decoder.instance {{ h =>\n{}}}", indented(&self.record_decoder(&item, fields, nspace, &params)))
+                }
+            };
+            items.push_str(&indented(&format!("{decoder},")));
+        }
+        format!("List[Decoder[{ty}]](\n{items}).reduceLeft(_ or _)")
+    }
+
+    fn choice_encoder(&self, c: &ast::Choice, variants: &[ChoiceVariant], ty: &str) -> String {
+        let name = sanitize(&c.name.name);
+        let mut cases = String::new();
+        for v in variants {
+            let item = format!("{name}.{}", sanitize(&v.name().name));
+            let case = match v {
+                ChoiceVariant::Unit { wire, .. } => format!("case {item} => Json.fromString({})", sc_string(wire)),
+                ChoiceVariant::Value { value: ast::Literal::Int { value, .. }, .. } => format!("case {item} => Json.fromLong({value}L)"),
+                ChoiceVariant::Value { value, .. } => format!("case {item} => Json.fromString({})", value_literal(value)),
+                ChoiceVariant::Wrap { field, .. } => {
+                    let field = binder(field);
+                    format!("case {item}({field}) => {field}.asJson")
+                }
+                ChoiceVariant::Record { fields, tag, .. } => {
+                    let binders = fields.iter().map(|f| binder(&f.name)).collect::<Vec<_>>();
+                    let pairs = fields.iter().map(|f| (f.name.clone(), binder(&f.name))).collect::<Vec<_>>();
+                    format!("case {item}({}) =>\n{}", binders.join(", "), indented(&json_object(&pairs, tag.as_ref())).trim_end())
+                }
+            };
+            cases.push_str(&case);
+            cases.push('\n');
+        }
+        format!("(x: {ty}) => x match {{\n{}}}", indented(&cases))
+    }
+
+    /// Services are traits with one method per service method.
+    pub fn gen_service(&self, out: &mut String, s: &ast::Service, nspace: &[String]) {
+        let params = s.name.param_names();
+        out.push_str(&doc_block(&s.doc, ""));
+        out.push_str(&format!("trait {}{} {{\n", sanitize(&s.name.name), generics(&params)));
+        for (i, m) in s.methods.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let mut mparams = params.clone();
+            let own = m.name.param_names();
+            mparams.extend(own.iter().cloned());
+            let args = m.args
+                .iter()
+                .map(|a| format!("{}: {}", sanitize(&a.name), self.sc_type(&a.type_path, nspace, &mparams)))
+                .collect::<Vec<_>>();
+            out.push_str(&doc_block(&m.doc, INDENT));
+            out.push_str(&format!(
+                "{INDENT}def {}{}({}): {}\n",
+                sanitize(&m.name.name),
+                generics(&own),
+                args.join(", "),
+                self.sc_type(&m.result, nspace, &mparams),
+            ));
+        }
+        out.push_str("}\n");
+    }
+
+    pub fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<Option<String>, IozhError> {
+        self.imports.borrow_mut().clear();
+        let mut body = String::new();
+        for item in &nspace.items {
+            let mut decl = String::new();
+            match item {
+                ast::NspaceItem::Structure(idx) => self.gen_structure(&mut decl, self.p.get_structure(*idx)?, path),
+                ast::NspaceItem::Choice(idx) => self.gen_choice(&mut decl, self.p.get_choice(*idx)?, path),
+                ast::NspaceItem::Service(s) => self.gen_service(&mut decl, s, path),
+                ast::NspaceItem::HttpService(_) | ast::NspaceItem::Storage(_) | ast::NspaceItem::Events(_) | ast::NspaceItem::Nspace(_) => {}
+            }
+            if !decl.is_empty() {
+                body.push('\n');
+                body.push_str(&decl);
+            }
+        }
+        if body.is_empty() {
+            return Ok(None);
+        }
+        let mut imports = self.imports.borrow_mut();
+        imports.extend(IMPORTS.iter().map(|i| i.to_string()));
+        let mut out = String::from(HEADER);
+        out.push_str(&format!("package {}\n\n", package(path)));
+        for import in imports.iter() {
+            out.push_str(&format!("import {import}\n"));
+        }
+        out.push_str(&body);
+        Ok(Some(out))
+    }
+}
+
+/// Writes one file per namespace, `a.b` to `a/b/B.scala`, plus
+/// `iozh/circe/Givens.scala`. The generated code needs circe and speaks the
+/// same JSON as the Scala 2 backend.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let ctx = Scala3Context::new(&project);
+    let support = target_folder.join("iozh").join("circe");
+    out.write(&support.join("Givens.scala"), SUPPORT)?;
+    for (path, nspace) in project.all_nspaces() {
+        let content = match ctx.gen_nspace(&path, nspace)? {
+            Some(content) => content,
+            None => continue,
+        };
+        let dir = path.iter().fold(target_folder.to_path_buf(), |acc, p| acc.join(fs_sanitize(p)));
+        let mut file_name = fs_sanitize(&nspace.name);
+        if let Some(first) = file_name.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        out.write(&dir.join(format!("{file_name}.scala")), &content)?;
+    }
+    Ok(())
+}
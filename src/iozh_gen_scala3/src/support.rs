@@ -0,0 +1,24 @@
+/// Written as `iozh/circe/Givens.scala`: codecs for the builtins circe has
+/// none for, encoded the way the Scala 2 backend's `iozh.circe.Implicits` does.
+pub const SUPPORT: &str = r#"// Generated by iozh. Do not edit.
+package iozh.circe
+
+import io.circe.{Decoder, Encoder}
+import java.io.File
+import scala.concurrent.duration.{Duration, FiniteDuration}
+import scala.util.Try
+
+given durationEncoder: Encoder[Duration] = Encoder.encodeString.contramap(_.toString)
+given durationDecoder: Decoder[Duration] = Decoder.decodeString.emap { str =>
+  Try(Duration(str)).toEither.left.map(_.getMessage)
+}
+
+given finiteDurationEncoder: Encoder[FiniteDuration] = Encoder.encodeString.contramap(_.toString)
+given finiteDurationDecoder: Decoder[FiniteDuration] = durationDecoder.emap {
+  case d: FiniteDuration => Right(d)
+  case d => Left("Expected a finite duration but got " + d)
+}
+
+given fileEncoder: Encoder[File] = Encoder.encodeString.contramap(_.getName)
+given fileDecoder: Decoder[File] = Decoder.decodeString.map(File(_))
+"#;
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::TypeIndex;
+use iozh_output::Output;
+use iozh_gen_scala2::gen::sanitize;
+
+use crate::model::*;
+use crate::scala::*;
+use crate::support::DOOBIE_SUPPORT;
+
+/// `Meta` instances for the columns of iozh types, by Scala type.
+fn metas(repos: &[Repo]) -> BTreeMap<String, String> {
+    let mut metas = BTreeMap::new();
+    for c in repos.iter().flat_map(|r| r.table.columns.iter()).filter(|c| !is_native(c)) {
+        let ty = scala_type(value_type(c));
+        let meta = match c.sql_type.as_str() {
+            "TEXT" => "textMeta",
+            "BIGINT" => "longMeta",
+            _ => "jsonbMeta",
+        };
+        let name = lower_first(&ty.chars().filter(|c| c.is_alphanumeric()).collect::<String>());
+        metas.insert(ty.clone(), format!("implicit lazy val {name}Meta: Meta[{ty}] = {meta}[{ty}]"));
+    }
+    metas
+}
+
+fn where_clause(columns: &[&Column]) -> String {
+    columns
+        .iter()
+        .map(|c| format!("{} = ${{{}}}", ident(&c.name), sanitize(&c.field)))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+fn params(columns: &[&Column]) -> String {
+    columns
+        .iter()
+        .map(|c| format!("{}: {}", sanitize(&c.field), scala_type(value_type(c))))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn def(signature: &str, body: String) -> String {
+    let inner = INDENT.repeat(2);
+    format!("{inner}def {signature} =\n{inner}{INDENT}{body}\n")
+}
+
+fn gen_repo(r: &Repo) -> String {
+    let t = r.table;
+    let table = t.qualified();
+    let row = &r.row;
+    let columns = columns_list(&r.columns());
+    let mut defs = vec![];
+    let values = r.columns()
+        .iter()
+        .map(|c| format!("${{row.{}}}", sanitize(&c.field)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!("INSERT INTO {table} ({columns}) VALUES ({values})");
+    defs.push(def(&format!("insert(row: {row}): ConnectionIO[Int]"), format!("sql{}.update.run", sql_string(&sql))));
+    for l in r.key.iter().chain(r.lookups.iter()) {
+        let mut sql = format!("SELECT {columns} FROM {table} WHERE {}", where_clause(&l.columns));
+        if let Some(c) = l.order_by {
+            sql.push_str(&format!(" ORDER BY {}", ident(&c.name)));
+        }
+        let (result, run) = if l.unique { (format!("Option[{row}]"), "option") } else { (format!("List[{row}]"), "to[List]") };
+        let signature = format!("{}({}): ConnectionIO[{result}]", l.name, params(&l.columns));
+        defs.push(def(&signature, format!("sql{}.query[{row}].{run}", sql_string(&sql))));
+    }
+    if let Some(key) = &r.key {
+        let values = r.values();
+        if !values.is_empty() {
+            let set = values
+                .iter()
+                .map(|c| format!("{} = ${{row.{}}}", ident(&c.name), sanitize(&c.field)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let cond = key.columns
+                .iter()
+                .map(|c| format!("{} = ${{row.{}}}", ident(&c.name), sanitize(&c.field)))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            let sql = format!("UPDATE {table} SET {set} WHERE {cond}");
+            defs.push(def(&format!("update(row: {row}): ConnectionIO[Int]"), format!("sql{}.update.run", sql_string(&sql))));
+        }
+        let sql = format!("DELETE FROM {table} WHERE {}", where_clause(&key.columns));
+        let signature = format!("delete({}): ConnectionIO[Int]", params(&key.columns));
+        defs.push(def(&signature, format!("sql{}.update.run", sql_string(&sql))));
+    }
+    format!("{INDENT}object {} {{\n{}{INDENT}}}\n", r.name(), defs.join("\n"))
+}
+
+/// `DoobieRepos.scala` of a namespace with storage blocks.
+pub fn gen_nspace(p: &ast::Project, index: &TypeIndex, path: &[String], tables: &[&Table]) -> String {
+    let repos = tables.iter().map(|t| repo(p, t)).collect::<Vec<_>>();
+    let (mut imports, packages) = imports(index, tables, path);
+    let metas = metas(&repos);
+    imports.extend([
+        "doobie._".to_string(),
+        "doobie.implicits._".to_string(),
+        "doobie.postgres.implicits._".to_string(),
+        "iozh.sql.DoobieSupport._".to_string(),
+    ]);
+    if !metas.is_empty() {
+        imports.insert("iozh.circe.Implicits._".to_string());
+        imports.extend(packages.iter().map(|p| format!("{}.CirceImplicits._", p.join("."))));
+    }
+    let mut out = String::from(HEADER);
+    out.push_str(&format!("package {}\n\n", path.join(".")));
+    for i in &imports {
+        out.push_str(&format!("import {i}\n"));
+    }
+    out.push_str("\nobject DoobieRepos {\n");
+    for meta in metas.values() {
+        out.push_str(&format!("\n{INDENT}{meta}\n"));
+    }
+    for r in &repos {
+        out.push('\n');
+        if let Some(class) = &r.row_class {
+            out.push_str(&format!("{INDENT}{class}\n\n"));
+        }
+        out.push_str(&gen_repo(r));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Writes `DoobieRepos.scala` next to where the Scala 2 backend puts the
+/// classes of each namespace with storage blocks, plus `iozh/sql/DoobieSupport.scala`.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let schema = Schema::build(&project)?;
+    let index = TypeIndex::build(&project);
+    let support = target_folder.join("iozh").join("sql");
+    out.write(&support.join("DoobieSupport.scala"), DOOBIE_SUPPORT)?;
+    for (path, tables) in schema.by_nspace() {
+        let folder = target_folder.join(path.join("/"));
+        out.write(&folder.join("DoobieRepos.scala"), &gen_nspace(&project, &index, &path, &tables))?;
+    }
+    Ok(())
+}
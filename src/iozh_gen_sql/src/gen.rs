@@ -0,0 +1,83 @@
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::doc_text;
+use iozh_output::Output;
+
+use crate::model::*;
+
+const HEADER: &str = "-- Generated by iozh. Do not edit.\n";
+const INDENT: &str = "  ";
+
+fn comment(doc: &str, indent: &str) -> String {
+    doc_text(doc).lines().map(|l| format!("{indent}-- {l}").trim_end().to_string() + "\n").collect()
+}
+
+fn idents(names: &[String]) -> String {
+    names.iter().map(|n| ident(n)).collect::<Vec<_>>().join(", ")
+}
+
+pub fn column_def(c: &Column) -> String {
+    let null = if c.nullable { "" } else { " NOT NULL" };
+    format!("{} {}{}", ident(&c.name), c.sql_type, null)
+}
+
+pub fn create_table(t: &Table) -> String {
+    let mut lines = vec![];
+    for c in &t.columns {
+        lines.push(format!("{}{INDENT}{}", comment(&c.doc, INDENT), column_def(c)));
+    }
+    if let Some(k) = &t.primary_key {
+        lines.push(format!("{INDENT}CONSTRAINT {} PRIMARY KEY ({})", ident(&k.name), idents(&k.columns)));
+    }
+    for k in &t.uniques {
+        lines.push(format!("{INDENT}CONSTRAINT {} UNIQUE ({})", ident(&k.name), idents(&k.columns)));
+    }
+    format!("{}CREATE TABLE {} (\n{}\n);\n", comment(&t.doc, ""), t.qualified(), lines.join(",\n"))
+}
+
+pub fn add_foreign_key(t: &Table, fk: &ForeignKey) -> String {
+    let cascade = if fk.cascade { " ON DELETE CASCADE" } else { "" };
+    format!(
+        "ALTER TABLE {}\n{INDENT}ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({}){};\n",
+        t.qualified(),
+        ident(&fk.name),
+        idents(&fk.columns),
+        fk.references,
+        idents(&fk.ref_columns),
+        cascade,
+    )
+}
+
+pub fn create_index(t: &Table, k: &Key) -> String {
+    format!("CREATE INDEX {} ON {} ({});\n", ident(&k.name), t.qualified(), idents(&k.columns))
+}
+
+/// The whole schema: tables first, then the foreign keys between them,
+/// so that tables may reference each other in any order, then indexes.
+pub fn gen_schema(schema: &Schema) -> String {
+    let mut parts = vec![];
+    let mut schemas = vec![];
+    for t in &schema.tables {
+        if !schemas.contains(&t.schema) {
+            schemas.push(t.schema.clone());
+        }
+    }
+    if !schemas.is_empty() {
+        parts.push(schemas.iter().map(|s| format!("CREATE SCHEMA IF NOT EXISTS {};\n", ident(s))).collect::<String>());
+    }
+    parts.extend(schema.tables.iter().map(create_table));
+    for t in &schema.tables {
+        parts.extend(t.foreign_keys.iter().map(|fk| add_foreign_key(t, fk)));
+    }
+    for t in &schema.tables {
+        parts.extend(t.indexes.iter().map(|k| create_index(t, k)));
+    }
+    format!("{HEADER}\n{}", parts.join("\n"))
+}
+
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let schema = Schema::build(&project)?;
+    out.write(&target_folder.join("schema.sql"), &gen_schema(&schema))?;
+    Ok(())
+}
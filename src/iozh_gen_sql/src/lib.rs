@@ -0,0 +1,7 @@
+pub mod model;
+pub mod gen;
+pub mod scala;
+pub mod doobie;
+pub mod skunk;
+pub mod migrate;
+mod support;
@@ -0,0 +1,339 @@
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+
+use crate::gen::*;
+use crate::model::*;
+
+const HEADER: &str = "-- Generated by iozh.\n";
+
+/// A statement of a migration, with what may go wrong running it.
+pub struct Step {
+    pub sql: String,
+    /// Set when the statement loses data.
+    pub destructive: Option<String>,
+    /// Set when the statement may fail on existing rows.
+    pub warning: Option<String>,
+}
+
+impl Step {
+    fn safe(sql: String) -> Step {
+        Step { sql, destructive: None, warning: None }
+    }
+
+    fn destructive(sql: String, why: String) -> Step {
+        Step { sql, destructive: Some(why), warning: None }
+    }
+
+    fn warning(sql: String, why: String) -> Step {
+        Step { sql, destructive: None, warning: Some(why) }
+    }
+}
+
+pub struct Migration {
+    pub steps: Vec<Step>,
+}
+
+fn find<'a>(tables: &'a [Table], t: &Table) -> Option<&'a Table> {
+    tables.iter().find(|o| o.schema == t.schema && o.name == t.name)
+}
+
+fn same_key(a: &Key, b: &Key) -> bool {
+    a.name == b.name && a.columns == b.columns
+}
+
+fn same_fk(a: &ForeignKey, b: &ForeignKey) -> bool {
+    a.name == b.name
+        && a.columns == b.columns
+        && a.references == b.references
+        && a.ref_columns == b.ref_columns
+        && a.cascade == b.cascade
+}
+
+/// Whether `fk` references the key `k` of `t`, whatever the order of
+/// the columns.
+fn references(fk: &ForeignKey, t: &Table, k: &Key) -> bool {
+    let sorted = |columns: &[String]| {
+        let mut columns = columns.to_vec();
+        columns.sort();
+        columns
+    };
+    fk.references == t.qualified() && sorted(&fk.ref_columns) == sorted(&k.columns)
+}
+
+fn alter(t: &Table, action: String) -> String {
+    format!("ALTER TABLE {} {action};\n", t.qualified())
+}
+
+fn drop_constraint(t: &Table, name: &str) -> String {
+    alter(t, format!("DROP CONSTRAINT {}", ident(name)))
+}
+
+fn add_key(t: &Table, k: &Key, kind: &str) -> String {
+    let columns = k.columns.iter().map(|c| ident(c)).collect::<Vec<_>>().join(", ");
+    alter(t, format!("ADD CONSTRAINT {} {kind} ({columns})", ident(&k.name)))
+}
+
+/// Unique constraints of a table, the primary key included, with the
+/// keyword adding them.
+fn keys(t: &Table) -> Vec<(&Key, &'static str)> {
+    t.primary_key.iter().map(|k| (k, "PRIMARY KEY")).chain(t.uniques.iter().map(|k| (k, "UNIQUE"))).collect()
+}
+
+impl Migration {
+    /// Statements turning the tables of `old` into those of `new`. Renames
+    /// are not detected: a renamed column is dropped and added again.
+    pub fn diff(old: &Schema, new: &Schema) -> Migration {
+        let mut steps = vec![];
+        let kept = new.tables.iter().filter_map(|t| find(&old.tables, t).map(|o| (o, t))).collect::<Vec<_>>();
+        let added = new.tables.iter().filter(|t| find(&old.tables, t).is_none()).collect::<Vec<_>>();
+        let dropped = old.tables.iter().filter(|t| find(&new.tables, t).is_none()).collect::<Vec<_>>();
+
+        // Foreign keys depend on the key they reference: those of a key
+        // that changes are dropped along with it and added again after.
+        let dropped_keys = kept
+            .iter()
+            .flat_map(|(o, t)| {
+                keys(o)
+                    .into_iter()
+                    .filter(|(k, _)| !keys(t).iter().any(|(n, _)| same_key(k, n)))
+                    .map(move |(k, _)| (*o, k))
+            })
+            .collect::<Vec<_>>();
+        let rebuilt = |fk: &ForeignKey| dropped_keys.iter().any(|(o, k)| references(fk, o, k));
+
+        // Constraints go first, so that nothing depends on the columns
+        // and tables dropped below.
+        for (o, t) in &kept {
+            for fk in o.foreign_keys.iter().filter(|fk| !t.foreign_keys.iter().any(|n| same_fk(fk, n)) || rebuilt(fk)) {
+                steps.push(Step::safe(drop_constraint(o, &fk.name)));
+            }
+        }
+        // Dropped tables may reference each other in any declaration order.
+        for o in &dropped {
+            for fk in &o.foreign_keys {
+                steps.push(Step::safe(drop_constraint(o, &fk.name)));
+            }
+        }
+        for (o, t) in &kept {
+            for k in o.indexes.iter().filter(|k| !t.indexes.iter().any(|n| same_key(k, n))) {
+                steps.push(Step::safe(format!("DROP INDEX {}.{};\n", ident(&o.schema), ident(&k.name))));
+            }
+        }
+        for (o, k) in &dropped_keys {
+            steps.push(Step::safe(drop_constraint(o, &k.name)));
+        }
+
+        let mut schemas = vec![];
+        for t in &added {
+            if !old.tables.iter().any(|o| o.schema == t.schema) && !schemas.contains(&t.schema) {
+                schemas.push(t.schema.clone());
+                steps.push(Step::safe(format!("CREATE SCHEMA IF NOT EXISTS {};\n", ident(&t.schema))));
+            }
+        }
+        steps.extend(added.iter().map(|t| Step::safe(create_table(t))));
+
+        for (o, t) in &kept {
+            for c in &t.columns {
+                match o.columns.iter().find(|oc| oc.name == c.name) {
+                    None if c.nullable => {
+                        steps.push(Step::safe(alter(t, format!("ADD COLUMN {}", column_def(c)))));
+                    }
+                    None => {
+                        let why = format!("{}.{} is NOT NULL without a default: fails when the table has rows", t.qualified(), ident(&c.name));
+                        steps.push(Step::warning(alter(t, format!("ADD COLUMN {}", column_def(c))), why));
+                    }
+                    Some(oc) => {
+                        let column = format!("{}.{}", t.qualified(), ident(&c.name));
+                        if oc.sql_type != c.sql_type {
+                            let action = format!("ALTER COLUMN {} TYPE {} USING {}::{}", ident(&c.name), c.sql_type, ident(&c.name), c.sql_type);
+                            let why = format!("{column} changes from {} to {}: existing values are converted or the statement fails", oc.sql_type, c.sql_type);
+                            steps.push(Step::destructive(alter(t, action), why));
+                        }
+                        if oc.nullable && !c.nullable {
+                            let why = format!("{column} becomes NOT NULL: fails when some rows hold NULL");
+                            steps.push(Step::warning(alter(t, format!("ALTER COLUMN {} SET NOT NULL", ident(&c.name))), why));
+                        } else if !oc.nullable && c.nullable {
+                            steps.push(Step::safe(alter(t, format!("ALTER COLUMN {} DROP NOT NULL", ident(&c.name)))));
+                        }
+                    }
+                }
+            }
+            for oc in o.columns.iter().filter(|oc| !t.columns.iter().any(|c| c.name == oc.name)) {
+                let why = format!("drops column {}.{} and its data", o.qualified(), ident(&oc.name));
+                steps.push(Step::destructive(alter(o, format!("DROP COLUMN {}", ident(&oc.name))), why));
+            }
+        }
+        for o in dropped.iter().rev() {
+            let why = format!("drops table {} and its data", o.qualified());
+            steps.push(Step::destructive(format!("DROP TABLE {};\n", o.qualified()), why));
+        }
+
+        for (o, t) in &kept {
+            for (k, kind) in keys(t).into_iter().filter(|(k, _)| !keys(o).iter().any(|(n, _)| same_key(k, n))) {
+                let why = format!("{} must hold unique values in ({}) before the constraint is added", t.qualified(), k.columns.join(", "));
+                steps.push(Step::warning(add_key(t, k, kind), why));
+            }
+        }
+        let all = kept.iter().map(|(o, t)| (Some(*o), *t)).chain(added.iter().map(|t| (None, *t))).collect::<Vec<_>>();
+        for (o, t) in &all {
+            for fk in &t.foreign_keys {
+                if o.is_none_or(|o| !o.foreign_keys.iter().any(|n| same_fk(fk, n))) || rebuilt(fk) {
+                    steps.push(Step::safe(add_foreign_key(t, fk)));
+                }
+            }
+        }
+        for (o, t) in &all {
+            for k in &t.indexes {
+                if o.is_none_or(|o| !o.indexes.iter().any(|n| same_key(k, n))) {
+                    steps.push(Step::safe(create_index(t, k)));
+                }
+            }
+        }
+        Migration { steps }
+    }
+
+    pub fn build(old: &ast::Project, new: &ast::Project) -> Result<Migration, IozhError> {
+        Ok(Migration::diff(&Schema::build(old)?, &Schema::build(new)?))
+    }
+
+    /// Migration script; statements that lose data or may fail are
+    /// preceded by a comment saying so.
+    pub fn sql(&self) -> String {
+        if self.steps.is_empty() {
+            return format!("{HEADER}\n-- No changes.\n");
+        }
+        let steps = self.steps
+            .iter()
+            .map(|s| {
+                let mut out = String::new();
+                if let Some(why) = &s.destructive {
+                    out.push_str(&format!("-- DESTRUCTIVE: {why}\n"));
+                }
+                if let Some(why) = &s.warning {
+                    out.push_str(&format!("-- WARNING: {why}\n"));
+                }
+                out + &s.sql
+            })
+            .collect::<Vec<_>>();
+        format!("{HEADER}\n{}", steps.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "User\n  id: I64\n  email: String\n  name: String\n\n";
+    const ORDER: &str = "Order\n  id: I64\n  userEmail: String\n\n";
+
+    /// Migration from the tables of `old` to those of `new`, both in the
+    /// namespace `shop` along with `types`.
+    fn diff(types: &str, old: &str, new: &str) -> Migration {
+        let project = |tables: &str| ast::Project::parse(&format!("namespace shop\n\n{types}pgsql {{\n{tables}\n}}\n")).unwrap();
+        Migration::build(&project(old), &project(new)).unwrap()
+    }
+
+    fn statements(m: &Migration) -> Vec<&str> {
+        m.steps.iter().map(|s| s.sql.as_str()).collect()
+    }
+
+    /// Index of the statement containing `part`, which must be there.
+    fn position(m: &Migration, part: &str) -> usize {
+        let position = m.steps.iter().position(|s| s.sql.contains(part));
+        position.unwrap_or_else(|| panic!("no statement with {part} in {:?}", statements(m)))
+    }
+
+    #[test]
+    fn no_changes() {
+        let m = diff(USER, "User { unique(id) }", "User { unique(id) }");
+        assert!(m.steps.is_empty());
+        assert!(m.sql().ends_with("-- No changes.\n"));
+    }
+
+    #[test]
+    fn adds_and_drops_tables() {
+        let m = diff(&format!("{USER}{ORDER}"), "User { unique(id) }", "User { unique(id) }\nOrder { unique(id) }");
+        assert_eq!(statements(&m).len(), 1);
+        assert!(m.steps[0].sql.starts_with("CREATE TABLE shop.\"order\""));
+
+        let m = diff(&format!("{USER}{ORDER}"), "User { unique(id) }\nOrder { unique(id) }", "User { unique(id) }");
+        assert_eq!(statements(&m), vec!["DROP TABLE shop.\"order\";\n"]);
+        assert!(m.steps[0].destructive.is_some());
+    }
+
+    #[test]
+    fn adds_changes_and_drops_columns() {
+        let old = "User\n  id: I64\n  name: String\n  age: I32\n  nick: Option[String]\n\n";
+        let new = "User\n  id: I64\n  age: I64\n  nick: String\n  bio: Option[String]\n  score: I32\n\n";
+        let project = |types: &str| ast::Project::parse(&format!("namespace shop\n\n{types}pgsql {{\nUser {{ unique(id) }}\n}}\n")).unwrap();
+        let m = Migration::build(&project(old), &project(new)).unwrap();
+
+        let step = &m.steps[position(&m, "ADD COLUMN bio TEXT;")];
+        assert!(step.destructive.is_none() && step.warning.is_none());
+        assert!(m.steps[position(&m, "ADD COLUMN score INTEGER NOT NULL")].warning.is_some());
+        assert!(m.steps[position(&m, "ALTER COLUMN age TYPE BIGINT")].destructive.is_some());
+        assert!(m.steps[position(&m, "ALTER COLUMN nick SET NOT NULL")].warning.is_some());
+        assert!(m.steps[position(&m, "DROP COLUMN name")].destructive.is_some());
+
+        let m = Migration::build(&project(new), &project(old)).unwrap();
+        let step = &m.steps[position(&m, "ALTER COLUMN nick DROP NOT NULL")];
+        assert!(step.destructive.is_none() && step.warning.is_none());
+    }
+
+    #[test]
+    fn adds_changes_and_drops_keys() {
+        let m = diff(USER, "User { unique(id) index(name) }", "User { unique(id) unique(email) index(email) }");
+        assert_eq!(
+            statements(&m),
+            vec![
+                "DROP INDEX shop.user_name_idx;\n",
+                "ALTER TABLE shop.\"user\" ADD CONSTRAINT user_email_key UNIQUE (email);\n",
+                "CREATE INDEX user_email_idx ON shop.\"user\" (email);\n",
+            ],
+        );
+        assert!(m.steps[1].warning.is_some());
+
+        let m = diff(USER, "User { unique(id) unique(email) }", "User { unique(id) }");
+        assert_eq!(statements(&m), vec!["ALTER TABLE shop.\"user\" DROP CONSTRAINT user_email_key;\n"]);
+
+        // The primary key moves: dropped first, then added over the new columns.
+        let m = diff(USER, "User { unique(id) }", "User { unique(email) }");
+        assert!(position(&m, "DROP CONSTRAINT user_pkey") < position(&m, "ADD CONSTRAINT user_pkey PRIMARY KEY (email)"));
+    }
+
+    #[test]
+    fn adds_changes_and_drops_foreign_keys() {
+        let types = format!("{USER}{ORDER}");
+        let m = diff(&types, "User { unique(id) unique(email) }\nOrder { unique(id) }", "User { unique(id) unique(email) }\nOrder { unique(id) userEmail: User(email) }");
+        assert_eq!(statements(&m).len(), 1);
+        assert!(m.steps[0].sql.contains("ADD CONSTRAINT order_user_email_fkey FOREIGN KEY (user_email) REFERENCES shop.\"user\" (email)"));
+
+        let m = diff(&types, "User { unique(id) unique(email) }\nOrder { unique(id) userEmail: User(email) }", "User { unique(id) unique(email) }\nOrder { unique(id) }");
+        assert_eq!(statements(&m), vec!["ALTER TABLE shop.\"order\" DROP CONSTRAINT order_user_email_fkey;\n"]);
+
+        // Referencing another column of the same table changes the key.
+        let types = format!("{USER}Order\n  id: I64\n  user: String\n\n");
+        let m = diff(&types, "User { unique(id) unique(email) unique(name) }\nOrder { unique(id) user: User(email) }", "User { unique(id) unique(email) unique(name) }\nOrder { unique(id) user: User(name) }");
+        assert!(position(&m, "DROP CONSTRAINT order_user_fkey") < position(&m, "REFERENCES shop.\"user\" (name)"));
+    }
+
+    #[test]
+    fn rebuilds_the_foreign_keys_of_a_changed_key() {
+        let types = format!("{USER}{ORDER}");
+        let m = diff(&types, "User { unique(email) }\nOrder { unique(id) userEmail: User(email) }", "User { unique(id) unique(email) }\nOrder { unique(id) userEmail: User(email) }");
+        let drop_fk = position(&m, "DROP CONSTRAINT order_user_email_fkey");
+        let drop_key = position(&m, "DROP CONSTRAINT user_pkey");
+        let add_key = position(&m, "ADD CONSTRAINT user_email_key UNIQUE (email)");
+        let add_fk = position(&m, "ADD CONSTRAINT order_user_email_fkey");
+        assert!(drop_fk < drop_key && drop_key < add_key && add_key < add_fk, "{:?}", statements(&m));
+    }
+
+    #[test]
+    fn drops_the_foreign_keys_of_dropped_tables_first() {
+        let types = format!("{USER}{ORDER}");
+        let m = diff(&types, "Order { unique(id) userEmail: User(email) }\nUser { unique(email) }", "");
+        let drop_fk = position(&m, "DROP CONSTRAINT order_user_email_fkey");
+        assert!(drop_fk < position(&m, "DROP TABLE shop.\"user\""));
+        assert!(drop_fk < position(&m, "DROP TABLE shop.\"order\""));
+    }
+}
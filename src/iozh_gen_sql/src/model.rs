@@ -0,0 +1,500 @@
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+
+/// Words PostgreSQL reserves, which must be quoted when used as names.
+const RESERVED: [&str; 101] = [
+    "all", "analyse", "analyze", "and", "any", "array", "as", "asc", "asymmetric", "authorization",
+    "binary", "both", "case", "cast", "check", "collate", "collation", "column", "concurrently",
+    "constraint", "create", "cross", "current_catalog", "current_date", "current_role",
+    "current_schema", "current_time", "current_timestamp", "current_user", "default", "deferrable",
+    "desc", "distinct", "do", "else", "end", "except", "false", "fetch", "for", "foreign", "freeze",
+    "from", "full", "grant", "group", "having", "ilike", "in", "initially", "inner", "intersect",
+    "into", "is", "isnull", "join", "lateral", "leading", "left", "like", "limit", "localtime",
+    "localtimestamp", "natural", "not", "notnull", "null", "offset", "on", "only", "or", "order",
+    "outer", "overlaps", "placing", "primary", "references", "returning", "right", "select",
+    "session_user", "similar", "some", "symmetric", "system_user", "table", "tablesample", "then",
+    "to", "trailing", "true", "union", "unique", "user", "using", "variadic", "verbose", "when",
+    "where", "window", "with",
+];
+
+/// An SQL name, quoted when it is reserved or not a plain lower case identifier.
+pub fn ident(name: &str) -> String {
+    let plain = name.chars().next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if plain && !RESERVED.contains(&name) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// `message_id` to `messageId`, for the fields of columns iozh made up.
+pub fn camel_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = !out.is_empty();
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn error(pos: &ast::Pos, msg: String) -> IozhError {
+    IozhError { pos: pos.clone(), msg }
+}
+
+pub struct Column {
+    /// Field the column stores, as named in iozh.
+    pub field: String,
+    pub name: String,
+    pub doc: String,
+    /// Type of the field, `Option` included.
+    pub type_path: ast::TypePath,
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+/// Primary key, unique constraint or index over some columns.
+pub struct Key {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    /// Qualified name of the referenced table.
+    pub references: String,
+    pub ref_columns: Vec<String>,
+    pub cascade: bool,
+}
+
+pub enum TableKind {
+    /// Rows of a structure.
+    Entity,
+    /// Elements of the `List[T(field)]` field `field` of the table at
+    /// index `owner`, one row per element, in list order.
+    List { owner: usize, field: String },
+}
+
+pub struct Table {
+    /// Namespace of the storage block.
+    pub nspace: Vec<String>,
+    /// Stored structure; for list tables, the structure of the owner.
+    pub entry: TypeEntry,
+    pub doc: String,
+    pub kind: TableKind,
+    pub schema: String,
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub primary_key: Option<Key>,
+    pub uniques: Vec<Key>,
+    pub indexes: Vec<Key>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+impl Table {
+    pub fn qualified(&self) -> String {
+        format!("{}.{}", ident(&self.schema), ident(&self.name))
+    }
+
+    pub fn column(&self, field: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.field == field)
+    }
+
+    /// Whether some key makes `columns` unique, which foreign keys to them require.
+    pub fn is_unique(&self, columns: &[String]) -> bool {
+        self.primary_key.iter().chain(self.uniques.iter()).any(|k| k.columns == columns)
+    }
+
+    fn key_name(&self, columns: &[String], suffix: &str) -> String {
+        format!("{}_{}_{}", self.name, columns.join("_"), suffix)
+    }
+}
+
+/// Tables of every `pgsql` block of a project, in declaration order, with
+/// the tables holding `List` references after the others.
+pub struct Schema {
+    pub tables: Vec<Table>,
+}
+
+impl Schema {
+    /// Tables grouped by the namespace of their storage block, in order.
+    pub fn by_nspace(&self) -> Vec<(Vec<String>, Vec<&Table>)> {
+        let mut groups: Vec<(Vec<String>, Vec<&Table>)> = vec![];
+        for t in &self.tables {
+            match groups.iter_mut().find(|(path, _)| path == &t.nspace) {
+                Some((_, tables)) => tables.push(t),
+                None => groups.push((t.nspace.clone(), vec![t])),
+            }
+        }
+        groups
+    }
+}
+
+/// `List[T(field)]` columns to turn into tables once every key is known.
+struct ListRef<'a> {
+    owner: usize,
+    target: usize,
+    r: &'a ast::StorageRef,
+}
+
+struct Builder<'a> {
+    p: &'a ast::Project,
+    index: TypeIndex,
+    tables: Vec<Table>,
+}
+
+impl Schema {
+    pub fn build(p: &ast::Project) -> Result<Schema, IozhError> {
+        let mut b = Builder { p, index: TypeIndex::build(p), tables: vec![] };
+        let mut decls = vec![];
+        for (path, nspace) in p.all_nspaces() {
+            for item in &nspace.items {
+                if let ast::NspaceItem::Storage(s) = item {
+                    decls.extend(s.tables.iter().map(|t| (path.clone(), t)));
+                }
+            }
+        }
+        for (path, t) in &decls {
+            b.add_table(path, t)?;
+        }
+        let mut lists = vec![];
+        for (i, (path, t)) in decls.iter().enumerate() {
+            lists.extend(b.add_refs(i, path, t)?);
+        }
+        for (i, (_, t)) in decls.iter().enumerate() {
+            b.add_keys(i, t)?;
+        }
+        for (i, (path, t)) in decls.iter().enumerate() {
+            b.check_refs(i, path, t)?;
+        }
+        for l in lists {
+            b.add_list_table(l)?;
+        }
+        Ok(Schema { tables: b.tables })
+    }
+}
+
+impl <'a> Builder<'a> {
+    /// Column type of a field and whether it is nullable.
+    fn column_type(&self, tp: &ast::TypePath, from: &[String]) -> Result<(String, bool), IozhError> {
+        let arg = || tp.last()
+            .and_then(|t| t.args.first())
+            .ok_or_else(|| error(&tp.pos, format!("Missing type argument: {}", iozh_parse::format::format_type_path(tp))));
+        let sql = match Builtin::from_path(tp) {
+            Some(Builtin::Option) => return Ok((self.column_type(arg()?, from)?.0, true)),
+            Some(Builtin::List) => {
+                let elem = arg()?;
+                match Builtin::from_path(elem) {
+                    Some(Builtin::I32 | Builtin::I64 | Builtin::F32 | Builtin::F64 | Builtin::Bool | Builtin::String) => {
+                        self.column_type(elem, from)?.0 + "[]"
+                    }
+                    _ => "JSONB".to_string(),
+                }
+            }
+            Some(Builtin::I32) => "INTEGER".to_string(),
+            Some(Builtin::I64) => "BIGINT".to_string(),
+            Some(Builtin::F32) => "REAL".to_string(),
+            Some(Builtin::F64) => "DOUBLE PRECISION".to_string(),
+            Some(Builtin::Bool) => "BOOLEAN".to_string(),
+            Some(Builtin::String) | Some(Builtin::File) => "TEXT".to_string(),
+            Some(Builtin::DateTime) => "TIMESTAMPTZ".to_string(),
+            Some(Builtin::Duration) => "INTERVAL".to_string(),
+            None => match self.index.resolve_path(tp, from) {
+                Some(TypeEntry { def: TypeDef::Choice(idx), .. }) => {
+                    let c = self.p.get_choice(*idx)?;
+                    let variants = c.variants(self.p);
+                    let text = |v: &ChoiceVariant| match v {
+                        ChoiceVariant::Unit { .. } => true,
+                        ChoiceVariant::Value { value, .. } => matches!(value, ast::Literal::String { .. }),
+                        _ => false,
+                    };
+                    let int = |v: &ChoiceVariant| matches!(v, ChoiceVariant::Value { value: ast::Literal::Int { .. }, .. });
+                    if variants.is_empty() {
+                        "JSONB".to_string()
+                    } else if variants.iter().all(text) {
+                        "TEXT".to_string()
+                    } else if variants.iter().all(int) {
+                        "BIGINT".to_string()
+                    } else {
+                        "JSONB".to_string()
+                    }
+                }
+                Some(_) => "JSONB".to_string(),
+                None => {
+                    let name = iozh_parse::format::format_type_path(tp);
+                    return Err(error(&tp.pos, format!("Unknown type: {name}")));
+                }
+            },
+        };
+        Ok((sql, false))
+    }
+
+    fn column(&self, field: &ast::Field, from: &[String]) -> Result<Column, IozhError> {
+        let (sql_type, nullable) = self.column_type(&field.type_path, from)?;
+        Ok(Column {
+            field: field.name.clone(),
+            name: snake_case(&field.name),
+            doc: field.doc.clone(),
+            type_path: field.type_path.clone(),
+            sql_type,
+            nullable,
+        })
+    }
+
+    fn set_column(&mut self, table: usize, column: Column) {
+        let columns = &mut self.tables[table].columns;
+        match columns.iter_mut().find(|c| c.field == column.field) {
+            Some(c) => *c = column,
+            None => columns.push(column),
+        }
+    }
+
+    /// Table of the structure a storage table or reference names.
+    fn find_table(&self, tp: &ast::TypePath, from: &[String]) -> Option<usize> {
+        let entry = self.index.resolve_path(tp, from)?;
+        self.tables.iter().position(|t| {
+            matches!(t.kind, TableKind::Entity) && t.entry.path == entry.path && t.entry.name == entry.name
+        })
+    }
+
+    /// Adds a table with the fields of its structure and the plain fields
+    /// the storage block declares on top of them.
+    fn add_table(&mut self, path: &[String], t: &ast::StorageTable) -> Result<(), IozhError> {
+        let name = iozh_parse::format::format_type_path(&t.name);
+        let entry = match self.index.resolve_path(&t.name, path) {
+            Some(e) if !matches!(e.def, TypeDef::Choice(_)) => e.clone(),
+            _ => return Err(error(&t.pos, format!("Not a structure: {name}"))),
+        };
+        if self.find_table(&t.name, path).is_some() {
+            return Err(error(&t.pos, format!("Table declared twice: {name}")));
+        }
+        let sidx = match entry.def {
+            TypeDef::Structure(idx) | TypeDef::Variant { structure: idx, .. } => idx,
+            TypeDef::Choice(_) => unreachable!(),
+        };
+        let s = self.p.get_structure(sidx)?;
+        if !s.name.args.is_empty() {
+            return Err(error(&t.pos, format!("Generic structures cannot be stored: {name}")));
+        }
+        let mut columns = vec![];
+        for f in s.get_fields() {
+            columns.push(self.column(f, &entry.path)?);
+        }
+        let doc = if t.doc.is_empty() { s.doc.clone() } else { t.doc.clone() };
+        self.tables.push(Table {
+            nspace: path.to_vec(),
+            schema: path.iter().map(|n| snake_case(n)).collect::<Vec<_>>().join("_"),
+            name: snake_case(&entry.name.replace('.', "")),
+            entry,
+            doc,
+            kind: TableKind::Entity,
+            columns,
+            primary_key: None,
+            uniques: vec![],
+            indexes: vec![],
+            foreign_keys: vec![],
+        });
+        let table = self.tables.len() - 1;
+        for item in &t.items {
+            if let ast::StorageItem::Field(f) = item {
+                let column = self.column(f, path)?;
+                self.set_column(table, column);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the reference columns of a table with their foreign keys, and
+    /// returns the `List` references, which get tables of their own.
+    fn add_refs<'r>(&mut self, table: usize, path: &[String], t: &'r ast::StorageTable) -> Result<Vec<ListRef<'r>>, IozhError> {
+        let mut lists = vec![];
+        for item in &t.items {
+            let ast::StorageItem::Ref(r) = item else { continue };
+            let target_name = iozh_parse::format::format_type_path(&r.target);
+            let target = self.find_table(&r.target, path)
+                .ok_or_else(|| error(&r.pos, format!("Not a table of a pgsql block: {target_name}")))?;
+            let referenced = self.tables[target].column(&r.field)
+                .ok_or_else(|| error(&r.pos, format!("Unknown column: {target_name}({})", r.field)))?;
+            let (sql_type, type_path) = (referenced.sql_type.clone(), referenced.type_path.clone());
+            let ref_column = referenced.name.clone();
+            let nullable = match r.wrapper.as_deref() {
+                None => false,
+                Some("Option") => true,
+                Some("List") => {
+                    lists.push(ListRef { owner: table, target, r });
+                    let columns = &mut self.tables[table].columns;
+                    columns.retain(|c| c.field != r.name);
+                    continue;
+                }
+                Some(w) => return Err(error(&r.pos, format!("Expected Option or List around a reference, got {w}"))),
+            };
+            let type_path = if nullable {
+                ast::TypePath {
+                    pos: r.pos.clone(),
+                    path: vec![ast::TypeTag { pos: r.pos.clone(), name: "Option".to_string(), args: vec![type_path] }],
+                }
+            } else {
+                type_path
+            };
+            let column = Column {
+                field: r.name.clone(),
+                name: snake_case(&r.name),
+                doc: r.doc.clone(),
+                type_path,
+                sql_type,
+                nullable,
+            };
+            let fk = ForeignKey {
+                name: self.tables[table].key_name(std::slice::from_ref(&column.name), "fkey"),
+                columns: vec![column.name.clone()],
+                references: self.tables[target].qualified(),
+                ref_columns: vec![ref_column],
+                cascade: false,
+            };
+            self.set_column(table, column);
+            self.tables[table].foreign_keys.push(fk);
+        }
+        Ok(lists)
+    }
+
+    /// The first `unique` whose columns are all required becomes the
+    /// primary key, other ones unique constraints.
+    fn add_keys(&mut self, table: usize, t: &ast::StorageTable) -> Result<(), IozhError> {
+        for item in &t.items {
+            let (key, unique) = match item {
+                ast::StorageItem::Unique(k) => (k, true),
+                ast::StorageItem::Index(k) => (k, false),
+                _ => continue,
+            };
+            let tbl = &mut self.tables[table];
+            let mut columns = vec![];
+            let mut required = true;
+            for f in &key.fields {
+                let c = tbl.column(f)
+                    .ok_or_else(|| error(&key.pos, format!("Unknown column in key: {f}")))?;
+                required &= !c.nullable;
+                columns.push(c.name.clone());
+            }
+            if !unique {
+                let name = tbl.key_name(&columns, "idx");
+                tbl.indexes.push(Key { name, columns });
+            } else if required && tbl.primary_key.is_none() {
+                let name = format!("{}_pkey", tbl.name);
+                tbl.primary_key = Some(Key { name, columns });
+            } else {
+                let name = tbl.key_name(&columns, "key");
+                tbl.uniques.push(Key { name, columns });
+            }
+        }
+        Ok(())
+    }
+
+    /// PostgreSQL wants referenced columns to be unique.
+    fn check_refs(&self, table: usize, path: &[String], t: &ast::StorageTable) -> Result<(), IozhError> {
+        for item in &t.items {
+            let ast::StorageItem::Ref(r) = item else { continue };
+            let target = &self.tables[self.find_table(&r.target, path).unwrap_or(table)];
+            let column = target.column(&r.field).map(|c| c.name.clone()).unwrap_or_default();
+            if !target.is_unique(&[column]) {
+                let target_name = iozh_parse::format::format_type_path(&r.target);
+                return Err(error(&r.pos, format!("Referenced column is not unique: add unique({}) to {target_name}", r.field)));
+            }
+        }
+        Ok(())
+    }
+
+    /// `reactions: List[Reaction(id)]` on `Message` is kept in table
+    /// `message_reactions`, keyed by the primary key of the message and
+    /// the position in the list.
+    fn add_list_table(&mut self, l: ListRef) -> Result<(), IozhError> {
+        let owner = &self.tables[l.owner];
+        let target = &self.tables[l.target];
+        let Some(pkey) = &owner.primary_key else {
+            let msg = format!("Table with a List reference needs a unique key: {}", owner.entry.name);
+            return Err(error(&l.r.pos, msg));
+        };
+        let mut columns = vec![];
+        let mut owner_columns = vec![];
+        for name in &pkey.columns {
+            let c = owner.columns.iter().find(|c| &c.name == name).expect("key columns exist");
+            let column = format!("{}_{}", owner.name, c.name);
+            owner_columns.push(column.clone());
+            columns.push(Column {
+                field: camel_case(&column),
+                name: column,
+                doc: String::new(),
+                type_path: c.type_path.clone(),
+                sql_type: c.sql_type.clone(),
+                nullable: false,
+            });
+        }
+        let position = ast::TypePath {
+            pos: l.r.pos.clone(),
+            path: vec![ast::TypeTag { pos: l.r.pos.clone(), name: "I32".to_string(), args: vec![] }],
+        };
+        columns.push(Column {
+            field: "position".to_string(),
+            name: "position".to_string(),
+            doc: String::new(),
+            type_path: position,
+            sql_type: "INTEGER".to_string(),
+            nullable: false,
+        });
+        let referenced = target.column(&l.r.field).expect("checked by add_refs");
+        let mut column = format!("{}_{}", target.name, referenced.name);
+        if owner_columns.contains(&column) {
+            column = format!("{}_{}", snake_case(&l.r.name), referenced.name);
+        }
+        columns.push(Column {
+            field: camel_case(&column),
+            name: column.clone(),
+            doc: l.r.doc.clone(),
+            type_path: referenced.type_path.clone(),
+            sql_type: referenced.sql_type.clone(),
+            nullable: false,
+        });
+        let name = format!("{}_{}", owner.name, snake_case(&l.r.name));
+        let mut key_columns = owner_columns.clone();
+        key_columns.push("position".to_string());
+        let foreign_keys = vec![
+            ForeignKey {
+                name: format!("{}_{}_fkey", name, owner_columns.join("_")),
+                columns: owner_columns,
+                references: owner.qualified(),
+                ref_columns: pkey.columns.clone(),
+                cascade: true,
+            },
+            ForeignKey {
+                name: format!("{name}_{column}_fkey"),
+                columns: vec![column],
+                references: target.qualified(),
+                ref_columns: vec![referenced.name.clone()],
+                cascade: false,
+            },
+        ];
+        let table = Table {
+            nspace: owner.nspace.clone(),
+            entry: owner.entry.clone(),
+            doc: String::new(),
+            kind: TableKind::List { owner: l.owner, field: l.r.name.clone() },
+            schema: owner.schema.clone(),
+            primary_key: Some(Key { name: format!("{name}_pkey"), columns: key_columns }),
+            name,
+            columns,
+            uniques: vec![],
+            indexes: vec![],
+            foreign_keys,
+        };
+        self.tables.push(table);
+        Ok(())
+    }
+}
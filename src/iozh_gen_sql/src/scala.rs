@@ -0,0 +1,193 @@
+use std::collections::BTreeSet;
+use iozh_parse::ast;
+use iozh_parse::types::*;
+use iozh_gen_scala2::gen::{imports_for, map_type, sanitize};
+
+use crate::model::*;
+
+pub const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+pub const INDENT: &str = "  ";
+
+pub fn pascal_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub fn lower_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Scala type of a field, as the Scala 2 backend spells it.
+pub fn scala_type(tp: &ast::TypePath) -> String {
+    tp.path
+        .iter()
+        .map(|t| {
+            let name = map_type(&sanitize(&t.name)).to_string();
+            if t.args.is_empty() {
+                name
+            } else {
+                format!("{name}[{}]", t.args.iter().map(scala_type).collect::<Vec<_>>().join(", "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The type a column holds, without `Option`: lookups take the value
+/// itself, and nullability is the column's business.
+pub fn value_type(c: &Column) -> &ast::TypePath {
+    match Builtin::from_path(&c.type_path) {
+        Some(Builtin::Option) => c.type_path.last().and_then(|t| t.args.first()).unwrap_or(&c.type_path),
+        _ => &c.type_path,
+    }
+}
+
+/// Whether a column value is read and written by the database driver
+/// itself rather than through circe: builtins, and arrays of them.
+pub fn is_native(c: &Column) -> bool {
+    let tp = value_type(c);
+    match Builtin::from_path(tp) {
+        Some(Builtin::List) => !c.sql_type.starts_with("JSONB"),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// SQL statement as a Scala string, the triple quotes leaving `"` alone.
+pub fn sql_string(sql: &str) -> String {
+    format!("\"\"\"{sql}\"\"\"")
+}
+
+pub fn columns_list(columns: &[&Column]) -> String {
+    columns.iter().map(|c| ident(&c.name)).collect::<Vec<_>>().join(", ")
+}
+
+/// Query by some columns: unique keys find at most one row, indexes and
+/// the owner of list tables any number of them.
+pub struct Lookup<'a> {
+    pub name: String,
+    pub columns: Vec<&'a Column>,
+    pub unique: bool,
+    pub order_by: Option<&'a Column>,
+}
+
+pub struct Repo<'a> {
+    pub table: &'a Table,
+    /// Name of the table as a Scala identifier, `MessageReactions`.
+    pub base: String,
+    /// Row type: the stored case class when the columns are just its
+    /// fields, otherwise `<Name>Row`, declared by `row_class`.
+    pub row: String,
+    pub row_class: Option<String>,
+    pub key: Option<Lookup<'a>>,
+    pub lookups: Vec<Lookup<'a>>,
+}
+
+impl <'a> Repo<'a> {
+    pub fn name(&self) -> String {
+        format!("{}Repo", self.base)
+    }
+
+    /// Columns `update` sets: all but the primary key ones.
+    pub fn values(&self) -> Vec<&'a Column> {
+        let key = self.key.as_ref().map(|k| k.columns.clone()).unwrap_or_default();
+        self.table.columns.iter().filter(|c| !key.iter().any(|k| k.name == c.name)).collect()
+    }
+
+    pub fn columns(&self) -> Vec<&'a Column> {
+        self.table.columns.iter().collect()
+    }
+}
+
+fn lookup<'a>(t: &'a Table, columns: &[String], unique: bool) -> Lookup<'a> {
+    let columns = columns
+        .iter()
+        .filter_map(|name| t.columns.iter().find(|c| &c.name == name))
+        .collect::<Vec<_>>();
+    let name = format!("findBy{}", columns.iter().map(|c| pascal_case(&c.field)).collect::<Vec<_>>().join("And"));
+    Lookup { name, columns, unique, order_by: None }
+}
+
+/// Case class of the stored structure, if its fields are exactly the columns.
+fn stored_class(p: &ast::Project, t: &Table) -> Option<String> {
+    let sidx = match t.entry.def {
+        TypeDef::Structure(idx) | TypeDef::Variant { structure: idx, .. } => idx,
+        TypeDef::Choice(_) => return None,
+    };
+    let fields = p.get_structure(sidx).ok()?.get_fields();
+    let same = fields.len() == t.columns.len()
+        && fields.iter().zip(&t.columns).all(|(f, c)| {
+            f.name == c.field && scala_type(&f.type_path) == scala_type(&c.type_path)
+        });
+    same.then(|| t.entry.name.split('.').map(sanitize).collect::<Vec<_>>().join("."))
+}
+
+pub fn repo<'a>(p: &ast::Project, t: &'a Table) -> Repo<'a> {
+    let base = match &t.kind {
+        TableKind::Entity => t.entry.name.replace('.', ""),
+        TableKind::List { field, .. } => t.entry.name.replace('.', "") + &pascal_case(field),
+    };
+    let (row, row_class) = match (&t.kind, stored_class(p, t)) {
+        (TableKind::Entity, Some(class)) => (class, None),
+        _ => {
+            let row = format!("{base}Row");
+            let fields = t.columns
+                .iter()
+                .map(|c| format!("{}: {}", sanitize(&c.field), scala_type(&c.type_path)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            (row.clone(), Some(format!("case class {row}({fields})")))
+        }
+    };
+    let key = t.primary_key.as_ref().map(|k| lookup(t, &k.columns, true));
+    let mut lookups = t.uniques.iter().map(|k| lookup(t, &k.columns, true)).collect::<Vec<_>>();
+    if let (TableKind::List { .. }, Some(k)) = (&t.kind, &t.primary_key) {
+        let mut owner = lookup(t, &k.columns[..k.columns.len() - 1], false);
+        owner.order_by = t.columns.iter().find(|c| c.name == "position");
+        lookups.push(owner);
+    }
+    lookups.extend(t.indexes.iter().map(|k| lookup(t, &k.columns, false)));
+    Repo { table: t, base, row, row_class, key, lookups }
+}
+
+/// Imports the rows of the tables in namespace `nspace` need: the
+/// packages of the stored classes and of the types of their fields, and
+/// the Java types the Scala 2 backend maps builtins to.
+pub fn imports(index: &TypeIndex, tables: &[&Table], nspace: &[String]) -> (BTreeSet<String>, BTreeSet<Vec<String>>) {
+    fn walk(index: &TypeIndex, tp: &ast::TypePath, from: &[String], imports: &mut BTreeSet<String>, packages: &mut BTreeSet<Vec<String>>) {
+        match Builtin::from_path(tp) {
+            Some(_) => {
+                if let Some(t) = tp.last() {
+                    imports.extend(imports_for(map_type(&t.name)));
+                }
+            }
+            None => {
+                if let Some(entry) = index.resolve_path(tp, from) {
+                    packages.insert(entry.path.clone());
+                }
+            }
+        }
+        for arg in tp.path.iter().flat_map(|t| t.args.iter()) {
+            walk(index, arg, from, imports, packages);
+        }
+    }
+    let mut imports = BTreeSet::new();
+    let mut packages = BTreeSet::new();
+    for t in tables {
+        packages.insert(t.entry.path.clone());
+        for c in &t.columns {
+            walk(index, &c.type_path, &t.entry.path, &mut imports, &mut packages);
+        }
+    }
+    for package in packages.iter().filter(|p| p.as_slice() != nspace) {
+        imports.insert(format!("{}._", package.join(".")));
+    }
+    (imports, packages)
+}
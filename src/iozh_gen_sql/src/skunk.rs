@@ -0,0 +1,173 @@
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::{Builtin, TypeIndex};
+use iozh_output::Output;
+use iozh_gen_scala2::gen::sanitize;
+
+use crate::model::*;
+use crate::scala::*;
+use crate::support::SKUNK_SUPPORT;
+
+/// Codec of the value of a column, `NULL` aside.
+fn value_codec(c: &Column) -> String {
+    let tp = value_type(c);
+    let builtin = |b: Option<Builtin>, tp: &ast::TypePath| match b {
+        Some(Builtin::I32) => "int4",
+        Some(Builtin::I64) => "int8",
+        Some(Builtin::F32) => "float4",
+        Some(Builtin::F64) => "float8",
+        Some(Builtin::Bool) => "bool",
+        Some(Builtin::String) => "text",
+        Some(Builtin::DateTime) => "instant",
+        Some(Builtin::Duration) if tp.last().is_some_and(|t| t.name == "FiniteDuration") => "finiteDuration",
+        Some(Builtin::Duration) => "duration",
+        Some(Builtin::File) => "file",
+        _ => "",
+    };
+    if is_native(c) {
+        match Builtin::from_path(tp) {
+            Some(Builtin::List) => {
+                let elem = tp.last().and_then(|t| t.args.first()).unwrap_or(tp);
+                format!("list(_{})", builtin(Builtin::from_path(elem), elem))
+            }
+            b => builtin(b, tp).to_string(),
+        }
+    } else {
+        let ty = scala_type(tp);
+        match c.sql_type.as_str() {
+            "TEXT" => format!("textAs[{ty}]"),
+            "BIGINT" => format!("longAs[{ty}]"),
+            _ => format!("jsonb[{ty}]"),
+        }
+    }
+}
+
+fn codec(c: &Column) -> String {
+    let codec = value_codec(c);
+    if c.nullable { format!("{codec}.opt") } else { codec }
+}
+
+fn where_clause(columns: &[&Column]) -> String {
+    columns
+        .iter()
+        .map(|c| format!("{} = ${}", ident(&c.name), braced(&value_codec(c))))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// An interpolated codec, braced unless it is a plain name.
+fn braced(codec: &str) -> String {
+    if codec.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        codec.to_string()
+    } else {
+        format!("{{{codec}}}")
+    }
+}
+
+/// Input type of a query by some columns.
+fn key_type(columns: &[&Column]) -> String {
+    match columns {
+        [c] => scala_type(value_type(c)),
+        _ => {
+            let types = columns.iter().map(|c| scala_type(value_type(c))).collect::<Vec<_>>();
+            format!("{} *: EmptyTuple", types.join(" *: "))
+        }
+    }
+}
+
+fn val(decl: &str, body: String) -> String {
+    let inner = INDENT.repeat(2);
+    format!("{inner}val {decl} =\n{inner}{INDENT}{body}\n")
+}
+
+fn gen_repo(r: &Repo) -> String {
+    let t = r.table;
+    let table = t.qualified();
+    let row = &r.row;
+    let all = r.columns();
+    let columns = columns_list(&all);
+    let mut vals = vec![];
+    let codec_body = match all.as_slice() {
+        [c] => format!("{}.imap({row}(_))(_.{})", codec(c), sanitize(&c.field)),
+        _ => format!("({}).to[{row}]", all.iter().map(|c| codec(c)).collect::<Vec<_>>().join(" *: ")),
+    };
+    vals.push(val(&format!("codec: Codec[{row}]"), codec_body));
+    let sql = format!("INSERT INTO {table} ({columns}) VALUES ($codec)");
+    vals.push(val(&format!("insert: Command[{row}]"), format!("sql{}.command", sql_string(&sql))));
+    for l in r.key.iter().chain(r.lookups.iter()) {
+        let mut sql = format!("SELECT {columns} FROM {table} WHERE {}", where_clause(&l.columns));
+        if let Some(c) = l.order_by {
+            sql.push_str(&format!(" ORDER BY {}", ident(&c.name)));
+        }
+        let decl = format!("{}: Query[{}, {row}]", l.name, key_type(&l.columns));
+        vals.push(val(&decl, format!("sql{}.query(codec)", sql_string(&sql))));
+    }
+    if let Some(key) = &r.key {
+        let values = r.values();
+        if !values.is_empty() {
+            let set = values
+                .iter()
+                .map(|c| format!("{0} = v.{0}", ident(&c.name)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let cond = key.columns
+                .iter()
+                .map(|c| format!("t.{0} = v.{0}", ident(&c.name)))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            let sql = format!("UPDATE {table} AS t SET {set} FROM (VALUES ($codec)) AS v ({columns}) WHERE {cond}");
+            vals.push(val(&format!("update: Command[{row}]"), format!("sql{}.command", sql_string(&sql))));
+        }
+        let sql = format!("DELETE FROM {table} WHERE {}", where_clause(&key.columns));
+        let decl = format!("delete: Command[{}]", key_type(&key.columns));
+        vals.push(val(&decl, format!("sql{}.command", sql_string(&sql))));
+    }
+    format!("{INDENT}object {} {{\n{}{INDENT}}}\n", r.name(), vals.join("\n"))
+}
+
+/// `SkunkRepos.scala` of a namespace with storage blocks.
+pub fn gen_nspace(p: &ast::Project, index: &TypeIndex, path: &[String], tables: &[&Table]) -> String {
+    let repos = tables.iter().map(|t| repo(p, t)).collect::<Vec<_>>();
+    let (mut imports, packages) = imports(index, tables, path);
+    imports.extend([
+        "iozh.sql.SkunkSupport._".to_string(),
+        "skunk._".to_string(),
+        "skunk.codec.all._".to_string(),
+        "skunk.implicits._".to_string(),
+    ]);
+    if tables.iter().flat_map(|t| t.columns.iter()).any(|c| !is_native(c)) {
+        imports.insert("iozh.circe.Implicits._".to_string());
+        imports.insert("skunk.circe.codec.all._".to_string());
+        imports.extend(packages.iter().map(|p| format!("{}.CirceImplicits._", p.join("."))));
+    }
+    let mut out = String::from(HEADER);
+    out.push_str(&format!("package {}\n\n", path.join(".")));
+    for i in &imports {
+        out.push_str(&format!("import {i}\n"));
+    }
+    out.push_str("\nobject SkunkRepos {\n");
+    for r in &repos {
+        out.push('\n');
+        if let Some(class) = &r.row_class {
+            out.push_str(&format!("{INDENT}{class}\n\n"));
+        }
+        out.push_str(&gen_repo(r));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Writes `SkunkRepos.scala` next to where the Scala 2 backend puts the
+/// classes of each namespace with storage blocks, plus `iozh/sql/SkunkSupport.scala`.
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    let schema = Schema::build(&project)?;
+    let index = TypeIndex::build(&project);
+    let support = target_folder.join("iozh").join("sql");
+    out.write(&support.join("SkunkSupport.scala"), SKUNK_SUPPORT)?;
+    for (path, tables) in schema.by_nspace() {
+        let folder = target_folder.join(path.join("/"));
+        out.write(&folder.join("SkunkRepos.scala"), &gen_nspace(&project, &index, &path, &tables))?;
+    }
+    Ok(())
+}
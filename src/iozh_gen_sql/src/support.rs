@@ -0,0 +1,91 @@
+/// Written as `iozh/sql/DoobieSupport.scala`: doobie mappings for the
+/// builtins doobie has none for, and for columns of iozh types, which hold
+/// what their circe codecs produce.
+pub const DOOBIE_SUPPORT: &str = r#"// Generated by iozh. Do not edit.
+package iozh.sql
+
+import cats.syntax.all._
+import doobie.{Get, Meta, Put}
+import doobie.postgres.circe.jsonb.implicits._
+import doobie.postgres.implicits._
+import io.circe.{Decoder, Encoder, Json}
+import io.circe.syntax._
+import java.io.File
+import org.postgresql.util.PGInterval
+import org.tpolecat.typename.TypeName
+import scala.concurrent.duration.{Duration, FiniteDuration, MILLISECONDS}
+
+object DoobieSupport {
+
+  /** `TEXT` column of a choice circe encodes as a string. */
+  def textMeta[A: Encoder: Decoder: TypeName]: Meta[A] = new Meta(
+    Get[String].temap(s => Json.fromString(s).as[A].leftMap(_.message)),
+    Put[String].tcontramap(a => a.asJson.asString.getOrElse(a.asJson.noSpaces)),
+  )
+
+  /** `BIGINT` column of a choice circe encodes as a number. */
+  def longMeta[A: Encoder: Decoder: TypeName]: Meta[A] = new Meta(
+    Get[Long].temap(l => Json.fromLong(l).as[A].leftMap(_.message)),
+    Put[Long].tcontramap(a => a.asJson.asNumber.flatMap(_.toLong).getOrElse(0L)),
+  )
+
+  /** `JSONB` column of any other type. */
+  def jsonbMeta[A: Encoder: Decoder: TypeName]: Meta[A] = new Meta(
+    Get[Json].temap(_.as[A].leftMap(_.message)),
+    Put[Json].tcontramap(_.asJson),
+  )
+
+  /** `INTERVAL`: months are taken as 30 days. */
+  implicit lazy val durationMeta: Meta[Duration] = Meta[PGInterval].timap[Duration] { i =>
+    val days = (i.getYears * 12 + i.getMonths) * 30L + i.getDays
+    val seconds = days * 86400 + i.getHours * 3600L + i.getMinutes * 60L
+    Duration(seconds * 1000 + math.round(i.getSeconds * 1000), MILLISECONDS)
+  }(d => new PGInterval(0, 0, 0, 0, 0, d.toMillis / 1000.0))
+
+  implicit lazy val finiteDurationMeta: Meta[FiniteDuration] = durationMeta.tiemap[FiniteDuration] {
+    case d: FiniteDuration => Right(d)
+    case d => Left("Expected a finite duration but got " + d)
+  }(identity)
+
+  implicit lazy val fileMeta: Meta[File] = Meta[String].timap(new File(_))(_.getPath)
+}
+"#;
+
+/// Written as `iozh/sql/SkunkSupport.scala`: skunk codecs for the builtins
+/// as the Scala 2 backend types them, and for columns of iozh types.
+pub const SKUNK_SUPPORT: &str = r#"// Generated by iozh. Do not edit.
+package iozh.sql
+
+import cats.syntax.all._
+import io.circe.{Decoder, Encoder, Json}
+import io.circe.syntax._
+import java.io.File
+import java.time.{Instant, ZoneOffset}
+import scala.concurrent.duration.{Duration, FiniteDuration, NANOSECONDS}
+import skunk.Codec
+import skunk.codec.all._
+import skunk.data.Arr
+
+object SkunkSupport {
+
+  val instant: Codec[Instant] = timestamptz.imap(_.toInstant)(_.atOffset(ZoneOffset.UTC))
+
+  val finiteDuration: Codec[FiniteDuration] =
+    interval.imap(d => FiniteDuration(d.toNanos, NANOSECONDS))(d => java.time.Duration.ofNanos(d.toNanos))
+
+  val duration: Codec[Duration] = finiteDuration.imap[Duration](identity)(d => FiniteDuration(d.toNanos, NANOSECONDS))
+
+  val file: Codec[File] = text.imap(new File(_))(_.getPath)
+
+  /** Array column as a list, `list(_int8)`. */
+  def list[A](codec: Codec[Arr[A]]): Codec[List[A]] = codec.imap(_.flattenTo(List))(Arr.fromFoldable(_))
+
+  /** `TEXT` column of a choice circe encodes as a string. */
+  def textAs[A: Encoder: Decoder]: Codec[A] =
+    text.eimap(s => Json.fromString(s).as[A].leftMap(_.message))(a => a.asJson.asString.getOrElse(a.asJson.noSpaces))
+
+  /** `BIGINT` column of a choice circe encodes as a number. */
+  def longAs[A: Encoder: Decoder]: Codec[A] =
+    int8.eimap(l => Json.fromLong(l).as[A].leftMap(_.message))(a => a.asJson.asNumber.flatMap(_.toLong).getOrElse(0L))
+}
+"#;
@@ -0,0 +1,823 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::*;
+use iozh_output::Output;
+
+const HEADER: &str = "// Generated by iozh. Do not edit.\n";
+const INDENT: &str = "  ";
+
+/// Same pattern as the JSON Schema backend: `Duration#toString` as put on
+/// the wire by circe.
+const DURATION_REGEX: &str =
+    r"/^(-?\d+ (day|hour|minute|second|millisecond|microsecond|nanosecond)s?|Duration\.(Inf|MinusInf|Undefined))$/";
+
+const RESERVED: [&str; 42] = [
+    "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do",
+    "else", "enum", "export", "extends", "false", "finally", "for", "function", "if", "import",
+    "in", "instanceof", "new", "null", "return", "super", "switch", "this", "throw", "true",
+    "try", "typeof", "var", "void", "while", "with", "let", "static", "yield", "await",
+    "implements", "interface",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct TsOptions {
+    /// Emit a zod schema next to every type.
+    pub validators: bool,
+}
+
+pub struct TsContext<'a> {
+    pub p: &'a ast::Project,
+    pub index: TypeIndex,
+    pub options: TsOptions,
+}
+
+/// Output of one namespace: `a.b` goes to `a/b.ts`.
+struct TsFile {
+    nspace: Vec<String>,
+    /// Namespace type names are resolved from, usually `nspace`.
+    scope: Vec<String>,
+    /// Namespaces referenced from this one, imported as `import * as a_b`.
+    imports: BTreeSet<Vec<String>>,
+    body: String,
+}
+
+pub fn sanitize(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+fn property(name: &str) -> String {
+    if is_identifier(name) {
+        name.to_string()
+    } else {
+        ts_string(name)
+    }
+}
+
+pub fn ts_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn ts_literal(l: &ast::Literal) -> String {
+    match l {
+        ast::Literal::Int { value, .. } => value.to_string(),
+        l => ts_string(&l.unquoted()),
+    }
+}
+
+fn doc_block(doc: &str, indent: &str) -> String {
+    let text = doc_text(doc);
+    if text.is_empty() {
+        return String::new();
+    }
+    let text = text.replace("*/", "* /");
+    if text.contains('\n') {
+        let lines = text.lines().map(|l| format!("{indent} * {l}").trim_end().to_string()).collect::<Vec<_>>();
+        format!("{indent}/**\n{}\n{indent} */\n", lines.join("\n"))
+    } else {
+        format!("{indent}/** {text} */\n")
+    }
+}
+
+fn generics(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", params.join(", "))
+    }
+}
+
+fn alias(path: &[String]) -> String {
+    path.join("_")
+}
+
+/// Module specifier of namespace `to` as imported from the file of `from`.
+fn import_path(from: &[String], to: &[String]) -> String {
+    let from_dir = &from[..from.len().saturating_sub(1)];
+    let to_dir = &to[..to.len().saturating_sub(1)];
+    let common = from_dir.iter().zip(to_dir.iter()).take_while(|(a, b)| a == b).count();
+    let ups = from_dir.len() - common;
+    let prefix = if ups == 0 { "./".to_string() } else { "../".repeat(ups) };
+    prefix + &to[common..].join("/")
+}
+
+/// Schema of a generic declaration is a function of the schemas of its
+/// type arguments: `<T extends z.ZodTypeAny>(TSchema: T) => ...`.
+fn schema_generics(params: &[String]) -> String {
+    let bounds = params.iter().map(|p| format!("{p} extends z.ZodTypeAny")).collect::<Vec<_>>();
+    let args = params.iter().map(|p| format!("{p}Schema: {p}")).collect::<Vec<_>>();
+    format!("<{}>({})", bounds.join(", "), args.join(", "))
+}
+
+fn schema_decl(indent: &str, name: &str, ty: &str, params: &[String], schema: &str) -> String {
+    if params.is_empty() {
+        format!("{indent}export const {name}Schema: z.ZodType<{ty}, z.ZodTypeDef, unknown> = {schema};\n")
+    } else {
+        format!("{indent}export const {name}Schema = {} =>\n{indent}{INDENT}{schema};\n", schema_generics(params))
+    }
+}
+
+fn union(members: &[String]) -> String {
+    match members {
+        [] => "z.never()".to_string(),
+        [single] => single.clone(),
+        members => format!("z.union([{}])", members.join(", ")),
+    }
+}
+
+impl <'a> TsContext<'a> {
+    pub fn new(p: &'a ast::Project, options: TsOptions) -> TsContext<'a> {
+        TsContext {
+            p,
+            index: TypeIndex::build(p),
+            options,
+        }
+    }
+
+    /// Name of a declaration as seen from `file`, importing its namespace when needed.
+    fn qualified(&self, entry: &TypeEntry, file: &mut TsFile) -> String {
+        if entry.path == file.nspace {
+            entry.name.clone()
+        } else {
+            file.imports.insert(entry.path.clone());
+            format!("{}.{}", alias(&entry.path), entry.name)
+        }
+    }
+
+    fn type_arg(&self, tp: &ast::TypePath, file: &mut TsFile, params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.first()) {
+            Some(arg) => self.ts_type(arg, file, params),
+            None => "unknown".to_string(),
+        }
+    }
+
+    fn ts_type(&self, tp: &ast::TypePath, file: &mut TsFile, params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "unknown".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return tag.name.clone();
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 | Builtin::I64 | Builtin::F32 | Builtin::F64 => "number".to_string(),
+                Builtin::Bool => "boolean".to_string(),
+                Builtin::String | Builtin::DateTime | Builtin::Duration | Builtin::File => "string".to_string(),
+                Builtin::Option => format!("{} | null", self.type_arg(tp, file, params)),
+                Builtin::List => format!("Array<{}>", self.type_arg(tp, file, params)),
+            };
+        }
+        if tp.path.len() == 1 && tag.name == "Unit" {
+            return "void".to_string();
+        }
+        let args = tag.args.iter().map(|a| self.ts_type(a, file, params)).collect::<Vec<_>>();
+        match self.index.resolve_path(tp, &file.scope) {
+            Some(entry) => self.qualified(entry, file) + &generics(&args),
+            None => tp.path.iter().map(|t| t.name.clone()).collect::<Vec<_>>().join(".") + &generics(&args),
+        }
+    }
+
+    fn schema_arg(&self, tp: &ast::TypePath, file: &mut TsFile, params: &[String]) -> String {
+        match tp.last().and_then(|t| t.args.first()) {
+            Some(arg) => self.schema(arg, file, params),
+            None => "z.unknown()".to_string(),
+        }
+    }
+
+    /// zod schema of `tp`. Declared types are referenced lazily, which
+    /// covers both recursion and declaration order.
+    fn schema(&self, tp: &ast::TypePath, file: &mut TsFile, params: &[String]) -> String {
+        let tag = match tp.last() {
+            Some(tag) => tag,
+            None => return "z.unknown()".to_string(),
+        };
+        if tp.path.len() == 1 && params.contains(&tag.name) {
+            return format!("{}Schema", tag.name);
+        }
+        if let Some(builtin) = Builtin::from_path(tp) {
+            return match builtin {
+                Builtin::I32 | Builtin::I64 => "z.number().int()".to_string(),
+                Builtin::F32 | Builtin::F64 => "z.number()".to_string(),
+                Builtin::Bool => "z.boolean()".to_string(),
+                Builtin::String | Builtin::File => "z.string()".to_string(),
+                Builtin::DateTime => "z.string().datetime()".to_string(),
+                Builtin::Duration => format!("z.string().regex({DURATION_REGEX})"),
+                Builtin::Option => format!("{}.nullable()", self.schema_arg(tp, file, params)),
+                Builtin::List => format!("z.array({})", self.schema_arg(tp, file, params)),
+            };
+        }
+        match self.index.resolve_path(tp, &file.scope) {
+            Some(entry) => {
+                let name = self.qualified(entry, file);
+                if tag.args.is_empty() {
+                    format!("z.lazy(() => {name}Schema)")
+                } else {
+                    let args = tag.args.iter().map(|a| self.schema(a, file, params)).collect::<Vec<_>>();
+                    format!("z.lazy(() => {name}Schema({}))", args.join(", "))
+                }
+            }
+            None => "z.unknown()".to_string(),
+        }
+    }
+
+    /// Interface body and zod object of a structure, with the tag of a
+    /// tagged choice item as a literal first property.
+    fn gen_object(
+        &self,
+        file: &mut TsFile,
+        indent: &str,
+        s: &ast::Structure,
+        params: &[String],
+        fields: &[&ast::Field],
+        tag: Option<&(String, String)>,
+    ) {
+        let name = &s.name.name;
+        let inner = format!("{indent}{INDENT}");
+        let mut body = doc_block(&s.doc, indent);
+        let mut shape = vec![];
+        body.push_str(&format!("{indent}export interface {name}{} {{\n", generics(params)));
+        if let Some((key, value)) = tag {
+            body.push_str(&format!("{inner}{}: {};\n", property(key), ts_string(value)));
+            shape.push(format!("{}: z.literal({})", property(key), ts_string(value)));
+        }
+        for f in fields {
+            body.push_str(&doc_block(&f.doc, &inner));
+            let key = property(&f.name);
+            match Builtin::from_path(&f.type_path) {
+                Some(Builtin::Option) => {
+                    body.push_str(&format!("{inner}{key}?: {};\n", self.type_arg(&f.type_path, file, params)));
+                    shape.push(format!("{key}: {}.optional()", self.schema_arg(&f.type_path, file, params)));
+                }
+                Some(Builtin::List) => {
+                    body.push_str(&format!("{inner}{key}: {};\n", self.ts_type(&f.type_path, file, params)));
+                    shape.push(format!("{key}: {}.default([])", self.schema(&f.type_path, file, params)));
+                }
+                _ => {
+                    body.push_str(&format!("{inner}{key}: {};\n", self.ts_type(&f.type_path, file, params)));
+                    shape.push(format!("{key}: {}", self.schema(&f.type_path, file, params)));
+                }
+            }
+        }
+        body.push_str(&format!("{indent}}}\n"));
+        if self.options.validators {
+            let schema = if shape.is_empty() {
+                "z.object({})".to_string()
+            } else {
+                let props = shape.iter().map(|s| format!("{inner}{INDENT}{s},\n")).collect::<String>();
+                format!("z.object({{\n{props}{inner}}})")
+            };
+            let schema = if params.is_empty() { schema.replace(&format!("\n{INDENT}"), "\n") } else { schema };
+            body.push('\n');
+            body.push_str(&schema_decl(indent, name, &format!("{name}{}", generics(params)), params, &schema));
+        }
+        file.body.push_str(&body);
+    }
+
+    fn gen_structure(&self, file: &mut TsFile, s: &ast::Structure) {
+        let fields = s.get_fields();
+        self.gen_object(file, "", s, &s.name.param_names(), &fields, None);
+    }
+
+    /// A choice is the union of its items' wire representations, with the
+    /// item types and constants in a namespace of the same name. Tagged
+    /// structure items carry their tag as a literal property, so the union
+    /// discriminates on the tag key.
+    fn gen_choice(&self, file: &mut TsFile, c: &ast::Choice) {
+        let name = &c.name.name;
+        let params = c.name.param_names();
+        let variants = c.variants(self.p);
+        let mut members = vec![];
+        let mut schemas = vec![];
+
+        let mut items = TsFile { nspace: file.nspace.clone(), scope: file.scope.clone(), imports: BTreeSet::new(), body: String::new() };
+        for v in &variants {
+            let item = &v.name().name;
+            if !items.body.is_empty() {
+                items.body.push('\n');
+            }
+            match v {
+                ChoiceVariant::Unit { doc, wire, .. } => {
+                    items.body.push_str(&doc_block(doc, INDENT));
+                    items.body.push_str(&format!("{INDENT}export const {item} = {} as const;\n", ts_string(wire)));
+                    members.push(format!("typeof {name}.{item}"));
+                    schemas.push(format!("z.literal({name}.{item})"));
+                }
+                ChoiceVariant::Value { doc, value, .. } => {
+                    items.body.push_str(&doc_block(doc, INDENT));
+                    items.body.push_str(&format!("{INDENT}export const {item} = {} as const;\n", ts_literal(value)));
+                    members.push(format!("typeof {name}.{item}"));
+                    schemas.push(format!("z.literal({name}.{item})"));
+                }
+                ChoiceVariant::Wrap { doc, target, .. } => {
+                    let vparams = params.iter().filter(|p| target.mentions(p)).cloned().collect::<Vec<_>>();
+                    let ty = self.ts_type(target, &mut items, &params);
+                    items.body.push_str(&doc_block(doc, INDENT));
+                    items.body.push_str(&format!("{INDENT}export type {item}{} = {ty};\n", generics(&vparams)));
+                    members.push(format!("{name}.{item}{}", generics(&vparams)));
+                    schemas.push(self.schema(target, &mut items, &params));
+                }
+                ChoiceVariant::Record { structure, fields, tag } => {
+                    let vparams = used_params(&params, fields);
+                    self.gen_object(&mut items, INDENT, structure, &vparams, fields, tag.as_ref());
+                    members.push(format!("{name}.{item}{}", generics(&vparams)));
+                    let args = vparams.iter().map(|p| format!("{p}Schema")).collect::<Vec<_>>();
+                    schemas.push(match args.is_empty() {
+                        true => format!("{name}.{item}Schema"),
+                        false => format!("{name}.{item}Schema({})", args.join(", ")),
+                    });
+                }
+            }
+        }
+        file.imports.extend(items.imports);
+
+        file.body.push_str(&doc_block(&c.doc, ""));
+        let members = if members.is_empty() { vec!["never".to_string()] } else { members };
+        file.body.push_str(&format!("export type {name}{} =\n", generics(&params)));
+        let members = members.iter().map(|m| format!("{INDENT}| {m}")).collect::<Vec<_>>();
+        file.body.push_str(&members.join("\n"));
+        file.body.push_str(";\n");
+        if !items.body.is_empty() {
+            file.body.push_str(&format!("\nexport namespace {name} {{\n{}}}\n", items.body));
+        }
+        if self.options.validators {
+            file.body.push('\n');
+            let ty = format!("{name}{}", generics(&params));
+            file.body.push_str(&schema_decl("", name, &ty, &params, &union(&schemas)));
+        }
+    }
+
+    /// Services are implemented asynchronously on the TypeScript side,
+    /// streams as async iterables.
+    fn gen_service(&self, file: &mut TsFile, s: &ast::Service) {
+        let params = s.name.param_names();
+        file.body.push_str(&doc_block(&s.doc, ""));
+        file.body.push_str(&format!("export interface {}{} {{\n", s.name.name, generics(&params)));
+        for m in &s.methods {
+            let mut mparams = params.clone();
+            let own = m.name.param_names();
+            mparams.extend(own.iter().cloned());
+            let args = m.args
+                .iter()
+                .map(|a| {
+                    let ty = self.ts_type(&a.type_path, file, &mparams);
+                    let ty = if m.is_stream_arg(&a.name) { format!("AsyncIterable<{ty}>") } else { ty };
+                    format!("{}: {ty}", sanitize(&a.name))
+                })
+                .collect::<Vec<_>>();
+            let result = self.ts_type(&m.result, file, &mparams);
+            let result = if m.stream_result { format!("AsyncIterable<{result}>") } else { format!("Promise<{result}>") };
+            file.body.push_str(&doc_block(&m.doc, INDENT));
+            file.body.push_str(&format!(
+                "{INDENT}{}{}({}): {result};\n",
+                sanitize(&m.name.name),
+                generics(&own),
+                args.join(", "),
+            ));
+        }
+        file.body.push_str("}\n");
+    }
+
+    /// Events are a union discriminated by their `event` name, the payload
+    /// next to it; `route{Name}` calls the handler of the event's kind.
+    fn gen_events(&self, file: &mut TsFile, e: &ast::Events) {
+        let name = &e.name;
+        let mut members = vec![];
+        let mut handlers = vec![];
+        let mut cases = vec![];
+        let mut schemas = vec![];
+        for ev in &e.events {
+            let ty = self.ts_type(&ev.type_path, file, &[]);
+            let wire = ts_string(&ev.name);
+            let method = sanitize(&ev.name);
+            members.push(format!("{INDENT}| {{ event: {wire}; payload: {ty} }}"));
+            handlers.push(format!("{}{INDENT}{method}(payload: {ty}): Promise<void>;\n", doc_block(&ev.doc, INDENT)));
+            cases.push(format!("{INDENT}{INDENT}case {wire}:\n{INDENT}{INDENT}{INDENT}return handler.{method}(event.payload);\n"));
+            schemas.push(format!("z.object({{ event: z.literal({wire}), payload: {} }})", self.schema(&ev.type_path, file, &[])));
+        }
+        file.body.push_str(&doc_block(&e.doc, ""));
+        file.body.push_str(&format!("export type {name} =\n{};\n", members.join("\n")));
+        file.body.push_str(&format!("\nexport interface {name}Handler {{\n{}}}\n", handlers.concat()));
+        file.body.push_str(&format!(
+            "\nexport interface {name}Publisher {{\n{INDENT}publish(event: {name}): Promise<void>;\n}}\n",
+        ));
+        file.body.push_str(&format!(
+            "\nexport function route{name}(handler: {name}Handler, event: {name}): Promise<void> {{\n{INDENT}switch (event.event) {{\n{}{INDENT}}}\n}}\n",
+            cases.concat(),
+        ));
+        if self.options.validators {
+            file.body.push('\n');
+            file.body.push_str(&schema_decl("", name, name, &[], &union(&schemas)));
+        }
+    }
+
+    /// A `fetch` client with one method per route: path variables and the
+    /// route fields become arguments, sent where their source says (fields
+    /// without one go to the query), the route input becomes the JSON body.
+    /// Streams go as newline-delimited JSON, results yielded as they come.
+    fn gen_http_service(&self, file: &mut TsFile, s: &ast::HttpService) -> Result<(), IozhError> {
+        let inner = format!("{INDENT}{INDENT}");
+        file.body.push_str(&format!("export class {}Client {{\n", s.name.name));
+        file.body.push_str(&format!(
+            "{INDENT}constructor(private readonly baseUrl: string, private readonly fetchFn: typeof fetch = globalThis.fetch.bind(globalThis)) {{}}\n",
+        ));
+        let mut taken = HashSet::new();
+        for r in &s.routes {
+            let target = self.p.resolve_method(&r.method, &file.nspace);
+            let mut method_name = r.method.path.last().map(|t| sanitize(&t.name)).unwrap_or_else(|| "call".to_string());
+            let mut n = 1;
+            while !taken.insert(method_name.clone()) {
+                n += 1;
+                method_name = format!("{}{n}", method_name.trim_end_matches(char::is_numeric));
+            }
+
+            let params = r.params()?;
+            let body_kind = r.body_kind()?;
+            let streamed_input = target.as_ref().is_some_and(|(_, _, m)| r.streams_input(m, &params));
+            let streamed_result = target.as_ref().is_some_and(|(_, _, m)| m.stream_result);
+            let mut args = vec![];
+            for p in params.iter().filter(|p| p.source == ast::ParamSource::Path) {
+                args.push(format!("{}: {}", sanitize(&p.field.name), self.ts_type(&p.field.type_path, file, &[])));
+            }
+            let others = params.iter().filter(|p| p.source != ast::ParamSource::Path).collect::<Vec<_>>();
+            for p in &others {
+                let f = &p.field;
+                let (optional, tp) = match Builtin::from_path(&f.type_path) {
+                    Some(Builtin::Option) => (true, f.type_path.path[0].args.first().unwrap_or(&f.type_path)),
+                    _ => (false, &f.type_path),
+                };
+                let ty = match (&p.source, Builtin::from_path(tp)) {
+                    // Uploaded as they are rather than as the file name.
+                    (ast::ParamSource::Multipart, Some(Builtin::File)) => "Blob".to_string(),
+                    _ => self.ts_type(tp, file, &[]),
+                };
+                args.push(format!("{}{}: {ty}", sanitize(&f.name), if optional { "?" } else { "" }));
+            }
+            if streamed_input {
+                args.push(format!("body: AsyncIterable<{}>", self.ts_type(&r.input, file, &[])));
+            } else if r.has_input() {
+                args.push(format!("body: {}", self.ts_type(&r.input, file, &[])));
+            }
+            // Optional parameters must come last.
+            args.sort_by_key(|a| a.split(':').next().is_some_and(|n| n.ends_with('?')));
+
+            let (result_type, result_schema) = match &target {
+                // Errors of a streamed result come along with its values.
+                Some((path, _, m)) if m.stream_result && m.error.is_some() => {
+                    file.scope = path.clone();
+                    let types = [&m.result, m.error.as_ref().unwrap_or(&m.result)];
+                    let ty = types.iter().map(|t| self.ts_type(t, file, &[])).collect::<Vec<_>>().join(" | ");
+                    let schema = self.options.validators.then(|| {
+                        format!("z.union([{}])", types.iter().map(|t| self.schema(t, file, &[])).collect::<Vec<_>>().join(", "))
+                    });
+                    file.scope = file.nspace.clone();
+                    (ty, schema)
+                }
+                Some((path, _, m)) => {
+                    // The result type is declared next to the service.
+                    file.scope = path.clone();
+                    let ty = self.ts_type(&m.result, file, &[]);
+                    let schema = self.options.validators.then(|| self.schema(&m.result, file, &[]));
+                    file.scope = file.nspace.clone();
+                    (ty, schema)
+                }
+                None => ("unknown".to_string(), None),
+            };
+
+            let url = r.pattern.segments
+                .iter()
+                .map(|s| match s {
+                    ast::PathSegment::Literal(l) => format!("/{}", l.replace('`', "\\`").replace("${", "\\${")),
+                    ast::PathSegment::Var { name, .. } => format!("/${{encodeURIComponent(String({}))}}", sanitize(name)),
+                    ast::PathSegment::Wildcard(name) => {
+                        format!("/${{String({}).split(\"/\").map(encodeURIComponent).join(\"/\")}}", sanitize(name))
+                    }
+                })
+                .collect::<String>();
+            let from = |wanted: &[ast::ParamSource]| others.iter().filter(|p| wanted.contains(&p.source)).map(|p| &p.field).collect::<Vec<_>>();
+            let query = from(&[ast::ParamSource::Query]);
+            let json = from(&[ast::ParamSource::Body]);
+            let form = from(&[ast::ParamSource::Form, ast::ParamSource::Multipart]);
+            let headers = others
+                .iter()
+                .filter_map(|p| match &p.source {
+                    ast::ParamSource::Header(name) => Some((name, &p.field)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            file.body.push('\n');
+            file.body.push_str(&doc_block(target.as_ref().map(|(_, _, m)| m.doc.as_str()).unwrap_or(""), INDENT));
+            if streamed_result {
+                file.body.push_str(&format!("{INDENT}async *{method_name}({}): AsyncGenerator<{result_type}> {{\n", args.join(", ")));
+            } else {
+                file.body.push_str(&format!("{INDENT}async {method_name}({}): Promise<{result_type}> {{\n", args.join(", ")));
+            }
+            let url = if url.is_empty() { "/".to_string() } else { url };
+            let mut fetch_url = format!("`${{this.baseUrl}}{url}`");
+            if !query.is_empty() {
+                file.body.push_str(&format!("{inner}const query = new URLSearchParams();\n"));
+                for f in &query {
+                    file.body.push_str(&format!("{inner}appendQuery(query, {}, {});\n", ts_string(&f.name), sanitize(&f.name)));
+                }
+                file.body.push_str(&format!("{inner}const qs = query.toString();\n"));
+                fetch_url.push_str(" + (qs ? `?${qs}` : \"\")");
+            }
+            let has_headers = !headers.is_empty() || body_kind == BodyKind::Json;
+            if has_headers {
+                file.body.push_str(&format!("{inner}const headers: Record<string, string> = {{}};\n"));
+                if streamed_input {
+                    file.body.push_str(&format!("{inner}headers[\"Content-Type\"] = \"application/x-ndjson\";\n"));
+                } else if body_kind == BodyKind::Json {
+                    file.body.push_str(&format!("{inner}headers[\"Content-Type\"] = \"application/json\";\n"));
+                }
+                for (name, f) in &headers {
+                    let value = sanitize(&f.name);
+                    let set = format!("headers[{}] = String({value});", ts_string(name));
+                    match Builtin::from_path(&f.type_path) {
+                        Some(Builtin::Option) => file.body.push_str(&format!("{inner}if ({value} !== undefined && {value} !== null) {{\n{inner}{INDENT}{set}\n{inner}}}\n")),
+                        _ => file.body.push_str(&format!("{inner}{set}\n")),
+                    }
+                }
+            }
+            let body = match body_kind {
+                BodyKind::None => None,
+                BodyKind::Json if streamed_input => Some("jsonLinesBody(body)".to_string()),
+                BodyKind::Json if json.is_empty() => Some("JSON.stringify(body)".to_string()),
+                BodyKind::Json => {
+                    let mut props = json.iter().map(|f| format!("{}: {}", ts_string(&f.name), sanitize(&f.name))).collect::<Vec<_>>();
+                    if r.has_input() {
+                        props.insert(0, "...body".to_string());
+                    }
+                    Some(format!("JSON.stringify({{ {} }})", props.join(", ")))
+                }
+                BodyKind::Form | BodyKind::Multipart => {
+                    let (class, append) = if body_kind == BodyKind::Form { ("URLSearchParams", "appendQuery") } else { ("FormData", "appendPart") };
+                    file.body.push_str(&format!("{inner}const form = new {class}();\n"));
+                    for f in &form {
+                        file.body.push_str(&format!("{inner}{append}(form, {}, {});\n", ts_string(&f.name), sanitize(&f.name)));
+                    }
+                    Some("form".to_string())
+                }
+            };
+            file.body.push_str(&format!("{inner}const res = await this.fetchFn({fetch_url}, {{\n"));
+            file.body.push_str(&format!("{inner}{INDENT}method: {},\n", ts_string(&r.verb)));
+            if has_headers {
+                file.body.push_str(&format!("{inner}{INDENT}headers,\n"));
+            }
+            if let Some(body) = body {
+                file.body.push_str(&format!("{inner}{INDENT}body: {body},\n"));
+            }
+            // Required by `fetch` to send a stream.
+            if streamed_input {
+                file.body.push_str(&format!("{inner}{INDENT}...({{ duplex: \"half\" }} as RequestInit),\n"));
+            }
+            file.body.push_str(&format!("{inner}}});\n"));
+            // Declared statuses are answered with the result, the failing
+            // ones included; `None` comes without a body. Errors are thrown
+            // as a `ResponseError`: those declared, and the 500 the server
+            // answers the others with.
+            let responses = match &target {
+                Some((path, _, m)) => r.responses(self.p, &self.index, path, m)?,
+                None => vec![],
+            };
+            if let Some((path, _, m)) = target.as_ref().filter(|_| !streamed_result) {
+                if let Some(error) = &m.error {
+                    let mut statuses = responses.iter().filter(|x| x.error).map(|x| x.response.status).collect::<Vec<_>>();
+                    let items = match self.index.resolve_path(error, path).map(|e| e.def) {
+                        Some(TypeDef::Choice(idx)) => self.p.get_choice(idx)?.choices.iter().filter(|c| !matches!(c, ast::ChoiceItem::Nil)).count(),
+                        _ => 0,
+                    };
+                    let answered = responses.iter().filter(|x| x.error && matches!(x.case, ResponseCase::Item { .. })).count();
+                    if !responses.iter().any(|x| x.error && x.case == ResponseCase::Result) && (items == 0 || answered < items) {
+                        statuses.push(500);
+                    }
+                    let statuses = statuses.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+                    file.scope = path.clone();
+                    let decoded = match self.options.validators {
+                        true => format!("{}.parse(await res.json())", self.schema(error, file, &[])),
+                        false => format!("(await res.json()) as {}", self.ts_type(error, file, &[])),
+                    };
+                    file.scope = file.nspace.clone();
+                    let test = match statuses.as_slice() {
+                        [status] => format!("res.status === {status}"),
+                        _ => format!("[{}].includes(res.status)", statuses.join(", ")),
+                    };
+                    file.body.push_str(&format!("{inner}if ({test}) {{\n{inner}{INDENT}throw new ResponseError(res.status, {decoded});\n{inner}}}\n"));
+                }
+            }
+            let declares_option = responses.iter().any(|x| matches!(x.case, ResponseCase::Some | ResponseCase::None));
+            if declares_option {
+                let none = responses.iter().find(|x| x.case == ResponseCase::None).map(|x| x.response.status).unwrap_or(404);
+                file.body.push_str(&format!("{inner}if (res.status === {none}) {{\n{inner}{INDENT}return null;\n{inner}}}\n"));
+            }
+            let failing = responses
+                .iter()
+                .filter(|x| !x.error && x.case != ResponseCase::None)
+                .map(|x| x.response.status)
+                .filter(|s| !(200..300).contains(s))
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>();
+            let ok = match failing.as_slice() {
+                [] => "res.ok".to_string(),
+                [status] => format!("(res.ok || res.status === {status})"),
+                _ => format!("(res.ok || [{}].includes(res.status))", failing.join(", ")),
+            };
+            file.body.push_str(&format!(
+                "{inner}if (!{ok}) {{\n{inner}{INDENT}throw new Error(`{} {} failed with status ${{res.status}}`);\n{inner}}}\n",
+                r.verb,
+                r.pattern.to_string().replace('`', "\\`").replace("${", "\\${"),
+            ));
+            match (result_type.as_str(), result_schema) {
+                (_, Some(schema)) if streamed_result => {
+                    file.body.push_str(&format!("{inner}for await (const value of jsonLines(res)) {{\n{inner}{INDENT}yield {schema}.parse(value);\n{inner}}}\n"));
+                }
+                (ty, None) if streamed_result => {
+                    file.body.push_str(&format!("{inner}for await (const value of jsonLines(res)) {{\n{inner}{INDENT}yield value as {ty};\n{inner}}}\n"));
+                }
+                ("void", _) => {}
+                (_, Some(schema)) => file.body.push_str(&format!("{inner}return {schema}.parse(await res.json());\n")),
+                (ty, None) => file.body.push_str(&format!("{inner}return (await res.json()) as {ty};\n")),
+            }
+            file.body.push_str(&format!("{INDENT}}}\n"));
+        }
+        file.body.push_str("}\n");
+        Ok(())
+    }
+
+    fn gen_nspace(&self, path: &[String], nspace: &ast::Nspace) -> Result<Option<TsFile>, IozhError> {
+        let mut file = TsFile { nspace: path.to_vec(), scope: path.to_vec(), imports: BTreeSet::new(), body: String::new() };
+        let mut has_client = false;
+        let mut has_upload = false;
+        let mut has_errors = false;
+        let mut has_streams = false;
+        for item in &nspace.items {
+            let before = file.body.len();
+            if before > 0 {
+                file.body.push('\n');
+            }
+            match item {
+                ast::NspaceItem::Structure(idx) => self.gen_structure(&mut file, self.p.get_structure(*idx)?),
+                ast::NspaceItem::Choice(idx) => self.gen_choice(&mut file, self.p.get_choice(*idx)?),
+                ast::NspaceItem::Service(s) => self.gen_service(&mut file, s),
+                ast::NspaceItem::Events(e) => self.gen_events(&mut file, e),
+                ast::NspaceItem::HttpService(s) => {
+                    has_client = true;
+                    has_upload |= s.routes.iter().any(|r| r.fields.iter().any(|f| f.source == Some(ast::ParamSource::Multipart)));
+                    has_errors |= s.routes.iter().any(|r| self.p.resolve_method(&r.method, path).is_some_and(|(_, _, m)| m.error.is_some() && !m.stream_result));
+                    has_streams |= s.routes.iter().any(|r| self.p.resolve_method(&r.method, path).is_some_and(|(_, _, m)| m.has_streams()));
+                    self.gen_http_service(&mut file, s)?
+                }
+                ast::NspaceItem::Storage(_) | ast::NspaceItem::Nspace(_) => file.body.truncate(before),
+            }
+        }
+        if file.body.is_empty() {
+            return Ok(None);
+        }
+        if has_client {
+            file.body.push_str(APPEND_QUERY);
+        }
+        if has_upload {
+            file.body.push_str(APPEND_PART);
+        }
+        if has_errors {
+            file.body.push_str(RESPONSE_ERROR);
+        }
+        if has_streams {
+            file.body.push_str(JSON_LINES);
+        }
+        Ok(Some(file))
+    }
+}
+
+const APPEND_QUERY: &str = r#"
+function appendQuery(query: URLSearchParams, name: string, value: unknown): void {
+  if (value === undefined || value === null) {
+    return;
+  }
+  if (Array.isArray(value)) {
+    value.forEach((v) => appendQuery(query, name, v));
+  } else {
+    query.append(name, typeof value === "object" ? JSON.stringify(value) : String(value));
+  }
+}
+"#;
+
+const APPEND_PART: &str = r#"
+function appendPart(form: FormData, name: string, value: unknown): void {
+  if (value === undefined || value === null) {
+    return;
+  }
+  if (value instanceof Blob) {
+    form.append(name, value);
+  } else if (Array.isArray(value)) {
+    value.forEach((v) => appendPart(form, name, v));
+  } else {
+    form.append(name, typeof value === "object" ? JSON.stringify(value) : String(value));
+  }
+}
+"#;
+
+const RESPONSE_ERROR: &str = r#"
+/** An error a service method declares, answered with `status`. */
+export class ResponseError<E> extends Error {
+  constructor(public readonly status: number, public readonly error: E) {
+    super(`Request failed with status ${status}`);
+  }
+}
+"#;
+
+const JSON_LINES: &str = r#"
+/** Values of a newline-delimited JSON response, as they come. */
+async function* jsonLines(res: Response): AsyncGenerator<unknown> {
+  if (!res.body) {
+    return;
+  }
+  const reader = res.body.getReader();
+  const decoder = new TextDecoder();
+  let buffer = "";
+  for (;;) {
+    const { done, value } = await reader.read();
+    buffer += decoder.decode(value, { stream: !done });
+    const lines = buffer.split("\n");
+    buffer = lines.pop() ?? "";
+    for (const line of lines.filter((l) => l.trim())) {
+      yield JSON.parse(line);
+    }
+    if (done) {
+      break;
+    }
+  }
+  if (buffer.trim()) {
+    yield JSON.parse(buffer);
+  }
+}
+
+/** A request body sending values as newline-delimited JSON. */
+function jsonLinesBody(values: AsyncIterable<unknown>): ReadableStream<Uint8Array> {
+  const encoder = new TextEncoder();
+  const iterator = values[Symbol.asyncIterator]();
+  return new ReadableStream({
+    async pull(controller) {
+      const { done, value } = await iterator.next();
+      if (done) {
+        controller.close();
+      } else {
+        controller.enqueue(encoder.encode(JSON.stringify(value) + "\n"));
+      }
+    },
+  });
+}
+"#;
+
+/// Writes one module per namespace, `a.b` to `a/b.ts`. With validators the
+/// generated code imports `zod` (v3).
+pub fn generate_with(project: ast::Project, target_folder: &Path, out: &mut dyn Output, options: TsOptions) -> Result<(), IozhError> {
+    let ctx = TsContext::new(&project, options);
+    for (path, nspace) in project.all_nspaces() {
+        let file = match ctx.gen_nspace(&path, nspace)? {
+            Some(file) => file,
+            None => continue,
+        };
+        let mut content = String::from(HEADER);
+        if ctx.options.validators {
+            content.push_str("import { z } from \"zod\";\n");
+        }
+        for import in &file.imports {
+            content.push_str(&format!("import * as {} from {};\n", alias(import), ts_string(&import_path(&path, import))));
+        }
+        content.push('\n');
+        content.push_str(&file.body);
+
+        let (dirs, name) = path.split_at(path.len() - 1);
+        let dir = dirs.iter().fold(target_folder.to_path_buf(), |acc, d| acc.join(d));
+        out.write(&dir.join(format!("{}.ts", name[0])), &content)?;
+    }
+    Ok(())
+}
+
+pub fn generate(project: ast::Project, target_folder: &Path, out: &mut dyn Output) -> Result<(), IozhError> {
+    generate_with(project, target_folder, out, TsOptions::default())
+}
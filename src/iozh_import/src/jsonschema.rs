@@ -0,0 +1,22 @@
+use serde_json::Value;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use crate::names::*;
+use crate::schema::SchemaImporter;
+
+/// Imports `$defs` (or draft-07 `definitions`) and the root schema itself
+/// into a namespace called after `name`, usually the file stem.
+pub fn import(root: &Value, name: &str) -> Result<ast::Project, IozhError> {
+    let defs_pointer = if root.get("$defs").is_some() { "/$defs" } else { "/definitions" };
+    let mut importer = SchemaImporter::new(root, defs_pointer);
+    importer.declare_all()?;
+    let root_name = root.get("title").and_then(|t| t.as_str()).unwrap_or(name);
+    importer.declare_root(root_name)?;
+    let mut project = importer.project;
+    project.nspaces.push(ast::Nspace {
+        pos: ast::Pos::default(),
+        name: member_name(name),
+        items: importer.items,
+    });
+    Ok(project)
+}
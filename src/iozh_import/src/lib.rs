@@ -0,0 +1,54 @@
+mod names;
+mod schema;
+pub mod openapi;
+pub mod jsonschema;
+
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use serde_json::Value;
+
+/// Reads a JSON or YAML document. YAML mapping keys that are not strings
+/// (response codes such as `200:`) are turned into strings.
+pub fn load(source: &str) -> Result<Value, IozhError> {
+    let yaml: serde_yaml::Value = serde_yaml::from_str(source)
+        .map_err(|e| IozhError::from(format!("Failed to read spec: {}", e)))?;
+    Ok(yaml_to_json(yaml))
+}
+
+fn yaml_to_json(v: serde_yaml::Value) -> Value {
+    match v {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                Value::from(u)
+            } else {
+                n.as_f64().map(Value::from).unwrap_or(Value::Null)
+            }
+        }
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(s) => Value::Array(s.into_iter().map(yaml_to_json).collect()),
+        serde_yaml::Value::Mapping(m) => Value::Object(
+            m.into_iter()
+                .map(|(k, v)| {
+                    let key = match yaml_to_json(k) {
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    (key, yaml_to_json(v))
+                })
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(t) => yaml_to_json(t.value),
+    }
+}
+
+pub fn import_openapi(source: &str) -> Result<ast::Project, IozhError> {
+    openapi::import(&load(source)?)
+}
+
+pub fn import_jsonschema(source: &str, name: &str) -> Result<ast::Project, IozhError> {
+    jsonschema::import(&load(source)?, name)
+}
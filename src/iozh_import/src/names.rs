@@ -0,0 +1,73 @@
+/// Splits arbitrary text into identifier words: `pet-store_v2` gives
+/// `pet`, `store`, `v2`, and `petId` gives `pet`, `Id`.
+fn words(s: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in s.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(w: &str) -> String {
+    let mut chars = w.chars();
+    match chars.next() {
+        Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+fn guard_start(name: String, prefix: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => name,
+        Some(_) => format!("{prefix}{name}"),
+        None => prefix.to_string(),
+    }
+}
+
+pub fn type_name(s: &str) -> String {
+    guard_start(words(s).iter().map(|w| capitalize(w)).collect(), "T")
+}
+
+pub fn member_name(s: &str) -> String {
+    let ws = words(s);
+    let name = ws.iter().enumerate()
+        .map(|(i, w)| if i == 0 { w.to_ascii_lowercase() } else { capitalize(w) })
+        .collect::<String>();
+    guard_start(name, "n")
+}
+
+/// Field names are the JSON keys, so they are kept verbatim when they are
+/// already valid identifiers.
+pub fn field_name(s: &str) -> String {
+    let valid = s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid { s.to_string() } else { member_name(s) }
+}
+
+/// Appends a counter until `name` is not taken yet, then reserves it.
+pub fn unique(name: String, taken: &mut std::collections::HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut n = 2;
+    while taken.contains(&candidate) {
+        candidate = format!("{name}{n}");
+        n += 1;
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
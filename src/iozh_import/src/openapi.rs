@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+use serde_json::Value;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use iozh_parse::types::is_unit;
+use crate::names::*;
+use crate::schema::*;
+
+const VERBS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+/// Operations sharing a tag (or first path segment), imported as a service
+/// plus an http service in a namespace of their own.
+struct Group {
+    name: String,
+    methods: Vec<ast::Method>,
+    routes: Vec<ast::HttpRoute>,
+    taken: HashSet<String>,
+}
+
+/// Imports an OpenAPI 3 (or Swagger 2) document: schemas become structures
+/// and choices of a namespace named after the API title, operations become
+/// services and routes of nested namespaces, one per tag.
+pub fn import(root: &Value) -> Result<ast::Project, IozhError> {
+    let defs_pointer = if root.get("swagger").is_some() { "/definitions" } else { "/components/schemas" };
+    let mut importer = SchemaImporter::new(root, defs_pointer);
+    importer.declare_all()?;
+
+    let mut groups: Vec<Group> = vec![];
+    if let Some(Value::Object(paths)) = root.get("paths") {
+        for (path, item) in paths {
+            let item = importer.deref(item);
+            let shared = item.get("parameters").and_then(|p| p.as_array()).map(|p| p.as_slice()).unwrap_or(&[]);
+            for verb in VERBS {
+                let op = match item.get(verb) {
+                    Some(op) => op,
+                    None => continue,
+                };
+                let group_name = op.pointer("/tags/0").and_then(|t| t.as_str())
+                    .map(|t| t.to_string())
+                    .or_else(|| path.split('/').find(|s| !s.is_empty() && !s.starts_with('{')).map(|s| s.to_string()))
+                    .unwrap_or_else(|| "default".to_string());
+                let pos = match groups.iter().position(|g| g.name == group_name) {
+                    Some(pos) => pos,
+                    None => {
+                        groups.push(Group { name: group_name, methods: vec![], routes: vec![], taken: HashSet::new() });
+                        groups.len() - 1
+                    }
+                };
+                import_operation(&mut importer, &mut groups[pos], path, verb, op, shared)?;
+            }
+        }
+    }
+
+    let mut items = std::mem::take(&mut importer.items);
+    for g in groups {
+        let service_name = type_name(&g.name) + "Service";
+        let mut group_items = vec![ast::NspaceItem::Service(ast::Service {
+            pos: ast::Pos::default(),
+            doc: String::new(),
+            name: type_tag(&service_name),
+            methods: g.methods,
+        })];
+        if !g.routes.is_empty() {
+            group_items.push(ast::NspaceItem::HttpService(ast::HttpService {
+                pos: ast::Pos::default(),
+                name: type_tag(&(type_name(&g.name) + "Routes")),
+                rests: vec![],
+                routes: g.routes,
+            }));
+        }
+        items.push(ast::NspaceItem::Nspace(ast::Nspace {
+            pos: ast::Pos::default(),
+            name: member_name(&g.name),
+            items: group_items,
+        }));
+    }
+
+    let title = root.pointer("/info/title").and_then(|t| t.as_str()).unwrap_or("api");
+    let mut project = importer.project;
+    project.nspaces.push(ast::Nspace {
+        pos: ast::Pos::default(),
+        name: member_name(title),
+        items,
+    });
+    Ok(project)
+}
+
+fn import_operation<'a>(
+    importer: &mut SchemaImporter<'a>,
+    group: &mut Group,
+    path: &str,
+    verb: &str,
+    op: &'a Value,
+    shared: &'a [Value],
+) -> Result<(), IozhError> {
+    let method_name = match op.get("operationId").and_then(|o| o.as_str()) {
+        Some(id) => member_name(id),
+        None => member_name(&format!("{verb} {}", path.replace(['{', '}'], " by "))),
+    };
+    let method_name = unique(method_name, &mut group.taken);
+    let type_hint = type_name(&method_name);
+
+    // Operation parameters override the path-level ones with the same name and location.
+    let own = op.get("parameters").and_then(|p| p.as_array()).map(|p| p.as_slice()).unwrap_or(&[]);
+    let mut params: Vec<&'a Value> = vec![];
+    for p in own.iter().chain(shared.iter()) {
+        let p = importer.deref(p);
+        let key = (p.get("name"), p.get("in"));
+        if !params.iter().any(|q| (q.get("name"), q.get("in")) == key) {
+            params.push(p);
+        }
+    }
+
+    let mut route_fields = vec![];
+    let mut args = vec![];
+    let mut body = None;
+    for p in params {
+        let name = p.get("name").and_then(|n| n.as_str()).unwrap_or("param");
+        let location = p.get("in").and_then(|i| i.as_str()).unwrap_or("query");
+        let schema = p.get("schema").unwrap_or(p);
+        let hint = format!("{type_hint}{}", type_name(name));
+        if location == "body" {
+            body = Some((importer.type_of(schema, &format!("{type_hint}Request"))?, true));
+            continue;
+        }
+        let mut tp = importer.type_of(schema, &hint)?;
+        let required = location == "path" || p.get("required") == Some(&Value::Bool(true));
+        if !required {
+            tp = type_path("Option", vec![tp]);
+        }
+        let field = ast::Field {
+            pos: ast::Pos::default(),
+            doc: doc(p),
+            name: field_name(name),
+            type_path: tp,
+        };
+        let source = match location {
+            "path" => None,
+            "query" => Some(ast::ParamSource::Query),
+            "header" => Some(ast::ParamSource::Header(name.to_string())),
+            "formData" if p.get("type").and_then(|t| t.as_str()) == Some("file") => Some(ast::ParamSource::Multipart),
+            "formData" => Some(ast::ParamSource::Form),
+            _ => {
+                args.push(field);
+                continue;
+            }
+        };
+        route_fields.push(ast::RouteField { source, field: field.clone() });
+        args.push(field);
+    }
+
+    if let Some(request) = op.get("requestBody").map(|r| importer.deref(r)) {
+        if let Some(schema) = media_schema(request) {
+            let required = request.get("required") == Some(&Value::Bool(true));
+            body = Some((importer.type_of(schema, &format!("{type_hint}Request"))?, required));
+        }
+    }
+    if let Some((tp, required)) = &body {
+        args.push(ast::Field {
+            pos: ast::Pos::default(),
+            doc: String::new(),
+            name: "body".to_string(),
+            type_path: if *required { tp.clone() } else { type_path("Option", vec![tp.clone()]) },
+        });
+    }
+
+    let success = success_response(op);
+    let result = match success.map(|(_, r)| importer.deref(r)) {
+        Some(response) => match media_schema(response).or_else(|| response.get("schema")) {
+            Some(schema) => importer.type_of(schema, &format!("{type_hint}Response"))?,
+            None => type_path("Unit", vec![]),
+        },
+        None => type_path("Unit", vec![]),
+    };
+
+    let service_name = type_name(&group.name) + "Service";
+    group.routes.push(ast::HttpRoute {
+        pos: ast::Pos::default(),
+        verb: verb.to_ascii_uppercase(),
+        input: body.map(|(tp, _)| tp).unwrap_or_else(|| type_path("Unit", vec![])),
+        pattern: ast::HttpRoutePattern {
+            pos: ast::Pos::default(),
+            segments: route_pattern(path),
+            query: vec![],
+        },
+        method: ast::MethodRef {
+            pos: ast::Pos::default(),
+            path: vec![type_tag(&service_name), type_tag(&method_name)],
+        },
+        fields: route_fields,
+        // Only the success status carries over: the other responses have
+        // types of their own, which a single method result cannot hold.
+        // Response headers are left out too, lacking body fields to read.
+        responses: match success.and_then(|(code, _)| code.parse::<u16>().ok()) {
+            Some(status) if status != 200 && !(status == 204 && is_unit(&result)) => vec![ast::HttpResponse {
+                pos: ast::Pos::default(),
+                status,
+                type_path: result.clone(),
+                headers: vec![],
+            }],
+            _ => vec![],
+        },
+    });
+    group.methods.push(ast::Method {
+        pos: ast::Pos::default(),
+        doc: doc(op),
+        name: type_tag(&method_name),
+        args,
+        result,
+        stream_result: false,
+        stream_args: vec![],
+        error: None,
+    });
+    Ok(())
+}
+
+/// Schema of the JSON content of a request or response, or of the first
+/// content type when there is no JSON one.
+fn media_schema(v: &Value) -> Option<&Value> {
+    let content = v.get("content")?.as_object()?;
+    content.iter()
+        .find(|(k, _)| k.contains("json"))
+        .or_else(|| content.iter().next())
+        .and_then(|(_, m)| m.get("schema"))
+}
+
+fn success_response(op: &Value) -> Option<(&String, &Value)> {
+    let responses = op.get("responses")?.as_object()?;
+    responses.iter()
+        .find(|(code, _)| code.starts_with('2'))
+        .or_else(|| responses.iter().find(|(code, _)| *code == "default"))
+}
+
+fn is_path_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "/.-_~!$&'()+,;=@%".contains(c)
+}
+
+/// Splits `/pets/{pet-id}/owner` into literal segments and a `petId`
+/// variable.
+fn route_pattern(path: &str) -> Vec<ast::PathSegment> {
+    let mut segments = vec![];
+    for part in path.split('/').filter(|p| !p.is_empty()) {
+        // `{a}.{b}` and `v{a}` have no counterpart: the whole segment
+        // becomes the first variable, the rest of it is lost.
+        if let Some(start) = part.find('{') {
+            let end = part[start..].find('}').map(|e| start + e).unwrap_or(part.len());
+            let name = field_name(&part[start + 1..end]);
+            segments.push(ast::PathSegment::Var { name, type_path: None });
+        } else {
+            let literal = part.chars()
+                .map(|c| if is_path_char(c) { c.to_string() } else { format!("%{:02X}", c as u32) })
+                .collect::<String>();
+            segments.push(ast::PathSegment::Literal(literal));
+        }
+    }
+    segments
+}
@@ -0,0 +1,456 @@
+use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+use iozh_parse::ast;
+use iozh_parse::error::IozhError;
+use crate::names::*;
+
+#[derive(PartialEq)]
+enum Kind {
+    Enum,
+    Choice,
+    Object,
+    /// Primitives, arrays and maps: these have no declaration of their own,
+    /// references to them are replaced by the type they stand for.
+    Alias,
+}
+
+fn kind(schema: &Value) -> Kind {
+    if schema.get("enum").is_some() {
+        Kind::Enum
+    } else if schema.get("oneOf").is_some() || schema.get("anyOf").is_some() {
+        Kind::Choice
+    } else if schema.get("properties").is_some()
+        || schema.get("allOf").and_then(|a| a.as_array()).is_some_and(|a| a.len() > 1)
+        || (schema_type(schema) == Some("object") && schema.get("additionalProperties").is_none_or(|a| a == false))
+    {
+        Kind::Object
+    } else {
+        Kind::Alias
+    }
+}
+
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => Some(t),
+        Some(Value::Array(ts)) => ts.iter().filter_map(|t| t.as_str()).find(|t| *t != "null"),
+        _ => None,
+    }
+}
+
+fn nullable(schema: &Value) -> bool {
+    schema.get("nullable") == Some(&Value::Bool(true))
+        || matches!(schema.get("type"), Some(Value::Array(ts)) if ts.iter().any(|t| t == "null"))
+}
+
+pub fn type_path(name: &str, args: Vec<ast::TypePath>) -> ast::TypePath {
+    ast::TypePath {
+        pos: ast::Pos::default(),
+        path: vec![ast::TypeTag { pos: ast::Pos::default(), name: name.to_string(), args }],
+    }
+}
+
+pub fn type_tag(name: &str) -> ast::TypeTag {
+    ast::TypeTag { pos: ast::Pos::default(), name: name.to_string(), args: vec![] }
+}
+
+fn option_of(tp: ast::TypePath) -> ast::TypePath {
+    match tp.path.as_slice() {
+        [t] if t.name == "Option" => tp,
+        _ => type_path("Option", vec![tp]),
+    }
+}
+
+pub fn doc(schema: &Value) -> String {
+    match schema.get("description").or_else(|| schema.get("summary")).and_then(|d| d.as_str()) {
+        Some(d) if !d.trim().is_empty() => format!("/* {} */", d.trim().replace("*/", "* /")),
+        _ => String::new(),
+    }
+}
+
+fn last_segment(pointer: &str) -> &str {
+    pointer.rsplit('/').next().unwrap_or(pointer)
+}
+
+/// Turns the schemas of a JSON Schema or OpenAPI document into iozh
+/// structures and choices, collected as items of a single namespace.
+pub struct SchemaImporter<'a> {
+    pub root: &'a Value,
+    pub project: ast::Project,
+    pub items: Vec<ast::NspaceItem>,
+    taken: HashSet<String>,
+    /// Type every `$ref` pointer stands for.
+    refs: HashMap<String, ast::TypePath>,
+    /// Objects referenced only as `oneOf`/`anyOf` alternatives: they become
+    /// structure items of the choices and get no declaration of their own.
+    inlined: HashSet<String>,
+    /// Inline schemas already declared, by address, so that a schema copied
+    /// into several choices is still declared once.
+    declared: HashMap<usize, ast::TypePath>,
+    defs: Vec<(String, &'a Value)>,
+}
+
+impl <'a> SchemaImporter<'a> {
+    pub fn new(root: &'a Value, defs_pointer: &str) -> SchemaImporter<'a> {
+        let defs = match root.pointer(defs_pointer) {
+            Some(Value::Object(m)) => m.iter()
+                .map(|(k, v)| (format!("#{}/{}", defs_pointer, k.replace('~', "~0").replace('/', "~1")), v))
+                .collect(),
+            _ => vec![],
+        };
+        let mut total = HashMap::new();
+        let mut alternatives = HashMap::new();
+        count_refs(root, false, &mut total, &mut alternatives);
+        let mut importer = SchemaImporter {
+            root,
+            project: ast::Project::new(),
+            items: vec![],
+            taken: HashSet::new(),
+            refs: HashMap::new(),
+            inlined: HashSet::new(),
+            declared: HashMap::new(),
+            defs,
+        };
+        for (pointer, schema) in &importer.defs {
+            let alt = alternatives.get(pointer).copied().unwrap_or(0);
+            if alt > 0 && total.get(pointer) == Some(&alt) && kind(schema) == Kind::Object {
+                importer.inlined.insert(pointer.clone());
+            }
+        }
+        importer
+    }
+
+    fn lookup(&self, pointer: &str) -> Option<&'a Value> {
+        match pointer {
+            "#" => Some(self.root),
+            p if p.starts_with("#/") => self.root.pointer(&p[1..]),
+            _ => None,
+        }
+    }
+
+    /// Follows a `$ref`, if any, to the schema it points to.
+    pub fn deref(&self, schema: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(|r| r.as_str()).and_then(|r| self.lookup(r)) {
+            Some(target) => target,
+            None => schema,
+        }
+    }
+
+    /// Declares every named schema in document order. Names are reserved
+    /// up front so that forward references resolve to the right type.
+    pub fn declare_all(&mut self) -> Result<(), IozhError> {
+        let defs = self.defs.clone();
+        let mut pending = vec![];
+        for (pointer, schema) in defs {
+            if self.inlined.contains(&pointer) || kind(schema) == Kind::Alias {
+                continue;
+            }
+            let name = unique(type_name(last_segment(&pointer)), &mut self.taken);
+            self.refs.insert(pointer, type_path(&name, vec![]));
+            pending.push((name, schema));
+        }
+        for (name, schema) in pending {
+            self.declare(&name, schema)?;
+        }
+        Ok(())
+    }
+
+    /// Declares the document root itself: standalone JSON Schema files
+    /// usually describe one top-level type.
+    pub fn declare_root(&mut self, name: &str) -> Result<(), IozhError> {
+        if kind(self.root) == Kind::Alias {
+            return Ok(());
+        }
+        let name = unique(type_name(name), &mut self.taken);
+        self.refs.insert("#".to_string(), type_path(&name, vec![]));
+        self.declare(&name, self.root)
+    }
+
+    /// Declares `schema` under `name`, which must be reserved already.
+    fn declare(&mut self, name: &str, schema: &'a Value) -> Result<(), IozhError> {
+        let item = match kind(schema) {
+            Kind::Enum => {
+                let c = self.enum_choice(name, schema);
+                ast::NspaceItem::Choice(self.project.new_choice(c))
+            }
+            Kind::Choice => {
+                let c = self.choice(name, schema)?;
+                ast::NspaceItem::Choice(self.project.new_choice(c))
+            }
+            _ => {
+                let s = self.structure(name, schema, None)?;
+                ast::NspaceItem::Structure(self.project.new_structure(s))
+            }
+        };
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn resolve_ref(&mut self, pointer: &str) -> Result<ast::TypePath, IozhError> {
+        if let Some(tp) = self.refs.get(pointer) {
+            return Ok(tp.clone());
+        }
+        let name = type_name(last_segment(pointer));
+        let tp = match self.lookup(pointer) {
+            Some(target) if kind(target) == Kind::Alias => self.type_of(target, &name)?,
+            Some(target) => {
+                let name = unique(name, &mut self.taken);
+                self.refs.insert(pointer.to_string(), type_path(&name, vec![]));
+                self.declare(&name, target)?;
+                type_path(&name, vec![])
+            }
+            // External documents are not followed; keep the name so the
+            // result can be completed by hand.
+            None => type_path(&name, vec![]),
+        };
+        self.refs.insert(pointer.to_string(), tp.clone());
+        Ok(tp)
+    }
+
+    /// Type of a schema used in place; inline objects, enums and unions are
+    /// declared next to the named ones under a name derived from `hint`.
+    pub fn type_of(&mut self, schema: &'a Value, hint: &str) -> Result<ast::TypePath, IozhError> {
+        if let Some(r) = schema.get("$ref").and_then(|r| r.as_str()) {
+            return self.resolve_ref(r);
+        }
+        if kind(schema) != Kind::Alias {
+            let addr = schema as *const Value as usize;
+            if let Some(tp) = self.declared.get(&addr) {
+                return Ok(tp.clone());
+            }
+            let name = unique(type_name(hint), &mut self.taken);
+            self.declared.insert(addr, type_path(&name, vec![]));
+            self.declare(&name, schema)?;
+            return Ok(type_path(&name, vec![]));
+        }
+        if let Some(Value::Array(parts)) = schema.get("allOf") {
+            if let [part] = parts.as_slice() {
+                return self.type_of(part, hint);
+            }
+        }
+        let format = schema.get("format").and_then(|f| f.as_str()).unwrap_or("");
+        let tp = match schema_type(schema) {
+            Some("integer") if format == "int32" => type_path("I32", vec![]),
+            Some("integer") => type_path("I64", vec![]),
+            Some("number") if format == "float" => type_path("F32", vec![]),
+            Some("number") => type_path("F64", vec![]),
+            Some("boolean") => type_path("Bool", vec![]),
+            Some("string") => match format {
+                "date-time" => type_path("DateTime", vec![]),
+                "duration" => type_path("Duration", vec![]),
+                "binary" => type_path("File", vec![]),
+                _ => type_path("String", vec![]),
+            },
+            // Swagger 2 upload parameters.
+            Some("file") => type_path("File", vec![]),
+            Some("array") => {
+                let items = match schema.get("items") {
+                    Some(items) => self.type_of(items, &format!("{hint}Item"))?,
+                    None => type_path("Json", vec![]),
+                };
+                type_path("List", vec![items])
+            }
+            Some("object") => match schema.get("additionalProperties") {
+                Some(v) if v.is_object() => {
+                    let value = self.type_of(v, &format!("{hint}Value"))?;
+                    type_path("Map", vec![type_path("String", vec![]), value])
+                }
+                _ => type_path("Json", vec![]),
+            },
+            _ => type_path("Json", vec![]),
+        };
+        Ok(tp)
+    }
+
+    /// Properties of an object schema, including those of its `allOf` parts,
+    /// together with the names of the required ones.
+    fn properties(&self, schema: &'a Value, acc: &mut Vec<(String, &'a Value)>, required: &mut HashSet<String>) {
+        let schema = self.deref(schema);
+        if let Some(Value::Array(parts)) = schema.get("allOf") {
+            for part in parts {
+                self.properties(part, acc, required);
+            }
+        }
+        if let Some(Value::Object(props)) = schema.get("properties") {
+            for (k, v) in props {
+                if !acc.iter().any(|(name, _)| name == k) {
+                    acc.push((k.clone(), v));
+                }
+            }
+        }
+        if let Some(Value::Array(rs)) = schema.get("required") {
+            required.extend(rs.iter().filter_map(|r| r.as_str()).map(|r| r.to_string()));
+        }
+    }
+
+    fn fields(&mut self, owner: &str, schema: &'a Value, skip: Option<&str>) -> Result<Vec<ast::Field>, IozhError> {
+        let mut props = vec![];
+        let mut required = HashSet::new();
+        self.properties(schema, &mut props, &mut required);
+        let mut fields = vec![];
+        for (key, prop) in props {
+            if Some(key.as_str()) == skip {
+                continue;
+            }
+            let mut tp = self.type_of(prop, &format!("{owner}{}", type_name(&key)))?;
+            if !required.contains(&key) || nullable(prop) {
+                tp = option_of(tp);
+            }
+            fields.push(ast::Field {
+                pos: ast::Pos::default(),
+                doc: doc(prop),
+                name: field_name(&key),
+                type_path: tp,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn structure(&mut self, name: &str, schema: &'a Value, skip: Option<&str>) -> Result<ast::Structure, IozhError> {
+        let fields = self.fields(name, schema, skip)?;
+        Ok(ast::Structure {
+            pos: ast::Pos::default(),
+            doc: doc(schema),
+            name: type_tag(name),
+            fields: fields.into_iter().map(ast::StructItem::Field).collect(),
+        })
+    }
+
+    fn enum_choice(&mut self, name: &str, schema: &'a Value) -> ast::Choice {
+        let mut taken = HashSet::new();
+        let values = schema.get("enum").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        let choices = values
+            .iter()
+            .filter_map(|v| {
+                let (item, value) = match v {
+                    // Literals have no escapes, so such values cannot be written.
+                    Value::String(s) if s.contains('"') => return None,
+                    Value::String(s) => {
+                        let item = if s.is_empty() { "Empty".to_string() } else { type_name(s) };
+                        (item, ast::Literal::String { pos: ast::Pos::default(), value: format!("\"{s}\"") })
+                    }
+                    Value::Number(n) => {
+                        let n = n.as_i64()?;
+                        let item = if n < 0 { format!("Minus{}", -n) } else { format!("V{n}") };
+                        (item, ast::Literal::Int { pos: ast::Pos::default(), value: n })
+                    }
+                    _ => return None,
+                };
+                Some(ast::ChoiceItem::Value {
+                    doc: String::new(),
+                    name: type_tag(&unique(item, &mut taken)),
+                    value,
+                })
+            })
+            .collect();
+        ast::Choice {
+            pos: ast::Pos::default(),
+            doc: doc(schema),
+            name: type_tag(name),
+            fields: vec![],
+            choices,
+        }
+    }
+
+    fn choice(&mut self, name: &str, schema: &'a Value) -> Result<ast::Choice, IozhError> {
+        let alternatives = schema.get("oneOf").or_else(|| schema.get("anyOf"))
+            .and_then(|a| a.as_array()).map(|a| a.as_slice()).unwrap_or(&[]);
+        let alternatives = alternatives.iter()
+            .map(|a| self.alternative(a))
+            .collect::<Vec<_>>();
+        let discriminator = schema.pointer("/discriminator/propertyName").and_then(|p| p.as_str());
+        let mapping = schema.pointer("/discriminator/mapping").and_then(|m| m.as_object());
+        let mut taken = HashSet::new();
+        let mut choices = vec![];
+        for (i, (pointer, alt)) in alternatives.into_iter().enumerate() {
+            let alt_name = match (&pointer, alt.get("title").and_then(|t| t.as_str())) {
+                (Some(p), _) => type_name(last_segment(p)),
+                (None, Some(title)) => type_name(title),
+                (None, None) => format!("{name}Option{}", i + 1),
+            };
+            // Without a discriminator a referenced type is put on the wire
+            // as is, which is what a wrap item does.
+            let wrap_ref = discriminator.is_none() && pointer.as_ref().is_some_and(|p| !self.inlined.contains(p));
+            if kind(alt) == Kind::Object && !wrap_ref {
+                let item_name = unique(alt_name, &mut taken);
+                let mut s = self.structure(&item_name, alt, discriminator)?;
+                if let Some(key) = discriminator {
+                    let value = pointer.as_ref()
+                        .and_then(|p| mapping.and_then(|m| m.iter().find(|(_, v)| v.as_str() == Some(p))))
+                        .map(|(k, _)| k.clone())
+                        .or_else(|| alt.pointer(&format!("/properties/{key}/enum/0")).and_then(|v| v.as_str()).map(|v| v.to_string()))
+                        .or_else(|| pointer.as_ref().map(|p| last_segment(p).to_string()))
+                        .unwrap_or_else(|| item_name.clone());
+                    s.fields.insert(0, ast::StructItem::Tag(ast::Tag {
+                        pos: ast::Pos::default(),
+                        name: field_name(key),
+                        value: ast::Literal::String { pos: ast::Pos::default(), value: format!("\"{value}\"") },
+                    }));
+                }
+                choices.push(ast::ChoiceItem::Structure(self.project.new_structure(s)));
+            } else {
+                let (target, wrap_name) = match &pointer {
+                    Some(p) => (self.resolve_ref(p)?, alt_name),
+                    None => {
+                        let target = self.type_of(alt, &format!("{name}{alt_name}"))?;
+                        let wrap_name = type_name(&iozh_parse::format::format_type_path(&target));
+                        (target, wrap_name)
+                    }
+                };
+                choices.push(ast::ChoiceItem::Wrap {
+                    doc: doc(alt),
+                    name: type_tag(&unique(wrap_name + "Value", &mut taken)),
+                    field: "value".to_string(),
+                    target,
+                });
+            }
+        }
+        let fields = if schema.get("properties").is_some() {
+            self.fields(name, schema, discriminator)?
+        } else {
+            vec![]
+        };
+        Ok(ast::Choice {
+            pos: ast::Pos::default(),
+            doc: doc(schema),
+            name: type_tag(name),
+            fields,
+            choices,
+        })
+    }
+
+    fn alternative(&self, alt: &'a Value) -> (Option<String>, &'a Value) {
+        match alt.get("$ref").and_then(|r| r.as_str()) {
+            Some(r) => (Some(r.to_string()), self.deref(alt)),
+            None => (None, alt),
+        }
+    }
+}
+
+fn count_refs(v: &Value, in_alternatives: bool, total: &mut HashMap<String, usize>, alternatives: &mut HashMap<String, usize>) {
+    match v {
+        Value::Object(m) => {
+            if let Some(Value::String(r)) = m.get("$ref") {
+                *total.entry(r.clone()).or_default() += 1;
+                if in_alternatives {
+                    *alternatives.entry(r.clone()).or_default() += 1;
+                }
+            }
+            for (k, child) in m {
+                match (k.as_str(), child) {
+                    ("oneOf" | "anyOf", Value::Array(alts)) => {
+                        for alt in alts {
+                            count_refs(alt, true, total, alternatives);
+                        }
+                    }
+                    _ => count_refs(child, false, total, alternatives),
+                }
+            }
+        }
+        Value::Array(a) => {
+            for child in a {
+                count_refs(child, false, total, alternatives);
+            }
+        }
+        _ => {}
+    }
+}
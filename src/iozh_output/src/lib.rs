@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use iozh_parse::error::IozhError;
+
+/// Where backends put the files they generate, addressed by their path on
+/// disk.
+pub trait Output {
+    fn write(&mut self, path: &Path, content: &str) -> Result<(), IozhError>;
+}
+
+/// Writes to the filesystem. Files that already have the content are left
+/// alone, keeping their modification time for incremental builds.
+pub struct FsTree;
+
+impl Output for FsTree {
+    fn write(&mut self, path: &Path, content: &str) -> Result<(), IozhError> {
+        if read(path)?.as_deref() == Some(content) {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| IozhError::from(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+        fs::write(path, content)
+            .map_err(|e| IozhError::from(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// Keeps the files in memory, to compare them with the disk.
+#[derive(Debug, Default)]
+pub struct MemTree {
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl Output for MemTree {
+    fn write(&mut self, path: &Path, content: &str) -> Result<(), IozhError> {
+        self.files.insert(path.to_path_buf(), content.to_string());
+        Ok(())
+    }
+}
+
+/// A file of a `MemTree` that differs from the disk.
+#[derive(Debug)]
+pub struct Change<'a> {
+    pub path: &'a Path,
+    /// Content on disk, `None` for a file that does not exist yet.
+    pub old: Option<String>,
+    pub new: &'a str,
+}
+
+impl MemTree {
+    /// The files writing the tree would create or change, by path.
+    pub fn changes(&self) -> Result<Vec<Change<'_>>, IozhError> {
+        let mut changes = vec![];
+        for (path, content) in &self.files {
+            let old = read(path)?;
+            if old.as_deref() != Some(content.as_str()) {
+                changes.push(Change { path, old, new: content });
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Content of a file, `None` when there is none. Bytes that are not
+/// UTF-8 are replaced, so such a file never reads as generated text.
+fn read(path: &Path) -> Result<Option<String>, IozhError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(IozhError::from(format!("Failed to read {}: {}", path.display(), e))),
+    }
+}
@@ -79,6 +79,12 @@ pub struct Method {
     pub name: TypeTag,
     pub args: Vec<Field>,
     pub result: TypePath,
+    /// `=> stream Update`: the result comes as many values over time.
+    pub stream_result: bool,
+    /// Arguments declared `offsets: stream I64`, by name.
+    pub stream_args: Vec<String>,
+    /// `! UserError`: what the method fails with besides its result.
+    pub error: Option<TypePath>,
 }
 
 #[derive(Debug)]
@@ -94,9 +100,43 @@ pub struct MethodRef {
     pub path: Vec<TypeTag>,
 }
 
+#[derive(Debug, Clone)]
+pub enum PathSegment {
+    Literal(String),
+    /// `:id`, `{id}` or `{id: I64}`.
+    Var { name: String, type_path: Option<TypePath> },
+    /// `*rest`: every remaining segment.
+    Wildcard(String),
+}
+
+#[derive(Clone)]
 pub struct HttpRoutePattern {
     pub pos: Pos,
-    pub items: Vec<String>,
+    pub segments: Vec<PathSegment>,
+    /// `?page&size`: fields read from the query.
+    pub query: Vec<String>,
+}
+
+/// Where a route field is read from, as given by `@query`, `@header("X-Token")`,
+/// `@body`, `@form` or `@multipart`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamSource {
+    Path,
+    Query,
+    Header(String),
+    /// Property of the JSON body.
+    Body,
+    /// Field of an `application/x-www-form-urlencoded` body.
+    Form,
+    /// Part of a `multipart/form-data` body.
+    Multipart,
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteField {
+    /// `None` unless annotated: see `HttpRoute::params`.
+    pub source: Option<ParamSource>,
+    pub field: Field,
 }
 
 #[derive(Debug)]
@@ -106,21 +146,120 @@ pub struct HttpRoute {
     pub input: TypePath,
     pub pattern: HttpRoutePattern,
     pub method: MethodRef,
-    pub fields: Vec<Field>,
+    pub fields: Vec<RouteField>,
+    /// Declared responses; without any, the result is answered with 200,
+    /// or 204 when it is `Unit`.
+    pub responses: Vec<HttpResponse>,
+}
+
+/// `ETag = version`: a header set from a field of the response body.
+#[derive(Debug, Clone)]
+pub struct ResponseHeader {
+    pub pos: Pos,
+    pub name: String,
+    pub field: String,
+}
+
+/// `404 => NotFound`: the status answering the results of the method of
+/// that type, see `HttpRoute::responses`.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub pos: Pos,
+    pub status: u16,
+    pub type_path: TypePath,
+    pub headers: Vec<ResponseHeader>,
+}
+
+/// `REST Game(id: I64) as /games via GameService`: the CRUD routes of a
+/// structure, expanded into plain routes by `Project::expand_rest`.
+#[derive(Debug)]
+pub struct HttpRest {
+    pub pos: Pos,
+    pub resource: TypePath,
+    /// Field identifying a resource, the `id` of the structure by default.
+    pub key: Option<RestKey>,
+    /// Path of the collection, the pluralized resource name by default.
+    pub path: Option<String>,
+    /// Service the routes call, `<Resource>Service` by default.
+    pub service: Option<TypeTag>,
+}
+
+/// Key of a `REST` item.
+#[derive(Debug, Clone)]
+pub enum RestKey {
+    /// `REST User(id: I64)`.
+    Typed(Field),
+    /// `REST User(id)`, typed like the field of the resource.
+    Named { pos: Pos, name: String },
 }
 
 #[derive(Debug)]
 pub struct HttpService {
     pub pos: Pos,
     pub name: TypeTag,
+    pub rests: Vec<HttpRest>,
     pub routes: Vec<HttpRoute>,
 }
 
+#[derive(Debug)]
+pub struct StorageKey {
+    pub pos: Pos,
+    pub fields: Vec<String>,
+}
+
+/// Column referencing `field` of another table, `userId: User(id)`.
+/// `wrapper` is set for `Option[User(id)]` and `List[User(id)]`.
+#[derive(Debug)]
+pub struct StorageRef {
+    pub pos: Pos,
+    pub doc: String,
+    pub name: String,
+    pub wrapper: Option<String>,
+    pub target: TypePath,
+    pub field: String,
+}
+
+#[derive(Debug)]
+pub enum StorageItem {
+    Unique(StorageKey),
+    Index(StorageKey),
+    Field(Field),
+    Ref(StorageRef),
+}
+
+#[derive(Debug)]
+pub struct StorageTable {
+    pub pos: Pos,
+    pub doc: String,
+    pub name: TypePath,
+    pub items: Vec<StorageItem>,
+}
+
+#[derive(Debug)]
+pub struct Storage {
+    pub pos: Pos,
+    pub doc: String,
+    pub kind: String,
+    pub tables: Vec<StorageTable>,
+}
+
+/// `events UserEvents { created: User }`: what a namespace emits or is
+/// pushed, one payload type per event name.
+#[derive(Debug)]
+pub struct Events {
+    pub pos: Pos,
+    pub doc: String,
+    pub name: String,
+    pub events: Vec<Field>,
+}
+
 pub enum NspaceItem {
     Structure(Idx),
     Choice(Idx),
     Service(Service),
     HttpService(HttpService),
+    Storage(Storage),
+    Events(Events),
     Nspace(Nspace),
 }
 
@@ -131,9 +270,19 @@ pub struct Nspace {
     pub items: Vec<NspaceItem>,
 }
 
+/// `scala2.errors = monad-error` in the `options { ... }` heading a
+/// project: a setting for the backend named by the first part of the key.
+#[derive(Debug, Clone)]
+pub struct ProjectOption {
+    pub pos: Pos,
+    pub key: String,
+    pub value: String,
+}
+
 #[derive(Default, Debug)]
 pub struct Project {
     pub pos: Pos,
+    pub options: Vec<ProjectOption>,
     pub nspaces: Vec<Nspace>,
     structures: Vec<Structure>,
     choices: Vec<Choice>,
@@ -165,7 +314,7 @@ impl Project {
     }
 
     pub fn get_choice(&self, idx: Idx) -> Result<&Choice, IozhError> {
-        if idx >= self.structures.len() {
+        if idx >= self.choices.len() {
             Err(IozhError::from(format!("Wrong choice index: {idx}")))
         } else {
             Ok(&self.choices[idx])
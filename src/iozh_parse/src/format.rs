@@ -0,0 +1,324 @@
+use std::fmt;
+use crate::ast::*;
+
+const INDENT: &str = "  ";
+
+/// Renders a project back to `.iozh` source in a canonical layout:
+/// braces everywhere, one declaration per line, two-space indentation.
+pub struct Formatter<'a> {
+    p: &'a Project,
+    out: String,
+    depth: usize,
+}
+
+impl Project {
+    pub fn format(&self) -> String {
+        let mut f = Formatter { p: self, out: String::new(), depth: 0 };
+        if !self.options.is_empty() {
+            f.open("options");
+            for o in &self.options {
+                f.line(&format!("{} = {}", o.key, o.value));
+            }
+            f.close();
+            f.out.push('\n');
+        }
+        for (i, nspace) in self.nspaces.iter().enumerate() {
+            if i > 0 {
+                f.out.push('\n');
+            }
+            f.nspace(nspace);
+        }
+        f.out
+    }
+}
+
+pub fn format_doc(doc: &str) -> String {
+    let text = crate::types::doc_text(doc).replace("*/", "* /");
+    if text.contains('\n') {
+        format!("/*\n{}\n*/", text)
+    } else {
+        format!("/* {} */", text)
+    }
+}
+
+pub fn format_type_tag(t: &TypeTag) -> String {
+    if t.args.is_empty() {
+        t.name.clone()
+    } else {
+        let args = t.args.iter().map(format_type_path).collect::<Vec<_>>().join(", ");
+        format!("{}[{}]", t.name, args)
+    }
+}
+
+pub fn format_type_path(tp: &TypePath) -> String {
+    tp.path.iter().map(format_type_tag).collect::<Vec<_>>().join(".")
+}
+
+fn format_literal(l: &Literal) -> String {
+    match l {
+        Literal::String { value, .. } => value.clone(),
+        Literal::Int { value, .. } => value.to_string(),
+        Literal::Nil => "\"\"".to_string(),
+    }
+}
+
+fn format_field(f: &Field) -> String {
+    format!("{}: {}", f.name, format_type_path(&f.type_path))
+}
+
+fn format_method_args(m: &Method) -> String {
+    m.args
+        .iter()
+        .map(|a| match m.is_stream_arg(&a.name) {
+            true => format!("{}: stream {}", a.name, format_type_path(&a.type_path)),
+            false => format_field(a),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_route_field(f: &RouteField) -> String {
+    let source = match &f.source {
+        None | Some(ParamSource::Path) => String::new(),
+        Some(ParamSource::Query) => "@query ".to_string(),
+        Some(ParamSource::Header(name)) if *name == f.field.name => "@header ".to_string(),
+        Some(ParamSource::Header(name)) => format!("@header(\"{name}\") "),
+        Some(ParamSource::Body) => "@body ".to_string(),
+        Some(ParamSource::Form) => "@form ".to_string(),
+        Some(ParamSource::Multipart) => "@multipart ".to_string(),
+    };
+    source + &format_field(&f.field)
+}
+
+fn format_response(r: &HttpResponse) -> String {
+    let mut out = format!("{} => {}", r.status, format_type_path(&r.type_path));
+    if !r.headers.is_empty() {
+        let headers = r.headers.iter().map(|h| format!("{} = {}", h.name, h.field)).collect::<Vec<_>>();
+        out.push_str(&format!(" with {}", headers.join(", ")));
+    }
+    out
+}
+
+/// `/users/:id/{size: I32}/*rest?page&limit`, `/` for the root.
+impl fmt::Display for HttpRoutePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            write!(f, "/")?;
+        }
+        for s in &self.segments {
+            match s {
+                PathSegment::Literal(l) => write!(f, "/{l}")?,
+                PathSegment::Var { name, type_path: None } => write!(f, "/:{name}")?,
+                PathSegment::Var { name, type_path: Some(tp) } => write!(f, "/{{{name}: {}}}", format_type_path(tp))?,
+                PathSegment::Wildcard(name) => write!(f, "/*{name}")?,
+            }
+        }
+        if !self.query.is_empty() {
+            write!(f, "?{}", self.query.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+impl <'a> Formatter<'a> {
+    fn line(&mut self, s: &str) {
+        for l in s.lines() {
+            if !l.is_empty() {
+                self.out.push_str(&INDENT.repeat(self.depth));
+                self.out.push_str(l);
+            }
+            self.out.push('\n');
+        }
+    }
+
+    fn doc(&mut self, doc: &str) {
+        if !doc.trim().is_empty() {
+            self.line(&format_doc(doc));
+        }
+    }
+
+    fn open(&mut self, header: &str) {
+        self.line(&format!("{header} {{"));
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    fn nspace(&mut self, n: &Nspace) {
+        self.open(&format!("namespace {}", n.name));
+        for (i, item) in n.items.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            match item {
+                NspaceItem::Structure(idx) => {
+                    if let Ok(s) = self.p.get_structure(*idx) {
+                        self.structure(s);
+                    }
+                }
+                NspaceItem::Choice(idx) => {
+                    if let Ok(c) = self.p.get_choice(*idx) {
+                        self.choice(c);
+                    }
+                }
+                NspaceItem::Service(s) => self.service(s),
+                NspaceItem::HttpService(s) => self.http_service(s),
+                NspaceItem::Storage(s) => self.storage(s),
+                NspaceItem::Events(e) => self.events(e),
+                NspaceItem::Nspace(n) => self.nspace(n),
+            }
+        }
+        self.close();
+    }
+
+    fn structure(&mut self, s: &Structure) {
+        self.doc(&s.doc);
+        if s.fields.is_empty() {
+            self.line(&format!("{} {{}}", format_type_tag(&s.name)));
+            return;
+        }
+        self.open(&format_type_tag(&s.name));
+        for item in &s.fields {
+            match item {
+                StructItem::Field(f) => {
+                    self.doc(&f.doc);
+                    self.line(&format_field(f));
+                }
+                StructItem::Tag(t) => {
+                    self.line(&format!("{}: {}", t.name, format_literal(&t.value)));
+                }
+            }
+        }
+        self.close();
+    }
+
+    fn choice(&mut self, c: &Choice) {
+        self.doc(&c.doc);
+        self.open(&format!("{} =", format_type_tag(&c.name)));
+        for f in &c.fields {
+            self.doc(&f.doc);
+            self.line(&format_field(f));
+        }
+        for item in &c.choices {
+            match item {
+                ChoiceItem::Nil => {}
+                ChoiceItem::TypeTag { doc, choice } => {
+                    self.doc(doc);
+                    self.line(&format_type_tag(choice));
+                }
+                ChoiceItem::Structure(idx) => {
+                    if let Ok(s) = self.p.get_structure(*idx) {
+                        self.structure(s);
+                    }
+                }
+                ChoiceItem::Value { doc, name, value } => {
+                    self.doc(doc);
+                    self.line(&format!("{} = {}", format_type_tag(name), format_literal(value)));
+                }
+                ChoiceItem::Wrap { doc, name, field, target } => {
+                    self.doc(doc);
+                    self.line(&format!("{} ({}) = {}", format_type_tag(name), field, format_type_path(target)));
+                }
+            }
+        }
+        self.close();
+    }
+
+    fn service(&mut self, s: &Service) {
+        self.doc(&s.doc);
+        self.open(&format_type_tag(&s.name));
+        for m in &s.methods {
+            self.doc(&m.doc);
+            let error = m.error.as_ref().map(|e| format!(" ! {}", format_type_path(e))).unwrap_or_default();
+            self.line(&format!(
+                "def {}({}) => {}{}{error}",
+                format_type_tag(&m.name),
+                format_method_args(m),
+                if m.stream_result { "stream " } else { "" },
+                format_type_path(&m.result),
+            ));
+        }
+        self.close();
+    }
+
+    fn http_service(&mut self, s: &HttpService) {
+        self.open(&format_type_tag(&s.name));
+        for r in &s.rests {
+            let mut line = format!("REST {}", format_type_path(&r.resource));
+            match &r.key {
+                Some(RestKey::Typed(key)) => line.push_str(&format!("({})", format_field(key))),
+                Some(RestKey::Named { name, .. }) => line.push_str(&format!("({name})")),
+                None => {}
+            }
+            if let Some(path) = &r.path {
+                line.push_str(&format!(" as {path}"));
+            }
+            if let Some(service) = &r.service {
+                line.push_str(&format!(" via {}", format_type_tag(service)));
+            }
+            self.line(&line);
+        }
+        for r in &s.routes {
+            let method = r.method.path.iter().map(format_type_tag).collect::<Vec<_>>().join(".");
+            self.line(&format!(
+                "{} {} *> {} *> {} ({})",
+                r.verb,
+                format_type_path(&r.input),
+                r.pattern,
+                method,
+                r.fields.iter().map(format_route_field).collect::<Vec<_>>().join(", "),
+            ));
+            self.depth += 1;
+            for response in &r.responses {
+                self.line(&format_response(response));
+            }
+            self.depth -= 1;
+        }
+        self.close();
+    }
+
+    fn events(&mut self, e: &Events) {
+        self.doc(&e.doc);
+        self.open(&format!("events {}", e.name));
+        for event in &e.events {
+            self.doc(&event.doc);
+            self.line(&format_field(event));
+        }
+        self.close();
+    }
+
+    fn storage(&mut self, s: &Storage) {
+        self.doc(&s.doc);
+        self.open(&s.kind);
+        for (i, t) in s.tables.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            self.doc(&t.doc);
+            self.open(&format_type_path(&t.name));
+            for item in &t.items {
+                match item {
+                    StorageItem::Unique(k) => self.line(&format!("unique({})", k.fields.join(", "))),
+                    StorageItem::Index(k) => self.line(&format!("index({})", k.fields.join(", "))),
+                    StorageItem::Field(f) => {
+                        self.doc(&f.doc);
+                        self.line(&format_field(f));
+                    }
+                    StorageItem::Ref(r) => {
+                        self.doc(&r.doc);
+                        let target = format!("{}({})", format_type_path(&r.target), r.field);
+                        match &r.wrapper {
+                            Some(w) => self.line(&format!("{}: {}[{}]", r.name, w, target)),
+                            None => self.line(&format!("{}: {}", r.name, target)),
+                        }
+                    }
+                }
+            }
+            self.close();
+        }
+        self.close();
+    }
+}
@@ -4,4 +4,7 @@ extern crate pest_derive;
 
 pub mod ast;
 pub mod error;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod types;
+pub mod format;
+pub mod rest;
\ No newline at end of file
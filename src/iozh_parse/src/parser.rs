@@ -48,12 +48,11 @@ impl Choice {
     pub fn get_most_common_tag_key(&self, project: &Project) -> Option<String> {
         let counts = self.choices
             .iter()
-            .map(|c| c.get_tags(project))
-            .flatten()
+            .flat_map(|c| c.get_tags(project))
             .map(|t| t.name)
             .sorted()
             .counts();
-        if counts.len() > 0 {
+        if !counts.is_empty() {
             counts.iter()
                 .max_by_key(|(_, count)| *count)
                 .map(|(name, _)| name.clone())
@@ -64,13 +63,13 @@ impl Choice {
 }
 
 impl Structure {
-    fn get_tag(&self, tag: &str) -> Option<Tag> {
+    pub fn get_tag(&self, tag: &str) -> Option<Tag> {
         self.fields.iter().find_map(|field| match field {
             StructItem::Tag(t) if t.name == tag => Some(t.clone()),
             _ => None,
         })
     }
-    fn get_tags(&self) -> Vec<Tag> {
+    pub fn get_tags(&self) -> Vec<Tag> {
         self.fields
             .iter()
             .filter_map(|field| match field {
@@ -89,7 +88,7 @@ impl fmt::Debug for Pos {
 
 impl fmt::Debug for TypeTag {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.args.len() > 0 {
+        if !self.args.is_empty() {
             let args = self.args.iter().map(|a| format!("{:#?}", a)).collect::<Vec<String>>().join(", ");
             write!(f, "{}[{}]", self.name, args)
         } else {
@@ -120,7 +119,7 @@ impl fmt::Debug for StructItem {
 impl fmt::Debug for Field {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let type_path = self.type_path.path.iter().map(|p| format!("{p:#?}")).collect::<Vec<String>>().join(".");
-        if self.doc.len() > 0 {
+        if !self.doc.is_empty() {
             writeln!(f, "{}", self.doc)?;
         }
         write!(f, "{}: {:#?}", self.name, type_path)
@@ -141,6 +140,8 @@ impl fmt::Debug for NspaceItem {
             NspaceItem::Choice(c) => write!(f, "{:#?}", c),
             NspaceItem::Service(s) => write!(f, "{:#?}", s),
             NspaceItem::HttpService(s) => write!(f, "{:#?}", s),
+            NspaceItem::Storage(s) => write!(f, "{:#?}", s),
+            NspaceItem::Events(e) => write!(f, "{:#?}", e),
             NspaceItem::Nspace(n) => write!(f, "{:#?}", n),
         }
     }
@@ -150,7 +151,7 @@ impl fmt::Debug for ChoiceItem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ChoiceItem::TypeTag{ doc, choice } => {
-                if doc.len() > 0 {
+                if !doc.is_empty() {
                     writeln!(f, "{}", doc)?;
                 }
                 write!(f, "{:#?}", choice)
@@ -165,7 +166,7 @@ impl fmt::Debug for ChoiceItem {
                 write!(f, "nil")
             }
             ChoiceItem::Wrap { doc, name, field, target } => {
-                if doc.len() > 0 {
+                if !doc.is_empty() {
                     writeln!(f, "{}", doc)?;
                 }
                 write!(f, "{:#?}({:#?}) = {:#?}", name, field, target)
@@ -176,8 +177,7 @@ impl fmt::Debug for ChoiceItem {
 
 impl fmt::Debug for HttpRoutePattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let items = self.items.iter().map(|i| format!("{:#?}", i)).collect::<Vec<String>>().join(", ");
-        write!(f, "[{}]", items)
+        write!(f, "{}", self)
     }
 }
 
@@ -307,6 +307,8 @@ fn parse_field(pair: Pair<Rule>) -> Field {
             Rule::type_path => {
                 type_path = parse_type_path(pair);
             }
+            // The `stream` modifier of a method argument, read by `parse_method`.
+            Rule::stream => {}
             r => unreachable!("unhandled rule: {:#?}", r),
         }
     }
@@ -388,9 +390,14 @@ fn parse_method(pair: Pair<Rule>) -> Method {
         pos: Pos { line: 0, col: 0 },
         path: Vec::new(),
     };
+    let mut error = None;
+    let mut stream_result = false;
+    let mut stream_args = Vec::new();
     let (mut line, mut col) = (0, 0);
     for pair in pair.into_inner() {
-        (line, col) = pair.as_span().start_pos().line_col();
+        if pair.as_rule() != Rule::method_error {
+            (line, col) = pair.as_span().start_pos().line_col();
+        }
         match pair.as_rule() {
             Rule::doc => {
                 doc = pair.as_str().to_string();
@@ -398,14 +405,25 @@ fn parse_method(pair: Pair<Rule>) -> Method {
             Rule::type_tag => {
                 name = parse_type_tag(pair);
             }
-            Rule::field => {
-                args.push(parse_field(pair));
+            Rule::method_arg => {
+                let stream = pair.clone().into_inner().any(|p| p.as_rule() == Rule::stream);
+                let field = parse_field(pair);
+                if stream {
+                    stream_args.push(field.name.clone());
+                }
+                args.push(field);
+            }
+            Rule::stream => {
+                stream_result = true;
             }
             Rule::method_result => {
                 for p in pair.into_inner() {
                     result = parse_type_path(p);
                 }
             }
+            Rule::method_error => {
+                error = pair.into_inner().next().map(parse_type_path);
+            }
             r => unreachable!("unhandled rule: {:#?}", r),
         }
     }
@@ -415,6 +433,9 @@ fn parse_method(pair: Pair<Rule>) -> Method {
         name,
         args,
         result,
+        stream_result,
+        stream_args,
+        error,
     }
 }
 
@@ -470,24 +491,98 @@ fn parse_method_ref(pair: Pair<Rule>) -> MethodRef {
     }
 }
 
+fn parse_http_segment_var(pair: Pair<Rule>) -> PathSegment {
+    let mut name = String::new();
+    let mut type_path = None;
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::field_name => {
+                name = pair.as_str().to_string();
+            }
+            Rule::type_path => {
+                type_path = Some(parse_type_path(pair));
+            }
+            r => unreachable!("unhandled rule: {:#?}", r),
+        }
+    }
+    PathSegment::Var { name, type_path }
+}
+
 fn parse_http_route_pattern(pair: Pair<Rule>) -> HttpRoutePattern {
-    let (mut line, mut col) = (0, 0);
-    let mut items = Vec::new();
+    let pos = Pos::from(&pair);
+    let mut segments = Vec::new();
+    let mut query = Vec::new();
     for pair in pair.into_inner() {
-        (line, col) = pair.as_span().start_pos().line_col();
         match pair.as_rule() {
-            Rule::http_route_var => {
-                items.push(pair.as_str().to_string());
+            Rule::http_segment_literal => {
+                segments.push(PathSegment::Literal(pair.as_str().to_string()));
+            }
+            Rule::http_segment_var => {
+                segments.push(parse_http_segment_var(pair));
             }
-            Rule::http_path_part => {
-                items.push(pair.as_str().to_string());
+            Rule::http_segment_wildcard => {
+                let name = pair.into_inner().map(|p| p.as_str().to_string()).collect();
+                segments.push(PathSegment::Wildcard(name));
+            }
+            Rule::http_query_template => {
+                query = pair.into_inner().map(|p| p.as_str().to_string()).collect();
             }
             r => unreachable!("unhandled rule: {:#?}", r),
         }
     }
     HttpRoutePattern {
-        pos: Pos { line, col },
-        items,
+        pos,
+        segments,
+        query,
+    }
+}
+
+fn parse_param_source(pair: Pair<Rule>, field: &str) -> ParamSource {
+    match pair.as_str() {
+        "@query" => ParamSource::Query,
+        "@body" => ParamSource::Body,
+        "@form" => ParamSource::Form,
+        "@multipart" => ParamSource::Multipart,
+        _ => {
+            let name = pair
+                .into_inner()
+                .flat_map(|p| p.into_inner())
+                .next()
+                .map(|p| p.as_str().trim_matches('"').to_string());
+            ParamSource::Header(name.unwrap_or_else(|| field.to_string()))
+        }
+    }
+}
+
+fn parse_route_field(pair: Pair<Rule>) -> RouteField {
+    let mut doc = String::new();
+    let mut name = String::new();
+    let mut source = None;
+    let mut type_path = TypePath {
+        pos: Pos { line: 0, col: 0 },
+        path: Vec::new(),
+    };
+    let pos = Pos::from(&pair);
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::doc => {
+                doc = pair.as_str().to_string();
+            }
+            Rule::param_source => {
+                source = Some(pair);
+            }
+            Rule::field_name => {
+                name = pair.as_str().to_string();
+            }
+            Rule::type_path => {
+                type_path = parse_type_path(pair);
+            }
+            r => unreachable!("unhandled rule: {:#?}", r),
+        }
+    }
+    RouteField {
+        source: source.map(|s| parse_param_source(s, &name)),
+        field: Field { pos, doc, name, type_path },
     }
 }
 
@@ -499,16 +594,20 @@ fn parse_http_route(pair: Pair<Rule>) -> HttpRoute {
     };
     let mut pattern: HttpRoutePattern = HttpRoutePattern {
         pos: Pos { line: 0, col: 0 },
-        items: Vec::new(),
+        segments: Vec::new(),
+        query: Vec::new(),
     };
     let mut method: MethodRef = MethodRef {
         pos: Pos { line: 0, col: 0 },
         path: Vec::new(),
     };
     let mut fields = Vec::new();
+    let mut responses = Vec::new();
     let (mut line, mut col) = (0, 0);
     for pair in pair.into_inner() {
-        (line, col) = pair.as_span().start_pos().line_col();
+        if pair.as_rule() != Rule::http_response {
+            (line, col) = pair.as_span().start_pos().line_col();
+        }
         match pair.as_rule() {
             Rule::http_method => {
                 verb = pair.as_str().to_string();
@@ -523,7 +622,13 @@ fn parse_http_route(pair: Pair<Rule>) -> HttpRoute {
                 method = parse_method_ref(pair);
             }
             Rule::field => {
-                fields.push(parse_field(pair));
+                fields.push(RouteField { source: None, field: parse_field(pair) });
+            }
+            Rule::route_field => {
+                fields.push(parse_route_field(pair));
+            }
+            Rule::http_response => {
+                responses.push(parse_http_response(pair));
             }
             r => unreachable!("unhandled rule: {:#?}", r),
         }
@@ -535,7 +640,69 @@ fn parse_http_route(pair: Pair<Rule>) -> HttpRoute {
         pattern,
         method,
         fields,
+        responses,
+    }
+}
+
+fn parse_http_response(pair: Pair<Rule>) -> HttpResponse {
+    let pos = Pos::from(&pair);
+    let mut response = HttpResponse {
+        pos,
+        status: 0,
+        type_path: TypePath { pos: Pos::default(), path: Vec::new() },
+        headers: Vec::new(),
+    };
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::http_status => {
+                response.status = pair.as_str().parse().unwrap_or_default();
+            }
+            Rule::type_path => {
+                response.type_path = parse_type_path(pair);
+            }
+            Rule::http_response_header => {
+                let pos = Pos::from(&pair);
+                let mut inner = pair.into_inner();
+                let name = inner.next().map(|p| p.as_str().to_string()).unwrap_or_default();
+                let field = inner.next().map(|p| p.as_str().to_string()).unwrap_or_default();
+                response.headers.push(ResponseHeader { pos, name, field });
+            }
+            r => unreachable!("unhandled rule: {:#?}", r),
+        }
     }
+    response
+}
+
+fn parse_http_rest(pair: Pair<Rule>) -> HttpRest {
+    let pos = Pos::from(&pair);
+    let mut rest = HttpRest {
+        pos,
+        resource: TypePath { pos: Pos::default(), path: Vec::new() },
+        key: None,
+        path: None,
+        service: None,
+    };
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::type_path => {
+                rest.resource = parse_type_path(pair);
+            }
+            Rule::field => {
+                rest.key = Some(RestKey::Typed(parse_field(pair)));
+            }
+            Rule::field_name => {
+                rest.key = Some(RestKey::Named { pos: Pos::from(&pair), name: pair.as_str().to_string() });
+            }
+            Rule::http_rest_path => {
+                rest.path = Some(pair.as_str().to_string());
+            }
+            Rule::type_tag => {
+                rest.service = Some(parse_type_tag(pair));
+            }
+            r => unreachable!("unhandled rule: {:#?}", r),
+        }
+    }
+    rest
 }
 
 fn parse_http_service(pair: Pair<Rule>) -> HttpService {
@@ -545,12 +712,16 @@ fn parse_http_service(pair: Pair<Rule>) -> HttpService {
         args: Vec::new(),
     };
     let (line, col) = pair.as_span().start_pos().line_col();
+    let mut rests = Vec::new();
     let mut routes = Vec::new();
     for pair in pair.into_inner() {
         match pair.as_rule() {
             Rule::type_tag => {
                 name = parse_type_tag(pair);
             }
+            Rule::http_rest => {
+                rests.push(parse_http_rest(pair));
+            }
             Rule::http_route => {
                 routes.push(parse_http_route(pair));
             }
@@ -560,10 +731,163 @@ fn parse_http_service(pair: Pair<Rule>) -> HttpService {
     HttpService {
         pos: Pos { line, col },
         name,
+        rests,
         routes,
     }
 }
 
+fn parse_storage_key(pair: Pair<Rule>) -> StorageKey {
+    let pos = Pos::from(&pair);
+    let fields = pair.into_inner().map(|p| p.as_str().to_string()).collect();
+    StorageKey { pos, fields }
+}
+
+fn parse_storage_ref(pair: Pair<Rule>, r: &mut StorageRef) {
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::type_path => {
+                r.target = parse_type_path(pair);
+            }
+            Rule::field_name => {
+                r.field = pair.as_str().to_string();
+            }
+            x => unreachable!("unhandled rule: {:#?}", x)
+        }
+    }
+}
+
+fn parse_storage_field(pair: Pair<Rule>) -> StorageRef {
+    let mut r = StorageRef {
+        pos: Pos::from(&pair),
+        doc: String::new(),
+        name: String::new(),
+        wrapper: None,
+        target: TypePath { pos: Pos::default(), path: Vec::new() },
+        field: String::new(),
+    };
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::doc => {
+                r.doc = pair.as_str().to_string();
+            }
+            Rule::field_name => {
+                r.name = pair.as_str().to_string();
+            }
+            Rule::storage_ref => {
+                parse_storage_ref(pair, &mut r);
+            }
+            Rule::storage_ref_wrap => {
+                for pp in pair.into_inner() {
+                    match pp.as_rule() {
+                        Rule::type_name => {
+                            r.wrapper = Some(pp.as_str().to_string());
+                        }
+                        Rule::storage_ref => {
+                            parse_storage_ref(pp, &mut r);
+                        }
+                        x => unreachable!("unhandled rule: {:#?}", x)
+                    }
+                }
+            }
+            x => unreachable!("unhandled rule: {:#?}", x)
+        }
+    }
+    r
+}
+
+fn parse_storage_table(pair: Pair<Rule>) -> StorageTable {
+    let pos = Pos::from(&pair);
+    let mut doc = String::new();
+    let mut name = TypePath { pos: Pos::default(), path: Vec::new() };
+    let mut items = Vec::new();
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::doc => {
+                doc = pair.as_str().to_string();
+            }
+            Rule::type_path => {
+                name = parse_type_path(pair);
+            }
+            Rule::storage_unique => {
+                items.push(StorageItem::Unique(parse_storage_key(pair)));
+            }
+            Rule::storage_index => {
+                items.push(StorageItem::Index(parse_storage_key(pair)));
+            }
+            Rule::storage_field => {
+                items.push(StorageItem::Ref(parse_storage_field(pair)));
+            }
+            Rule::field => {
+                items.push(StorageItem::Field(parse_field(pair)));
+            }
+            x => unreachable!("unhandled rule: {:#?}", x)
+        }
+    }
+    StorageTable { pos, doc, name, items }
+}
+
+fn parse_storage(pair: Pair<Rule>) -> Storage {
+    let pos = Pos::from(&pair);
+    let mut doc = String::new();
+    let mut kind = String::new();
+    let mut tables = Vec::new();
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::doc => {
+                doc = pair.as_str().to_string();
+            }
+            Rule::storage_kind => {
+                kind = pair.as_str().to_string();
+            }
+            Rule::storage_table => {
+                tables.push(parse_storage_table(pair));
+            }
+            x => unreachable!("unhandled rule: {:#?}", x)
+        }
+    }
+    Storage { pos, doc, kind, tables }
+}
+
+fn parse_events(pair: Pair<Rule>) -> Events {
+    let pos = Pos::from(&pair);
+    let mut doc = String::new();
+    let mut name = String::new();
+    let mut events = Vec::new();
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::doc => {
+                doc = pair.as_str().to_string();
+            }
+            Rule::type_name => {
+                name = pair.as_str().to_string();
+            }
+            Rule::field => {
+                events.push(parse_field(pair));
+            }
+            x => unreachable!("unhandled rule: {:#?}", x)
+        }
+    }
+    Events { pos, doc, name, events }
+}
+
+fn parse_project_option(pair: Pair<Rule>) -> ProjectOption {
+    let pos = Pos::from(&pair);
+    let mut key = String::new();
+    let mut value = String::new();
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::option_key => {
+                key = pair.as_str().to_string();
+            }
+            Rule::option_value => {
+                value = pair.as_str().to_string();
+            }
+            x => unreachable!("unhandled rule: {:#?}", x)
+        }
+    }
+    ProjectOption { pos, key, value }
+}
+
 use crate::error::IozhError;
 
 trait ResultExt<T> {
@@ -619,10 +943,14 @@ impl Project {
         for project in pair {
             for ns in project.into_inner() {
                 match ns.as_rule() {
+                    Rule::options => {
+                        self.options = ns.into_inner().map(parse_project_option).collect();
+                    }
                     Rule::nspace => {
                         let nspace = self.parse_namespace(ns)?;
                         nspaces.push(nspace);
                     }
+                    Rule::EOI => {}
                     r => r.unhandled()?
                 }
             }
@@ -656,7 +984,17 @@ impl Project {
                     items.push(NspaceItem::Service(parse_service(pair)));
                 }
                 Rule::http_service => {
-                    items.push(NspaceItem::HttpService(parse_http_service(pair)));
+                    let s = parse_http_service(pair);
+                    s.check()?;
+                    items.push(NspaceItem::HttpService(s));
+                }
+                Rule::storage => {
+                    items.push(NspaceItem::Storage(parse_storage(pair)));
+                }
+                Rule::events => {
+                    let e = parse_events(pair);
+                    e.check()?;
+                    items.push(NspaceItem::Events(e));
                 }
                 r => unreachable!("unhandled rule: {:#?}", r),
             }
@@ -722,11 +1060,8 @@ impl Project {
                 r => unreachable!("unhandled rule: {:#?}", r),
             }
         }
-        match &mut item {
-            ChoiceItem::TypeTag { doc , .. } => {
-                *doc = parsed_doc;
-            }
-            _ => {}
+        if let ChoiceItem::TypeTag { doc , .. } = &mut item {
+            *doc = parsed_doc;
         }
         Ok(item)
     }
@@ -0,0 +1,178 @@
+use crate::ast::*;
+use crate::error::IozhError;
+use crate::types::{snake_case, TypeDef, TypeIndex};
+
+/// `game` to `games`, `category` to `categories`, `box` to `boxes`.
+pub fn plural(word: &str) -> String {
+    let vowel = |c: char| "aeiou".contains(c);
+    if let Some(stem) = word.strip_suffix('y').filter(|s| s.chars().last().is_some_and(|c| !vowel(c))) {
+        format!("{stem}ies")
+    } else if ["s", "x", "z", "ch", "sh"].iter().any(|s| word.ends_with(s)) {
+        format!("{word}es")
+    } else {
+        format!("{word}s")
+    }
+}
+
+fn tag(pos: &Pos, name: &str) -> TypeTag {
+    TypeTag { pos: pos.clone(), name: name.to_string(), args: vec![] }
+}
+
+fn path_of(pos: &Pos, name: &str, args: Vec<TypePath>) -> TypePath {
+    TypePath { pos: pos.clone(), path: vec![TypeTag { pos: pos.clone(), name: name.to_string(), args }] }
+}
+
+/// The routes and service methods a `REST` item stands for.
+struct Expansion {
+    routes: Vec<HttpRoute>,
+    service: Service,
+}
+
+fn expand(rest: &HttpRest, key: Field) -> Expansion {
+    let pos = &rest.pos;
+    let resource = rest.resource.path.last().map(|t| t.name.clone()).unwrap_or_default();
+    let collection = rest.path
+        .clone()
+        .unwrap_or_else(|| format!("/{}", plural(&snake_case(&resource)).replace('_', "-")));
+    let collection = collection.trim_end_matches('/').to_string();
+    let service = rest.service.clone().unwrap_or_else(|| tag(pos, &format!("{resource}Service")));
+    let unit = path_of(pos, "Unit", vec![]);
+    let arg = Field {
+        pos: pos.clone(),
+        doc: String::new(),
+        name: resource[..1].to_lowercase() + &resource[1..],
+        type_path: rest.resource.clone(),
+    };
+    let key_name = key.name[..1].to_uppercase() + &key.name[1..];
+
+    let mut routes = vec![];
+    let mut methods = vec![];
+    let mut add = |verb: &str, input: &TypePath, by_key: bool, name: &str, args: Vec<Field>, result: TypePath, doc: &str| {
+        let mut segments = collection
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| PathSegment::Literal(s.to_string()))
+            .collect::<Vec<_>>();
+        if by_key {
+            segments.push(PathSegment::Var { name: key.name.clone(), type_path: None });
+        }
+        routes.push(HttpRoute {
+            pos: pos.clone(),
+            verb: verb.to_string(),
+            input: input.clone(),
+            pattern: HttpRoutePattern { pos: pos.clone(), segments, query: vec![] },
+            method: MethodRef { pos: pos.clone(), path: vec![service.clone(), tag(pos, name)] },
+            fields: if by_key { vec![RouteField { source: None, field: key.clone() }] } else { vec![] },
+            responses: vec![],
+        });
+        methods.push(Method {
+            pos: pos.clone(),
+            doc: format!("/* {doc} */"),
+            name: tag(pos, name),
+            args,
+            result,
+            stream_result: false,
+            stream_args: vec![],
+            error: None,
+        });
+    };
+    let list = path_of(pos, "List", vec![rest.resource.clone()]);
+    let option = path_of(pos, "Option", vec![rest.resource.clone()]);
+    add("GET", &unit, false, "findAll", vec![], list, &format!("Every {resource}"));
+    add("GET", &unit, true, &format!("findBy{key_name}"), vec![key.clone()], option, &format!("{resource} by {}", key.name));
+    add("POST", &rest.resource, false, "create", vec![arg.clone()], rest.resource.clone(), &format!("Stores a new {resource}"));
+    add("PUT", &rest.resource, true, "update", vec![key.clone(), arg], rest.resource.clone(), &format!("Replaces the {resource} with the given {}", key.name));
+    add("DELETE", &unit, true, "delete", vec![key.clone()], unit.clone(), &format!("Removes the {resource} with the given {}", key.name));
+    // A missing resource is a 404 and a stored one a 201.
+    let response = |status, type_path: &TypePath| HttpResponse { pos: pos.clone(), status, type_path: type_path.clone(), headers: vec![] };
+    routes[1].responses = vec![response(200, &rest.resource), response(404, &unit)];
+    routes[2].responses = vec![response(201, &rest.resource)];
+
+    Expansion {
+        routes,
+        service: Service {
+            pos: pos.clone(),
+            doc: format!("/* CRUD operations on {resource}, implied by `REST {resource}` */"),
+            name: service,
+            methods,
+        },
+    }
+}
+
+impl Project {
+    /// Replaces the `REST` items of every http service by the CRUD routes
+    /// they stand for, declaring the implied service next to the http
+    /// service unless the namespace already has one of that name. Backends
+    /// only ever see plain routes.
+    pub fn expand_rest(&mut self) -> Result<(), IozhError> {
+        let index = TypeIndex::build(self);
+        let mut nspaces = std::mem::take(&mut self.nspaces);
+        let result = nspaces.iter_mut().try_for_each(|n| self.expand_rest_in(&index, n, &[]));
+        self.nspaces = nspaces;
+        result
+    }
+
+    fn expand_rest_in(&self, index: &TypeIndex, nspace: &mut Nspace, parent: &[String]) -> Result<(), IozhError> {
+        let mut path = parent.to_vec();
+        path.push(nspace.name.clone());
+        let mut i = 0;
+        while i < nspace.items.len() {
+            let mut implied = vec![];
+            match &mut nspace.items[i] {
+                NspaceItem::Nspace(n) => self.expand_rest_in(index, n, &path)?,
+                NspaceItem::HttpService(s) => {
+                    for rest in std::mem::take(&mut s.rests) {
+                        let key = self.rest_key(index, &rest, &path)?;
+                        let expansion = expand(&rest, key);
+                        s.routes.extend(expansion.routes);
+                        implied.push(expansion.service);
+                    }
+                    s.routes.sort_by_key(|r| (r.pos.line, r.pos.col));
+                    s.check()?;
+                }
+                _ => {}
+            }
+            for service in implied {
+                let declared = nspace.items.iter().any(|item| match item {
+                    NspaceItem::Service(s) => s.name.name == service.name.name,
+                    _ => false,
+                });
+                if !declared {
+                    nspace.items.insert(i, NspaceItem::Service(service));
+                    i += 1;
+                }
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// The given key, typed like the field of the resource when only named,
+    /// else the `id` field of the resource, else `id: I64`.
+    fn rest_key(&self, index: &TypeIndex, rest: &HttpRest, path: &[String]) -> Result<Field, IozhError> {
+        let structure = match index.resolve_path(&rest.resource, path).map(|e| e.def) {
+            Some(TypeDef::Structure(idx)) | Some(TypeDef::Variant { structure: idx, .. }) => self.get_structure(idx)?,
+            _ => return Err(IozhError {
+                pos: rest.resource.pos.clone(),
+                msg: "REST resource is not a structure".to_string(),
+            }),
+        };
+        match &rest.key {
+            Some(RestKey::Typed(key)) => return Ok(key.clone()),
+            Some(RestKey::Named { pos, name }) => {
+                return structure.get_fields().into_iter().find(|f| &f.name == name).cloned().ok_or_else(|| IozhError {
+                    pos: pos.clone(),
+                    msg: format!("REST resource has no field {name} to use as its key"),
+                });
+            }
+            None => {}
+        }
+        let id = structure.get_fields().into_iter().find(|f| f.name == "id").cloned();
+        Ok(id.unwrap_or_else(|| Field {
+            pos: rest.pos.clone(),
+            doc: String::new(),
+            name: "id".to_string(),
+            type_path: path_of(&rest.pos, "I64", vec![]),
+        }))
+    }
+}
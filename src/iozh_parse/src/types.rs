@@ -0,0 +1,709 @@
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::error::IozhError;
+use crate::format::format_type_path;
+
+/// Builtin types understood by every backend. Both iozh names (`I64`)
+/// and their Scala spellings (`Long`) are accepted, since existing
+/// schemas use either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    I32,
+    I64,
+    F32,
+    F64,
+    Bool,
+    String,
+    DateTime,
+    Duration,
+    File,
+    Option,
+    List,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "I32" | "Int" => Some(Builtin::I32),
+            "I64" | "Long" => Some(Builtin::I64),
+            "F32" | "Float" => Some(Builtin::F32),
+            "F64" | "Double" => Some(Builtin::F64),
+            "Bool" | "Boolean" => Some(Builtin::Bool),
+            "String" => Some(Builtin::String),
+            "DateTime" | "Instant" => Some(Builtin::DateTime),
+            "Duration" | "FiniteDuration" => Some(Builtin::Duration),
+            "File" => Some(Builtin::File),
+            "Option" => Some(Builtin::Option),
+            "List" => Some(Builtin::List),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(tp: &TypePath) -> Option<Builtin> {
+        match tp.path.as_slice() {
+            [t] => Builtin::from_name(&t.name),
+            [.., t] if t.name == "File" => Some(Builtin::File),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeDef {
+    Structure(Idx),
+    Choice(Idx),
+    /// Structure item of a choice, addressed as `Choice.Item`.
+    Variant { choice: Idx, structure: Idx },
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeEntry {
+    /// Namespace the type is declared in.
+    pub path: Vec<String>,
+    /// Name relative to the namespace, `Choice.Item` for variants.
+    pub name: String,
+    pub def: TypeDef,
+}
+
+/// Index of the structures and choices declared directly in namespaces,
+/// used by backends to resolve field types to their declarations.
+#[derive(Debug, Default)]
+pub struct TypeIndex {
+    entries: HashMap<String, Vec<TypeEntry>>,
+}
+
+impl TypeIndex {
+    pub fn build(project: &Project) -> TypeIndex {
+        let mut index = TypeIndex::default();
+        for nspace in &project.nspaces {
+            index.add_nspace(project, nspace, &[]);
+        }
+        index
+    }
+
+    fn add_nspace(&mut self, project: &Project, nspace: &Nspace, parent: &[String]) {
+        let mut path = parent.to_vec();
+        path.push(nspace.name.clone());
+        for item in &nspace.items {
+            match item {
+                NspaceItem::Structure(idx) => {
+                    if let Ok(s) = project.get_structure(*idx) {
+                        self.add(&path, s.name.name.clone(), TypeDef::Structure(*idx));
+                    }
+                }
+                NspaceItem::Choice(idx) => {
+                    if let Ok(c) = project.get_choice(*idx) {
+                        self.add(&path, c.name.name.clone(), TypeDef::Choice(*idx));
+                        for ci in &c.choices {
+                            if let ChoiceItem::Structure(sidx) = ci {
+                                if let Ok(s) = project.get_structure(*sidx) {
+                                    let name = format!("{}.{}", c.name.name, s.name.name);
+                                    self.add(&path, name, TypeDef::Variant { choice: *idx, structure: *sidx });
+                                }
+                            }
+                        }
+                    }
+                }
+                NspaceItem::Nspace(n) => self.add_nspace(project, n, &path),
+                _ => {}
+            }
+        }
+    }
+
+    fn add(&mut self, path: &[String], name: String, def: TypeDef) {
+        self.entries.entry(name.clone()).or_default().push(TypeEntry {
+            path: path.to_vec(),
+            name,
+            def,
+        });
+    }
+
+    /// Resolves a type name as seen from namespace `from`: the declaration
+    /// in the closest enclosing namespace wins, otherwise the first one found.
+    pub fn resolve(&self, name: &str, from: &[String]) -> Option<&TypeEntry> {
+        let candidates = self.entries.get(name)?;
+        candidates
+            .iter()
+            .filter(|e| from.starts_with(&e.path))
+            .max_by_key(|e| e.path.len())
+            .or_else(|| candidates.first())
+    }
+
+    /// Resolves a possibly qualified type path: `Name`, `Choice.Item`,
+    /// `nspace.Name` or `nspace.Choice.Item`.
+    pub fn resolve_path(&self, tp: &TypePath, from: &[String]) -> Option<&TypeEntry> {
+        let names = tp.path.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        for split in 0..names.len() {
+            let (nspace, local) = names.split_at(split);
+            let local = local.join(".");
+            let found = if nspace.is_empty() {
+                self.resolve(&local, from)
+            } else {
+                self.entries.get(&local).and_then(|c| c.iter().find(|e| e.path.ends_with(nspace)))
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+impl Project {
+    /// Every namespace of the project, depth first, together with its full path.
+    pub fn all_nspaces(&self) -> Vec<(Vec<String>, &Nspace)> {
+        fn walk<'a>(nspace: &'a Nspace, parent: &[String], acc: &mut Vec<(Vec<String>, &'a Nspace)>) {
+            let mut path = parent.to_vec();
+            path.push(nspace.name.clone());
+            acc.push((path.clone(), nspace));
+            for item in &nspace.items {
+                if let NspaceItem::Nspace(n) = item {
+                    walk(n, &path, acc);
+                }
+            }
+        }
+        let mut acc = vec![];
+        for nspace in &self.nspaces {
+            walk(nspace, &[], &mut acc);
+        }
+        acc
+    }
+
+    /// Service method a route points at, with the namespace of the service.
+    /// Like types, the closest enclosing namespace wins.
+    pub fn resolve_method(&self, r: &MethodRef, from: &[String]) -> Option<(Vec<String>, &Service, &Method)> {
+        let (method, rest) = r.path.split_last()?;
+        let (service, qualifier) = rest.split_last()?;
+        let qualifier = qualifier.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        let candidates = self.all_nspaces()
+            .into_iter()
+            .filter(|(path, _)| path.ends_with(&qualifier))
+            .filter_map(|(path, nspace)| {
+                nspace.items.iter().find_map(|item| match item {
+                    NspaceItem::Service(s) if s.name.name == service.name => Some((path.clone(), s)),
+                    _ => None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let (path, s) = candidates
+            .iter()
+            .filter(|(path, _)| from.starts_with(path))
+            .max_by_key(|(path, _)| path.len())
+            .or_else(|| candidates.first())?;
+        let m = s.methods.iter().find(|m| m.name.name == method.name)?;
+        Some((path.clone(), *s, m))
+    }
+}
+
+impl HttpRoutePattern {
+    /// Names bound by the path, variables and wildcard alike.
+    pub fn vars(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|s| match s {
+                PathSegment::Var { name, .. } | PathSegment::Wildcard(name) => Some(name.as_str()),
+                PathSegment::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    fn check(&self) -> Result<(), IozhError> {
+        let error = |msg: String| Err(IozhError { pos: self.pos.clone(), msg });
+        let names = self.vars().into_iter().chain(self.query.iter().map(|q| q.as_str())).collect::<Vec<_>>();
+        if let Some((i, name)) = names.iter().enumerate().find(|(i, n)| names[..*i].contains(n)) {
+            let what = if i < self.vars().len() { "Path variable" } else { "Query parameter" };
+            return error(format!("{what} {name} is bound twice in {self}"));
+        }
+        let wildcard = self.segments.iter().position(|s| matches!(s, PathSegment::Wildcard(_)));
+        if wildcard.is_some_and(|i| i + 1 != self.segments.len()) {
+            return error(format!("A wildcard must be the last segment of {self}"));
+        }
+        Ok(())
+    }
+
+    /// Whether every path `other` matches is matched by this pattern too.
+    fn covers(&self, other: &HttpRoutePattern) -> bool {
+        for (i, s) in self.segments.iter().enumerate() {
+            match (s, other.segments.get(i)) {
+                (PathSegment::Wildcard(_), _) => return true,
+                (_, None | Some(PathSegment::Wildcard(_))) => return false,
+                (PathSegment::Var { .. }, _) => {}
+                (PathSegment::Literal(a), Some(PathSegment::Literal(b))) if a == b => {}
+                (PathSegment::Literal(_), _) => return false,
+            }
+        }
+        self.segments.len() == other.segments.len()
+    }
+}
+
+impl HttpService {
+    /// Checks the route patterns, and that no route is shadowed by an
+    /// earlier one of the same verb matching every path it matches.
+    pub fn check(&self) -> Result<(), IozhError> {
+        for (i, r) in self.routes.iter().enumerate() {
+            r.pattern.check()?;
+            if let Some(earlier) = self.routes[..i].iter().find(|e| e.verb == r.verb && e.pattern.covers(&r.pattern)) {
+                return Err(IozhError {
+                    pos: r.pos.clone(),
+                    msg: format!(
+                        "{} {} is ambiguous: {} {} at line {} matches every path it does",
+                        r.verb, r.pattern, earlier.verb, earlier.pattern, earlier.pos.line,
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Events {
+    /// Checks that there are events and that their names are distinct.
+    pub fn check(&self) -> Result<(), IozhError> {
+        if self.events.is_empty() {
+            return Err(IozhError { pos: self.pos.clone(), msg: format!("{} declares no events", self.name) });
+        }
+        for (i, e) in self.events.iter().enumerate() {
+            if let Some(earlier) = self.events[..i].iter().find(|x| x.name == e.name) {
+                return Err(IozhError {
+                    pos: e.pos.clone(),
+                    msg: format!("Event {} of {} is declared twice, the first time at line {}", e.name, self.name, earlier.pos.line),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a route carries its body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    None,
+    Json,
+    Form,
+    Multipart,
+}
+
+/// A route parameter with its source resolved. Variables of the pattern
+/// without a field of their own get one here, of type `String` unless
+/// typed in the pattern; so do names of the query template, which are
+/// optional: `Option[String]`.
+#[derive(Debug, Clone)]
+pub struct RouteParam {
+    pub field: Field,
+    pub source: ParamSource,
+    /// Set for fields without an annotation outside of the path: servers
+    /// read them from the query, then from the body when the query lacks them.
+    pub fallback: bool,
+}
+
+impl HttpRoute {
+    /// Whether the route reads an input from its body. GET, HEAD and DELETE
+    /// requests carry none, so their input type is ignored.
+    pub fn has_input(&self) -> bool {
+        !is_unit(&self.input) && !matches!(self.verb.as_str(), "GET" | "HEAD" | "DELETE")
+    }
+
+    fn implied_field(&self, name: &str, type_path: Option<TypePath>) -> Field {
+        let pos = self.pattern.pos.clone();
+        Field {
+            pos: pos.clone(),
+            doc: String::new(),
+            name: name.to_string(),
+            type_path: type_path.unwrap_or_else(|| TypePath {
+                pos: pos.clone(),
+                path: vec![TypeTag { pos, name: "String".to_string(), args: vec![] }],
+            }),
+        }
+    }
+
+    /// Whether the input of the route feeds a streamed argument of
+    /// `method`: the first argument no parameter binds.
+    pub fn streams_input(&self, method: &Method, params: &[RouteParam]) -> bool {
+        self.has_input()
+            && method.args
+                .iter()
+                .find(|a| !params.iter().any(|p| p.field.name == a.name))
+                .is_some_and(|a| method.is_stream_arg(&a.name))
+    }
+
+    /// Parameters of the route with where they are read from: path
+    /// variables first, then the fields, which are read from their
+    /// annotation, or the query.
+    pub fn params(&self) -> Result<Vec<RouteParam>, IozhError> {
+        let error = |f: &Field, msg: String| Err(IozhError { pos: f.pos.clone(), msg });
+        let declared = |name: &str| self.fields.iter().find(|f| f.field.name == name);
+        let mut params = vec![];
+        for s in &self.pattern.segments {
+            let (name, typed) = match s {
+                PathSegment::Var { name, type_path } => (name, type_path),
+                PathSegment::Wildcard(name) => (name, &None),
+                PathSegment::Literal(_) => continue,
+            };
+            let field = match (declared(name), typed) {
+                (Some(f), _) if f.source.is_some() => {
+                    return error(&f.field, "Path variables are read from the path and take no source annotation".to_string());
+                }
+                (Some(f), Some(_)) => return error(&f.field, format!("{name} is typed in the pattern and declared as a field")),
+                (Some(f), None) => f.field.clone(),
+                (None, typed) => self.implied_field(name, typed.clone()),
+            };
+            params.push(RouteParam { field, source: ParamSource::Path, fallback: false });
+        }
+        let vars = self.pattern.vars();
+        for f in self.fields.iter().filter(|f| !vars.contains(&f.field.name.as_str())) {
+            let templated = self.pattern.query.contains(&f.field.name);
+            let (source, fallback) = match (&f.source, templated) {
+                (None | Some(ParamSource::Query), true) => (ParamSource::Query, false),
+                (Some(_), true) => return error(&f.field, format!("{} is in the query template but read from elsewhere", f.field.name)),
+                (Some(source), false) => (source.clone(), false),
+                (None, false) => (ParamSource::Query, true),
+            };
+            params.push(RouteParam { field: f.field.clone(), source, fallback });
+        }
+        for q in self.pattern.query.iter().filter(|q| declared(q).is_none()) {
+            let mut field = self.implied_field(q, None);
+            let pos = field.pos.clone();
+            field.type_path = TypePath { pos: pos.clone(), path: vec![TypeTag { pos, name: "Option".to_string(), args: vec![field.type_path] }] };
+            params.push(RouteParam { field, source: ParamSource::Query, fallback: false });
+        }
+        Ok(params)
+    }
+
+    /// The body is JSON when the route has an input or `@body` fields, a
+    /// form or multipart one when it has such fields; a route cannot mix them.
+    pub fn body_kind(&self) -> Result<BodyKind, IozhError> {
+        let mut kind = if self.has_input() { BodyKind::Json } else { BodyKind::None };
+        for f in &self.fields {
+            let own = match f.source {
+                Some(ParamSource::Body) => BodyKind::Json,
+                Some(ParamSource::Form) => BodyKind::Form,
+                Some(ParamSource::Multipart) => BodyKind::Multipart,
+                _ => continue,
+            };
+            if kind != BodyKind::None && kind != own {
+                return Err(IozhError {
+                    pos: f.field.pos.clone(),
+                    msg: "A route body is either JSON, a form or multipart: its input and fields disagree".to_string(),
+                });
+            }
+            kind = own;
+        }
+        Ok(kind)
+    }
+}
+
+/// What a declared response answers among the results of its method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseCase {
+    /// Every result: the response is typed as the method result, or as its
+    /// error for error responses.
+    Result,
+    /// `Some` of an `Option` result, declared with the inner type.
+    Some,
+    /// `None` of an `Option` result, declared as `Unit`.
+    None,
+    /// An item of a choice; `object` for bare and literal items, which are
+    /// singletons rather than classes in Scala.
+    Item { name: String, object: bool },
+}
+
+impl ResponseCase {
+    /// Whether the response carries a body, answering a value of `tp`.
+    pub fn has_body(&self, tp: &TypePath) -> bool {
+        match self {
+            ResponseCase::Result => !is_unit(tp),
+            ResponseCase::None => false,
+            ResponseCase::Some | ResponseCase::Item { .. } => true,
+        }
+    }
+}
+
+pub struct RouteResponse<'a> {
+    pub response: &'a HttpResponse,
+    pub case: ResponseCase,
+    /// Set when the response answers the error of the method rather than
+    /// its result.
+    pub error: bool,
+}
+
+pub fn is_unit(tp: &TypePath) -> bool {
+    tp.path.len() == 1 && tp.path[0].name == "Unit"
+}
+
+/// The item of `choice` that `tp` names, as `Item` or `Choice.Item`, with
+/// the fields it may read headers from.
+fn choice_item(p: &Project, choice: &Choice, tp: &TypePath) -> Result<Option<(ResponseCase, Vec<String>)>, IozhError> {
+    let last = tp.last().map(|t| t.name.as_str()).unwrap_or_default();
+    let qualifier = tp.path.len().checked_sub(2).map(|i| tp.path[i].name.as_str());
+    if qualifier.is_some_and(|q| q != choice.name.name) {
+        return Ok(None);
+    }
+    for item in &choice.choices {
+        let found = match item {
+            ChoiceItem::TypeTag { choice: name, .. } | ChoiceItem::Value { name, .. } if name.name == last => {
+                (ResponseCase::Item { name: name.name.clone(), object: true }, vec![])
+            }
+            ChoiceItem::Wrap { name, field, .. } if name.name == last => {
+                (ResponseCase::Item { name: name.name.clone(), object: false }, vec![field.clone()])
+            }
+            ChoiceItem::Structure(idx) => {
+                let s = p.get_structure(*idx)?;
+                if s.name.name != last {
+                    continue;
+                }
+                let fields = s.get_fields().into_iter().chain(choice.fields.iter()).map(|f| f.name.clone()).collect();
+                (ResponseCase::Item { name: s.name.name.clone(), object: false }, fields)
+            }
+            _ => continue,
+        };
+        return Ok(Some(found));
+    }
+    Ok(None)
+}
+
+/// How a response type relates to `tp`, the result or the error of a
+/// method, with the fields of the body headers may read.
+fn response_case(p: &Project, index: &TypeIndex, from: &[String], tp: &TypePath, response: &TypePath) -> Result<Option<(ResponseCase, Vec<String>)>, IozhError> {
+    let fields_of = |body: &TypePath| -> Result<Vec<String>, IozhError> {
+        Ok(match index.resolve_path(body, from).map(|e| e.def) {
+            Some(TypeDef::Structure(idx)) | Some(TypeDef::Variant { structure: idx, .. }) => {
+                p.get_structure(idx)?.get_fields().into_iter().map(|f| f.name.clone()).collect()
+            }
+            _ => vec![],
+        })
+    };
+    let ty = format_type_path(response);
+    let inner = match Builtin::from_path(tp) {
+        Some(Builtin::Option) => tp.path[0].args.first(),
+        _ => None,
+    };
+    if ty == format_type_path(tp) {
+        return Ok(Some((ResponseCase::Result, fields_of(tp)?)));
+    }
+    if let Some(inner) = inner {
+        if format_type_path(inner) == ty {
+            return Ok(Some((ResponseCase::Some, fields_of(inner)?)));
+        }
+        if is_unit(response) {
+            return Ok(Some((ResponseCase::None, vec![])));
+        }
+    }
+    match index.resolve_path(tp, from).map(|e| e.def) {
+        Some(TypeDef::Choice(idx)) => choice_item(p, p.get_choice(idx)?, response),
+        _ => Ok(None),
+    }
+}
+
+impl HttpRoute {
+    /// Resolves the declared responses against `method`, the method of the
+    /// route declared in namespace `from`. Each response must be typed as
+    /// the result, as its inner type and `Unit` for an `Option`, as an item
+    /// of a choice, or likewise as the error or one of its items; headers
+    /// must name fields of the body.
+    pub fn responses<'a>(&'a self, p: &Project, index: &TypeIndex, from: &[String], method: &Method) -> Result<Vec<RouteResponse<'a>>, IozhError> {
+        let mut resolved: Vec<RouteResponse<'a>> = vec![];
+        for r in &self.responses {
+            let error = |msg: String| Err(IozhError { pos: r.pos.clone(), msg });
+            if method.stream_result {
+                return error(format!("{} {} streams its result, which is always answered with 200", self.verb, self.pattern));
+            }
+            if !(100..600).contains(&r.status) {
+                return error(format!("{} is not an HTTP status", r.status));
+            }
+            let ty = format_type_path(&r.type_path);
+            let found = match response_case(p, index, from, &method.result, &r.type_path)? {
+                Some((case, fields)) => Some((case, fields, false)),
+                None => match &method.error {
+                    Some(e) => response_case(p, index, from, e, &r.type_path)?.map(|(case, fields)| (case, fields, true)),
+                    None => None,
+                },
+            };
+            let Some((case, fields, is_error)) = found else {
+                let error_type = method.error.as_ref().map(|e| format!(" nor the error {}", format_type_path(e))).unwrap_or_default();
+                return error(format!("{ty} is neither the result {}{error_type} nor one of its cases", format_type_path(&method.result)));
+            };
+            if let Some(other) = resolved.iter().find(|o| o.response.status == r.status) {
+                return error(format!("Status {} is declared twice, the first time at line {}", r.status, other.response.pos.line));
+            }
+            if let Some(other) = resolved.iter().find(|o| o.case == case && o.error == is_error) {
+                return error(format!("{ty} is answered with {} already", other.response.status));
+            }
+            if let Some(h) = r.headers.iter().find(|h| !fields.contains(&h.field)) {
+                return Err(IozhError {
+                    pos: h.pos.clone(),
+                    msg: format!("Header {} reads {}, which is not a field of {ty}", h.name, h.field),
+                });
+            }
+            resolved.push(RouteResponse { response: r, case, error: is_error });
+        }
+        Ok(resolved)
+    }
+}
+
+impl TypePath {
+    /// Last segment of the path, i.e. the referenced type itself.
+    pub fn last(&self) -> Option<&TypeTag> {
+        self.path.last()
+    }
+
+    /// Whether the type parameter `param` occurs anywhere in the path.
+    pub fn mentions(&self, param: &str) -> bool {
+        self.path.iter().any(|t| t.name == param || t.args.iter().any(|a| a.mentions(param)))
+    }
+}
+
+impl TypeTag {
+    /// Names of the type parameters of a declaration, `[A, E]` for `Result[A, E]`.
+    pub fn param_names(&self) -> Vec<String> {
+        self.args.iter().map(crate::format::format_type_path).collect()
+    }
+}
+
+impl Method {
+    pub fn is_stream_arg(&self, name: &str) -> bool {
+        self.stream_args.iter().any(|a| a == name)
+    }
+
+    /// Whether the result or any argument is a stream.
+    pub fn has_streams(&self) -> bool {
+        self.stream_result || !self.stream_args.is_empty()
+    }
+}
+
+/// Type parameters of a choice that a variant's fields actually use.
+pub fn used_params(params: &[String], fields: &[&Field]) -> Vec<String> {
+    params
+        .iter()
+        .filter(|p| fields.iter().any(|f| f.type_path.mentions(p)))
+        .cloned()
+        .collect()
+}
+
+impl Literal {
+    /// Literal value without the surrounding quotes of string literals.
+    pub fn unquoted(&self) -> String {
+        match self {
+            Literal::String { value, .. } => value.trim_matches('"').to_string(),
+            Literal::Int { value, .. } => value.to_string(),
+            Literal::Nil => "nil".to_string(),
+        }
+    }
+}
+
+/// A choice item as it appears on the wire with the circe codecs generated
+/// by `iozh_gen_scala2`. Backends that must stay JSON-compatible with the
+/// Scala side build on this rather than on raw `ChoiceItem`s.
+pub enum ChoiceVariant<'a> {
+    /// Bare item, or a structure without fields: encoded as the string `wire`.
+    Unit { name: &'a TypeTag, doc: &'a str, wire: String },
+    /// Item bound to a literal: encoded as the literal itself.
+    Value { name: &'a TypeTag, doc: &'a str, value: &'a Literal },
+    /// Newtype item: encoded as the wrapped value, without any tag.
+    Wrap { name: &'a TypeTag, doc: &'a str, field: &'a str, target: &'a TypePath },
+    /// Structure item: a JSON object with the structure fields followed by
+    /// the choice's common fields, plus `tag.0: tag.1` when the choice is tagged.
+    Record { structure: &'a Structure, fields: Vec<&'a Field>, tag: Option<(String, String)> },
+}
+
+impl<'a> ChoiceVariant<'a> {
+    pub fn name(&self) -> &'a TypeTag {
+        match self {
+            ChoiceVariant::Unit { name, .. } => name,
+            ChoiceVariant::Value { name, .. } => name,
+            ChoiceVariant::Wrap { name, .. } => name,
+            ChoiceVariant::Record { structure, .. } => &structure.name,
+        }
+    }
+}
+
+impl Choice {
+    /// String a bare item is encoded as.
+    pub fn wire_unit_name(&self, item: &TypeTag) -> String {
+        (self.name.name.clone() + &item.name).to_ascii_lowercase()
+    }
+
+    pub fn variants<'a>(&'a self, project: &'a Project) -> Vec<ChoiceVariant<'a>> {
+        let tag_key = self.get_most_common_tag_key(project);
+        self.choices
+            .iter()
+            .filter_map(|item| match item {
+                ChoiceItem::Nil => None,
+                ChoiceItem::TypeTag { doc, choice } => Some(ChoiceVariant::Unit {
+                    name: choice,
+                    doc,
+                    wire: self.wire_unit_name(choice),
+                }),
+                ChoiceItem::Value { doc, name, value } => Some(ChoiceVariant::Value { name, doc, value }),
+                ChoiceItem::Wrap { doc, name, field, target } => Some(ChoiceVariant::Wrap { name, doc, field, target }),
+                ChoiceItem::Structure(idx) => {
+                    let s = project.get_structure(*idx).ok()?;
+                    let fields = s.get_fields().into_iter().chain(self.fields.iter()).collect::<Vec<_>>();
+                    if fields.is_empty() {
+                        return Some(ChoiceVariant::Unit {
+                            name: &s.name,
+                            doc: &s.doc,
+                            wire: self.wire_unit_name(&s.name),
+                        });
+                    }
+                    let tag = tag_key.as_ref().map(|key| {
+                        let value = s.get_tag(key)
+                            .map(|t| t.value.unquoted())
+                            .unwrap_or_else(|| s.name.name.clone());
+                        (key.clone(), value)
+                    });
+                    Some(ChoiceVariant::Record { structure: s, fields, tag })
+                }
+            })
+            .collect()
+    }
+}
+
+impl Structure {
+    pub fn get_fields(&self) -> Vec<&Field> {
+        self.fields
+            .iter()
+            .filter_map(|item| match item {
+                StructItem::Field(f) => Some(f),
+                StructItem::Tag(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// `createdAt` to `created_at`, for backends whose conventions call for it.
+pub fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        if *c == '-' || *c == ' ' {
+            out.push('_');
+        } else {
+            out.extend(c.to_lowercase());
+        }
+    }
+    out
+}
+
+/// Text of a `/* ... */` doc comment without the delimiters and with
+/// the common indentation removed.
+pub fn doc_text(doc: &str) -> String {
+    let body = doc.trim()
+        .trim_start_matches("/**")
+        .trim_start_matches("/*")
+        .trim_end_matches("*/");
+    body.lines()
+        .map(|l| l.trim())
+        .skip_while(|l| l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}